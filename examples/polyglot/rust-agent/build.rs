@@ -7,6 +7,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("cargo:rerun-if-changed=../../../proto/executions.proto");
     println!("cargo:rerun-if-changed=../../../proto/gateway.proto");
 
+    // Writes one file per proto package into generated/, e.g.
+    // generated/parallax.executions.rs; src/generated.rs includes each of them
+    //
     // build_transport(false): gateway.proto has an RPC named `Connect`, which
     // collides with tonic's generated transport constructor of the same name
     tonic_build::configure()
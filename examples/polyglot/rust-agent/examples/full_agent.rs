@@ -1,7 +1,7 @@
 use anyhow::Result;
 use parallaxai::{
     ParallaxAgent, AgentResult,
-    Client, ClientConfig,
+    Client,
 };
 use serde_json::json;
 use std::collections::HashMap;
@@ -19,10 +19,12 @@ impl DemoAgent {
         metadata.insert("expertise".to_string(), "0.85".to_string());
         metadata.insert("language".to_string(), "rust".to_string());
         
+        // Keep the same id across restarts so the agent reclaims its lease
+        let capabilities = vec!["code-analysis".to_string(), "testing".to_string()];
         let agent = ParallaxAgent::new(
-            "demo-agent-rust",
+            ParallaxAgent::stable_id("demo-agent-rust", &capabilities),
             "Rust Demo Agent",
-            vec!["code-analysis".to_string(), "testing".to_string()],
+            capabilities,
             metadata,
         ).set_analyze_fn(move |task: &str, data: Option<serde_json::Value>| {
             let task = task.to_string();
@@ -56,8 +58,8 @@ impl DemoAgent {
                             suggestions.push("Review unsafe code usage");
                         }
 
-                        Ok(AgentResult {
-                            value: json!({
+                        Ok(AgentResult::new(
+                            json!({
                                 "has_tests": has_tests,
                                 "has_docs": has_docs,
                                 "has_unsafe": has_unsafe,
@@ -65,25 +67,20 @@ impl DemoAgent {
                                 "suggestions": suggestions,
                                 "lines_analyzed": code.lines().count(),
                             }),
-                            confidence: 0.85,
-                            reasoning: Some(format!("Analyzed {} lines of Rust code", code.lines().count())),
-                            uncertainties: vec![],
-                            metadata: HashMap::new(),
-                        })
+                            0.85,
+                        )
+                        .with_reasoning(format!("Analyzed {} lines of Rust code", code.lines().count())))
                     }
                     "get-system-info" => {
-                        Ok(AgentResult {
-                            value: json!({
+                        Ok(AgentResult::new(
+                            json!({
                                 "version": "1.0.0",
                                 "language": "Rust",
                                 "platform": std::env::consts::OS,
                                 "arch": std::env::consts::ARCH,
                             }),
-                            confidence: 1.0,
-                            reasoning: None,
-                            uncertainties: vec![],
-                            metadata: HashMap::new(),
-                        })
+                            1.0,
+                        ))
                     }
                     _ => Err(format!("Unknown task: {}", task).into()),
                 }
@@ -98,6 +95,12 @@ impl DemoAgent {
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    // `--manifest` prints what the agent advertises, for CI checks, and exits
+    if std::env::args().any(|arg| arg == "--manifest") {
+        println!("{}", serde_json::to_string_pretty(&DemoAgent::new().agent.manifest())?);
+        return Ok(());
+    }
+
     // Initialize tracing
     tracing_subscriber::fmt::init();
     info!("🚀 Parallax Rust SDK Demo\n");
@@ -106,7 +109,7 @@ async fn main() -> Result<()> {
     info!("1️⃣  Creating Demo Agent...");
     let agent = DemoAgent::new();
     info!("✅ Agent created: {} ({})", agent.agent.name, agent.agent.id);
-    info!("   Capabilities: {:?}", agent.agent.capabilities);
+    info!("   Capabilities: {:?}", agent.agent.capabilities());
     info!("   Expertise: {}\n", agent.agent.metadata.get("expertise").unwrap_or(&"0.5".to_string()));
 
     // Test 2: Agent Methods
@@ -146,13 +149,13 @@ mod tests {
     // Test 3: Control Plane Client
     info!("3️⃣  Testing Control Plane Client...");
 
-    let config = ClientConfig {
-        endpoint: "http://localhost:8080".to_string(),
-        timeout: std::time::Duration::from_secs(5),
-        ..Default::default()
-    };
+    let client = Client::builder()
+        .endpoint("http://localhost:8080")
+        .timeout(std::time::Duration::from_secs(5))
+        .connect()
+        .await;
 
-    match Client::new(config).await {
+    match client {
         Ok(client) => {
             // Client created successfully
             info!("✅ Control plane client created");
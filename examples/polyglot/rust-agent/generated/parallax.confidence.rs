@@ -26,6 +26,27 @@ pub struct ConfidenceResult {
         ::prost::alloc::string::String,
         ::prost::alloc::string::String,
     >,
+    /// Result value as a Struct; non-objects under "value"
+    #[prost(message, optional, tag = "8")]
+    pub value_struct: ::core::option::Option<::prost_types::Struct>,
+    /// Set on log lines StreamAnalyze sends before the result
+    #[prost(message, optional, tag = "9")]
+    pub log: ::core::option::Option<LogEvent>,
+    /// value_json continues in the next StreamAnalyze message
+    #[prost(bool, tag = "10")]
+    pub more_chunks: bool,
+}
+/// Log line an agent emits while analyzing
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct LogEvent {
+    /// "debug", "info", "warn" or "error"
+    #[prost(string, tag = "1")]
+    pub level: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub message: ::prost::alloc::string::String,
+    /// When the line was logged
+    #[prost(message, optional, tag = "3")]
+    pub timestamp: ::core::option::Option<::prost_types::Timestamp>,
 }
 /// Agent capabilities
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -46,6 +67,9 @@ pub struct Capabilities {
         ::prost::alloc::string::String,
         f64,
     >,
+    /// JSON schema of result values, if declared
+    #[prost(message, optional, tag = "6")]
+    pub output_schema: ::core::option::Option<::prost_types::Struct>,
 }
 /// Health check response
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -131,6 +155,25 @@ pub struct AgentRequest {
     /// Optional pattern to use
     #[prost(string, tag = "6")]
     pub pattern_name: ::prost::alloc::string::String,
+    /// Higher runs first when the agent is busy
+    #[prost(int32, tag = "7")]
+    pub priority: i32,
+    /// Drop the request if not started by then
+    #[prost(message, optional, tag = "8")]
+    pub deadline: ::core::option::Option<::prost_types::Timestamp>,
+}
+/// Structured error attached to the status of a failed Analyze call
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ErrorDetail {
+    /// Agent-defined error code
+    #[prost(string, tag = "1")]
+    pub code: ::prost::alloc::string::String,
+    /// Human-readable description
+    #[prost(string, tag = "2")]
+    pub message: ::prost::alloc::string::String,
+    /// Whether retrying may succeed
+    #[prost(bool, tag = "3")]
+    pub retryable: bool,
 }
 /// Empty message for methods that don't need parameters
 #[derive(Clone, Copy, PartialEq, ::prost::Message)]
@@ -22,6 +22,17 @@ pub struct Execution {
     pub confidence: f64,
     #[prost(message, optional, tag = "10")]
     pub metrics: ::core::option::Option<::prost_types::Struct>,
+    #[prost(message, repeated, tag = "11")]
+    pub agents: ::prost::alloc::vec::Vec<ExecutionAgent>,
+}
+/// Agent that took part in an execution
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExecutionAgent {
+    #[prost(string, tag = "1")]
+    pub agent_id: ::prost::alloc::string::String,
+    /// Confidence of the agent's own result, unset when it was not recorded
+    #[prost(double, optional, tag = "2")]
+    pub confidence: ::core::option::Option<f64>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct GetExecutionRequest {
@@ -41,6 +52,16 @@ pub struct ListExecutionsRequest {
     pub offset: i32,
     #[prost(string, tag = "3")]
     pub status: ::prost::alloc::string::String,
+    /// Token from a previous response's next_cursor; when set, offset is ignored
+    #[prost(string, tag = "4")]
+    pub cursor: ::prost::alloc::string::String,
+    /// Further filters, applied before paginating; empty fields match everything
+    #[prost(string, tag = "5")]
+    pub pattern_name: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "6")]
+    pub started_after: ::core::option::Option<::prost_types::Timestamp>,
+    #[prost(message, optional, tag = "7")]
+    pub started_before: ::core::option::Option<::prost_types::Timestamp>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ListExecutionsResponse {
@@ -48,6 +69,21 @@ pub struct ListExecutionsResponse {
     pub executions: ::prost::alloc::vec::Vec<Execution>,
     #[prost(int32, tag = "2")]
     pub total: i32,
+    /// Opaque token for the following page; empty on the last page
+    #[prost(string, tag = "3")]
+    pub next_cursor: ::prost::alloc::string::String,
+}
+/// Filters for ExportExecutions; empty fields match everything
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExportExecutionsRequest {
+    #[prost(string, tag = "1")]
+    pub status: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub pattern_name: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "3")]
+    pub started_after: ::core::option::Option<::prost_types::Timestamp>,
+    #[prost(message, optional, tag = "4")]
+    pub started_before: ::core::option::Option<::prost_types::Timestamp>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct StreamExecutionRequest {
@@ -271,6 +307,36 @@ pub mod execution_service_client {
                 );
             self.inner.server_streaming(req, path, codec).await
         }
+        /// Stream every execution matching the filters, for bulk export
+        pub async fn export_executions(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ExportExecutionsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::Execution>>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/parallax.executions.ExecutionService/ExportExecutions",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new(
+                        "parallax.executions.ExecutionService",
+                        "ExportExecutions",
+                    ),
+                );
+            self.inner.server_streaming(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -313,6 +379,20 @@ pub mod execution_service_server {
             tonic::Response<Self::StreamExecutionStream>,
             tonic::Status,
         >;
+        /// Server streaming response type for the ExportExecutions method.
+        type ExportExecutionsStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::Execution, tonic::Status>,
+            >
+            + std::marker::Send
+            + 'static;
+        /// Stream every execution matching the filters, for bulk export
+        async fn export_executions(
+            &self,
+            request: tonic::Request<super::ExportExecutionsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<Self::ExportExecutionsStream>,
+            tonic::Status,
+        >;
     }
     #[derive(Debug)]
     pub struct ExecutionServiceServer<T> {
@@ -530,6 +610,54 @@ pub mod execution_service_server {
                     };
                     Box::pin(fut)
                 }
+                "/parallax.executions.ExecutionService/ExportExecutions" => {
+                    #[allow(non_camel_case_types)]
+                    struct ExportExecutionsSvc<T: ExecutionService>(pub Arc<T>);
+                    impl<
+                        T: ExecutionService,
+                    > tonic::server::ServerStreamingService<
+                        super::ExportExecutionsRequest,
+                    > for ExportExecutionsSvc<T> {
+                        type Response = super::Execution;
+                        type ResponseStream = T::ExportExecutionsStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ExportExecutionsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ExecutionService>::export_executions(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = ExportExecutionsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         let mut response = http::Response::new(empty_body());
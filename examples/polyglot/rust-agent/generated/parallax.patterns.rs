@@ -101,6 +101,12 @@ pub mod execute_pattern_request {
             ::prost::alloc::string::String,
             ::prost::alloc::string::String,
         >,
+        /// Overrides the pattern's min_agents when > 0
+        #[prost(int32, tag = "5")]
+        pub min_agents: i32,
+        /// Overrides the pattern's max_agents when > 0
+        #[prost(int32, tag = "6")]
+        pub max_agents: i32,
     }
 }
 /// Pattern execution response
@@ -124,6 +130,13 @@ pub struct ExecutePatternResponse {
     pub agent_results: ::prost::alloc::vec::Vec<super::confidence::ConfidenceResult>,
     #[prost(string, tag = "8")]
     pub error_message: ::prost::alloc::string::String,
+    /// StreamExecutePattern only: the engine event this message reports
+    /// ("started", "agent_completed", ..., then "completed" or "failed")
+    #[prost(string, tag = "9")]
+    pub event_type: ::prost::alloc::string::String,
+    /// The event's payload
+    #[prost(message, optional, tag = "10")]
+    pub event_data: ::core::option::Option<::prost_types::Struct>,
 }
 /// Nested message and enum types in `ExecutePatternResponse`.
 pub mod execute_pattern_response {
@@ -229,6 +242,69 @@ pub struct UploadPatternResponse {
     #[prost(string, tag = "3")]
     pub pattern_id: ::prost::alloc::string::String,
 }
+/// Watch request for pattern catalog changes
+#[derive(Clone, Copy, PartialEq, ::prost::Message)]
+pub struct WatchPatternsRequest {
+    /// Start with every current pattern as ADDED
+    #[prost(bool, tag = "1")]
+    pub include_initial: bool,
+}
+/// Pattern catalog change
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PatternCatalogEvent {
+    #[prost(enumeration = "pattern_catalog_event::EventType", tag = "1")]
+    pub r#type: i32,
+    /// For REMOVED, only the name is required
+    #[prost(message, optional, tag = "2")]
+    pub pattern: ::core::option::Option<Pattern>,
+    #[prost(message, optional, tag = "3")]
+    pub timestamp: ::core::option::Option<::prost_types::Timestamp>,
+}
+/// Nested message and enum types in `PatternCatalogEvent`.
+pub mod pattern_catalog_event {
+    #[derive(
+        Clone,
+        Copy,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash,
+        PartialOrd,
+        Ord,
+        ::prost::Enumeration
+    )]
+    #[repr(i32)]
+    pub enum EventType {
+        Unknown = 0,
+        Added = 1,
+        Updated = 2,
+        Removed = 3,
+    }
+    impl EventType {
+        /// String value of the enum field names used in the ProtoBuf definition.
+        ///
+        /// The values are not transformed in any way and thus are considered stable
+        /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+        pub fn as_str_name(&self) -> &'static str {
+            match self {
+                Self::Unknown => "UNKNOWN",
+                Self::Added => "ADDED",
+                Self::Updated => "UPDATED",
+                Self::Removed => "REMOVED",
+            }
+        }
+        /// Creates an enum from field names used in the ProtoBuf definition.
+        pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+            match value {
+                "UNKNOWN" => Some(Self::Unknown),
+                "ADDED" => Some(Self::Added),
+                "UPDATED" => Some(Self::Updated),
+                "REMOVED" => Some(Self::Removed),
+                _ => None,
+            }
+        }
+    }
+}
 /// Generated client implementations.
 pub mod pattern_service_client {
     #![allow(
@@ -445,6 +521,33 @@ pub mod pattern_service_client {
                 );
             self.inner.unary(req, path, codec).await
         }
+        /// Watch for patterns being added, updated or removed
+        pub async fn watch_patterns(
+            &mut self,
+            request: impl tonic::IntoRequest<super::WatchPatternsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<tonic::codec::Streaming<super::PatternCatalogEvent>>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::unknown(
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/parallax.patterns.PatternService/WatchPatterns",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(
+                    GrpcMethod::new("parallax.patterns.PatternService", "WatchPatterns"),
+                );
+            self.inner.server_streaming(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -503,6 +606,20 @@ pub mod pattern_service_server {
             tonic::Response<super::UploadPatternResponse>,
             tonic::Status,
         >;
+        /// Server streaming response type for the WatchPatterns method.
+        type WatchPatternsStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<super::PatternCatalogEvent, tonic::Status>,
+            >
+            + std::marker::Send
+            + 'static;
+        /// Watch for patterns being added, updated or removed
+        async fn watch_patterns(
+            &self,
+            request: tonic::Request<super::WatchPatternsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<Self::WatchPatternsStream>,
+            tonic::Status,
+        >;
     }
     /// Pattern execution service
     #[derive(Debug)]
@@ -812,6 +929,52 @@ pub mod pattern_service_server {
                     };
                     Box::pin(fut)
                 }
+                "/parallax.patterns.PatternService/WatchPatterns" => {
+                    #[allow(non_camel_case_types)]
+                    struct WatchPatternsSvc<T: PatternService>(pub Arc<T>);
+                    impl<
+                        T: PatternService,
+                    > tonic::server::ServerStreamingService<super::WatchPatternsRequest>
+                    for WatchPatternsSvc<T> {
+                        type Response = super::PatternCatalogEvent;
+                        type ResponseStream = T::WatchPatternsStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::WatchPatternsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as PatternService>::watch_patterns(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let method = WatchPatternsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.server_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         let mut response = http::Response::new(empty_body());
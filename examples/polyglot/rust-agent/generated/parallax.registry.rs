@@ -18,6 +18,9 @@ pub struct AgentRegistration {
     /// Time to live for registration
     #[prost(message, optional, tag = "7")]
     pub ttl: ::core::option::Option<::prost_types::Duration>,
+    /// Last lease renewal, set by the registry
+    #[prost(message, optional, tag = "8")]
+    pub last_heartbeat: ::core::option::Option<::prost_types::Timestamp>,
 }
 /// Nested message and enum types in `AgentRegistration`.
 pub mod agent_registration {
@@ -92,6 +95,9 @@ pub struct WatchRequest {
     /// Include current agents in initial response
     #[prost(bool, tag = "2")]
     pub include_initial: bool,
+    /// Watch only this agent, if set
+    #[prost(string, tag = "3")]
+    pub agent_id: ::prost::alloc::string::String,
 }
 /// Watch event
 #[derive(Clone, PartialEq, ::prost::Message)]
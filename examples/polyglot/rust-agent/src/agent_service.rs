@@ -1,25 +1,132 @@
 use crate::{
-    error::{Error, Result},
+    circuit_breaker::{self, CircuitBreaker},
+    client::{InterceptedChannel, InterceptorChain},
+    clock::{calculate_reconnect_delay, Clock, Instant, SystemClock},
+    error::{rpc_error, Error, Result, ResultExt},
     generated::parallax::registry::{
-        registry_client::RegistryClient, AgentRegistration, ListAgentsRequest,
-        RegisterRequest, RenewRequest, WatchRequest,
+        registry_client::RegistryClient, watch_event::EventType, AgentRegistration,
+        ListAgentsRequest, RegisterRequest, RenewRequest, WatchEvent, WatchRequest,
     },
-    types::{Agent, AgentStatus},
+    telemetry::traced,
+    transport::Transport,
+    types::{Agent, AgentEvent, AgentStatus, Metadata},
 };
 use futures::{Stream, StreamExt};
-use std::pin::Pin;
-use tonic::transport::Channel;
-use tracing::{debug, info};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    pin::Pin,
+    sync::Arc,
+    time::Duration,
+};
+use tonic::{codec::CompressionEncoding, Streaming};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+/// Registry label that carries an agent's status, since registrations have
+/// no dedicated status field
+const STATUS_LABEL: &str = "status";
+
+/// Registrations `register_batch` keeps in flight unless configured otherwise
+const DEFAULT_BATCH_CONCURRENCY: usize = 8;
+
+/// Consecutive attempts `stream_agents` makes to re-open a broken watch
+const WATCH_RECONNECT_ATTEMPTS: u32 = 5;
+/// Delay before re-opening a broken agent watch, doubled per failed attempt
+const WATCH_RECONNECT_DELAY_MS: u64 = 100;
+const WATCH_RECONNECT_MAX_DELAY_MS: u64 = 5_000;
+/// Window within which a repeat of an agent's last update is dropped
+const WATCH_DEDUPE_WINDOW: Duration = Duration::from_secs(1);
 
 /// Service for agent operations
 #[derive(Clone)]
 pub struct AgentService {
-    _channel: Channel,
+    _channel: Transport,
+    endpoint: String,
+    compression: Option<CompressionEncoding>,
+    interceptors: InterceptorChain,
+    closing: CancellationToken,
+    batch_concurrency: usize,
+    clock: Arc<dyn Clock>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    /// Held by `update_status` from reading an agent until its update is
+    /// registered, shared by every service of one client
+    status_updates: Arc<tokio::sync::Mutex<()>>,
 }
 
 impl AgentService {
-    pub(crate) fn new(channel: Channel) -> Self {
-        Self { _channel: channel }
+    pub(crate) fn new(channel: Transport, endpoint: String) -> Self {
+        Self {
+            _channel: channel,
+            endpoint,
+            compression: None,
+            interceptors: InterceptorChain::default(),
+            closing: CancellationToken::new(),
+            batch_concurrency: DEFAULT_BATCH_CONCURRENCY,
+            clock: Arc::new(SystemClock),
+            circuit_breaker: None,
+            status_updates: Arc::default(),
+        }
+    }
+
+    /// Set how many registrations `register_batch` runs at once (default: 8)
+    pub fn with_batch_concurrency(mut self, batch_concurrency: usize) -> Self {
+        self.batch_concurrency = batch_concurrency;
+        self
+    }
+
+    /// Compress requests and accept compressed responses with `compression`
+    pub(crate) fn with_compression(mut self, compression: Option<CompressionEncoding>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Run every request through `interceptors`
+    pub(crate) fn with_interceptors(mut self, interceptors: InterceptorChain) -> Self {
+        self.interceptors = interceptors;
+        self
+    }
+
+    /// Fail calls with `client closed` once `closing` is cancelled
+    pub(crate) fn with_closing(mut self, closing: CancellationToken) -> Self {
+        self.closing = closing;
+        self
+    }
+
+    /// Time watch reconnect backoff and update dedupe with `clock`
+    pub(crate) fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Fail calls fast while `circuit_breaker` is open
+    pub(crate) fn with_circuit_breaker(
+        mut self,
+        circuit_breaker: Option<Arc<CircuitBreaker>>,
+    ) -> Self {
+        self.circuit_breaker = circuit_breaker;
+        self
+    }
+
+    /// Serialize status updates with every other service holding `lock`
+    pub(crate) fn with_status_lock(mut self, lock: Arc<tokio::sync::Mutex<()>>) -> Self {
+        self.status_updates = lock;
+        self
+    }
+
+    fn client(&self) -> Result<RegistryClient<InterceptedChannel>> {
+        if self.closing.is_cancelled() {
+            return Err(Error::Connection("client closed".to_string()));
+        }
+        let channel = circuit_breaker::guard(
+            self._channel.clone(),
+            self.circuit_breaker.as_ref(),
+            &self.clock,
+        )?;
+        let client = RegistryClient::with_interceptor(channel, self.interceptors.clone());
+        Ok(match self.compression {
+            Some(encoding) => client.send_compressed(encoding).accept_compressed(encoding),
+            None => client,
+        })
     }
 
     /// Register a new agent
@@ -34,53 +141,98 @@ impl AgentService {
         // Update last seen
         agent.last_seen = chrono::Utc::now();
         
-        let metadata = agent.metadata.clone();
         let request = RegisterRequest {
-            agent: Some(AgentRegistration {
-                id: agent.id.clone(),
-                name: agent.name.clone(),
-                endpoint: agent.endpoint.clone(),
-                capabilities: agent.capabilities.clone(),
-                metadata: Some(crate::generated::parallax::registry::agent_registration::Metadata {
-                    version: metadata.get("version").cloned().unwrap_or_default(),
-                    region: metadata.get("region").cloned().unwrap_or_default(),
-                    labels: metadata,
-                    default_confidence: agent.confidence,
-                }),
-                registered_at: None,
-                ttl: None,
-            }),
+            agent: Some(agent_to_registration(&agent)),
             auto_renew: true,
         };
 
-        let mut client = RegistryClient::new(self._channel.clone());
-        client.register(request).await?;
+        let mut client = self.client()?;
+        traced("AgentService::register", &self.endpoint, None, request, |request| {
+            client.register(request)
+        })
+        .await
+        .map_err(rpc_error("AgentService::register", &self.endpoint))
+        .with_context("agent_id", agent.id.as_str())?;
 
         Ok(agent)
     }
 
+    /// Register several agents concurrently
+    ///
+    /// Agents without an id are assigned one first, as with [`register`](Self::register).
+    /// Returns one result per agent, in input order; a failed registration
+    /// does not affect the others. An agent reusing an id that appears
+    /// earlier in the batch is not registered and gets
+    /// `Error::InvalidArgument`. Runs up to the configured batch concurrency
+    /// at once.
+    pub async fn register_batch(&self, agents: Vec<Agent>) -> Result<Vec<Result<Agent>>> {
+        if self.batch_concurrency == 0 {
+            return Err(Error::InvalidArgument(
+                "batch concurrency must be at least 1".to_string(),
+            ));
+        }
+        info!("Registering batch of {} agents", agents.len());
+
+        let mut first_seen = HashMap::new();
+        let registrations: Vec<_> = agents
+            .into_iter()
+            .enumerate()
+            .map(|(index, mut agent)| {
+                if agent.id.is_empty() {
+                    agent.id = uuid::Uuid::new_v4().to_string();
+                }
+                match first_seen.get(&agent.id) {
+                    Some(first) => Err(Error::InvalidArgument(format!(
+                        "agent id {} at index {} duplicates the one at index {}",
+                        agent.id, index, first
+                    ))),
+                    None => {
+                        first_seen.insert(agent.id.clone(), index);
+                        Ok(agent)
+                    }
+                }
+            })
+            .collect();
+
+        Ok(futures::stream::iter(registrations)
+            .map(|agent| async move { self.register(agent?).await })
+            .buffered(self.batch_concurrency)
+            .collect()
+            .await)
+    }
+
     /// List all agents
     pub async fn list(&self) -> Result<Vec<Agent>> {
         debug!("Listing agents");
 
-        let mut client = RegistryClient::new(self._channel.clone());
-        let response = client
-            .list_agents(ListAgentsRequest {
-                capabilities: vec![],
-                labels: Default::default(),
-                limit: 0,
-                continuation_token: String::new(),
-            })
+        Ok(self
+            .list_registrations()
             .await?
-            .into_inner();
-
-        Ok(response
-            .agents
             .into_iter()
             .map(agent_from_registration)
             .collect())
     }
 
+    async fn list_registrations(&self) -> Result<Vec<AgentRegistration>> {
+        let mut client = self.client()?;
+        let response = traced(
+            "AgentService::list",
+            &self.endpoint,
+            None,
+            ListAgentsRequest {
+                capabilities: vec![],
+                labels: Default::default(),
+                limit: 0,
+                continuation_token: String::new(),
+            },
+            |request| client.list_agents(request),
+        )
+        .await
+        .map_err(rpc_error("AgentService::list", &self.endpoint))?
+        .into_inner();
+        Ok(response.agents)
+    }
+
     /// Get a specific agent
     pub async fn get(&self, id: &str) -> Result<Agent> {
         debug!("Getting agent: {}", id);
@@ -92,14 +244,54 @@ impl AgentService {
             .ok_or_else(|| crate::error::Error::NotFound(format!("Agent not found: {}", id)))
     }
 
+    /// Pick the agent best suited to handle `capability`
+    ///
+    /// Considers only active agents that advertise the capability and returns
+    /// the one with the highest confidence; ties go to the agent whose last
+    /// heartbeat, or registration if the registry reports none, is most
+    /// recent. Returns `Error::NotFound` when no active agent offers it.
+    pub async fn best_for_capability(&self, capability: &str) -> Result<Agent> {
+        debug!("Selecting best agent for capability: {}", capability);
+
+        self.list()
+            .await?
+            .into_iter()
+            .filter(|agent| {
+                agent.status == AgentStatus::Active
+                    && agent.capabilities.iter().any(|offered| offered == capability)
+            })
+            .max_by(|a, b| {
+                a.confidence
+                    .total_cmp(&b.confidence)
+                    .then_with(|| a.last_seen.cmp(&b.last_seen))
+            })
+            .ok_or_else(|| {
+                Error::NotFound(format!("no active agent offers capability {}", capability))
+            })
+    }
+
     /// Update agent status
+    ///
+    /// The status is stored as a registration label, so the agent is
+    /// re-registered with its current details. Transitions not allowed by
+    /// [`AgentStatus::can_transition_to`] are rejected.
     pub async fn update_status(&self, id: &str, status: AgentStatus) -> Result<()> {
         info!("Updating agent status: {} -> {:?}", id, status);
 
-        Err(Error::InvalidArgument(format!(
-            "update status is not supported by the registry API: {}",
-            id
-        )))
+        // Another update landing between the read and the write could make
+        // this one skip the transition check
+        let _update = self.status_updates.lock().await;
+        let mut agent = self.get(id).await?;
+        if !agent.status.can_transition_to(status) {
+            return Err(Error::InvalidArgument(format!(
+                "agent {} cannot move from {:?} to {:?}",
+                id, agent.status, status
+            )));
+        }
+
+        agent.status = status;
+        self.register(agent).await?;
+        Ok(())
     }
 
     /// Update agent confidence
@@ -123,13 +315,20 @@ impl AgentService {
     pub async fn heartbeat(&self, id: &str) -> Result<()> {
         debug!("Sending heartbeat for agent: {}", id);
 
-        let mut client = RegistryClient::new(self._channel.clone());
-        client
-            .renew(RenewRequest {
+        let mut client = self.client()?;
+        traced(
+            "AgentService::heartbeat",
+            &self.endpoint,
+            None,
+            RenewRequest {
                 lease_id: format!("lease-{}", id),
                 ttl: None,
-            })
-            .await?;
+            },
+            |request| client.renew(request),
+        )
+        .await
+        .map_err(rpc_error("AgentService::heartbeat", &self.endpoint))
+        .with_context("agent_id", id)?;
         Ok(())
     }
 
@@ -137,9 +336,12 @@ impl AgentService {
     pub async fn unregister(&self, id: &str) -> Result<()> {
         info!("Unregistering agent: {}", id);
 
-        let mut client = RegistryClient::new(self._channel.clone());
-        client
-            .unregister(AgentRegistration {
+        let mut client = self.client()?;
+        traced(
+            "AgentService::unregister",
+            &self.endpoint,
+            None,
+            AgentRegistration {
                 id: id.to_string(),
                 name: String::new(),
                 endpoint: String::new(),
@@ -147,38 +349,296 @@ impl AgentService {
                 metadata: None,
                 registered_at: None,
                 ttl: None,
-            })
-            .await?;
+                last_heartbeat: None,
+            },
+            |request| client.unregister(request),
+        )
+        .await
+        .map_err(rpc_error("AgentService::unregister", &self.endpoint))
+        .with_context("agent_id", id)?;
 
         Ok(())
     }
 
     /// Stream agent updates
-    pub async fn stream_agents(&self) -> Result<Pin<Box<dyn Stream<Item = Result<Agent>> + Send>>> {
+    ///
+    /// Starts with every currently registered agent as `Added`, then yields
+    /// each change as it happens. A repeat of an agent's last update within
+    /// a second is dropped. If the watch breaks it is re-opened, with
+    /// backoff, and the registry's agent set compared with what the stream
+    /// has reported: only the differences are yielded, including `Deleted`
+    /// for agents that went away in the meantime. The stream ends with an
+    /// error once re-opening has failed repeatedly or the registry rejects
+    /// the watch.
+    pub async fn stream_agents(
+        &self,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<AgentEvent>> + Send>>> {
         debug!("Streaming agents");
 
-        let mut client = RegistryClient::new(self._channel.clone());
-        let stream = client
-            .watch(WatchRequest {
+        let watch = AgentWatch::new(self.clone(), None, self.watch_agents(None, true).await?);
+        let stream = futures::stream::unfold(watch, |mut watch| async move {
+            let event = watch.next_event().await?;
+            Some((event, watch))
+        });
+
+        Ok(Box::pin(stream))
+    }
+
+    /// Stream one agent's updates
+    ///
+    /// Starts with the agent's current state, then yields it each time it
+    /// changes, re-opening a broken watch as [`stream_agents`] does. When the
+    /// agent unregisters, a final update with status `Inactive` is yielded
+    /// and the stream ends. Returns `Error::NotFound` if no agent has this id.
+    ///
+    /// [`stream_agents`]: Self::stream_agents
+    pub async fn watch(
+        &self,
+        agent_id: &str,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<Agent>> + Send>>> {
+        debug!("Watching agent: {}", agent_id);
+
+        // Opened before looking the agent up, so an unregistration right
+        // after the lookup still reaches the watch
+        let events = self.watch_agents(Some(agent_id), true).await?;
+        self.get(agent_id).await?;
+        let watch = AgentWatch::new(self.clone(), Some(agent_id.to_string()), events);
+        let stream = futures::stream::unfold(watch, |mut watch| async move {
+            let event = watch.next_event().await?;
+            Some((event.map(AgentEvent::into_agent), watch))
+        });
+
+        Ok(Box::pin(stream))
+    }
+
+    async fn watch_agents(
+        &self,
+        agent_id: Option<&str>,
+        include_initial: bool,
+    ) -> Result<Streaming<WatchEvent>> {
+        let mut client = self.client()?;
+        let response = traced(
+            "AgentService::stream_agents",
+            &self.endpoint,
+            None,
+            WatchRequest {
                 capabilities: vec![],
-                include_initial: true,
-            })
-            .await?
-            .into_inner();
+                include_initial,
+                agent_id: agent_id.unwrap_or_default().to_string(),
+            },
+            |request| client.watch(request),
+        )
+        .await
+        .map_err(rpc_error("AgentService::stream_agents", &self.endpoint))?;
+        Ok(response.into_inner())
+    }
+}
+
+/// State behind `stream_agents` and `watch`
+struct AgentWatch {
+    service: AgentService,
+    // The one agent watched, for `watch`
+    agent_id: Option<String>,
+    // The open watch, or `None` once it broke
+    events: Option<Streaming<WatchEvent>>,
+    // Attempts to re-open the watch since the last event arrived
+    failures: u32,
+    done: bool,
+    // Agents as last yielded, by id, with when and in which event type
+    known: BTreeMap<String, (Instant, i32, AgentRegistration)>,
+    // Differences found after re-opening the watch, yielded before its events
+    resynced: VecDeque<(EventType, AgentRegistration)>,
+}
+
+impl AgentWatch {
+    fn new(service: AgentService, agent_id: Option<String>, events: Streaming<WatchEvent>) -> Self {
+        Self {
+            service,
+            agent_id,
+            events: Some(events),
+            failures: 0,
+            done: false,
+            known: BTreeMap::new(),
+            resynced: VecDeque::new(),
+        }
+    }
 
-        let mapped = stream.filter_map(|event| async move {
-            match event {
-                Ok(event) => event.agent.map(|agent| Ok(agent_from_registration(agent))),
-                Err(error) => Some(Err(Error::from(error))),
+    async fn next_event(&mut self) -> Option<Result<AgentEvent>> {
+        while !self.done {
+            if let Some((event_type, agent)) = self.resynced.pop_front() {
+                match self.admit(event_type as i32, agent) {
+                    Some(event) => return Some(Ok(event)),
+                    None => continue,
+                }
             }
-        });
+            let Some(events) = self.events.as_mut() else {
+                if let Err(error) = self.reconnect().await {
+                    self.done = true;
+                    return Some(Err(error));
+                }
+                continue;
+            };
 
-        Ok(Box::pin(mapped))
+            let broken = match events.message().await {
+                Ok(Some(event)) => {
+                    self.failures = 0;
+                    let Some(agent) = event.agent else {
+                        continue;
+                    };
+                    match self.admit(event.r#type, agent) {
+                        Some(event) => return Some(Ok(event)),
+                        None => continue,
+                    }
+                }
+                Ok(None) => Error::Connection("agent watch closed".to_string()),
+                Err(status) => Error::from(status),
+            };
+            if !broken.is_retryable() {
+                self.done = true;
+                return Some(Err(broken));
+            }
+            warn!("Agent watch broke, re-syncing: {}", broken);
+            self.events = None;
+        }
+        None
+    }
+
+    /// The event for an update, unless it repeats that agent's last update
+    /// within `WATCH_DEDUPE_WINDOW` or deletes an agent never reported
+    fn admit(&mut self, event_type: i32, agent: AgentRegistration) -> Option<AgentEvent> {
+        if let Some(agent_id) = &self.agent_id {
+            // The registry may not filter the watch itself
+            if agent.id != *agent_id {
+                return None;
+            }
+        }
+        let deleted = event_type == EventType::Deleted as i32;
+        let now = self.service.clock.now();
+        match self.known.get(&agent.id) {
+            Some((at, last_type, last))
+                if *last_type == event_type
+                    && same_registration(last, &agent)
+                    && now.duration_since(*at) < WATCH_DEDUPE_WINDOW =>
+            {
+                debug!("Dropping repeated update for agent {}", agent.id);
+                return None;
+            }
+            None if deleted => return None,
+            _ => {}
+        }
+
+        if deleted {
+            self.known.remove(&agent.id);
+            // A watched agent that is gone has nothing more to report
+            self.done = self.agent_id.is_some();
+            let mut agent = agent_from_registration(agent);
+            agent.status = AgentStatus::Inactive;
+            return Some(AgentEvent::Deleted(agent));
+        }
+        self.known
+            .insert(agent.id.clone(), (now, event_type, agent.clone()));
+        let agent = agent_from_registration(agent);
+        Some(if event_type == EventType::Added as i32 {
+            AgentEvent::Added(agent)
+        } else {
+            AgentEvent::Modified(agent)
+        })
+    }
+
+    /// Queue what changed between the agents reported so far and the
+    /// registry's `current` set
+    fn resync(&mut self, current: Vec<AgentRegistration>) {
+        let mut current: BTreeMap<_, _> = current
+            .into_iter()
+            .filter(|agent| self.agent_id.as_ref().is_none_or(|id| agent.id == *id))
+            .map(|agent| (agent.id.clone(), agent))
+            .collect();
+        for (id, (_, _, last)) in &self.known {
+            match current.remove(id) {
+                None => self.resynced.push_back((EventType::Deleted, last.clone())),
+                Some(agent) if !same_registration(&agent, last) => {
+                    self.resynced.push_back((EventType::Modified, agent))
+                }
+                Some(_) => {}
+            }
+        }
+        self.resynced
+            .extend(current.into_values().map(|agent| (EventType::Added, agent)));
+    }
+
+    /// Re-open the watch, then queue the changes it missed. Opening the
+    /// watch before listing means no change falls between the two.
+    async fn reconnect(&mut self) -> Result<()> {
+        loop {
+            let delay = calculate_reconnect_delay(
+                self.failures,
+                WATCH_RECONNECT_DELAY_MS,
+                WATCH_RECONNECT_MAX_DELAY_MS,
+            );
+            self.service.clock.sleep(Duration::from_millis(delay)).await;
+            self.failures += 1;
+
+            let reopened = match self.service.watch_agents(self.agent_id.as_deref(), false).await {
+                Ok(events) => self
+                    .service
+                    .list_registrations()
+                    .await
+                    .map(|current| (events, current)),
+                Err(error) => Err(error),
+            };
+            match reopened {
+                Ok((events, current)) => {
+                    self.events = Some(events);
+                    self.resync(current);
+                    return Ok(());
+                }
+                Err(error) if error.is_retryable() && self.failures < WATCH_RECONNECT_ATTEMPTS => {
+                    debug!("Re-opening agent watch failed: {}", error);
+                }
+                Err(error) => return Err(error),
+            }
+        }
     }
 }
 
-fn agent_from_registration(agent: AgentRegistration) -> Agent {
-    let metadata = agent
+/// Whether two registrations differ in no more than their heartbeat, which
+/// the registry refreshes on every lease renewal without reporting a change
+fn same_registration(a: &AgentRegistration, b: &AgentRegistration) -> bool {
+    let without_heartbeat = |agent: &AgentRegistration| AgentRegistration {
+        last_heartbeat: None,
+        ..agent.clone()
+    };
+    without_heartbeat(a) == without_heartbeat(b)
+}
+
+pub(crate) fn agent_to_registration(agent: &Agent) -> AgentRegistration {
+    let mut labels = agent.metadata.to_labels();
+    labels.insert(STATUS_LABEL.to_string(), agent.status.to_string());
+    let metadata = &agent.metadata;
+
+    AgentRegistration {
+        id: agent.id.clone(),
+        name: agent.name.clone(),
+        endpoint: agent.endpoint.clone(),
+        capabilities: agent.capabilities.clone(),
+        metadata: Some(crate::generated::parallax::registry::agent_registration::Metadata {
+            version: metadata.get_str("version").unwrap_or_default().to_string(),
+            region: metadata.get_str("region").unwrap_or_default().to_string(),
+            labels,
+            default_confidence: agent.confidence,
+        }),
+        registered_at: Some(prost_types::Timestamp {
+            seconds: agent.last_seen.timestamp(),
+            nanos: agent.last_seen.timestamp_subsec_nanos() as i32,
+        }),
+        ttl: None,
+        // Set by the registry when the agent renews its lease
+        last_heartbeat: None,
+    }
+}
+
+pub(crate) fn agent_from_registration(agent: AgentRegistration) -> Agent {
+    let mut metadata = agent
         .metadata
         .as_ref()
         .map(|metadata| metadata.labels.clone())
@@ -188,15 +648,426 @@ fn agent_from_registration(agent: AgentRegistration) -> Agent {
         .as_ref()
         .map(|metadata| metadata.default_confidence)
         .unwrap_or(0.0);
+    let status = metadata
+        .remove(STATUS_LABEL)
+        .and_then(|label| label.parse().ok())
+        .unwrap_or_default();
 
     Agent {
         id: agent.id,
         name: agent.name,
-        status: AgentStatus::Active,
+        status,
         capabilities: agent.capabilities,
         endpoint: agent.endpoint,
-        last_seen: chrono::Utc::now(),
+        last_seen: agent
+            .last_heartbeat
+            .or(agent.registered_at)
+            .and_then(|time| chrono::DateTime::from_timestamp(time.seconds, time.nanos as u32))
+            .unwrap_or_else(chrono::Utc::now),
         confidence,
-        metadata,
+        metadata: Metadata::from_labels(metadata),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generated::parallax::registry::{
+        registry_server::{self, RegistryServer},
+        GetAgentRequest, ListAgentsResponse, RegisterResponse,
+    };
+    use std::{
+        collections::{BTreeMap, VecDeque},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    };
+    use tonic::{Request, Response, Status};
+
+    /// In-memory registry keyed by agent id
+    #[derive(Default)]
+    struct FakeRegistry {
+        agents: Mutex<BTreeMap<String, AgentRegistration>>,
+        register_calls: AtomicUsize,
+        // One scripted stream per `watch` call; later calls stay open idle
+        watch_streams: Mutex<VecDeque<Vec<std::result::Result<WatchEvent, Status>>>>,
+        watch_calls: AtomicUsize,
+    }
+
+    fn registered(response: &str) -> Response<RegisterResponse> {
+        Response::new(RegisterResponse {
+            success: true,
+            message: response.to_string(),
+            lease_id: String::new(),
+        })
+    }
+
+    #[tonic::async_trait]
+    impl registry_server::Registry for FakeRegistry {
+        async fn register(
+            &self,
+            request: Request<RegisterRequest>,
+        ) -> std::result::Result<Response<RegisterResponse>, Status> {
+            self.register_calls.fetch_add(1, Ordering::SeqCst);
+            let agent = request
+                .into_inner()
+                .agent
+                .ok_or_else(|| Status::invalid_argument("missing agent"))?;
+            self.agents.lock().unwrap().insert(agent.id.clone(), agent);
+            Ok(registered("Agent registered"))
+        }
+
+        async fn unregister(
+            &self,
+            request: Request<AgentRegistration>,
+        ) -> std::result::Result<Response<RegisterResponse>, Status> {
+            self.agents.lock().unwrap().remove(&request.into_inner().id);
+            Ok(registered("Agent unregistered"))
+        }
+
+        async fn renew(
+            &self,
+            _request: Request<RenewRequest>,
+        ) -> std::result::Result<Response<RegisterResponse>, Status> {
+            Ok(registered("Lease renewed"))
+        }
+
+        async fn list_agents(
+            &self,
+            _request: Request<ListAgentsRequest>,
+        ) -> std::result::Result<Response<ListAgentsResponse>, Status> {
+            let agents: Vec<_> = self.agents.lock().unwrap().values().cloned().collect();
+            Ok(Response::new(ListAgentsResponse {
+                total_count: agents.len() as i32,
+                agents,
+                next_continuation_token: String::new(),
+            }))
+        }
+
+        async fn get_agent(
+            &self,
+            request: Request<GetAgentRequest>,
+        ) -> std::result::Result<Response<AgentRegistration>, Status> {
+            self.agents
+                .lock()
+                .unwrap()
+                .get(&request.into_inner().agent_id)
+                .cloned()
+                .map(Response::new)
+                .ok_or_else(|| Status::not_found("Agent not found"))
+        }
+
+        type WatchStream =
+            futures::stream::BoxStream<'static, std::result::Result<WatchEvent, Status>>;
+
+        async fn watch(
+            &self,
+            _request: Request<WatchRequest>,
+        ) -> std::result::Result<Response<Self::WatchStream>, Status> {
+            self.watch_calls.fetch_add(1, Ordering::SeqCst);
+            let stream = match self.watch_streams.lock().unwrap().pop_front() {
+                Some(script) => futures::stream::iter(script).boxed(),
+                None => futures::stream::pending().boxed(),
+            };
+            Ok(Response::new(stream))
+        }
+    }
+
+    async fn service_for(fake: Arc<FakeRegistry>) -> AgentService {
+        let channel = crate::test_support::serve(
+            tonic::transport::Server::builder().add_service(RegistryServer::from_arc(fake)),
+        )
+        .await;
+        AgentService::new(channel, "http://fake".to_string())
+    }
+
+    fn agent(id: &str) -> Agent {
+        Agent {
+            id: id.to_string(),
+            name: format!("Agent {}", id),
+            status: AgentStatus::Active,
+            capabilities: vec!["analysis".to_string()],
+            endpoint: "localhost:50051".to_string(),
+            last_seen: chrono::Utc::now(),
+            confidence: 0.8,
+            metadata: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_register_batch_reports_duplicate_ids_per_entry() {
+        let fake = Arc::new(FakeRegistry::default());
+        let service = service_for(fake.clone()).await.with_batch_concurrency(2);
+        let mut duplicate = agent("agent-1");
+        duplicate.name = "Impostor".to_string();
+
+        let results = service
+            .register_batch(vec![agent("agent-1"), agent(""), duplicate, agent("agent-2")])
+            .await
+            .unwrap();
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap().id, "agent-1");
+        assert!(uuid::Uuid::parse_str(&results[1].as_ref().unwrap().id).is_ok());
+        assert!(
+            matches!(&results[2], Err(Error::InvalidArgument(message)) if message.contains("index 0")),
+            "{:?}",
+            results[2]
+        );
+        assert_eq!(results[3].as_ref().unwrap().id, "agent-2");
+
+        assert_eq!(fake.register_calls.load(Ordering::SeqCst), 3);
+        assert_eq!(service.get("agent-1").await.unwrap().name, "Agent agent-1");
+    }
+
+    #[tokio::test]
+    async fn test_agent_metadata_values_read_back_through_labels() {
+        let fake = Arc::new(FakeRegistry::default());
+        let service = service_for(fake.clone()).await;
+        service
+            .register(
+                agent("agent-1")
+                    .with_metadata("max_tokens", 4096)
+                    .with_metadata("gpu", true)
+                    .with_metadata("version", "1.0"),
+            )
+            .await
+            .unwrap();
+
+        let registered = service.get("agent-1").await.unwrap();
+        assert_eq!(registered.metadata.get_f64("max_tokens"), Some(4096.0));
+        assert_eq!(registered.metadata.get_bool("gpu"), Some(true));
+        assert_eq!(registered.metadata.get_str("version"), Some("1.0"));
+        let registration = fake.agents.lock().unwrap()["agent-1"].clone();
+        assert_eq!(registration.metadata.unwrap().version, "1.0");
+    }
+
+    #[tokio::test]
+    async fn test_update_status_follows_valid_transitions() {
+        let fake = Arc::new(FakeRegistry::default());
+        let service = service_for(fake.clone()).await;
+        service.register(agent("agent-1")).await.unwrap();
+
+        service.update_status("agent-1", AgentStatus::Draining).await.unwrap();
+        let draining = service.get("agent-1").await.unwrap();
+        assert_eq!(draining.status, AgentStatus::Draining);
+        assert!(draining.metadata.is_empty());
+
+        service.update_status("agent-1", AgentStatus::Inactive).await.unwrap();
+        assert_eq!(service.get("agent-1").await.unwrap().status, AgentStatus::Inactive);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_status_updates_each_check_the_latest_status() {
+        let fake = Arc::new(FakeRegistry::default());
+        let service = service_for(fake.clone()).await;
+        service.register(agent("agent-1")).await.unwrap();
+
+        // Draining after Inactive is not allowed, so whichever order the
+        // updates run in, the agent ends up inactive
+        let (inactive, draining) = tokio::join!(
+            service.update_status("agent-1", AgentStatus::Inactive),
+            service.update_status("agent-1", AgentStatus::Draining),
+        );
+
+        inactive.unwrap();
+        assert!(draining.is_ok() || matches!(draining, Err(Error::InvalidArgument(_))));
+        assert_eq!(service.get("agent-1").await.unwrap().status, AgentStatus::Inactive);
+    }
+
+    #[tokio::test]
+    async fn test_update_status_rejects_invalid_transition() {
+        let fake = Arc::new(FakeRegistry::default());
+        let service = service_for(fake.clone()).await;
+        let mut inactive = agent("agent-1");
+        inactive.status = AgentStatus::Inactive;
+        service.register(inactive).await.unwrap();
+
+        let error = service
+            .update_status("agent-1", AgentStatus::Draining)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, Error::InvalidArgument(_)));
+        assert_eq!(fake.register_calls.load(Ordering::SeqCst), 1);
+        assert_eq!(service.get("agent-1").await.unwrap().status, AgentStatus::Inactive);
+    }
+
+    #[tokio::test]
+    async fn test_best_for_capability_prefers_confident_then_recent_active_agents() {
+        let fake = Arc::new(FakeRegistry::default());
+        let service = service_for(fake.clone()).await;
+        let now = chrono::Utc::now();
+        let candidate = |id: &str, status, confidence, seconds_ago| {
+            let mut agent = agent(id);
+            agent.status = status;
+            agent.confidence = confidence;
+            agent.last_seen = now - chrono::Duration::seconds(seconds_ago);
+            agent
+        };
+        let mut other_capability = candidate("translator", AgentStatus::Active, 0.99, 0);
+        other_capability.capabilities = vec!["translation".to_string()];
+        let timestamp = |time: chrono::DateTime<chrono::Utc>| prost_types::Timestamp {
+            seconds: time.timestamp(),
+            nanos: time.timestamp_subsec_nanos() as i32,
+        };
+        for agent in [
+            candidate("stale", AgentStatus::Active, 0.9, 60),
+            candidate("recent", AgentStatus::Active, 0.9, 5),
+            candidate("weaker", AgentStatus::Active, 0.7, 0),
+            candidate("inactive", AgentStatus::Inactive, 0.95, 0),
+            candidate("draining", AgentStatus::Draining, 0.97, 0),
+            other_capability,
+        ] {
+            // The registry reports heartbeats; registration times all match
+            let mut registration = agent_to_registration(&agent);
+            registration.registered_at = Some(timestamp(now - chrono::Duration::hours(1)));
+            registration.last_heartbeat = Some(timestamp(agent.last_seen));
+            fake.agents.lock().unwrap().insert(agent.id.clone(), registration);
+        }
+
+        let best = service.best_for_capability("analysis").await.unwrap();
+        assert_eq!(best.id, "recent");
+        assert_eq!(best.confidence, 0.9);
+
+        let error = service.best_for_capability("summarization").await.unwrap_err();
+        assert!(matches!(error, Error::NotFound(message) if message.contains("summarization")));
+    }
+
+    fn event(event_type: EventType, agent: &Agent) -> std::result::Result<WatchEvent, Status> {
+        Ok(WatchEvent {
+            r#type: event_type as i32,
+            agent: Some(agent_to_registration(agent)),
+            timestamp: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_stream_agents_resyncs_after_watch_drops() {
+        let (alpha, beta, gamma) = (agent("alpha"), agent("beta"), agent("gamma"));
+        let mut busy_beta = agent("beta");
+        busy_beta.status = AgentStatus::Draining;
+        let mut busy_alpha = alpha.clone();
+        busy_alpha.status = AgentStatus::Draining;
+        let fake = Arc::new(FakeRegistry::default());
+        fake.watch_streams.lock().unwrap().extend([
+            vec![
+                event(EventType::Added, &alpha),
+                event(EventType::Added, &beta),
+                event(EventType::Modified, &busy_beta),
+                // Redundant repeat, dropped
+                event(EventType::Modified, &busy_beta),
+                Err(Status::unavailable("registry restarting")),
+            ],
+            // Changes after reconnecting
+            vec![event(EventType::Modified, &busy_alpha)],
+        ]);
+        // While the watch was down, beta left and gamma joined
+        fake.agents.lock().unwrap().extend(
+            [&alpha, &gamma].map(|agent| (agent.id.clone(), agent_to_registration(agent))),
+        );
+        let service = service_for(fake.clone()).await;
+
+        let updates: Vec<_> = service
+            .stream_agents()
+            .await
+            .unwrap()
+            .take(6)
+            .map(|event| {
+                let event = event.unwrap();
+                let kind = match &event {
+                    AgentEvent::Added(_) => "added",
+                    AgentEvent::Modified(_) => "modified",
+                    AgentEvent::Deleted(_) => "deleted",
+                };
+                let agent = event.into_agent();
+                (kind, agent.id, agent.status)
+            })
+            .collect()
+            .await;
+
+        assert_eq!(
+            updates,
+            [
+                ("added", "alpha".to_string(), AgentStatus::Active),
+                ("added", "beta".to_string(), AgentStatus::Active),
+                ("modified", "beta".to_string(), AgentStatus::Draining),
+                // Only the differences from the re-sync, not a replay
+                ("deleted", "beta".to_string(), AgentStatus::Inactive),
+                ("added", "gamma".to_string(), AgentStatus::Active),
+                ("modified", "alpha".to_string(), AgentStatus::Draining),
+            ]
+        );
+        assert_eq!(fake.watch_calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_watch_follows_one_agent_until_it_unregisters() {
+        let (alpha, beta) = (agent("alpha"), agent("beta"));
+        let mut draining = agent("alpha");
+        draining.status = AgentStatus::Draining;
+        draining.confidence = 0.6;
+        let fake = Arc::new(FakeRegistry::default());
+        fake.agents
+            .lock()
+            .unwrap()
+            .insert(alpha.id.clone(), agent_to_registration(&alpha));
+        fake.watch_streams.lock().unwrap().push_back(vec![
+            event(EventType::Added, &alpha),
+            event(EventType::Modified, &beta),
+            event(EventType::Modified, &draining),
+            event(EventType::Deleted, &draining),
+        ]);
+        let service = service_for(fake.clone()).await;
+
+        let updates: Vec<_> = service
+            .watch("alpha")
+            .await
+            .unwrap()
+            .map(|agent| {
+                let agent = agent.unwrap();
+                (agent.id, agent.status, agent.confidence)
+            })
+            .collect()
+            .await;
+
+        assert_eq!(
+            updates,
+            [
+                ("alpha".to_string(), AgentStatus::Active, 0.8),
+                ("alpha".to_string(), AgentStatus::Draining, 0.6),
+                ("alpha".to_string(), AgentStatus::Inactive, 0.6),
+            ]
+        );
+
+        let error = service.watch("missing").await.err().unwrap();
+        assert!(matches!(error, Error::NotFound(_)));
+    }
+
+    #[tokio::test]
+    async fn test_watch_reports_unregistration_missed_while_reconnecting() {
+        let alpha = agent("alpha");
+        let fake = Arc::new(FakeRegistry::default());
+        fake.agents
+            .lock()
+            .unwrap()
+            .insert(alpha.id.clone(), agent_to_registration(&alpha));
+        fake.watch_streams.lock().unwrap().push_back(vec![
+            event(EventType::Added, &alpha),
+            Err(Status::unavailable("registry restarting")),
+        ]);
+        let service = service_for(fake.clone()).await;
+
+        let mut updates = service.watch("alpha").await.unwrap();
+        assert_eq!(updates.next().await.unwrap().unwrap().status, AgentStatus::Active);
+        // Unregistered while the watch is down; its DELETED event is lost
+        fake.agents.lock().unwrap().clear();
+        let rest: Vec<_> = updates.map(|agent| agent.unwrap()).collect().await;
+
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].id, "alpha");
+        assert_eq!(rest[0].status, AgentStatus::Inactive);
+        assert_eq!(fake.watch_calls.load(Ordering::SeqCst), 2);
     }
 }
@@ -0,0 +1,225 @@
+//! Result caching wrapper for analyze functions
+//!
+//! [`with_cache`] remembers the [`AgentResult`] for each `(task, data)` pair
+//! so repeated identical requests to an expensive, deterministic analysis
+//! skip the work. Entries expire after a TTL and the least recently used
+//! one is evicted once the cache is full.
+
+use crate::parallax_agent::{AgentResult, AnalyzeFuture};
+use lru::LruCache;
+use serde_json::Value;
+use std::{
+    future::Future,
+    num::NonZeroUsize,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::debug;
+
+/// Flag under the request data's `metadata` object that skips the cache,
+/// e.g. `{"metadata": {"no_cache": true}}`
+pub const CACHE_BYPASS_KEY: &str = "no_cache";
+
+/// Configuration for [`with_cache`]
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// Most results kept at once; 0 disables caching
+    pub capacity: usize,
+    /// How long a result stays valid. A result's own, shorter `cache_ttl`
+    /// takes precedence.
+    pub ttl: Duration,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 256,
+            ttl: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Request a result is cached for: the task and the serialized data.
+/// Lookups compare the whole key, so distinct requests never share a result.
+type RequestKey = (String, Option<String>);
+
+struct Entry {
+    result: AgentResult,
+    expires_at: Instant,
+}
+
+/// LRU map from request to result
+struct ResultCache {
+    entries: LruCache<RequestKey, Entry>,
+}
+
+impl ResultCache {
+    fn get(&mut self, key: &RequestKey) -> Option<AgentResult> {
+        match self.entries.get(key) {
+            Some(entry) if entry.expires_at > Instant::now() => Some(entry.result.clone()),
+            Some(_) => {
+                self.entries.pop(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn insert(&mut self, key: RequestKey, result: AgentResult, ttl: Duration) {
+        // Drop expired entries from the cold end first, so a full cache
+        // evicts those before a live one
+        let now = Instant::now();
+        while self
+            .entries
+            .peek_lru()
+            .is_some_and(|(_, entry)| entry.expires_at <= now)
+        {
+            self.entries.pop_lru();
+        }
+
+        self.entries.put(
+            key,
+            Entry {
+                result,
+                expires_at: now + ttl,
+            },
+        );
+    }
+}
+
+/// Wrap an analyze function so identical requests reuse earlier results
+///
+/// Requests are keyed on the task and the serialized data. A hit
+/// returns the stored result without calling `analyze_fn`; failures are
+/// never cached. Set [`CACHE_BYPASS_KEY`] in the data's `metadata` object to
+/// always call through.
+pub fn with_cache<F, Fut>(
+    analyze_fn: F,
+    config: CacheConfig,
+) -> impl Fn(&str, Option<Value>) -> AnalyzeFuture<'static> + Send + Sync
+where
+    F: Fn(&str, Option<Value>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<AgentResult, Box<dyn std::error::Error>>> + Send + 'static,
+{
+    let cache = NonZeroUsize::new(config.capacity).map(|capacity| {
+        Arc::new(Mutex::new(ResultCache {
+            entries: LruCache::new(capacity),
+        }))
+    });
+
+    move |task: &str, data: Option<Value>| {
+        let cache = cache.clone().filter(|_| !bypasses_cache(data.as_ref()));
+        let key = cache.is_some().then(|| request_key(task, data.as_ref()));
+        let hit = cache
+            .as_ref()
+            .zip(key.as_ref())
+            .and_then(|(cache, key)| cache.lock().unwrap().get(key));
+        if let Some(result) = hit {
+            debug!("Analyze cache hit for task: {}", task);
+            return Box::pin(async move { Ok(result) });
+        }
+
+        let fut = analyze_fn(task, data);
+        let ttl = config.ttl;
+        Box::pin(async move {
+            let result = fut.await?;
+            let ttl = result.cache_ttl.map_or(ttl, |own| own.min(ttl));
+            if let Some((cache, key)) = cache.zip(key).filter(|_| !ttl.is_zero()) {
+                cache.lock().unwrap().insert(key, result.clone(), ttl);
+            }
+            Ok(result)
+        })
+    }
+}
+
+fn request_key(task: &str, data: Option<&Value>) -> RequestKey {
+    (task.to_string(), data.map(Value::to_string))
+}
+
+fn bypasses_cache(data: Option<&Value>) -> bool {
+    data.and_then(|data| data.get("metadata"))
+        .and_then(|metadata| metadata.get(CACHE_BYPASS_KEY))
+        .and_then(Value::as_bool)
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn counting(
+        calls: Arc<AtomicUsize>,
+    ) -> impl Fn(&str, Option<Value>) -> AnalyzeFuture<'static> + Send + Sync + 'static {
+        move |task: &str, _data: Option<Value>| {
+            let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            let task = task.to_string();
+            Box::pin(async move {
+                Ok(AgentResult {
+                    value: json!({ "task": task, "call": call }),
+                    confidence: 0.9,
+                    ..Default::default()
+                })
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_hit_skips_inner_function() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let analyze = with_cache(counting(calls.clone()), CacheConfig::default());
+        let data = json!({ "document": "report.pdf" });
+
+        let first = analyze("summarize", Some(data.clone())).await.unwrap();
+        let second = analyze("summarize", Some(data.clone())).await.unwrap();
+        assert_eq!(second.value, first.value);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Different data, or an explicit bypass, calls through
+        analyze("summarize", Some(json!({ "document": "other.pdf" }))).await.unwrap();
+        let bypass = json!({ "document": "report.pdf", "metadata": { "no_cache": true } });
+        let fresh = analyze("summarize", Some(bypass)).await.unwrap();
+        assert_eq!(fresh.value["call"], 3);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_cache_entries_expire_after_ttl() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let config = CacheConfig {
+            ttl: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let analyze = with_cache(counting(calls.clone()), config);
+
+        analyze("summarize", None).await.unwrap();
+        analyze("summarize", None).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        let recomputed = analyze("summarize", None).await.unwrap();
+        assert_eq!(recomputed.value["call"], 2);
+    }
+
+    #[tokio::test]
+    async fn test_cache_evicts_least_recently_used() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let config = CacheConfig {
+            capacity: 2,
+            ..Default::default()
+        };
+        let analyze = with_cache(counting(calls.clone()), config);
+
+        analyze("a", None).await.unwrap();
+        analyze("b", None).await.unwrap();
+        analyze("a", None).await.unwrap();
+        // "b" is now the least recently used and makes room for "c"
+        analyze("c", None).await.unwrap();
+        analyze("a", None).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        analyze("b", None).await.unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+}
@@ -0,0 +1,264 @@
+//! Circuit breaker around the client's control plane calls
+//!
+//! With [`ClientConfig::circuit_breaker`](crate::ClientConfig::circuit_breaker)
+//! set, the breaker counts consecutive calls that fail as if the control
+//! plane were down or overloaded: transport errors and `UNAVAILABLE`,
+//! `RESOURCE_EXHAUSTED` or `DEADLINE_EXCEEDED` statuses. Any other answer,
+//! errors included, shows the control plane is up and resets the count.
+//!
+//! Once the count reaches the threshold the breaker opens, and calls fail
+//! immediately with `Error::Connection("circuit open")` without reaching the
+//! network. After the open duration it lets a few probe calls through
+//! (half-open): if they all succeed it closes again, and if one fails it
+//! reopens. The breaker sits outside any retrying, such as stream
+//! reconnects, so retries of an open circuit fail fast too.
+
+use crate::{
+    clock::{Clock, Instant},
+    error::{Error, Result},
+    transport::{self, Transport, TransportError, TransportResult},
+};
+use futures::future::BoxFuture;
+use std::{
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+use tonic::{body::BoxBody, codegen::http, Code};
+use tracing::warn;
+
+/// When the client's circuit breaker opens and how it recovers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failed calls that open the circuit
+    pub failure_threshold: u32,
+    /// How long an open circuit fails calls before probing again
+    pub open_duration: Duration,
+    /// Probe calls let through while half-open; all must succeed to close
+    pub half_open_probes: u32,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(30),
+            half_open_probes: 1,
+        }
+    }
+}
+
+/// State of the client's circuit breaker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Calls go through
+    Closed,
+    /// Calls fail fast until the open duration has passed
+    Open,
+    /// Probe calls go through to test whether the control plane recovered
+    HalfOpen,
+}
+
+#[derive(Debug)]
+enum State {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    HalfOpen {
+        since: Instant,
+        admitted: u32,
+        succeeded: u32,
+    },
+}
+
+/// Breaker shared by every service of a client
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    pub(crate) fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config: CircuitBreakerConfig {
+                failure_threshold: config.failure_threshold.max(1),
+                half_open_probes: config.half_open_probes.max(1),
+                ..config
+            },
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    pub(crate) fn state(&self, now: Instant) -> CircuitState {
+        match *self.state.lock().unwrap() {
+            State::Closed { .. } => CircuitState::Closed,
+            State::Open { until } if now < until => CircuitState::Open,
+            State::Open { .. } | State::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Let a call through, or fail it fast while the circuit is open
+    fn admit(&self, now: Instant) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        match &mut *state {
+            State::Closed { .. } => return Ok(()),
+            State::Open { until } if now < *until => {}
+            State::Open { .. } => {
+                *state = State::HalfOpen {
+                    since: now,
+                    admitted: 1,
+                    succeeded: 0,
+                };
+                return Ok(());
+            }
+            State::HalfOpen {
+                since,
+                admitted,
+                succeeded,
+            } => {
+                // Probes that never reported back (e.g. dropped calls) don't
+                // keep the circuit half-open forever
+                if now >= *since + self.config.open_duration {
+                    (*since, *admitted, *succeeded) = (now, 0, 0);
+                }
+                if *admitted < self.config.half_open_probes {
+                    *admitted += 1;
+                    return Ok(());
+                }
+            }
+        }
+        Err(Error::Connection("circuit open".to_string()))
+    }
+
+    fn record(&self, failed: bool, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        let open = State::Open {
+            until: now + self.config.open_duration,
+        };
+        match &mut *state {
+            State::Closed { failures } if failed => {
+                *failures += 1;
+                if *failures >= self.config.failure_threshold {
+                    warn!(
+                        "Opening circuit to the control plane after {} failed calls",
+                        failures
+                    );
+                    *state = open;
+                }
+            }
+            State::Closed { failures } => *failures = 0,
+            State::Open { .. } => {}
+            State::HalfOpen { .. } if failed => {
+                warn!("Control plane probe failed, reopening circuit");
+                *state = open;
+            }
+            State::HalfOpen { succeeded, .. } => {
+                *succeeded += 1;
+                if *succeeded >= self.config.half_open_probes {
+                    *state = State::Closed { failures: 0 };
+                }
+            }
+        }
+    }
+}
+
+/// Admit a call through `breaker`, if the client has one, returning the
+/// channel to send it on
+pub(crate) fn guard(
+    channel: Transport,
+    breaker: Option<&Arc<CircuitBreaker>>,
+    clock: &Arc<dyn Clock>,
+) -> Result<BreakerChannel> {
+    if let Some(breaker) = breaker {
+        breaker.admit(clock.now())?;
+    }
+    Ok(BreakerChannel {
+        channel,
+        breaker: breaker.map(|breaker| (Arc::clone(breaker), Arc::clone(clock))),
+    })
+}
+
+/// Channel reporting the outcome of each call to the circuit breaker
+#[derive(Clone)]
+pub(crate) struct BreakerChannel {
+    channel: Transport,
+    breaker: Option<(Arc<CircuitBreaker>, Arc<dyn Clock>)>,
+}
+
+impl tower::Service<http::Request<BoxBody>> for BreakerChannel {
+    type Response = http::Response<transport::TransportBody>;
+    type Error = TransportError;
+    type Future = BoxFuture<'static, TransportResult>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<std::result::Result<(), Self::Error>> {
+        self.channel.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<BoxBody>) -> Self::Future {
+        let response = self.channel.call(request);
+        let breaker = self.breaker.clone();
+        transport::boxed(async move {
+            let result = response.await;
+            if let Some((breaker, clock)) = breaker {
+                breaker.record(is_failure(&result), clock.now());
+            }
+            result
+        })
+    }
+}
+
+/// Whether a call failed as if the control plane were down or overloaded.
+/// Error statuses arrive in the headers of a trailers-only response.
+fn is_failure<B, E>(result: &std::result::Result<http::Response<B>, E>) -> bool {
+    let Ok(response) = result else {
+        return true;
+    };
+    if response.status().is_server_error() {
+        return true;
+    }
+    let code = response
+        .headers()
+        .get("grpc-status")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<i32>().ok())
+        .map(Code::from_i32);
+    matches!(
+        code,
+        Some(Code::Unavailable | Code::ResourceExhausted | Code::DeadlineExceeded)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_half_open_lets_only_probes_through() {
+        let breaker = CircuitBreaker::new(CircuitBreakerConfig {
+            failure_threshold: 1,
+            open_duration: Duration::from_secs(10),
+            half_open_probes: 2,
+        });
+        let start = Instant::now();
+        breaker.record(true, start);
+        assert!(breaker.admit(start).is_err());
+
+        let later = start + Duration::from_secs(10);
+        assert!(breaker.admit(later).is_ok());
+        assert!(breaker.admit(later).is_ok());
+        assert!(breaker.admit(later).is_err());
+
+        // Probes that never report back are replaced after another period
+        let stuck = later + Duration::from_secs(10);
+        assert!(breaker.admit(stuck).is_ok());
+        breaker.record(false, stuck);
+        assert_eq!(breaker.state(stuck), CircuitState::HalfOpen);
+        assert!(breaker.admit(stuck).is_ok());
+        breaker.record(false, stuck);
+        assert_eq!(breaker.state(stuck), CircuitState::Closed);
+    }
+}
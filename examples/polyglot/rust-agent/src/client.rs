@@ -1,26 +1,160 @@
 use crate::{
-    agent_service::AgentService, error::Result, executions::ExecutionService, patterns::PatternService,
+    agent_service::AgentService,
+    circuit_breaker::{BreakerChannel, CircuitBreaker, CircuitBreakerConfig, CircuitState},
+    clock::{Clock, SystemClock},
+    connection::{ConnectionMonitor, ConnectionState, DisconnectHandler},
+    conversions::NonFinitePolicy,
+    error::{Error, Result},
+    executions::ExecutionService,
+    patterns::PatternService,
+    transport::Transport,
+    types::{AgentSelector, AgentStatus, ExecuteOptions, PatternExecution, SelectionStrategy},
 };
-use std::time::Duration;
-use tonic::transport::{Certificate, Channel, ClientTlsConfig, Endpoint, Identity};
-use tracing::info;
+#[cfg(feature = "native")]
+use crate::{
+    clock::calculate_reconnect_delay, connection::MonitoredConnector, failover::FailoverConnector,
+};
+use serde_json::json;
+use std::{
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+use tonic::{
+    codec::CompressionEncoding,
+    metadata::{Ascii, MetadataKey, MetadataValue},
+    service::{interceptor::InterceptedService, Interceptor},
+    codegen::http::Uri,
+    Request, Status,
+};
+#[cfg(feature = "native")]
+use tonic::transport::{Certificate, ClientTlsConfig, Endpoint, Identity};
+use tracing::{info, warn};
 
 /// Parallax client for interacting with the control plane
+///
+/// Clones of a client, and the services they hand out, share one channel:
+/// calls are multiplexed as HTTP/2 streams over the same connection rather
+/// than each opening their own.
 #[derive(Clone)]
 pub struct Client {
-    channel: Channel,
-    endpoint: String,
+    channel: Transport,
+    endpoints: Vec<String>,
+    /// Index in `endpoints` of the replica last connected to
+    active_endpoint: Arc<AtomicUsize>,
+    compression: Option<CompressionEncoding>,
+    interceptors: InterceptorChain,
+    clock: Arc<dyn Clock>,
+    connection: Arc<ConnectionMonitor>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    non_finite_policy: NonFinitePolicy,
+    status_updates: Arc<tokio::sync::Mutex<()>>,
 }
 
+/// Interceptors added with [`Client::with_interceptor`], run in the order
+/// they were added
+#[derive(Clone, Default)]
+pub(crate) struct InterceptorChain(Vec<Arc<Mutex<dyn Interceptor + Send>>>);
+
+impl Interceptor for InterceptorChain {
+    fn call(&mut self, request: Request<()>) -> std::result::Result<Request<()>, Status> {
+        self.0.iter().try_fold(request, |request, interceptor| {
+            interceptor
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .call(request)
+        })
+    }
+}
+
+/// Channel the service clients send their requests through
+pub(crate) type InterceptedChannel = InterceptedService<BreakerChannel, InterceptorChain>;
+
+/// Pattern run by [`Client::coordinate`]
+pub const CONSENSUS_PATTERN: &str = "ConsensusBuilder";
+
+// Environment variables read by `ClientConfig::from_env`
+const ENDPOINT_ENV: &str = "PARALLAX_ENDPOINT";
+const TIMEOUT_SECS_ENV: &str = "PARALLAX_TIMEOUT_SECS";
+const CA_CERT_ENV: &str = "PARALLAX_CA_CERT";
+const CLIENT_CERT_ENV: &str = "PARALLAX_CLIENT_CERT";
+const CLIENT_KEY_ENV: &str = "PARALLAX_CLIENT_KEY";
+const AUTH_TOKEN_ENV: &str = "PARALLAX_AUTH_TOKEN";
+
 /// Client configuration
-#[derive(Debug, Clone)]
+///
+/// Start from [`ClientConfig::default`] or [`ClientConfig::from_env`] and set
+/// fields, or configure through [`Client::builder`].
+#[derive(Clone)]
+#[non_exhaustive]
 pub struct ClientConfig {
+    /// Control plane address. A bare `host:port` gets `https://` when
+    /// `tls_config` is set and `http://` otherwise.
     pub endpoint: String,
+    /// Control plane replicas to fail over between, in order of preference.
+    /// Takes precedence over `endpoint` when non-empty. With TLS, set
+    /// `domain_name` to a name every replica's certificate covers.
+    pub endpoints: Vec<String>,
     pub timeout: Duration,
     pub connect_timeout: Duration,
     pub keep_alive_interval: Duration,
     pub keep_alive_timeout: Duration,
     pub tls_config: Option<TlsConfig>,
+    /// Compress requests with this encoding and accept compressed responses.
+    /// The control plane must support the encoding; `None` sends plain payloads.
+    pub compression: Option<CompressionEncoding>,
+    /// Called whenever the connection to the control plane is lost, e.g.
+    /// because keepalive pings went unanswered
+    pub on_disconnect: Option<DisconnectHandler>,
+    /// Sent as `authorization: Bearer <token>` with every request. Left out
+    /// of the `Debug` output.
+    pub auth_token: Option<String>,
+    /// Fail calls fast once the control plane looks down or overloaded; see
+    /// [`circuit_breaker`](crate::circuit_breaker). `None` disables it.
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+    /// Most calls waiting for response headers on the shared connection at
+    /// once; further calls queue until one gets its headers. A call's slot
+    /// is freed when its headers arrive, so server-streaming calls whose
+    /// stream is still open don't hold one, and this does not cap the
+    /// HTTP/2 streams open on the connection. `None` leaves calls unqueued.
+    /// Only the native transport applies it.
+    pub max_concurrent_requests: Option<NonZeroUsize>,
+    /// Times to try connecting before giving up, at least once
+    pub connect_attempts: u32,
+    /// Wait before the first connection retry, doubling after each further
+    /// failure
+    pub connect_backoff: Duration,
+    /// Metadata sent with every request, e.g. `("x-team", "search")`
+    pub headers: Vec<(String, String)>,
+    /// How NaN and infinite numbers in execution results, metrics and event
+    /// data are decoded by the pattern and execution services
+    pub non_finite_policy: NonFinitePolicy,
+}
+
+impl std::fmt::Debug for ClientConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientConfig")
+            .field("endpoint", &self.endpoint)
+            .field("endpoints", &self.endpoints)
+            .field("timeout", &self.timeout)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("keep_alive_interval", &self.keep_alive_interval)
+            .field("keep_alive_timeout", &self.keep_alive_timeout)
+            .field("tls_config", &self.tls_config)
+            .field("compression", &self.compression)
+            .field("on_disconnect", &self.on_disconnect)
+            .field("auth_token", &self.auth_token.as_ref().map(|_| "<redacted>"))
+            .field("circuit_breaker", &self.circuit_breaker)
+            .field("max_concurrent_requests", &self.max_concurrent_requests)
+            .field("connect_attempts", &self.connect_attempts)
+            .field("connect_backoff", &self.connect_backoff)
+            .field("headers", &self.headers)
+            .field("non_finite_policy", &self.non_finite_policy)
+            .finish()
+    }
 }
 
 /// TLS configuration
@@ -36,50 +170,289 @@ impl Default for ClientConfig {
     fn default() -> Self {
         Self {
             endpoint: "http://localhost:8080".to_string(),
+            endpoints: Vec::new(),
             timeout: Duration::from_secs(30),
             connect_timeout: Duration::from_secs(10),
             keep_alive_interval: Duration::from_secs(30),
             keep_alive_timeout: Duration::from_secs(10),
             tls_config: None,
+            compression: None,
+            on_disconnect: None,
+            auth_token: None,
+            circuit_breaker: None,
+            max_concurrent_requests: None,
+            connect_attempts: 1,
+            connect_backoff: Duration::from_millis(500),
+            headers: Vec::new(),
+            non_finite_policy: NonFinitePolicy::default(),
+        }
+    }
+}
+
+impl ClientConfig {
+    /// Build a configuration from environment variables, defaulting
+    /// whatever is unset or empty
+    ///
+    /// - `PARALLAX_ENDPOINT`: control plane address
+    /// - `PARALLAX_TIMEOUT_SECS`: request timeout in seconds, fractions allowed
+    /// - `PARALLAX_CA_CERT`: path to a PEM CA certificate, enabling TLS
+    /// - `PARALLAX_CLIENT_CERT` and `PARALLAX_CLIENT_KEY`: paths to a PEM
+    ///   client certificate and key for mutual TLS, set together
+    /// - `PARALLAX_AUTH_TOKEN`: bearer token sent with every request
+    ///
+    /// Returns `Error::InvalidArgument` naming the variable if a value is
+    /// malformed or a file can't be read.
+    pub fn from_env() -> Result<Self> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let var = |name: &str| lookup(name).filter(|value| !value.trim().is_empty());
+        let read = |name: &str| -> Result<Option<Vec<u8>>> {
+            var(name)
+                .map(|path| {
+                    std::fs::read(&path).map_err(|error| {
+                        Error::InvalidArgument(format!("{} ({}): {}", name, path, error))
+                    })
+                })
+                .transpose()
+        };
+        let mut config = Self::default();
+
+        if let Some(endpoint) = var(ENDPOINT_ENV) {
+            endpoint_with_scheme(&endpoint, false).map_err(|error| {
+                Error::InvalidArgument(format!("{}: {}", ENDPOINT_ENV, error))
+            })?;
+            config.endpoint = endpoint;
+        }
+        if let Some(timeout) = var(TIMEOUT_SECS_ENV) {
+            config.timeout = timeout
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                .filter(|timeout| !timeout.is_zero())
+                .ok_or_else(|| {
+                    Error::InvalidArgument(format!(
+                        "{} must be a positive number of seconds, got {:?}",
+                        TIMEOUT_SECS_ENV, timeout
+                    ))
+                })?;
+        }
+
+        let ca_cert = read(CA_CERT_ENV)?;
+        let identity = match (read(CLIENT_CERT_ENV)?, read(CLIENT_KEY_ENV)?) {
+            (Some(cert), Some(key)) => Some((cert, key)),
+            (None, None) => None,
+            _ => {
+                return Err(Error::InvalidArgument(format!(
+                    "{} and {} must be set together",
+                    CLIENT_CERT_ENV, CLIENT_KEY_ENV
+                )))
+            }
+        };
+        if ca_cert.is_some() || identity.is_some() {
+            let (client_cert, client_key) = identity.unzip();
+            config.tls_config = Some(TlsConfig {
+                ca_cert: ca_cert.unwrap_or_default(),
+                client_cert,
+                client_key,
+                domain_name: None,
+            });
         }
+
+        config.auth_token = var(AUTH_TOKEN_ENV);
+        Ok(config)
+    }
+
+    /// Add a control plane replica to fail over to
+    pub fn add_endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.endpoints.push(endpoint.into());
+        self
+    }
+
+    /// Run `callback` whenever the connection to the control plane is lost
+    pub fn on_disconnect(mut self, callback: impl Fn() + Send + Sync + 'static) -> Self {
+        self.on_disconnect = Some(DisconnectHandler::new(callback));
+        self
+    }
+
+    /// The endpoints the client will connect to, in order of preference
+    pub fn resolved_endpoints(&self) -> Vec<String> {
+        if self.endpoints.is_empty() {
+            vec![self.endpoint.clone()]
+        } else {
+            self.endpoints.clone()
+        }
+    }
+}
+
+/// `endpoint` with a scheme, adding one to a bare `host:port` to suit
+/// whether TLS is configured
+fn endpoint_with_scheme(endpoint: &str, tls: bool) -> Result<String> {
+    let endpoint = endpoint.trim();
+    let with_scheme = if endpoint.contains("://") {
+        endpoint.to_string()
+    } else {
+        format!("{}://{}", if tls { "https" } else { "http" }, endpoint)
+    };
+
+    let uri = with_scheme
+        .parse::<Uri>()
+        .ok()
+        .filter(|uri| matches!(uri.scheme_str(), Some("http" | "https")))
+        .filter(|uri| uri.host().is_some_and(|host| !host.is_empty()))
+        .ok_or_else(|| {
+            Error::InvalidArgument(format!(
+                "endpoint must include scheme http:// or https://, got {:?}",
+                endpoint
+            ))
+        })?;
+    if tls && uri.scheme_str() == Some("http") {
+        warn!(
+            "TLS is configured but endpoint {} uses http://; use https:// to connect over TLS",
+            with_scheme
+        );
+    }
+    Ok(with_scheme)
+}
+
+/// Builds a [`Client`], starting from [`ClientConfig::default`]
+///
+/// ```no_run
+/// # async fn example() -> parallaxai::Result<()> {
+/// let client = parallaxai::Client::builder()
+///     .endpoint("https://parallax.example.com")
+///     .timeout(std::time::Duration::from_secs(10))
+///     .header("x-team", "search")
+///     .connect()
+///     .await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ClientBuilder {
+    config: ClientConfig,
+}
+
+impl ClientBuilder {
+    /// Control plane address; see [`ClientConfig::endpoint`]
+    pub fn endpoint(mut self, endpoint: impl Into<String>) -> Self {
+        self.config.endpoint = endpoint.into();
+        self
+    }
+
+    /// Deadline for each request
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = timeout;
+        self
+    }
+
+    /// Connect over TLS
+    pub fn tls(mut self, tls: TlsConfig) -> Self {
+        self.config.tls_config = Some(tls);
+        self
+    }
+
+    /// Send `token` as `authorization: Bearer <token>` with every request
+    pub fn auth(mut self, token: impl Into<String>) -> Self {
+        self.config.auth_token = Some(token.into());
+        self
+    }
+
+    /// Try connecting up to `max_attempts` times, waiting `backoff` before
+    /// the first retry and doubling the wait after each further failure
+    pub fn retry(mut self, max_attempts: u32, backoff: Duration) -> Self {
+        self.config.connect_attempts = max_attempts;
+        self.config.connect_backoff = backoff;
+        self
+    }
+
+    /// Send `name: value` metadata with every request
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.config.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Connect to the control plane; see [`Client::new`]
+    pub async fn connect(self) -> Result<Client> {
+        Client::new(self.config).await
     }
 }
 
 impl Client {
+    /// Configure a client step by step
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
     /// Create a new client with the given configuration
+    ///
+    /// When several endpoints are configured, connections go to the first
+    /// reachable replica and move to the next one if it becomes unreachable.
+    /// Connecting is retried `connect_attempts` times before the error is
+    /// returned.
     pub async fn new(config: ClientConfig) -> Result<Self> {
-        let mut endpoint = Endpoint::from_shared(config.endpoint.clone())?
-            .timeout(config.timeout)
-            .connect_timeout(config.connect_timeout)
-            .http2_keep_alive_interval(config.keep_alive_interval)
-            .keep_alive_timeout(config.keep_alive_timeout);
-
-        // Configure TLS if provided
-        if let Some(tls) = config.tls_config {
-            let mut tls_config = ClientTlsConfig::new();
-            
-            if let Some(domain) = tls.domain_name {
-                tls_config = tls_config.domain_name(domain);
-            }
+        let endpoints = config
+            .resolved_endpoints()
+            .iter()
+            .map(|endpoint| endpoint_with_scheme(endpoint, config.tls_config.is_some()))
+            .collect::<Result<Vec<_>>>()?;
 
-            if !tls.ca_cert.is_empty() {
-                tls_config = tls_config.ca_certificate(Certificate::from_pem(tls.ca_cert));
-            }
-
-            if let (Some(cert), Some(key)) = (tls.client_cert, tls.client_key) {
-                tls_config = tls_config.identity(Identity::from_pem(cert, key));
-            }
-            
-            endpoint = endpoint.tls_config(tls_config)?;
+        let mut interceptors = InterceptorChain::default();
+        if let Some(token) = &config.auth_token {
+            let authorization: MetadataValue<Ascii> = format!("Bearer {}", token)
+                .parse()
+                .map_err(|_| Error::InvalidArgument("auth token is not valid ASCII".to_string()))?;
+            interceptors.0.push(Arc::new(Mutex::new(move |mut request: Request<()>| {
+                request.metadata_mut().insert("authorization", authorization.clone());
+                Ok(request)
+            })));
+        }
+        if !config.headers.is_empty() {
+            let headers = config
+                .headers
+                .iter()
+                .map(|(name, value)| {
+                    let key = MetadataKey::<Ascii>::from_bytes(name.as_bytes()).map_err(|_| {
+                        Error::InvalidArgument(format!("invalid header name {:?}", name))
+                    })?;
+                    let value: MetadataValue<Ascii> = value.parse().map_err(|_| {
+                        Error::InvalidArgument(format!("header {} is not valid ASCII", name))
+                    })?;
+                    Ok((key, value))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            interceptors.0.push(Arc::new(Mutex::new(move |mut request: Request<()>| {
+                for (key, value) in &headers {
+                    request.metadata_mut().insert(key.clone(), value.clone());
+                }
+                Ok(request)
+            })));
         }
 
-        let channel = endpoint.connect().await?;
+        let connection = ConnectionMonitor::new(config.on_disconnect.clone());
+        let active_endpoint = Arc::new(AtomicUsize::new(0));
+        let channel = connect_transport(&config, &endpoints, &connection, &active_endpoint).await?;
         
-        info!("Connected to Parallax control plane at {}", config.endpoint);
+        info!(
+            "Connected to Parallax control plane at {}",
+            endpoints[active_endpoint.load(Ordering::Relaxed)]
+        );
 
         Ok(Self {
             channel,
-            endpoint: config.endpoint,
+            endpoints,
+            active_endpoint,
+            compression: config.compression,
+            interceptors,
+            clock: Arc::new(SystemClock),
+            connection,
+            circuit_breaker: config
+                .circuit_breaker
+                .map(|config| Arc::new(CircuitBreaker::new(config))),
+            non_finite_policy: config.non_finite_policy,
+            status_updates: Arc::default(),
         })
     }
 
@@ -92,30 +465,828 @@ impl Client {
         Self::new(config).await
     }
 
+    /// Create a client backed by an in-memory control plane
+    ///
+    /// Returns the client along with the [`MockControlPlane`](crate::testing::MockControlPlane)
+    /// used to seed data, enqueue results and inspect calls. Must be called
+    /// from within a Tokio runtime.
+    #[cfg(any(test, feature = "testing"))]
+    pub fn mock() -> (Self, crate::testing::MockControlPlane) {
+        let control_plane = crate::testing::MockControlPlane::new();
+        let connection = ConnectionMonitor::new(None);
+        connection.assume_connected();
+        let client = Self {
+            channel: control_plane.channel(),
+            endpoints: vec![crate::testing::MOCK_ENDPOINT.to_string()],
+            active_endpoint: Arc::default(),
+            compression: None,
+            interceptors: InterceptorChain::default(),
+            clock: Arc::new(SystemClock),
+            connection,
+            circuit_breaker: None,
+            non_finite_policy: NonFinitePolicy::default(),
+            status_updates: Arc::default(),
+        };
+        (client, control_plane)
+    }
+
+    /// Run every request made through this client's services through
+    /// `interceptor`, e.g. to sign requests or enforce a rate limit
+    ///
+    /// Interceptors run in the order they were added, each seeing the
+    /// request as the previous one left it; an error from any of them fails
+    /// the call with that status. Clones made afterwards share the
+    /// interceptor.
+    pub fn with_interceptor(mut self, interceptor: impl Interceptor + Send + 'static) -> Self {
+        self.interceptors.0.push(Arc::new(Mutex::new(interceptor)));
+        self
+    }
+
+    /// Drive `wait_for_completion` timeouts and stream reconnect backoff
+    /// from `clock` instead of real time, e.g. a [`MockClock`](crate::MockClock)
+    /// in tests
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
     /// Get the pattern service
     pub fn patterns(&self) -> PatternService {
-        PatternService::new(self.channel.clone())
+        PatternService::new(self.channel.clone(), self.endpoint().to_string())
+            .with_compression(self.compression)
+            .with_interceptors(self.interceptors.clone())
+            .with_closing(self.connection.closing().clone())
+            .with_clock(self.clock.clone())
+            .with_circuit_breaker(self.circuit_breaker.clone())
+            .with_non_finite_policy(self.non_finite_policy)
     }
 
     /// Get the agent service
     pub fn agents(&self) -> AgentService {
-        AgentService::new(self.channel.clone())
+        AgentService::new(self.channel.clone(), self.endpoint().to_string())
+            .with_compression(self.compression)
+            .with_interceptors(self.interceptors.clone())
+            .with_closing(self.connection.closing().clone())
+            .with_clock(self.clock.clone())
+            .with_circuit_breaker(self.circuit_breaker.clone())
+            .with_status_lock(self.status_updates.clone())
     }
 
     /// Get the execution service
     pub fn executions(&self) -> ExecutionService {
-        ExecutionService::new(self.channel.clone())
+        ExecutionService::new(self.channel.clone(), self.endpoint().to_string())
+            .with_compression(self.compression)
+            .with_interceptors(self.interceptors.clone())
+            .with_closing(self.connection.closing().clone())
+            .with_clock(self.clock.clone())
+            .with_circuit_breaker(self.circuit_breaker.clone())
+            .with_non_finite_policy(self.non_finite_policy)
+    }
+
+    /// State of the circuit breaker; always closed if none is configured
+    pub fn circuit_state(&self) -> CircuitState {
+        self.circuit_breaker
+            .as_ref()
+            .map_or(CircuitState::Closed, |breaker| breaker.state(self.clock.now()))
     }
 
     /// Get the endpoint this client is connected to
+    ///
+    /// With failover configured this is the replica the connection last
+    /// reached, which moves on from the preferred one after a failover.
     pub fn endpoint(&self) -> &str {
-        &self.endpoint
+        &self.endpoints[self.active_endpoint.load(Ordering::Relaxed)]
+    }
+
+    /// Get every control plane replica this client may connect to
+    pub fn endpoints(&self) -> &[String] {
+        &self.endpoints
+    }
+
+    /// Whether the client currently holds a live connection
+    ///
+    /// Becomes [`ConnectionState::Disconnected`] as soon as the connection is
+    /// lost, and [`ConnectionState::Connected`] again once a later call
+    /// reconnects.
+    pub fn connection_state(&self) -> ConnectionState {
+        self.connection.state()
+    }
+
+    /// Close the connection to the control plane
+    ///
+    /// Open connections are torn down and no new ones are made. Returns once
+    /// the transports are closed. Calls through clones of this client, or
+    /// services obtained from it, then fail at once with
+    /// `Error::Connection("client closed")`; calls already in flight fail
+    /// with a transport error. The disconnect handler is not run.
+    pub async fn close(self) {
+        self.connection.close().await;
+    }
+
+    /// Reach consensus on `task` among agents offering `capability`
+    ///
+    /// Runs the `ConsensusBuilder` pattern with a best-fit selection of at
+    /// least `min_agents` agents and waits for it to finish. Fails with
+    /// [`Error::InsufficientAgents`] before executing anything if fewer
+    /// active agents with the capability are registered.
+    pub async fn coordinate(
+        &self,
+        task: &str,
+        capability: &str,
+        min_agents: u32,
+    ) -> Result<PatternExecution> {
+        let available = self
+            .agents()
+            .list()
+            .await?
+            .iter()
+            .filter(|agent| agent.status == AgentStatus::Active)
+            .filter(|agent| agent.capabilities.iter().any(|offered| offered == capability))
+            .count();
+        if available < min_agents as usize {
+            return Err(Error::InsufficientAgents {
+                capability: capability.to_string(),
+                required: min_agents,
+                available,
+            });
+        }
+
+        let options = ExecuteOptions::builder()
+            .agent_selector(AgentSelector {
+                capabilities: Some(vec![capability.to_string()]),
+                min_count: Some(min_agents),
+                strategy: Some(SelectionStrategy::BestFit),
+                ..Default::default()
+            })
+            .build()?;
+        let execution = self
+            .patterns()
+            .execute(CONSENSUS_PATTERN, json!({ "task": task }), Some(options))
+            .await?;
+        if execution.status.is_terminal() {
+            return Ok(execution);
+        }
+        self.executions().wait_for_completion(&execution.id, None).await
     }
 
     /// Check if the control plane is healthy
     pub async fn health_check(&self) -> Result<bool> {
-        let patterns = PatternService::new(self.channel.clone());
-        let result = patterns.list().await;
+        let result = self.patterns().list().await;
         Ok(result.is_ok())
     }
 }
+
+/// Connect tonic's HTTP/2 channel to the first reachable endpoint, retrying
+/// `connect_attempts` times
+#[cfg(feature = "native")]
+async fn connect_transport(
+    config: &ClientConfig,
+    endpoints: &[String],
+    connection: &Arc<ConnectionMonitor>,
+    active_endpoint: &Arc<AtomicUsize>,
+) -> Result<Transport> {
+    let mut endpoint = Endpoint::from_shared(endpoints[0].clone())?
+        .timeout(config.timeout)
+        .http2_keep_alive_interval(config.keep_alive_interval)
+        .keep_alive_timeout(config.keep_alive_timeout);
+    if let Some(limit) = config.max_concurrent_requests {
+        endpoint = endpoint.concurrency_limit(limit.get());
+    }
+
+    // Configure TLS if provided
+    if let Some(tls) = config.tls_config.clone() {
+        let mut tls_config = ClientTlsConfig::new();
+        
+        if let Some(domain) = tls.domain_name {
+            tls_config = tls_config.domain_name(domain);
+        }
+
+        if !tls.ca_cert.is_empty() {
+            tls_config = tls_config.ca_certificate(Certificate::from_pem(tls.ca_cert));
+        }
+
+        if let (Some(cert), Some(key)) = (tls.client_cert, tls.client_key) {
+            tls_config = tls_config.identity(Identity::from_pem(cert, key));
+        }
+        
+        endpoint = endpoint.tls_config(tls_config)?;
+    }
+
+    let max_attempts = config.connect_attempts.max(1);
+    let backoff_ms = config.connect_backoff.as_millis().min(u64::MAX as u128) as u64;
+    let mut attempt = 1;
+    loop {
+        let connector = MonitoredConnector::new(
+            FailoverConnector::new(endpoints, config.connect_timeout, active_endpoint.clone())?,
+            connection,
+        );
+        match endpoint.clone().connect_with_connector(connector).await {
+            Ok(channel) => return Ok(channel),
+            Err(error) if attempt >= max_attempts => return Err(error.into()),
+            Err(error) => warn!(
+                "Connect attempt {}/{} to {} failed, retrying: {}",
+                attempt, max_attempts, endpoints[0], error
+            ),
+        }
+        let delay = calculate_reconnect_delay(attempt - 1, backoff_ms, u64::MAX);
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+        attempt += 1;
+    }
+}
+
+/// Send calls to the first endpoint as grpc-web `fetch` requests. There is
+/// nothing to dial up front, and the browser owns TLS, keepalive and
+/// timeouts, so those settings do not apply.
+#[cfg(not(feature = "native"))]
+async fn connect_transport(
+    _config: &ClientConfig,
+    endpoints: &[String],
+    connection: &Arc<ConnectionMonitor>,
+    _active_endpoint: &Arc<AtomicUsize>,
+) -> Result<Transport> {
+    connection.assume_connected();
+    Ok(Transport::new(endpoints[0].clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_support::{
+            serve_at, serve_counting_connections, serve_recording_metadata, serve_until,
+            unused_endpoint,
+        },
+        testing::MockControlPlane,
+        types::{Agent, ExecutionStatus, Pattern},
+    };
+    use serde_json::Value;
+
+    fn pattern(name: &str) -> Pattern {
+        Pattern {
+            name: name.to_string(),
+            description: String::new(),
+            enabled: true,
+            required_capabilities: vec![],
+            config: Default::default(),
+            input_schema: None,
+        }
+    }
+
+    /// Configuration read from `vars` alone, as `from_env` reads the
+    /// process environment
+    fn from_vars(vars: &[(&str, &str)]) -> Result<ClientConfig> {
+        ClientConfig::from_lookup(|name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    fn temp_file(contents: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("parallax-{}.pem", uuid::Uuid::new_v4()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_from_env_defaults_when_unset() {
+        let config = from_vars(&[(TIMEOUT_SECS_ENV, " ")]).unwrap();
+        let defaults = ClientConfig::default();
+
+        assert_eq!(config.endpoint, defaults.endpoint);
+        assert_eq!(config.timeout, defaults.timeout);
+        assert!(config.tls_config.is_none());
+        assert!(config.auth_token.is_none());
+    }
+
+    #[test]
+    fn test_from_env_reads_overrides() {
+        let ca = temp_file("ca");
+        let cert = temp_file("cert");
+        let key = temp_file("key");
+        let config = from_vars(&[
+            (ENDPOINT_ENV, "https://control-plane:8443"),
+            (TIMEOUT_SECS_ENV, "2.5"),
+            (CA_CERT_ENV, ca.to_str().unwrap()),
+            (CLIENT_CERT_ENV, cert.to_str().unwrap()),
+            (CLIENT_KEY_ENV, key.to_str().unwrap()),
+            (AUTH_TOKEN_ENV, "test-token"),
+        ])
+        .unwrap();
+        for path in [ca, cert, key] {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        assert_eq!(config.endpoint, "https://control-plane:8443");
+        assert_eq!(config.timeout, Duration::from_millis(2500));
+        let tls = config.tls_config.as_ref().unwrap();
+        assert_eq!(tls.ca_cert, b"ca");
+        assert_eq!(tls.client_cert.as_deref(), Some(&b"cert"[..]));
+        assert_eq!(tls.client_key.as_deref(), Some(&b"key"[..]));
+        assert_eq!(config.auth_token.as_deref(), Some("test-token"));
+        assert!(!format!("{:?}", config).contains("test-token"));
+    }
+
+    #[test]
+    fn test_from_env_rejects_malformed_values() {
+        let cert = temp_file("cert");
+        let cases = [
+            (TIMEOUT_SECS_ENV, "soon"),
+            (TIMEOUT_SECS_ENV, "0"),
+            (TIMEOUT_SECS_ENV, "-1"),
+            (ENDPOINT_ENV, "not a uri"),
+            (CA_CERT_ENV, "/nonexistent/parallax/ca.pem"),
+            (CLIENT_CERT_ENV, cert.to_str().unwrap()),
+        ];
+        for (name, value) in cases {
+            let error = from_vars(&[(name, value)]).unwrap_err();
+            assert!(matches!(error, Error::InvalidArgument(_)), "{}={}", name, value);
+            assert!(error.to_string().contains(name), "{}", error);
+        }
+        std::fs::remove_file(cert).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_clones_multiplex_calls_over_one_connection() {
+        let control_plane = MockControlPlane::new();
+        let (endpoint, connections) = serve_counting_connections(control_plane.router()).await;
+        let client = Client::new(ClientConfig {
+            endpoint,
+            max_concurrent_requests: NonZeroUsize::new(8),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let calls = (0..64).map(|call| {
+            let client = client.clone();
+            async move {
+                if call % 2 == 0 {
+                    client.patterns().list().await.map(|_| ())
+                } else {
+                    client.agents().list().await.map(|_| ())
+                }
+            }
+        });
+        let results = futures::future::join_all(calls).await;
+
+        assert!(results.iter().all(|result| result.is_ok()));
+        assert_eq!(connections.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_bare_endpoint_gets_a_scheme() {
+        assert_eq!(endpoint_with_scheme("localhost:8080", false).unwrap(), "http://localhost:8080");
+        assert_eq!(endpoint_with_scheme(" localhost:8443", true).unwrap(), "https://localhost:8443");
+        assert_eq!(endpoint_with_scheme("https://cp:8443", false).unwrap(), "https://cp:8443");
+        for endpoint in ["", "not a uri", "ftp://cp:21", "http://"] {
+            let error = endpoint_with_scheme(endpoint, false).unwrap_err();
+            assert!(
+                matches!(&error, Error::InvalidArgument(message) if message.starts_with("endpoint must include scheme")),
+                "{:?}: {}",
+                endpoint,
+                error
+            );
+        }
+
+        let control_plane = MockControlPlane::new();
+        let endpoint = serve_at(control_plane.router()).await;
+        let client = Client::connect(endpoint.trim_start_matches("http://")).await.unwrap();
+        assert_eq!(client.endpoint(), endpoint);
+        assert!(client.patterns().list().await.is_ok());
+    }
+
+    #[test]
+    fn test_warns_when_tls_is_configured_for_an_http_endpoint() {
+        #[derive(Clone, Default)]
+        struct Captured(Arc<Mutex<Vec<u8>>>);
+
+        impl std::io::Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let captured = Captured::default();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer({
+                let captured = captured.clone();
+                move || captured.clone()
+            })
+            .finish();
+        tracing::subscriber::with_default(subscriber, || {
+            endpoint_with_scheme("https://cp:8443", true).unwrap();
+            endpoint_with_scheme("cp:8443", true).unwrap();
+            assert!(captured.0.lock().unwrap().is_empty());
+
+            endpoint_with_scheme("http://cp:8443", true).unwrap();
+        });
+
+        let logs = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(logs.contains("WARN"), "{}", logs);
+        assert!(logs.contains("http://cp:8443"), "{}", logs);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_opens_fails_fast_and_recovers() {
+        let control_plane = MockControlPlane::new();
+        let config = ClientConfig {
+            endpoint: serve_at(control_plane.router()).await,
+            circuit_breaker: Some(CircuitBreakerConfig {
+                failure_threshold: 3,
+                open_duration: Duration::from_secs(10),
+                half_open_probes: 1,
+            }),
+            ..Default::default()
+        };
+        let clock = crate::clock::MockClock::new();
+        let client = Client::new(config).await.unwrap().with_clock(clock.clone());
+        let fail = |times: usize| {
+            for _ in 0..times {
+                control_plane.fail_next("ListPatterns", Status::unavailable("overloaded"));
+            }
+        };
+
+        // Errors that don't suggest an outage reset the count
+        fail(2);
+        control_plane.fail_next("ListPatterns", Status::invalid_argument("bad filter"));
+        for _ in 0..3 {
+            assert!(client.patterns().list().await.is_err());
+        }
+        assert_eq!(client.circuit_state(), CircuitState::Closed);
+
+        // Closed -> open: calls fail fast without reaching the control plane
+        fail(3);
+        for _ in 0..3 {
+            assert!(client.patterns().list().await.is_err());
+        }
+        assert_eq!(client.circuit_state(), CircuitState::Open);
+        let sent = control_plane.calls_to("ListPatterns").len();
+        let error = client.agents().list().await.unwrap_err();
+        assert!(matches!(&error, Error::Connection(message) if message == "circuit open"));
+        assert!(error.is_retryable());
+        assert_eq!(control_plane.calls_to("ListPatterns").len(), sent);
+        assert!(control_plane.calls_to("ListAgents").is_empty());
+
+        // Open -> half-open: a failed probe reopens the circuit
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(client.circuit_state(), CircuitState::HalfOpen);
+        fail(1);
+        assert!(client.patterns().list().await.is_err());
+        assert_eq!(client.circuit_state(), CircuitState::Open);
+
+        // Half-open -> closed once a probe succeeds
+        clock.advance(Duration::from_secs(10));
+        client.patterns().list().await.unwrap();
+        assert_eq!(client.circuit_state(), CircuitState::Closed);
+        client.agents().list().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_auth_token_is_sent_as_bearer() {
+        let control_plane = MockControlPlane::new();
+        let (endpoint, seen) =
+            serve_recording_metadata(control_plane.router(), &["authorization"]).await;
+
+        let config = ClientConfig {
+            endpoint,
+            auth_token: Some("test-token".to_string()),
+            ..Default::default()
+        };
+        let client = Client::new(config).await.unwrap();
+        client.patterns().list().await.unwrap();
+        client.agents().list().await.unwrap();
+
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![vec![Some("Bearer test-token".to_string())]; 2]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_builder_connects_with_configured_metadata() {
+        let control_plane = MockControlPlane::new();
+        control_plane.add_pattern(pattern("consensus"));
+        let (endpoint, seen) =
+            serve_recording_metadata(control_plane.router(), &["authorization", "x-team"]).await;
+
+        let client = Client::builder()
+            .endpoint(endpoint.clone())
+            .timeout(Duration::from_secs(5))
+            .auth("test-token")
+            .header("x-team", "search")
+            .retry(3, Duration::from_millis(10))
+            .connect()
+            .await
+            .unwrap();
+
+        assert_eq!(client.endpoint(), endpoint);
+        let patterns = client.patterns().list().await.unwrap();
+        assert_eq!(patterns[0].name, "consensus");
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![vec![Some("Bearer test-token".to_string()), Some("search".to_string())]]
+        );
+
+        let error = Client::builder()
+            .endpoint(endpoint)
+            .header("bad header", "value")
+            .connect()
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(error, Error::InvalidArgument(_)), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_builder_retries_until_the_control_plane_is_up() {
+        let endpoint = unused_endpoint().await;
+        let addr = endpoint.trim_start_matches("http://").to_string();
+        let control_plane = MockControlPlane::new();
+        let router = control_plane.router();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+            router
+                .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener))
+                .await
+        });
+
+        let client = Client::builder()
+            .endpoint(endpoint)
+            .retry(10, Duration::from_millis(20))
+            .connect()
+            .await
+            .unwrap();
+        client.agents().list().await.unwrap();
+    }
+
+    #[test]
+    fn test_resolved_endpoints_prefer_replica_list() {
+        let config = ClientConfig::default();
+        assert_eq!(config.resolved_endpoints(), vec!["http://localhost:8080"]);
+
+        let config = config
+            .add_endpoint("http://cp-1:8080")
+            .add_endpoint("http://cp-2:8080");
+        assert_eq!(
+            config.resolved_endpoints(),
+            vec!["http://cp-1:8080", "http://cp-2:8080"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_to_reachable_replica() {
+        let control_plane = MockControlPlane::new();
+        control_plane.add_pattern(pattern("consensus"));
+        let live = serve_at(control_plane.router()).await;
+        let dead = unused_endpoint().await;
+
+        let config = ClientConfig::default()
+            .add_endpoint(dead.clone())
+            .add_endpoint(live.clone());
+        let client = Client::new(config).await.unwrap();
+
+        assert_eq!(client.endpoint(), live);
+        assert_eq!(client.endpoints(), [dead, live.clone()]);
+        let patterns = client.patterns().list().await.unwrap();
+        assert_eq!(patterns[0].name, "consensus");
+        assert_eq!(client.endpoint(), live);
+    }
+
+    #[tokio::test]
+    async fn test_gzip_round_trips_large_payload() {
+        let control_plane = MockControlPlane::new();
+        control_plane.add_pattern(pattern("consensus"));
+        let report = "finding ".repeat(64 * 1024);
+        let seeded = PatternExecution {
+            id: "exec-1".to_string(),
+            pattern: "consensus".to_string(),
+            status: ExecutionStatus::Completed,
+            input: Value::Null,
+            output: Some(json!({ "report": report })),
+            agents: vec![],
+            agent_contributions: Vec::new(),
+            start_time: chrono::Utc::now(),
+            end_time: None,
+            duration_ms: None,
+            confidence: Some(0.8),
+            error: None,
+            metadata: Default::default(),
+        };
+        control_plane.enqueue_execution("consensus", seeded);
+
+        let config = ClientConfig {
+            endpoint: serve_at(control_plane.router()).await,
+            compression: Some(CompressionEncoding::Gzip),
+            ..Default::default()
+        };
+        let client = Client::new(config).await.unwrap();
+
+        let input = json!({ "document": report });
+        let execution = client
+            .patterns()
+            .execute("consensus", input.clone(), None)
+            .await
+            .unwrap();
+
+        assert_eq!(execution.output.unwrap()["report"], report.as_str());
+        assert_eq!(control_plane.calls_to("ExecutePattern")[0].input, Some(input));
+    }
+
+    fn agent(id: &str, status: AgentStatus, capabilities: &[&str]) -> Agent {
+        Agent {
+            id: id.to_string(),
+            name: id.to_string(),
+            status,
+            capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+            endpoint: "localhost:50051".to_string(),
+            last_seen: chrono::Utc::now(),
+            confidence: 0.8,
+            metadata: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_coordinate_runs_consensus_among_capable_agents() {
+        let (client, control_plane) = Client::mock();
+        control_plane.add_pattern(pattern(CONSENSUS_PATTERN));
+        control_plane.add_agent(agent("a", AgentStatus::Active, &["review"]));
+        control_plane.add_agent(agent("b", AgentStatus::Active, &["review", "search"]));
+        control_plane.add_agent(agent("c", AgentStatus::Inactive, &["review"]));
+        control_plane.add_agent(agent("d", AgentStatus::Active, &["search"]));
+        control_plane.enqueue_execution(
+            CONSENSUS_PATTERN,
+            PatternExecution {
+                id: "exec-1".to_string(),
+                pattern: String::new(),
+                status: ExecutionStatus::Completed,
+                input: Value::Null,
+                output: Some(json!({ "verdict": "approve" })),
+                agents: vec!["a".to_string(), "b".to_string()],
+                agent_contributions: Vec::new(),
+                start_time: chrono::Utc::now(),
+                end_time: None,
+                duration_ms: None,
+                confidence: Some(0.85),
+                error: None,
+                metadata: Default::default(),
+            },
+        );
+
+        let execution = client.coordinate("review the PR", "review", 2).await.unwrap();
+        assert_eq!(execution.status, ExecutionStatus::Completed);
+        assert_eq!(execution.output.unwrap()["verdict"], "approve");
+        let calls = control_plane.calls_to("ExecutePattern");
+        assert_eq!(calls[0].target.as_deref(), Some(CONSENSUS_PATTERN));
+        assert_eq!(calls[0].input, Some(json!({ "task": "review the PR" })));
+
+        // The inactive agent does not count towards the minimum
+        let error = client.coordinate("review the PR", "review", 3).await.unwrap_err();
+        assert!(matches!(
+            error,
+            Error::InsufficientAgents { required: 3, available: 2, .. }
+        ));
+        assert_eq!(error.code(), crate::ErrorCode::FailedPrecondition);
+        control_plane.assert_called("ExecutePattern", 1);
+    }
+
+    #[tokio::test]
+    async fn test_connection_state_flips_when_server_goes_away() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let control_plane = MockControlPlane::new();
+        control_plane.add_pattern(pattern("consensus"));
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let endpoint = serve_until(control_plane.router(), async {
+            let _ = stopped.await;
+        })
+        .await;
+
+        let disconnects = Arc::new(AtomicUsize::new(0));
+        let config = ClientConfig {
+            endpoint,
+            ..Default::default()
+        }
+        .on_disconnect({
+            let disconnects = disconnects.clone();
+            move || {
+                disconnects.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        let client = Client::new(config).await.unwrap();
+        client.patterns().list().await.unwrap();
+        assert_eq!(client.connection_state(), ConnectionState::Connected);
+
+        stop.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while client.connection_state() != ConnectionState::Disconnected {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("client never noticed the server going away");
+        assert_eq!(disconnects.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_interceptors_run_in_order_on_every_service() {
+        // The server records the signature header of every request it gets
+        let control_plane = MockControlPlane::new();
+        let (endpoint, signatures) =
+            serve_recording_metadata(control_plane.router(), &["x-signature"]).await;
+
+        let config = ClientConfig {
+            endpoint,
+            ..Default::default()
+        };
+        let client = Client::new(config)
+            .await
+            .unwrap()
+            .with_interceptor(|mut request: Request<()>| {
+                request.metadata_mut().insert("x-signature", "first".parse().unwrap());
+                Ok(request)
+            })
+            .with_interceptor(|mut request: Request<()>| {
+                let first = request.metadata().get("x-signature").unwrap().to_str().unwrap();
+                let signature = format!("{}+second", first).parse().unwrap();
+                request.metadata_mut().insert("x-signature", signature);
+                Ok(request)
+            });
+
+        client.patterns().list().await.unwrap();
+        client.agents().list().await.unwrap();
+        client.executions().list(10, 0, None).await.unwrap();
+
+        let expected = vec![Some("first+second".to_string())];
+        assert_eq!(*signatures.lock().unwrap(), vec![expected; 3]);
+    }
+
+    #[tokio::test]
+    async fn test_interceptor_errors_fail_the_call() {
+        let (client, control_plane) = Client::mock();
+        let client = client.with_interceptor(|_request: Request<()>| {
+            Err(Status::resource_exhausted("client-side rate limit reached"))
+        });
+
+        let error = client.patterns().list().await.unwrap_err();
+
+        assert_eq!(error.code(), crate::ErrorCode::ResourceExhausted);
+        assert!(control_plane.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_closed_client_fails_calls_promptly() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let control_plane = MockControlPlane::new();
+        control_plane.add_pattern(pattern("consensus"));
+        let disconnects = Arc::new(AtomicUsize::new(0));
+        let config = ClientConfig {
+            endpoint: serve_at(control_plane.router()).await,
+            ..Default::default()
+        }
+        .on_disconnect({
+            let disconnects = disconnects.clone();
+            move || {
+                disconnects.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        let client = Client::new(config).await.unwrap();
+        let clone = client.clone();
+        let patterns = client.patterns();
+        patterns.list().await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), client.close())
+            .await
+            .expect("close did not finish");
+
+        assert_eq!(clone.connection_state(), ConnectionState::Closed);
+        for error in [
+            clone.agents().list().await.unwrap_err(),
+            patterns.list().await.unwrap_err(),
+        ] {
+            assert!(
+                matches!(&error, Error::Connection(message) if message == "client closed"),
+                "{}",
+                error
+            );
+        }
+        assert_eq!(disconnects.load(Ordering::SeqCst), 0);
+        control_plane.assert_called("ListPatterns", 1);
+    }
+
+    #[tokio::test]
+    async fn test_connect_fails_when_no_replica_is_reachable() {
+        let config = ClientConfig::default()
+            .add_endpoint(unused_endpoint().await)
+            .add_endpoint(unused_endpoint().await);
+
+        assert!(Client::new(config).await.is_err());
+    }
+}
@@ -0,0 +1,259 @@
+//! Time source for the SDK's timers
+//!
+//! Lease renewal, heartbeats, reconnect backoff and timeouts all go through a
+//! [`Clock`] instead of calling `tokio::time` directly. [`SystemClock`] is the
+//! default; with the `testing` feature, [`MockClock`] only moves when told to,
+//! so tests can fire a 30s renewal interval without waiting for it.
+//!
+//! In the browser (`wasm` without `native`) `std::time::Instant` panics, so
+//! [`Instant`] is `wasmtimer`'s, read from `performance.now()`, and timers
+//! run on `setTimeout`.
+
+use futures::{future::BoxFuture, stream::BoxStream, Future, StreamExt};
+use std::{fmt, time::Duration};
+
+#[cfg(feature = "native")]
+pub use std::time::Instant;
+#[cfg(not(feature = "native"))]
+pub use wasmtimer::std::Instant;
+
+/// Future returned by [`Clock::sleep`]
+pub type Sleep = BoxFuture<'static, ()>;
+
+/// Stream of tick instants returned by [`Clock::interval`]
+pub type Ticks = BoxStream<'static, Instant>;
+
+/// Source of the current time and of timers
+pub trait Clock: Send + Sync + fmt::Debug {
+    /// Current instant
+    fn now(&self) -> Instant;
+
+    /// Resolve once `duration` has elapsed
+    fn sleep(&self, duration: Duration) -> Sleep;
+
+    /// Tick immediately, then every `period`. Missed ticks are delivered
+    /// back to back, like `tokio::time::interval`.
+    fn interval(&self, period: Duration) -> Ticks;
+}
+
+/// [`Clock`] backed by `tokio::time`, or by `wasmtimer` in the browser
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[cfg(feature = "native")]
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(tokio::time::sleep(duration))
+    }
+
+    fn interval(&self, period: Duration) -> Ticks {
+        tokio_stream::wrappers::IntervalStream::new(tokio::time::interval(period))
+            .map(tokio::time::Instant::into_std)
+            .boxed()
+    }
+}
+
+#[cfg(not(feature = "native"))]
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn sleep(&self, duration: Duration) -> Sleep {
+        Box::pin(wasmtimer::tokio::sleep(duration))
+    }
+
+    fn interval(&self, period: Duration) -> Ticks {
+        futures::stream::unfold(wasmtimer::tokio::interval(period), |mut interval| async {
+            let tick = interval.tick().await;
+            Some((tick, interval))
+        })
+        .boxed()
+    }
+}
+
+/// Calculate reconnect delay with exponential backoff
+pub(crate) fn calculate_reconnect_delay(
+    attempt: u32,
+    initial_delay_ms: u64,
+    max_delay_ms: u64,
+) -> u64 {
+    let delay = initial_delay_ms.saturating_mul(2u64.saturating_pow(attempt));
+    delay.min(max_delay_ms)
+}
+
+/// Run `future`, giving up with `None` once `limit` has elapsed on `clock`
+pub(crate) async fn timeout<F: Future>(
+    clock: &dyn Clock,
+    limit: Duration,
+    future: F,
+) -> Option<F::Output> {
+    tokio::select! {
+        biased;
+        output = future => Some(output),
+        _ = clock.sleep(limit) => None,
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+pub use mock::MockClock;
+
+#[cfg(any(test, feature = "testing"))]
+mod mock {
+    use super::{Clock, Sleep, Ticks};
+    use futures::StreamExt;
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+    use tokio::sync::oneshot;
+
+    /// [`Clock`] that stands still until [`advance`](Self::advance)d
+    ///
+    /// Clones share the same time, so keep one to drive a clock handed to an
+    /// agent or client.
+    #[derive(Debug, Clone)]
+    pub struct MockClock {
+        state: Arc<Mutex<State>>,
+    }
+
+    #[derive(Debug)]
+    struct State {
+        now: Instant,
+        sleepers: Vec<(Instant, oneshot::Sender<()>)>,
+    }
+
+    impl MockClock {
+        /// Create a clock starting at the current instant
+        pub fn new() -> Self {
+            Self {
+                state: Arc::new(Mutex::new(State {
+                    now: Instant::now(),
+                    sleepers: Vec::new(),
+                })),
+            }
+        }
+
+        /// Move time forward, waking every sleep that is now due
+        pub fn advance(&self, duration: Duration) {
+            let mut state = self.state.lock().unwrap();
+            state.now += duration;
+            let now = state.now;
+            let (due, pending) = std::mem::take(&mut state.sleepers)
+                .into_iter()
+                .partition(|(deadline, _)| *deadline <= now);
+            state.sleepers = pending;
+            for (_, waker) in due {
+                let _ = waker.send(());
+            }
+        }
+
+        /// Number of sleeps waiting for time to advance, e.g. to tell whether
+        /// a loop has parked on its next tick
+        pub fn pending_sleeps(&self) -> usize {
+            let mut state = self.state.lock().unwrap();
+            state.sleepers.retain(|(_, waker)| !waker.is_closed());
+            state.sleepers.len()
+        }
+    }
+
+    impl Default for MockClock {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            self.state.lock().unwrap().now
+        }
+
+        fn sleep(&self, duration: Duration) -> Sleep {
+            let mut state = self.state.lock().unwrap();
+            if duration.is_zero() {
+                return Box::pin(async {});
+            }
+            let (waker, woken) = oneshot::channel();
+            let deadline = state.now + duration;
+            state.sleepers.retain(|(_, waker)| !waker.is_closed());
+            state.sleepers.push((deadline, waker));
+
+            // Holding the state keeps the sender alive until woken
+            let state = Arc::clone(&self.state);
+            Box::pin(async move {
+                let _ = woken.await;
+                drop(state);
+            })
+        }
+
+        fn interval(&self, period: Duration) -> Ticks {
+            let start = self.now();
+            futures::stream::unfold((self.clone(), start), move |(clock, next)| async move {
+                clock
+                    .sleep(next.saturating_duration_since(clock.now()))
+                    .await;
+                Some((next, (clock, next + period)))
+            })
+            .boxed()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    #[tokio::test]
+    async fn test_mock_clock_wakes_sleeps_only_once_due() {
+        let clock = MockClock::new();
+        let started = clock.now();
+        let mut short = clock.sleep(Duration::from_secs(10));
+        let mut long = clock.sleep(Duration::from_secs(60));
+        assert_eq!(clock.pending_sleeps(), 2);
+
+        clock.advance(Duration::from_secs(9));
+        assert!((&mut short).now_or_never().is_none());
+
+        clock.advance(Duration::from_secs(1));
+        assert!((&mut short).now_or_never().is_some());
+        assert!((&mut long).now_or_never().is_none());
+        assert_eq!(clock.pending_sleeps(), 1);
+        assert_eq!(clock.now() - started, Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_mock_clock_interval_bursts_missed_ticks() {
+        let clock = MockClock::new();
+        let started = clock.now();
+        let mut ticks = clock.interval(Duration::from_secs(30));
+
+        assert_eq!(ticks.next().await, Some(started));
+        assert!(ticks.next().now_or_never().is_none());
+
+        clock.advance(Duration::from_secs(95));
+        for expected in [30, 60, 90] {
+            assert_eq!(
+                ticks.next().await,
+                Some(started + Duration::from_secs(expected))
+            );
+        }
+        assert!(ticks.next().now_or_never().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_timeout_gives_up_when_clock_passes_limit() {
+        let clock = MockClock::new();
+        let pending = timeout(&clock, Duration::from_secs(5), std::future::pending::<()>());
+        tokio::pin!(pending);
+        assert!((&mut pending).now_or_never().is_none());
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(pending.await, None);
+        assert_eq!(timeout(&clock, Duration::from_secs(5), async { 7 }).await, Some(7));
+    }
+}
@@ -1,12 +1,39 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, LazyLock};
 use regex::Regex;
 use serde_json::Value;
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
 
-use crate::parallax_agent::AgentResult;
+use crate::error::Error;
+use crate::parallax_agent::{AgentResult, AnalyzeFuture};
+
+/// Patterns for confidence values embedded in free text, compiled once,
+/// with the confidence field each one reads (if any)
+static CONFIDENCE_PATTERNS: LazyLock<Vec<(Option<&'static str>, Regex)>> = LazyLock::new(|| {
+    [
+        (Some("confidence"), r"confidence:\s*(\d+\.?\d*)"),
+        (Some("certainty"), r"certainty:\s*(\d+\.?\d*)"),
+        (Some("probability"), r"probability:\s*(\d+\.?\d*)"),
+        (Some("score"), r"score:\s*(\d+\.?\d*)"),
+        (None, r"(\d+\.?\d*)\s*%\s*(?:confident|certain|sure)"),
+    ]
+    .iter()
+    .map(|(field, pattern)| (*field, Regex::new(pattern).expect("valid confidence pattern")))
+    .collect()
+});
+
+/// Fields read as confidence by default, highest priority first
+pub const DEFAULT_CONFIDENCE_FIELDS: [&str; 5] =
+    ["confidence", "_confidence", "score", "certainty", "probability"];
+
+/// Callback scoring a result for [`ExtractionStrategy::Custom`]
+pub type CustomExtractor = Arc<dyn Fn(&Value) -> f64 + Send + Sync>;
 
 /// Strategy for extracting confidence from results
-#[derive(Debug, Clone, Copy)]
+#[derive(Clone)]
 pub enum ExtractionStrategy {
     /// Extract from LLM-style structured responses
     Llm,
@@ -14,6 +41,21 @@ pub enum ExtractionStrategy {
     Keywords,
     /// Hybrid approach combining both strategies
     Hybrid,
+    /// Score with a user callback, e.g. for a model's own confidence schema.
+    /// Scores are clamped to 0.0-1.0, and a non-finite score falls back to
+    /// the default confidence.
+    Custom(CustomExtractor),
+}
+
+impl fmt::Debug for ExtractionStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtractionStrategy::Llm => f.write_str("Llm"),
+            ExtractionStrategy::Keywords => f.write_str("Keywords"),
+            ExtractionStrategy::Hybrid => f.write_str("Hybrid"),
+            ExtractionStrategy::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
 }
 
 /// Configuration for confidence extraction
@@ -21,6 +63,27 @@ pub enum ExtractionStrategy {
 pub struct ConfidenceConfig {
     pub default_confidence: f64,
     pub strategy: ExtractionStrategy,
+    /// Language of the analyzed text as an ISO 639-1 code ("en", "es", "de")
+    pub language: String,
+    /// Extra keyword modifiers merged over the language's built-in table;
+    /// positive values raise the score, negative values lower it, and entries
+    /// here replace built-in ones with the same word
+    pub custom_keywords: HashMap<String, f64>,
+    /// Result fields holding a confidence, highest priority first. Fields
+    /// left out are ignored, both as structured fields and as `field: value`
+    /// text, e.g. drop `"score"` when it is an unbounded relevance score.
+    pub confidence_fields: Vec<String>,
+}
+
+impl ConfidenceConfig {
+    /// Default configuration scoring results with `extract` instead of a
+    /// built-in strategy
+    pub fn with_custom_strategy(extract: impl Fn(&Value) -> f64 + Send + Sync + 'static) -> Self {
+        Self {
+            strategy: ExtractionStrategy::Custom(Arc::new(extract)),
+            ..Default::default()
+        }
+    }
 }
 
 impl Default for ConfidenceConfig {
@@ -28,6 +91,205 @@ impl Default for ConfidenceConfig {
         Self {
             default_confidence: 0.5,
             strategy: ExtractionStrategy::Hybrid,
+            language: "en".to_string(),
+            custom_keywords: HashMap::new(),
+            confidence_fields: DEFAULT_CONFIDENCE_FIELDS.map(String::from).to_vec(),
+        }
+    }
+}
+
+/// Confidence keywords and hedging patterns for one language
+#[derive(Debug, Clone)]
+pub struct KeywordLexicon {
+    /// Words that raise (positive) or lower (negative) the score when
+    /// present, applied in alphabetical order so scores are reproducible
+    pub modifiers: BTreeMap<String, f64>,
+    /// Regex patterns for hedging phrases; each match lowers the score by 0.1
+    pub hedging_patterns: Vec<String>,
+}
+
+impl KeywordLexicon {
+    /// Built-in lexicon for a language code, ignoring any region suffix
+    /// (so "es-MX" resolves to Spanish)
+    pub fn for_language(language: &str) -> Option<Self> {
+        let code = language
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+
+        match code.as_str() {
+            "en" => Some(Self::english()),
+            "es" => Some(Self::spanish()),
+            "de" => Some(Self::german()),
+            _ => None,
+        }
+    }
+
+    /// English keywords
+    pub fn english() -> Self {
+        Self::from_tables(
+            &[
+                // High confidence indicators
+                ("definitely", 0.15),
+                ("certainly", 0.15),
+                ("absolutely", 0.15),
+                ("confirmed", 0.15),
+                ("verified", 0.15),
+                ("guaranteed", 0.15),
+                ("certain", 0.12),
+                ("sure", 0.12),
+                ("clear", 0.10),
+                ("obvious", 0.10),
+                ("undoubtedly", 0.12),
+                ("unquestionably", 0.12),
+                ("conclusive", 0.12),
+                ("definitive", 0.12),
+                ("established", 0.10),
+                // Medium confidence indicators
+                ("probably", 0.05),
+                ("likely", 0.05),
+                ("appears", 0.05),
+                ("seems", 0.05),
+                ("suggests", 0.05),
+                ("indicates", 0.05),
+                ("mostly", 0.04),
+                ("generally", 0.04),
+                ("typically", 0.04),
+                ("reasonable", 0.05),
+                ("plausible", 0.05),
+                ("expected", 0.04),
+                // Low confidence indicators
+                ("possibly", -0.15),
+                ("maybe", -0.15),
+                ("might", -0.12),
+                ("could", -0.10),
+                ("uncertain", -0.15),
+                ("unclear", -0.15),
+                ("unsure", -0.15),
+                ("doubt", -0.15),
+                ("guess", -0.12),
+                ("assume", -0.10),
+                ("questionable", -0.15),
+                ("tentative", -0.12),
+                ("approximate", -0.08),
+                ("estimated", -0.08),
+                ("roughly", -0.08),
+            ],
+            &[
+                r"(?:i|we)\s+(?:think|believe|suppose)",
+                r"(?:may|might)\s+be",
+                r"(?:could|would)\s+(?:be|suggest)",
+                r"(?:perhaps|presumably)",
+            ],
+        )
+    }
+
+    /// Spanish keywords
+    pub fn spanish() -> Self {
+        Self::from_tables(
+            &[
+                // High confidence indicators
+                ("definitivamente", 0.15),
+                ("ciertamente", 0.15),
+                ("absolutamente", 0.15),
+                ("confirmado", 0.15),
+                ("verificado", 0.15),
+                ("garantizado", 0.15),
+                ("seguro", 0.12),
+                ("claro", 0.10),
+                ("obvio", 0.10),
+                ("indudablemente", 0.12),
+                ("sin duda", 0.12),
+                ("concluyente", 0.12),
+                ("establecido", 0.10),
+                // Medium confidence indicators
+                ("probablemente", 0.05),
+                ("parece", 0.05),
+                ("sugiere", 0.05),
+                ("indica", 0.05),
+                ("mayormente", 0.04),
+                ("generalmente", 0.04),
+                ("típicamente", 0.04),
+                ("razonable", 0.05),
+                ("plausible", 0.05),
+                ("esperado", 0.04),
+                // Low confidence indicators
+                ("posiblemente", -0.15),
+                ("quizá", -0.15),
+                ("tal vez", -0.15),
+                ("podría", -0.10),
+                ("incierto", -0.15),
+                ("dudoso", -0.15),
+                ("supongo", -0.10),
+                ("cuestionable", -0.15),
+                ("tentativo", -0.12),
+                ("aproximado", -0.08),
+                ("estimado", -0.08),
+                ("aproximadamente", -0.08),
+            ],
+            &[
+                r"(?:creo|creemos|pienso|pensamos)\s+que",
+                r"(?:puede|podría)\s+ser",
+                r"(?:acaso|presumiblemente)",
+            ],
+        )
+    }
+
+    /// German keywords
+    pub fn german() -> Self {
+        Self::from_tables(
+            &[
+                // High confidence indicators
+                ("definitiv", 0.15),
+                ("sicherlich", 0.15),
+                ("absolut", 0.15),
+                ("bestätigt", 0.15),
+                ("verifiziert", 0.15),
+                ("garantiert", 0.15),
+                ("gewiss", 0.12),
+                ("eindeutig", 0.12),
+                ("offensichtlich", 0.10),
+                ("zweifellos", 0.12),
+                ("klar", 0.10),
+                ("nachgewiesen", 0.10),
+                // Medium confidence indicators
+                ("wahrscheinlich", 0.05),
+                ("scheint", 0.05),
+                ("deutet", 0.05),
+                ("meistens", 0.04),
+                ("normalerweise", 0.04),
+                ("plausibel", 0.05),
+                ("vernünftig", 0.05),
+                ("erwartet", 0.04),
+                // Low confidence indicators
+                ("möglicherweise", -0.15),
+                ("vielleicht", -0.15),
+                ("eventuell", -0.12),
+                ("könnte", -0.10),
+                ("unsicher", -0.15),
+                ("unklar", -0.15),
+                ("bezweifle", -0.15),
+                ("vermutlich", -0.12),
+                ("fraglich", -0.15),
+                ("ungefähr", -0.08),
+                ("geschätzt", -0.08),
+            ],
+            &[
+                r"(?:ich|wir)\s+(?:glaube|glauben|denke|denken|vermute|vermuten)",
+                r"(?:kann|könnte)\s+sein",
+                r"(?:womöglich|angeblich)",
+            ],
+        )
+    }
+
+    fn from_tables(modifiers: &[(&str, f64)], hedging_patterns: &[&str]) -> Self {
+        Self {
+            modifiers: modifiers
+                .iter()
+                .map(|(word, modifier)| (word.to_string(), *modifier))
+                .collect(),
+            hedging_patterns: hedging_patterns.iter().map(|p| p.to_string()).collect(),
         }
     }
 }
@@ -40,7 +302,7 @@ pub trait WithConfidence {
         &self,
         analyze_fn: F,
         config: Option<ConfidenceConfig>,
-    ) -> impl Fn(&str, Option<Value>) -> futures::future::BoxFuture<'_, Result<AgentResult, Box<dyn std::error::Error>>> + Send + Sync
+    ) -> impl Fn(&str, Option<Value>) -> AnalyzeFuture<'_> + Send + Sync
     where
         F: Fn(&str, Option<Value>) -> Fut + Send + Sync + 'static,
         Fut: std::future::Future<Output = Result<Value, Box<dyn std::error::Error>>> + Send + 'static;
@@ -49,17 +311,64 @@ pub trait WithConfidence {
 /// Implementation of confidence extraction for any type
 pub struct ConfidenceExtractor {
     config: ConfidenceConfig,
+    lexicon: KeywordLexicon,
+    // The lexicon's hedging patterns, compiled at construction
+    hedging: Vec<Regex>,
 }
 
 impl ConfidenceExtractor {
     /// Create a new confidence extractor
+    ///
+    /// Languages without a built-in lexicon fall back to English.
     pub fn new(config: ConfidenceConfig) -> Self {
-        Self { config }
+        let lexicon = KeywordLexicon::for_language(&config.language).unwrap_or_else(|| {
+            warn!(
+                "No confidence keywords for language '{}', falling back to English",
+                config.language
+            );
+            KeywordLexicon::english()
+        });
+        Self::with_lexicon(config, lexicon).expect("valid built-in hedging patterns")
+    }
+
+    /// Create an extractor that scores keywords and hedging with `lexicon`
+    /// instead of the built-in one for `config.language`
+    ///
+    /// Fails with `InvalidArgument` if a hedging pattern is not a valid regex.
+    pub fn with_lexicon(config: ConfidenceConfig, mut lexicon: KeywordLexicon) -> crate::Result<Self> {
+        // Text is lowercased before matching, so keys must be too
+        lexicon.modifiers.extend(
+            config
+                .custom_keywords
+                .iter()
+                .map(|(word, modifier)| (word.to_lowercase(), *modifier)),
+        );
+        let hedging = lexicon
+            .hedging_patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|error| {
+                    Error::InvalidArgument(format!("invalid hedging pattern {:?}: {}", pattern, error))
+                })
+            })
+            .collect::<crate::Result<_>>()?;
+        Ok(Self { config, lexicon, hedging })
     }
     
     /// Extract confidence from a result
+    ///
+    /// Scores are stable: the same result and configuration always produce
+    /// the same value, bit for bit, across extractors and runs.
     pub fn extract(&self, result: &Value) -> f64 {
-        match self.config.strategy {
+        match &self.config.strategy {
+            ExtractionStrategy::Custom(extract) => {
+                let confidence = extract(result);
+                if confidence.is_finite() {
+                    confidence.clamp(0.0, 1.0)
+                } else {
+                    self.config.default_confidence
+                }
+            }
             ExtractionStrategy::Llm => self.extract_from_llm(result),
             ExtractionStrategy::Keywords => self.extract_from_keywords(result),
             ExtractionStrategy::Hybrid => {
@@ -74,11 +383,10 @@ impl ConfidenceExtractor {
     /// Extract confidence from LLM-style responses
     fn extract_from_llm(&self, result: &Value) -> f64 {
         // Check for explicit confidence fields
+        let confidence_fields = &self.config.confidence_fields;
         if let Some(obj) = result.as_object() {
-            let confidence_fields = ["confidence", "_confidence", "score", "certainty", "probability"];
-            
-            for field in &confidence_fields {
-                if let Some(val) = obj.get(*field) {
+            for field in confidence_fields {
+                if let Some(val) = obj.get(field) {
                     if let Some(conf) = self.normalize_confidence(val) {
                         return conf;
                     }
@@ -87,8 +395,8 @@ impl ConfidenceExtractor {
             
             // Check nested metadata
             if let Some(metadata) = obj.get("metadata").and_then(|m| m.as_object()) {
-                for field in &confidence_fields {
-                    if let Some(val) = metadata.get(*field) {
+                for field in confidence_fields {
+                    if let Some(val) = metadata.get(field) {
                         if let Some(conf) = self.normalize_confidence(val) {
                             return conf;
                         }
@@ -100,22 +408,14 @@ impl ConfidenceExtractor {
         // Try to extract from text representation
         let text = result.to_string();
         
-        // Confidence patterns
-        let patterns = [
-            r"confidence:\s*(\d+\.?\d*)",
-            r"certainty:\s*(\d+\.?\d*)",
-            r"probability:\s*(\d+\.?\d*)",
-            r"score:\s*(\d+\.?\d*)",
-            r"(\d+\.?\d*)\s*%\s*(?:confident|certain|sure)",
-        ];
-        
-        for pattern in &patterns {
-            if let Ok(re) = Regex::new(pattern) {
-                if let Some(caps) = re.captures(&text) {
-                    if let Some(match_str) = caps.get(1) {
-                        if let Ok(val) = match_str.as_str().parse::<f64>() {
-                            return self.normalize_confidence_value(val);
-                        }
+        let patterns = CONFIDENCE_PATTERNS.iter().filter(|(field, _)| {
+            field.is_none_or(|field| confidence_fields.iter().any(|allowed| allowed == field))
+        });
+        for (_, re) in patterns {
+            if let Some(caps) = re.captures(&text) {
+                if let Some(match_str) = caps.get(1) {
+                    if let Ok(val) = match_str.as_str().parse::<f64>() {
+                        return self.normalize_confidence_value(val);
                     }
                 }
             }
@@ -129,97 +429,23 @@ impl ConfidenceExtractor {
         let text = result.to_string().to_lowercase();
         let mut score = self.config.default_confidence;
         
-        // High confidence indicators
-        let high_confidence: HashMap<&str, f64> = [
-            ("definitely", 0.15),
-            ("certainly", 0.15),
-            ("absolutely", 0.15),
-            ("confirmed", 0.15),
-            ("verified", 0.15),
-            ("guaranteed", 0.15),
-            ("certain", 0.12),
-            ("sure", 0.12),
-            ("clear", 0.10),
-            ("obvious", 0.10),
-            ("undoubtedly", 0.12),
-            ("unquestionably", 0.12),
-            ("conclusive", 0.12),
-            ("definitive", 0.12),
-            ("established", 0.10),
-        ].iter().cloned().collect();
-        
-        // Medium confidence indicators
-        let medium_confidence: HashMap<&str, f64> = [
-            ("probably", 0.05),
-            ("likely", 0.05),
-            ("appears", 0.05),
-            ("seems", 0.05),
-            ("suggests", 0.05),
-            ("indicates", 0.05),
-            ("mostly", 0.04),
-            ("generally", 0.04),
-            ("typically", 0.04),
-            ("reasonable", 0.05),
-            ("plausible", 0.05),
-            ("expected", 0.04),
-        ].iter().cloned().collect();
-        
-        // Low confidence indicators
-        let low_confidence: HashMap<&str, f64> = [
-            ("possibly", -0.15),
-            ("maybe", -0.15),
-            ("might", -0.12),
-            ("could", -0.10),
-            ("uncertain", -0.15),
-            ("unclear", -0.15),
-            ("unsure", -0.15),
-            ("doubt", -0.15),
-            ("guess", -0.12),
-            ("assume", -0.10),
-            ("questionable", -0.15),
-            ("tentative", -0.12),
-            ("approximate", -0.08),
-            ("estimated", -0.08),
-            ("roughly", -0.08),
-        ].iter().cloned().collect();
-        
-        // Apply modifiers
-        for (word, modifier) in high_confidence {
-            if text.contains(word) {
-                score += modifier;
-            }
-        }
-        
-        for (word, modifier) in medium_confidence {
-            if text.contains(word) {
-                score += modifier;
-            }
-        }
-        
-        for (word, modifier) in low_confidence {
-            if text.contains(word) {
+        // Apply modifiers in key order; summing in hash order would make the
+        // floating-point total vary between extractors
+        for (word, modifier) in &self.lexicon.modifiers {
+            if text.contains(word.as_str()) {
                 score += modifier;
             }
         }
         
         // Check for hedging patterns
-        let hedging_patterns = [
-            r"(?:i|we)\s+(?:think|believe|suppose)",
-            r"(?:may|might)\s+be",
-            r"(?:could|would)\s+(?:be|suggest)",
-            r"(?:perhaps|presumably)",
-        ];
-        
-        for pattern in &hedging_patterns {
-            if let Ok(re) = Regex::new(pattern) {
-                if re.is_match(&text) {
-                    score -= 0.1;
-                }
+        for re in &self.hedging {
+            if re.is_match(&text) {
+                score -= 0.1;
             }
         }
         
         // Clamp to valid range
-        score.max(0.1).min(0.95)
+        score.clamp(0.1, 0.95)
     }
     
     /// Normalize a confidence value to 0.0-1.0 range
@@ -250,7 +476,7 @@ impl ConfidenceExtractor {
 pub fn with_confidence<F, Fut>(
     analyze_fn: F,
     config: Option<ConfidenceConfig>,
-) -> impl Fn(&str, Option<Value>) -> futures::future::BoxFuture<'_, Result<AgentResult, Box<dyn std::error::Error>>> + Send + Sync
+) -> impl Fn(&str, Option<Value>) -> AnalyzeFuture<'_> + Send + Sync
 where
     F: Fn(&str, Option<Value>) -> Fut + Send + Sync + 'static,
     Fut: std::future::Future<Output = Result<Value, Box<dyn std::error::Error>>> + Send + 'static,
@@ -271,13 +497,18 @@ where
                 value: result_value,
                 confidence,
                 reasoning: None,
+                reasoning_steps: Vec::new(),
                 uncertainties: Vec::new(),
                 metadata: HashMap::new(),
+                cache_ttl: None,
             })
         })
     }
 }
 
+/// Distance kept from 0 and 1 when converting confidences to log-odds
+const LOG_ODDS_EPSILON: f64 = 1e-6;
+
 /// Aggregator for combining multiple confidence values
 pub struct ConfidenceAggregator;
 
@@ -321,34 +552,103 @@ impl ConfidenceAggregator {
                 let consensus_factor = 1.0 - (variance * 2.0).min(0.5);
                 mean * consensus_factor
             }
+            "bayesian" => {
+                // Independent opinions with a uniform prior: sum the log-odds
+                if confidences.len() == 1 {
+                    return confidences[0];
+                }
+                let log_odds: f64 = confidences
+                    .iter()
+                    .map(|c| {
+                        let p = c.clamp(LOG_ODDS_EPSILON, 1.0 - LOG_ODDS_EPSILON);
+                        (p / (1.0 - p)).ln()
+                    })
+                    .sum();
+                1.0 / (1.0 + (-log_odds).exp())
+            }
             _ => confidences.iter().sum::<f64>() / confidences.len() as f64,
         }
     }
     
+    /// Combine confidence values and return `(lower, point, upper)`
+    ///
+    /// The bounds are a 95% interval around the combined value, using the
+    /// standard error of the inputs and clamped to `[0, 1]`. With fewer than
+    /// two inputs there is no spread, so both bounds equal the point value.
+    pub fn combine_with_interval(confidences: &[f64], strategy: &str) -> (f64, f64, f64) {
+        let point = Self::combine(confidences, strategy, None);
+        if confidences.len() < 2 {
+            return (point, point, point);
+        }
+
+        let n = confidences.len() as f64;
+        let mean = confidences.iter().sum::<f64>() / n;
+        let sample_variance = confidences
+            .iter()
+            .map(|c| (c - mean).powi(2))
+            .sum::<f64>()
+            / (n - 1.0);
+        let margin = 1.96 * (sample_variance / n).sqrt();
+
+        (
+            (point - margin).clamp(0.0, 1.0),
+            point,
+            (point + margin).clamp(0.0, 1.0),
+        )
+    }
+
     /// Calculate confidence based on result consistency
+    ///
+    /// Results are compared in canonical JSON form, so values differing only
+    /// in key order or in how a number is written (`1` vs `1.0`) agree.
+    /// Fewer than two results give 0.5; full agreement gives 0.95.
     pub fn from_consistency(results: &[Value]) -> f64 {
+        Self::from_consistency_with_decay(results, 1.0)
+    }
+
+    /// [`from_consistency`](Self::from_consistency) with recent results
+    /// weighted higher
+    ///
+    /// `results` run oldest first, and each result weighs `decay` times as
+    /// much as the one after it, so with a decay of 0.5 a result two places
+    /// before the newest counts a quarter as much. A decay of 1 weighs all
+    /// results equally; values outside `(0, 1]` are treated as 1. Agreement
+    /// is the weight of results repeating one at least as recent, relative
+    /// to the most such weight possible, so an old outlier costs less than a
+    /// new one.
+    pub fn from_consistency_with_decay(results: &[Value], decay: f64) -> f64 {
         if results.len() < 2 {
             return 0.5;
         }
-        
-        // Convert results to comparable strings
-        let str_results: Vec<String> = results.iter()
-            .map(|r| serde_json::to_string(r).unwrap_or_else(|_| r.to_string()))
-            .collect();
-        
-        // Count unique results
-        let unique_count = str_results.iter()
-            .collect::<std::collections::HashSet<_>>()
-            .len();
-        
+        let decay = if decay > 0.0 && decay <= 1.0 {
+            decay
+        } else {
+            warn!("Consistency decay {} is outside (0, 1], using 1", decay);
+            1.0
+        };
+
+        // Heaviest (newest) weight per distinct result, and the total weight
+        let newest = results.len() - 1;
+        let mut representatives: HashMap<String, f64> = HashMap::new();
+        let mut total_weight = 0.0;
+        for (i, result) in results.iter().enumerate() {
+            let weight = decay.powi((newest - i) as i32);
+            total_weight += weight;
+            let representative = representatives
+                .entry(crate::conversions::canonical_json(result))
+                .or_insert(0.0);
+            *representative = representative.max(weight);
+        }
+
         // Perfect agreement = high confidence
-        if unique_count == 1 {
+        if representatives.len() == 1 {
             return 0.95;
         }
-        
-        // Calculate consistency score
-        let consistency = 1.0 - (unique_count - 1) as f64 / (results.len() - 1) as f64;
-        
+
+        // Weight of the repeats, relative to its value when all agree
+        let repeated = total_weight - representatives.values().sum::<f64>();
+        let consistency = (repeated / (total_weight - 1.0)).clamp(0.0, 1.0);
+
         // Map to confidence range 0.5-0.95
         0.5 + (consistency * 0.45)
     }
@@ -359,7 +659,120 @@ impl ConfidenceAggregator {
         let calibrated = (raw_confidence - 0.5) * scale + 0.5 - bias;
         
         // Ensure valid range
-        calibrated.max(0.0).min(1.0)
+        calibrated.clamp(0.0, 1.0)
+    }
+
+    /// Calibrate an agent's confidence using its learned parameters
+    ///
+    /// Agents the model has not been fitted for are passed through unchanged.
+    pub fn calibrate_for_agent(
+        raw_confidence: f64,
+        model: &CalibrationModel,
+        agent_id: &str,
+    ) -> f64 {
+        let params = model.params(agent_id);
+        Self::calibrate(raw_confidence, params.bias, params.scale)
+    }
+}
+
+/// Calibration parameters for a single agent, as used by
+/// [`ConfidenceAggregator::calibrate`]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationParams {
+    pub bias: f64,
+    pub scale: f64,
+}
+
+impl Default for CalibrationParams {
+    fn default() -> Self {
+        Self {
+            bias: 0.0,
+            scale: 1.0,
+        }
+    }
+}
+
+impl CalibrationParams {
+    /// Fit parameters by least-squares regression of observed outcomes on
+    /// predicted confidences
+    pub fn fit(predicted: &[f64], actual: &[bool]) -> crate::Result<Self> {
+        if predicted.len() != actual.len() {
+            return Err(Error::InvalidArgument(format!(
+                "{} predictions but {} outcomes",
+                predicted.len(),
+                actual.len()
+            )));
+        }
+        if predicted.len() < 2 {
+            return Err(Error::InvalidArgument(
+                "at least two observations are required to fit calibration".to_string(),
+            ));
+        }
+
+        let n = predicted.len() as f64;
+        let outcomes: Vec<f64> = actual.iter().map(|&hit| if hit { 1.0 } else { 0.0 }).collect();
+        let mean_x = predicted.iter().sum::<f64>() / n;
+        let mean_y = outcomes.iter().sum::<f64>() / n;
+        let covariance: f64 = predicted
+            .iter()
+            .zip(&outcomes)
+            .map(|(x, y)| (x - mean_x) * (y - mean_y))
+            .sum();
+        let variance: f64 = predicted.iter().map(|x| (x - mean_x).powi(2)).sum();
+        if variance <= f64::EPSILON {
+            return Err(Error::InvalidArgument(
+                "predicted confidences must vary to fit calibration".to_string(),
+            ));
+        }
+
+        // outcome ≈ intercept + slope * predicted, rewritten in the
+        // (raw - 0.5) * scale + 0.5 - bias form used by `calibrate`
+        let slope = covariance / variance;
+        let intercept = mean_y - slope * mean_x;
+        Ok(Self {
+            bias: 0.5 - 0.5 * slope - intercept,
+            scale: slope,
+        })
+    }
+}
+
+/// Learned calibration parameters keyed by agent id
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CalibrationModel {
+    pub agents: HashMap<String, CalibrationParams>,
+}
+
+impl CalibrationModel {
+    /// Create an empty model
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fit and store parameters for an agent from its prediction history
+    pub fn fit(
+        &mut self,
+        agent_id: &str,
+        predicted: &[f64],
+        actual: &[bool],
+    ) -> crate::Result<CalibrationParams> {
+        let params = CalibrationParams::fit(predicted, actual)?;
+        self.agents.insert(agent_id.to_string(), params);
+        Ok(params)
+    }
+
+    /// Parameters for an agent, or the identity calibration if unknown
+    pub fn params(&self, agent_id: &str) -> CalibrationParams {
+        self.agents.get(agent_id).copied().unwrap_or_default()
+    }
+
+    /// Serialize the model to JSON
+    pub fn to_json(&self) -> crate::Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// Load a model previously saved with [`to_json`](Self::to_json)
+    pub fn from_json(json: &str) -> crate::Result<Self> {
+        Ok(serde_json::from_str(json)?)
     }
 }
 
@@ -381,3 +794,293 @@ macro_rules! require_confidence {
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_invalid_hedging_pattern_is_rejected() {
+        for language in ["en", "es", "de"] {
+            let lexicon = KeywordLexicon::for_language(language).unwrap();
+            assert!(ConfidenceExtractor::with_lexicon(ConfidenceConfig::default(), lexicon).is_ok());
+        }
+
+        let mut lexicon = KeywordLexicon::english();
+        lexicon.hedging_patterns.push(r"\b(maybe".to_string());
+        let error = ConfidenceExtractor::with_lexicon(ConfidenceConfig::default(), lexicon)
+            .err()
+            .unwrap();
+        assert!(
+            matches!(&error, Error::InvalidArgument(message) if message.contains("(maybe")),
+            "{}",
+            error
+        );
+    }
+
+    fn keyword_confidence(language: &str, value: Value) -> f64 {
+        let extractor = ConfidenceExtractor::new(ConfidenceConfig {
+            strategy: ExtractionStrategy::Keywords,
+            language: language.to_string(),
+            ..Default::default()
+        });
+        extractor.extract(&value)
+    }
+
+    #[test]
+    fn test_spanish_keywords_raise_confidence_like_english() {
+        let english = keyword_confidence("en", json!({ "answer": "definitely yes" }));
+        let spanish = keyword_confidence("es", json!({ "respuesta": "definitivamente sí" }));
+
+        assert!(english > 0.5);
+        assert!((spanish - english).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_german_hedging_lowers_confidence() {
+        let hedged = keyword_confidence("de-AT", json!({ "antwort": "vielleicht, ich glaube ja" }));
+        assert!(hedged < 0.5);
+    }
+
+    #[test]
+    fn test_custom_keywords_shift_and_override_confidence() {
+        let extractor = ConfidenceExtractor::new(ConfidenceConfig {
+            strategy: ExtractionStrategy::Keywords,
+            custom_keywords: HashMap::from([
+                ("Alleged".to_string(), -0.2),
+                ("confirmed".to_string(), 0.3),
+            ]),
+            ..Default::default()
+        });
+
+        let alleged = extractor.extract(&json!({ "finding": "the alleged breach" }));
+        assert!((alleged - 0.3).abs() < 1e-9);
+
+        // The custom weight replaces the built-in +0.15 for "confirmed"
+        let confirmed = extractor.extract(&json!({ "finding": "confirmed" }));
+        assert!((confirmed - 0.8).abs() < 1e-9);
+
+        // The final clamp still applies
+        let floor = extractor.extract(&json!({ "finding": "alleged, maybe, unclear" }));
+        assert_eq!(floor, 0.1);
+    }
+
+    #[test]
+    fn test_excluded_confidence_fields_are_skipped() {
+        let result = json!({ "score": 87, "metadata": { "confidence": 0.6 } });
+
+        // By default the top-level "score" wins and is read as a percentage
+        let default = ConfidenceExtractor::new(ConfidenceConfig {
+            strategy: ExtractionStrategy::Llm,
+            ..Default::default()
+        });
+        assert_eq!(default.extract(&result), 0.87);
+
+        let extractor = ConfidenceExtractor::new(ConfidenceConfig {
+            strategy: ExtractionStrategy::Llm,
+            confidence_fields: vec!["confidence".to_string(), "certainty".to_string()],
+            ..Default::default()
+        });
+        assert_eq!(extractor.extract(&result), 0.6);
+        assert_eq!(extractor.extract(&json!("relevance score: 87")), 0.5);
+        assert_eq!(extractor.extract(&json!("certainty: 0.8, score: 87")), 0.8);
+    }
+
+    #[test]
+    fn test_extraction_is_deterministic() {
+        let config = ConfidenceConfig {
+            custom_keywords: HashMap::from([
+                ("sound".to_string(), 0.07),
+                ("shaky".to_string(), -0.03),
+                ("solid".to_string(), 0.11),
+            ]),
+            ..Default::default()
+        };
+        let result = json!({
+            "finding": "probably a solid, sound and likely fix, though shaky tests \
+                        suggest it might be unclear; generally expected, seems plausible",
+            "confidence": 0.73
+        });
+
+        let first = ConfidenceExtractor::new(config.clone()).extract(&result);
+        for _ in 0..100 {
+            let again = ConfidenceExtractor::new(config.clone()).extract(&result);
+            assert_eq!(again.to_bits(), first.to_bits());
+        }
+    }
+
+    #[test]
+    fn test_llm_text_patterns_still_match() {
+        let extractor = ConfidenceExtractor::new(ConfidenceConfig {
+            strategy: ExtractionStrategy::Llm,
+            ..Default::default()
+        });
+
+        assert_eq!(extractor.extract(&json!("certainty: 0.8")), 0.8);
+        assert_eq!(extractor.extract(&json!("I am 90% sure")), 0.9);
+        assert_eq!(extractor.extract(&json!("no signal here")), 0.5);
+    }
+
+    #[test]
+    fn test_bayesian_combination_multiplies_odds() {
+        // Odds 4 * 4 = 16
+        let two = ConfidenceAggregator::combine(&[0.8, 0.8], "bayesian", None);
+        assert!((two - 16.0 / 17.0).abs() < 1e-9);
+
+        // Odds 7/3 * 3/2 * 2/3 = 7/3
+        let three = ConfidenceAggregator::combine(&[0.7, 0.6, 0.4], "bayesian", None);
+        assert!((three - 0.7).abs() < 1e-9);
+
+        assert_eq!(ConfidenceAggregator::combine(&[0.3], "bayesian", None), 0.3);
+    }
+
+    #[test]
+    fn test_bayesian_combination_handles_certain_inputs() {
+        let certain = ConfidenceAggregator::combine(&[1.0, 0.9], "bayesian", None);
+        assert!(certain.is_finite() && certain < 1.0 && certain > 0.99);
+
+        let conflicting = ConfidenceAggregator::combine(&[1.0, 0.0], "bayesian", None);
+        assert!((conflicting - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_interval_widens_with_input_variance() {
+        let (low, _, high) = ConfidenceAggregator::combine_with_interval(&[0.7, 0.7, 0.7], "avg");
+        assert!(high - low < 1e-9);
+
+        let narrow = ConfidenceAggregator::combine_with_interval(&[0.65, 0.7, 0.75], "avg");
+        let wide = ConfidenceAggregator::combine_with_interval(&[0.4, 0.7, 1.0], "avg");
+        assert!((narrow.1 - 0.7).abs() < 1e-9 && (wide.1 - 0.7).abs() < 1e-9);
+        assert!(narrow.0 < 0.7 && narrow.2 > 0.7);
+        assert!(wide.2 - wide.0 > narrow.2 - narrow.0);
+
+        // mean ± 1.96 * (0.05 / sqrt(3))
+        let margin = 1.96 * 0.05 / 3f64.sqrt();
+        assert!((narrow.0 - (0.7 - margin)).abs() < 1e-9);
+        assert!((narrow.2 - (0.7 + margin)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_interval_collapses_for_single_input_and_stays_in_range() {
+        assert_eq!(
+            ConfidenceAggregator::combine_with_interval(&[0.8], "avg"),
+            (0.8, 0.8, 0.8)
+        );
+
+        let (low, _, high) = ConfidenceAggregator::combine_with_interval(&[0.0, 1.0], "avg");
+        assert_eq!((low, high), (0.0, 1.0));
+    }
+
+    /// An agent that is 30 points overconfident: it says 0.6 when right 30%
+    /// of the time and 0.9 when right 60% of the time
+    fn overconfident_history() -> (Vec<f64>, Vec<bool>) {
+        let mut predicted = Vec::new();
+        let mut actual = Vec::new();
+        for (confidence, hits) in [(0.6, 3), (0.9, 6)] {
+            for i in 0..10 {
+                predicted.push(confidence);
+                actual.push(i < hits);
+            }
+        }
+        (predicted, actual)
+    }
+
+    #[test]
+    fn test_calibration_fit_corrects_biased_predictor() {
+        let (predicted, actual) = overconfident_history();
+        let mut model = CalibrationModel::new();
+        let params = model.fit("agent-1", &predicted, &actual).unwrap();
+
+        assert!((params.bias - 0.3).abs() < 1e-9);
+        assert!((params.scale - 1.0).abs() < 1e-9);
+
+        let calibrated = ConfidenceAggregator::calibrate_for_agent(0.9, &model, "agent-1");
+        assert!((calibrated - 0.6).abs() < 1e-9);
+
+        // Unknown agents are left alone
+        assert_eq!(ConfidenceAggregator::calibrate_for_agent(0.9, &model, "other"), 0.9);
+    }
+
+    #[test]
+    fn test_calibration_model_round_trips_through_json() {
+        let (predicted, actual) = overconfident_history();
+        let mut model = CalibrationModel::new();
+        model.fit("agent-1", &predicted, &actual).unwrap();
+
+        let restored = CalibrationModel::from_json(&model.to_json().unwrap()).unwrap();
+        assert_eq!(restored, model);
+    }
+
+    #[test]
+    fn test_calibration_fit_rejects_unusable_history() {
+        assert!(CalibrationParams::fit(&[0.5, 0.6], &[true]).is_err());
+        assert!(CalibrationParams::fit(&[0.7, 0.7], &[true, false]).is_err());
+    }
+
+    #[test]
+    fn test_custom_strategy_reads_bespoke_field() {
+        let extractor = ConfidenceExtractor::new(ConfidenceConfig::with_custom_strategy(|result| {
+            // The model reports confidence as a percentage under `my_conf`
+            result["my_conf"].as_f64().map_or(f64::NAN, |percent| percent / 100.0)
+        }));
+
+        assert_eq!(extractor.extract(&json!({ "answer": "yes", "my_conf": 82 })), 0.82);
+        assert_eq!(extractor.extract(&json!({ "my_conf": 140 })), 1.0);
+        // Falls back to the default when the field is missing
+        assert_eq!(extractor.extract(&json!({ "confidence": 0.9 })), 0.5);
+        assert_eq!(
+            format!("{:?}", ConfidenceConfig::with_custom_strategy(|_| 1.0).strategy),
+            "Custom(..)"
+        );
+    }
+
+    #[test]
+    fn test_unknown_language_falls_back_to_english() {
+        let value = json!({ "answer": "definitely yes" });
+        assert_eq!(keyword_confidence("xx", value.clone()), keyword_confidence("en", value));
+    }
+
+    #[test]
+    fn test_consistency_treats_reordered_json_as_agreement() {
+        let results: Vec<Value> = [
+            r#"{"verdict": "approve", "score": 1, "tags": ["a", "b"]}"#,
+            r#"{"tags":["a","b"],"verdict":"approve","score":1.0}"#,
+            r#"{ "score": 1, "tags": [ "a", "b" ], "verdict": "approve" }"#,
+        ]
+        .iter()
+        .map(|json| serde_json::from_str(json).unwrap())
+        .collect();
+        assert_eq!(ConfidenceAggregator::from_consistency(&results), 0.95);
+
+        // Order within arrays still matters
+        let reordered = json!({ "verdict": "approve", "score": 1, "tags": ["b", "a"] });
+        let mixed = [results[0].clone(), results[1].clone(), reordered];
+        assert!((ConfidenceAggregator::from_consistency(&mixed) - 0.725).abs() < 1e-9);
+
+        assert_eq!(ConfidenceAggregator::from_consistency(&[]), 0.5);
+        assert_eq!(ConfidenceAggregator::from_consistency(&results[..1]), 0.5);
+    }
+
+    #[test]
+    fn test_consistency_decay_discounts_old_disagreement() {
+        let (agree, outlier) = (json!({ "answer": 42 }), json!({ "answer": 7 }));
+        let old_outlier = [outlier.clone(), agree.clone(), agree.clone()];
+        let new_outlier = [agree.clone(), agree, outlier];
+
+        let unweighted = ConfidenceAggregator::from_consistency(&old_outlier);
+        assert_eq!(unweighted, ConfidenceAggregator::from_consistency(&new_outlier));
+        assert!((unweighted - 0.725).abs() < 1e-9);
+
+        let old = ConfidenceAggregator::from_consistency_with_decay(&old_outlier, 0.5);
+        let new = ConfidenceAggregator::from_consistency_with_decay(&new_outlier, 0.5);
+        assert!((old - 0.8).abs() < 1e-9, "{}", old);
+        assert!((new - 0.65).abs() < 1e-9, "{}", new);
+
+        // Out-of-range decay falls back to equal weights
+        assert_eq!(
+            ConfidenceAggregator::from_consistency_with_decay(&old_outlier, 0.0),
+            unweighted
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! Connection state tracking for the control plane channel
+//!
+//! A tonic `Channel` reconnects on demand and does not report whether it is
+//! currently connected. [`MonitoredConnector`] wraps the connector the
+//! channel dials through and follows each transport it hands out: the state
+//! becomes connected when a dial succeeds, and disconnected once the
+//! transport is closed, whether because keepalive pings went unanswered or
+//! because the server went away.
+//!
+//! [`ConnectionMonitor::close`] tears the connection down on purpose: open
+//! transports fail their next read or write, and no new ones are dialled.
+//!
+//! In the browser there is no connection to follow: each call is its own
+//! `fetch`, so a client reports itself connected until it is closed.
+
+use std::{fmt, sync::Arc};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use tracing::info;
+#[cfg(feature = "native")]
+use {
+    futures::future::BoxFuture,
+    hyper::rt::{Read, ReadBufCursor, Write},
+    std::{
+        future::Future,
+        io,
+        pin::Pin,
+        sync::Weak,
+        task::{Context, Poll},
+    },
+    tokio_util::sync::WaitForCancellationFutureOwned,
+    tonic::transport::Uri,
+    tracing::warn,
+};
+
+/// Whether a [`Client`](crate::Client) currently holds a live connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConnectionState {
+    /// No connection has been established yet
+    Connecting,
+    Connected,
+    /// The connection was lost. The next call reconnects.
+    Disconnected,
+    /// The client was closed with [`Client::close`](crate::Client::close)
+    Closed,
+}
+
+/// Callback run when a client loses its connection to the control plane
+///
+/// Never run in the browser, where there is no connection to lose.
+#[derive(Clone)]
+pub struct DisconnectHandler(
+    #[cfg_attr(not(feature = "native"), allow(dead_code))] Arc<dyn Fn() + Send + Sync>,
+);
+
+impl DisconnectHandler {
+    pub fn new(callback: impl Fn() + Send + Sync + 'static) -> Self {
+        Self(Arc::new(callback))
+    }
+}
+
+impl fmt::Debug for DisconnectHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DisconnectHandler")
+    }
+}
+
+/// Shared connection state of a client and its clones
+pub(crate) struct ConnectionMonitor {
+    state: watch::Sender<ConnectionState>,
+    #[cfg_attr(not(feature = "native"), allow(dead_code))]
+    on_disconnect: Option<DisconnectHandler>,
+    // Transports currently open; the client is disconnected when none are
+    open: watch::Sender<usize>,
+    // Cancelled by `close`
+    closing: CancellationToken,
+}
+
+impl ConnectionMonitor {
+    pub(crate) fn new(on_disconnect: Option<DisconnectHandler>) -> Arc<Self> {
+        Arc::new(Self {
+            state: watch::channel(ConnectionState::Connecting).0,
+            on_disconnect,
+            open: watch::channel(0).0,
+            closing: CancellationToken::new(),
+        })
+    }
+
+    pub(crate) fn state(&self) -> ConnectionState {
+        *self.state.borrow()
+    }
+
+    /// Token cancelled once the client is closed
+    pub(crate) fn closing(&self) -> &CancellationToken {
+        &self.closing
+    }
+
+    #[cfg(feature = "native")]
+    pub(crate) fn connected(&self) {
+        self.open.send_modify(|open| *open += 1);
+        if self.closing.is_cancelled() {
+            return;
+        }
+        if self.state.send_replace(ConnectionState::Connected) != ConnectionState::Connected {
+            info!("Connection to control plane established");
+        }
+    }
+
+    /// Report a connection that is not made through a monitored connector,
+    /// such as the mock control plane's in-memory one or the browser's `fetch`
+    #[cfg(any(test, feature = "testing", not(feature = "native")))]
+    pub(crate) fn assume_connected(&self) {
+        self.state.send_replace(ConnectionState::Connected);
+    }
+
+    #[cfg(feature = "native")]
+    fn transport_closed(&self) {
+        self.open.send_modify(|open| *open -= 1);
+        if *self.open.borrow() != 0 || self.closing.is_cancelled() {
+            return;
+        }
+        self.state.send_replace(ConnectionState::Disconnected);
+        warn!("Connection to control plane lost");
+        if let Some(on_disconnect) = &self.on_disconnect {
+            (on_disconnect.0)();
+        }
+    }
+
+    /// Close every open transport and refuse new ones, then wait until the
+    /// transports are gone. The disconnect handler is not called.
+    pub(crate) async fn close(&self) {
+        self.closing.cancel();
+        self.state.send_replace(ConnectionState::Closed);
+        let _ = self.open.subscribe().wait_for(|open| *open == 0).await;
+        info!("Connection to control plane closed");
+    }
+}
+
+/// Connector reporting the transports it opens to a [`ConnectionMonitor`]
+///
+/// Holds the monitor weakly, so dropping every client silences it instead
+/// of reporting the channel's teardown as a disconnect.
+#[cfg(feature = "native")]
+#[derive(Clone)]
+pub(crate) struct MonitoredConnector<C> {
+    inner: C,
+    monitor: Weak<ConnectionMonitor>,
+    closing: CancellationToken,
+}
+
+#[cfg(feature = "native")]
+impl<C> MonitoredConnector<C> {
+    pub(crate) fn new(inner: C, monitor: &Arc<ConnectionMonitor>) -> Self {
+        Self {
+            inner,
+            monitor: Arc::downgrade(monitor),
+            closing: monitor.closing.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "native")]
+impl<C> tower::Service<Uri> for MonitoredConnector<C>
+where
+    C: tower::Service<Uri>,
+    C::Response: Send,
+    C::Error: From<io::Error> + Send,
+    C::Future: Send + 'static,
+{
+    type Response = MonitoredIo<C::Response>;
+    type Error = C::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let connecting = self.inner.call(uri);
+        let monitor = self.monitor.clone();
+        let closing = self.closing.clone();
+        Box::pin(async move {
+            if closing.is_cancelled() {
+                return Err(client_closed().into());
+            }
+            let inner = connecting.await?;
+            if let Some(monitor) = monitor.upgrade() {
+                monitor.connected();
+            }
+            Ok(MonitoredIo {
+                inner,
+                monitor,
+                closing: Box::pin(closing.cancelled_owned()),
+            })
+        })
+    }
+}
+
+#[cfg(feature = "native")]
+fn client_closed() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "client closed")
+}
+
+/// Transport that reports its closing to the monitor, and fails once the
+/// client is closed
+#[cfg(feature = "native")]
+pub(crate) struct MonitoredIo<T> {
+    inner: T,
+    monitor: Weak<ConnectionMonitor>,
+    closing: Pin<Box<WaitForCancellationFutureOwned>>,
+}
+
+#[cfg(feature = "native")]
+impl<T> MonitoredIo<T> {
+    /// Fail with `client closed` once the client is closed, registering to
+    /// be woken when it is
+    fn check_open(&mut self, cx: &mut Context<'_>) -> io::Result<()> {
+        match self.closing.as_mut().poll(cx) {
+            Poll::Ready(()) => Err(client_closed()),
+            Poll::Pending => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "native")]
+impl<T> Drop for MonitoredIo<T> {
+    fn drop(&mut self) {
+        if let Some(monitor) = self.monitor.upgrade() {
+            monitor.transport_closed();
+        }
+    }
+}
+
+#[cfg(feature = "native")]
+impl<T: Read + Unpin> Read for MonitoredIo<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.check_open(cx)?;
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+#[cfg(feature = "native")]
+impl<T: Write + Unpin> Write for MonitoredIo<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.check_open(cx)?;
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.check_open(cx)?;
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+}
@@ -0,0 +1,370 @@
+//! Conversions between JSON values and protobuf `Struct`/`Value`
+//!
+//! Protobuf numbers are doubles, so JSON integers come back as floats and
+//! integers beyond 2^53 lose precision. Doubles with no JSON equivalent (NaN
+//! and the infinities) become `null`, with a warning; the execution service
+//! can instead reject or stringify them (see [`NonFinitePolicy`]).
+//!
+//! A protobuf `Value` whose kind is unset is not the same as an explicit
+//! `NullValue`, though both convert to `null` by default. The agent can
+//! instead omit struct fields with an unset kind (see
+//! [`ParallaxAgent::with_omit_unset_fields`](crate::ParallaxAgent::with_omit_unset_fields)),
+//! so a missing field stays distinguishable from an explicit `null`.
+
+use prost_types::{value::Kind, ListValue, Struct, Value as ProtoValue};
+use serde_json::{Map, Value};
+use tracing::warn;
+
+/// Convert JSON to a protobuf `Struct`
+///
+/// Objects map field by field. Any other value is wrapped as the single
+/// field `"value"`, since a `Struct` must be an object.
+pub fn json_to_struct(value: Value) -> Struct {
+    match value {
+        Value::Object(map) => object_to_struct(map),
+        other => Struct {
+            fields: [("value".to_string(), json_to_prost_value(other))].into(),
+        },
+    }
+}
+
+/// Convert JSON to a protobuf `Value`
+pub fn json_to_prost_value(value: Value) -> ProtoValue {
+    let kind = match value {
+        Value::Null => Kind::NullValue(0),
+        Value::Bool(value) => Kind::BoolValue(value),
+        Value::Number(value) => Kind::NumberValue(value.as_f64().unwrap_or_default()),
+        Value::String(value) => Kind::StringValue(value),
+        Value::Array(values) => Kind::ListValue(ListValue {
+            values: values.into_iter().map(json_to_prost_value).collect(),
+        }),
+        Value::Object(map) => Kind::StructValue(object_to_struct(map)),
+    };
+
+    ProtoValue { kind: Some(kind) }
+}
+
+fn object_to_struct(map: Map<String, Value>) -> Struct {
+    Struct {
+        fields: map
+            .into_iter()
+            .map(|(key, value)| (key, json_to_prost_value(value)))
+            .collect(),
+    }
+}
+
+/// Convert a protobuf `Struct` to a JSON object
+pub fn struct_to_json(value: Struct) -> Value {
+    struct_to_json_within(value, usize::MAX, false).unwrap_or_default()
+}
+
+/// Convert a protobuf `Value` to JSON; a value without a kind is `null`
+pub fn prost_value_to_json(value: ProtoValue) -> Value {
+    let mut nodes = usize::MAX;
+    value_to_json_bounded(value, usize::MAX, &mut nodes, false, NonFinitePolicy::default())
+        .unwrap_or_default()
+}
+
+/// Convert a protobuf `Struct` to a JSON object, or `None` if structs and
+/// lists nest more than `depth` levels deep (the struct itself being the
+/// first). With `omit_unset`, struct fields whose value has no kind are left
+/// out rather than converted to `null`; list elements always keep their
+/// position and become `null`.
+pub(crate) fn struct_to_json_within(
+    value: Struct,
+    depth: usize,
+    omit_unset: bool,
+) -> Option<Value> {
+    let mut nodes = usize::MAX;
+    struct_to_json_bounded(value, depth, &mut nodes, omit_unset, NonFinitePolicy::default()).ok()
+}
+
+/// What converting a NaN or infinite protobuf number to JSON produces
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NonFinitePolicy {
+    /// `null`, with a warning
+    #[default]
+    NullOnNonFinite,
+    /// Fail the conversion with `Error::Internal`
+    ErrorOnNonFinite,
+    /// The string `"NaN"`, `"Infinity"` or `"-Infinity"`
+    StringifyNonFinite,
+}
+
+/// Bounds on protobuf values converted from a server response, so a deep or
+/// huge `Struct` is rejected instead of exhausting the stack or memory
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct JsonLimits {
+    /// Deepest nesting of structs and lists, the outer struct being the first
+    pub(crate) max_depth: usize,
+    /// Most values converted in total, containers included
+    pub(crate) max_nodes: usize,
+    /// How NaN and infinite numbers are converted
+    pub(crate) non_finite: NonFinitePolicy,
+}
+
+impl Default for JsonLimits {
+    fn default() -> Self {
+        Self {
+            max_depth: 64,
+            max_nodes: 1_000_000,
+            non_finite: NonFinitePolicy::default(),
+        }
+    }
+}
+
+impl JsonLimits {
+    /// Convert a protobuf `Struct` to a JSON object within these limits
+    pub(crate) fn struct_to_json(&self, value: Struct) -> crate::Result<Value> {
+        let mut nodes = self.max_nodes;
+        struct_to_json_bounded(value, self.max_depth, &mut nodes, false, self.non_finite).map_err(
+            |rejected| {
+                crate::Error::Internal(match rejected {
+                    Rejected::Depth => "nesting too deep".to_string(),
+                    Rejected::Nodes => "too many values".to_string(),
+                    Rejected::NonFinite(value) => format!("non-finite number {}", value),
+                })
+            },
+        )
+    }
+}
+
+/// What stopped a conversion
+enum Rejected {
+    Depth,
+    Nodes,
+    NonFinite(f64),
+}
+
+fn struct_to_json_bounded(
+    value: Struct,
+    depth: usize,
+    nodes: &mut usize,
+    omit_unset: bool,
+    non_finite: NonFinitePolicy,
+) -> Result<Value, Rejected> {
+    let depth = depth.checked_sub(1).ok_or(Rejected::Depth)?;
+    let map = value
+        .fields
+        .into_iter()
+        .filter(|(_, value)| !(omit_unset && value.kind.is_none()))
+        .map(|(key, value)| {
+            Ok((key, value_to_json_bounded(value, depth, nodes, omit_unset, non_finite)?))
+        })
+        .collect::<Result<Map<_, _>, _>>()?;
+    Ok(Value::Object(map))
+}
+
+fn value_to_json_bounded(
+    value: ProtoValue,
+    depth: usize,
+    nodes: &mut usize,
+    omit_unset: bool,
+    non_finite: NonFinitePolicy,
+) -> Result<Value, Rejected> {
+    *nodes = nodes.checked_sub(1).ok_or(Rejected::Nodes)?;
+    Ok(match value.kind {
+        Some(Kind::NullValue(_)) | None => Value::Null,
+        Some(Kind::BoolValue(value)) => Value::Bool(value),
+        Some(Kind::NumberValue(value)) => number_to_json(value, non_finite)?,
+        Some(Kind::StringValue(value)) => Value::String(value),
+        Some(Kind::ListValue(list)) => {
+            let depth = depth.checked_sub(1).ok_or(Rejected::Depth)?;
+            let values = list
+                .values
+                .into_iter()
+                .map(|value| value_to_json_bounded(value, depth, nodes, omit_unset, non_finite))
+                .collect::<Result<Vec<_>, _>>()?;
+            Value::Array(values)
+        }
+        Some(Kind::StructValue(value)) => {
+            struct_to_json_bounded(value, depth, nodes, omit_unset, non_finite)?
+        }
+    })
+}
+
+fn number_to_json(value: f64, non_finite: NonFinitePolicy) -> Result<Value, Rejected> {
+    if let Some(number) = serde_json::Number::from_f64(value) {
+        return Ok(Value::Number(number));
+    }
+    match non_finite {
+        NonFinitePolicy::NullOnNonFinite => {
+            warn!("Converting non-finite number {} to null", value);
+            Ok(Value::Null)
+        }
+        NonFinitePolicy::ErrorOnNonFinite => Err(Rejected::NonFinite(value)),
+        NonFinitePolicy::StringifyNonFinite => Ok(Value::String(
+            match value {
+                f64::INFINITY => "Infinity",
+                f64::NEG_INFINITY => "-Infinity",
+                _ => "NaN",
+            }
+            .to_string(),
+        )),
+    }
+}
+
+/// Render JSON in a canonical form, so semantically equal values render
+/// the same: object keys are sorted, there is no whitespace, and numbers
+/// equal in value render alike (`1` and `1.0` both as `1`)
+pub(crate) fn canonical_json(value: &Value) -> String {
+    let mut out = String::new();
+    write_canonical(value, &mut out);
+    out
+}
+
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Array(values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(value, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            out.push('{');
+            for (i, (key, value)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::String(key.clone()).to_string());
+                out.push(':');
+                write_canonical(value, out);
+            }
+            out.push('}');
+        }
+        Value::Number(number) => match number.as_f64() {
+            // Integral floats render as the integer they equal
+            Some(float)
+                if number.is_f64() && float.fract() == 0.0 && float.abs() < 2f64.powi(53) =>
+            {
+                out.push_str(&(float as i64).to_string())
+            }
+            _ => out.push_str(&number.to_string()),
+        },
+        other => out.push_str(&other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_nested_values_round_trip() {
+        let value = json!({
+            "name": "review",
+            "passed": true,
+            "skipped": null,
+            "score": 0.875,
+            "tags": ["security", ["nested", null], { "depth": 2.0 }],
+            "limits": {
+                "tiny": 1.5e-300,
+                "huge": -1.7976931348623157e308,
+                "max_exact_integer": 9007199254740992.0,
+                "empty_list": [],
+                "empty_object": {}
+            }
+        });
+
+        assert_eq!(struct_to_json(json_to_struct(value.clone())), value);
+        let tags = value["tags"].clone();
+        assert_eq!(prost_value_to_json(json_to_prost_value(tags.clone())), tags);
+    }
+
+    #[test]
+    fn test_integers_become_doubles() {
+        let value = json!({ "count": 3, "big": u64::MAX });
+
+        let converted = struct_to_json(json_to_struct(value));
+
+        assert_eq!(converted["count"], json!(3.0));
+        assert_eq!(converted["big"].as_f64(), Some(u64::MAX as f64));
+    }
+
+    #[test]
+    fn test_non_object_is_wrapped() {
+        let converted = struct_to_json(json_to_struct(json!([1.0, "two"])));
+        assert_eq!(converted, json!({ "value": [1.0, "two"] }));
+    }
+
+    #[test]
+    fn test_non_finite_numbers_become_null() {
+        for number in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let value = ProtoValue {
+                kind: Some(Kind::NumberValue(number)),
+            };
+            assert_eq!(prost_value_to_json(value.clone()), Value::Null);
+
+            let nested = Struct {
+                fields: [("n".to_string(), value)].into(),
+            };
+            assert_eq!(struct_to_json_within(nested, 1, false), Some(json!({ "n": null })));
+        }
+        assert_eq!(prost_value_to_json(ProtoValue { kind: None }), Value::Null);
+    }
+
+    #[test]
+    fn test_depth_limit_counts_structs_and_lists() {
+        let nested = json_to_struct(json!({ "list": [{ "leaf": 1.0 }] }));
+
+        assert!(struct_to_json_within(nested.clone(), 2, false).is_none());
+        assert!(struct_to_json_within(nested.clone(), 3, false).is_some());
+        assert_eq!(struct_to_json_within(Struct::default(), 0, false), None);
+    }
+
+    #[test]
+    fn test_unset_fields_are_null_or_omitted() {
+        let unset = ProtoValue { kind: None };
+        let null = ProtoValue {
+            kind: Some(Kind::NullValue(0)),
+        };
+        let inner = Struct {
+            fields: [("missing".to_string(), unset.clone())].into(),
+        };
+        let data = Struct {
+            fields: [
+                ("missing".to_string(), unset.clone()),
+                ("explicit".to_string(), null),
+                ("nested".to_string(), ProtoValue {
+                    kind: Some(Kind::StructValue(inner)),
+                }),
+                ("list".to_string(), ProtoValue {
+                    kind: Some(Kind::ListValue(ListValue { values: vec![unset] })),
+                }),
+            ]
+            .into(),
+        };
+
+        assert_eq!(
+            struct_to_json_within(data.clone(), 8, false),
+            Some(json!({
+                "missing": null,
+                "explicit": null,
+                "nested": { "missing": null },
+                "list": [null]
+            }))
+        );
+        assert_eq!(
+            struct_to_json_within(data, 8, true),
+            Some(json!({ "explicit": null, "nested": {}, "list": [null] }))
+        );
+    }
+
+    #[test]
+    fn test_canonical_json_ignores_key_order_and_number_form() {
+        let a: Value = serde_json::from_str(r#"{"b": [1, {"y": 2.0, "x": null}], "a": "é"}"#).unwrap();
+        let b: Value = serde_json::from_str(r#"{"a":"é","b":[1.0,{"x":null,"y":2}]}"#).unwrap();
+        assert_eq!(canonical_json(&a), r#"{"a":"é","b":[1,{"x":null,"y":2}]}"#);
+        assert_eq!(canonical_json(&a), canonical_json(&b));
+        assert_ne!(canonical_json(&json!([1, 2])), canonical_json(&json!([2, 1])));
+        assert_eq!(canonical_json(&json!(0.5)), "0.5");
+    }
+}
@@ -1,3 +1,4 @@
+use crate::generated::ErrorDetail;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -19,18 +20,455 @@ pub enum Error {
     
     #[error("Internal error: {0}")]
     Internal(String),
+
+    /// Fewer active agents offer a capability than an operation needs
+    #[error("Insufficient agents: {available} active with capability {capability}, need {required}")]
+    InsufficientAgents {
+        capability: String,
+        required: u32,
+        available: usize,
+    },
     
+    /// A completed execution was less confident than
+    /// `ExecuteOptions::min_confidence` requires
+    #[error("Confidence below threshold: got {got}, need at least {required}")]
+    ConfidenceBelowThreshold { got: f64, required: f64 },
+
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
     
+    /// A gRPC status without a more specific variant; see `From<tonic::Status>`
     #[error("gRPC error: {0}")]
-    Grpc(#[from] tonic::Status),
+    Grpc(tonic::Status),
     
+    /// Failure of the native HTTP/2 transport. In the browser, `fetch`
+    /// failures arrive as `Grpc` statuses instead.
+    #[cfg(feature = "native")]
     #[error("Transport error: {0}")]
     Transport(#[from] tonic::transport::Error),
     
     #[error(transparent)]
     Other(#[from] anyhow::Error),
+
+    /// An error without a message of its own, such as `Serialization`,
+    /// with key/value context attached; see [`Error::with_context`]
+    #[error("{source} [{}]", render_context(context))]
+    Context {
+        source: Box<Error>,
+        context: Vec<(String, String)>,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Error an agent's analyze function can return to report a failure in
+/// structured form
+///
+/// The agent server encodes it in the details of the call's status, as an
+/// `ErrorDetail` packed in the `google.rpc.Status` gRPC expects there, so
+/// clients can read it back with [`Error::agent_error`] instead of parsing
+/// the status message. Retryable errors are sent as `UNAVAILABLE`, others
+/// as `INTERNAL`.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{code}: {message}")]
+pub struct AgentError {
+    /// Agent-defined code, e.g. `"MODEL_OVERLOADED"`
+    pub code: String,
+    pub message: String,
+    /// Whether retrying the same request may succeed
+    pub retryable: bool,
+}
+
+impl AgentError {
+    /// A failure that retrying will not fix
+    pub fn new(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            code: code.into(),
+            message: message.into(),
+            retryable: false,
+        }
+    }
+
+    /// A transient failure, such as an overloaded model
+    pub fn retryable(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            retryable: true,
+            ..Self::new(code, message)
+        }
+    }
+
+    /// The error carried in a status's details, if it has one
+    pub fn from_status(status: &tonic::Status) -> Option<Self> {
+        let rpc_status: RpcStatus = prost::Message::decode(status.details()).ok()?;
+        let detail = rpc_status
+            .details
+            .iter()
+            .find(|detail| detail.type_url == ERROR_DETAIL_TYPE_URL)?;
+        let detail: ErrorDetail = prost::Message::decode(detail.value.as_slice()).ok()?;
+        Some(Self {
+            code: detail.code,
+            message: detail.message,
+            retryable: detail.retryable,
+        })
+    }
+
+    /// Status reporting this error, with the error encoded in its details
+    #[cfg(feature = "native")]
+    pub(crate) fn to_status(&self) -> tonic::Status {
+        let code = if self.retryable {
+            tonic::Code::Unavailable
+        } else {
+            tonic::Code::Internal
+        };
+        let message = format!("analysis failed: {}", self);
+        let detail = ErrorDetail {
+            code: self.code.clone(),
+            message: self.message.clone(),
+            retryable: self.retryable,
+        };
+        let details = RpcStatus {
+            code: code as i32,
+            message: message.clone(),
+            details: vec![prost_types::Any {
+                type_url: ERROR_DETAIL_TYPE_URL.to_string(),
+                value: prost::Message::encode_to_vec(&detail),
+            }],
+        };
+        tonic::Status::with_details(code, message, prost::Message::encode_to_vec(&details).into())
+    }
+}
+
+/// Type URL of an [`ErrorDetail`] packed in a `google.protobuf.Any`
+const ERROR_DETAIL_TYPE_URL: &str = "type.googleapis.com/parallax.confidence.ErrorDetail";
+
+/// `google.rpc.Status`, the message gRPC carries in a status's details, so
+/// generic gRPC tooling can unpack the [`ErrorDetail`] inside it
+#[derive(Clone, PartialEq, prost::Message)]
+struct RpcStatus {
+    #[prost(int32, tag = "1")]
+    code: i32,
+    #[prost(string, tag = "2")]
+    message: String,
+    #[prost(message, repeated, tag = "3")]
+    details: Vec<prost_types::Any>,
+}
+
+/// Stable classification of an [`Error`], independent of its message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    Connection,
+    Authentication,
+    NotFound,
+    InvalidArgument,
+    Timeout,
+    Internal,
+    Serialization,
+    Unavailable,
+    ResourceExhausted,
+    Cancelled,
+    AlreadyExists,
+    FailedPrecondition,
+    Aborted,
+    Unimplemented,
+    Unknown,
+}
+
+impl From<tonic::Status> for Error {
+    /// Statuses with a matching variant map onto it; everything else keeps
+    /// the full status in [`Error::Grpc`]
+    fn from(status: tonic::Status) -> Self {
+        let message = status.message().to_string();
+        match status.code() {
+            tonic::Code::NotFound => Error::NotFound(message),
+            tonic::Code::InvalidArgument => Error::InvalidArgument(message),
+            tonic::Code::DeadlineExceeded => Error::Timeout(message),
+            // tonic cancels calls locally once their deadline passes
+            tonic::Code::Cancelled if message == tonic::TimeoutExpired(()).to_string() => {
+                Error::Timeout(message)
+            }
+            tonic::Code::Unauthenticated | tonic::Code::PermissionDenied => {
+                Error::Authentication(message)
+            }
+            _ => Error::Grpc(status),
+        }
+    }
+}
+
+impl Error {
+    /// Attach a key/value pair describing where or why the error happened.
+    ///
+    /// Errors with a message, including `Grpc` statuses, keep their variant
+    /// and gain the pair at the end of the message as ` [key=value]`, so
+    /// matching on the variant still works; repeated calls extend the same
+    /// bracket. Other errors are wrapped in [`Error::Context`], whose
+    /// [`root_cause`](Self::root_cause) is the original error.
+    pub fn with_context(self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        let entry = (key.into(), value.into());
+        match self {
+            Error::Connection(message) => Error::Connection(append_context(message, &entry)),
+            Error::Authentication(message) => {
+                Error::Authentication(append_context(message, &entry))
+            }
+            Error::NotFound(message) => Error::NotFound(append_context(message, &entry)),
+            Error::InvalidArgument(message) => {
+                Error::InvalidArgument(append_context(message, &entry))
+            }
+            Error::Timeout(message) => Error::Timeout(append_context(message, &entry)),
+            Error::Internal(message) => Error::Internal(append_context(message, &entry)),
+            Error::Grpc(status) => Error::Grpc(tonic::Status::with_details_and_metadata(
+                status.code(),
+                append_context(status.message().to_string(), &entry),
+                status.details().to_vec().into(),
+                status.metadata().clone(),
+            )),
+            Error::Context { source, mut context } => {
+                context.push(entry);
+                Error::Context { source, context }
+            }
+            other => Error::Context {
+                source: Box::new(other),
+                context: vec![entry],
+            },
+        }
+    }
+
+    /// Key/value context wrapped around an error without a message; see
+    /// [`Error::with_context`]
+    pub fn context(&self) -> &[(String, String)] {
+        match self {
+            Error::Context { context, .. } => context,
+            _ => &[],
+        }
+    }
+
+    /// Stable code for this error, looking through any attached context
+    pub fn code(&self) -> ErrorCode {
+        match self.root_cause() {
+            Error::Connection(_) => ErrorCode::Connection,
+            #[cfg(feature = "native")]
+            Error::Transport(_) => ErrorCode::Connection,
+            Error::Authentication(_) => ErrorCode::Authentication,
+            Error::NotFound(_) => ErrorCode::NotFound,
+            Error::InvalidArgument(_) => ErrorCode::InvalidArgument,
+            Error::Timeout(_) => ErrorCode::Timeout,
+            Error::Internal(_) => ErrorCode::Internal,
+            Error::InsufficientAgents { .. } | Error::ConfidenceBelowThreshold { .. } => {
+                ErrorCode::FailedPrecondition
+            }
+            Error::Serialization(_) => ErrorCode::Serialization,
+            Error::Grpc(status) => match status.code() {
+                tonic::Code::NotFound => ErrorCode::NotFound,
+                tonic::Code::InvalidArgument | tonic::Code::OutOfRange => {
+                    ErrorCode::InvalidArgument
+                }
+                tonic::Code::DeadlineExceeded => ErrorCode::Timeout,
+                tonic::Code::Unauthenticated | tonic::Code::PermissionDenied => {
+                    ErrorCode::Authentication
+                }
+                tonic::Code::Unavailable => ErrorCode::Unavailable,
+                tonic::Code::ResourceExhausted => ErrorCode::ResourceExhausted,
+                tonic::Code::Cancelled => ErrorCode::Cancelled,
+                tonic::Code::AlreadyExists => ErrorCode::AlreadyExists,
+                tonic::Code::FailedPrecondition => ErrorCode::FailedPrecondition,
+                tonic::Code::Aborted => ErrorCode::Aborted,
+                tonic::Code::Unimplemented => ErrorCode::Unimplemented,
+                tonic::Code::Internal | tonic::Code::DataLoss => ErrorCode::Internal,
+                tonic::Code::Ok | tonic::Code::Unknown => ErrorCode::Unknown,
+            },
+            Error::Other(_) | Error::Context { .. } => ErrorCode::Unknown,
+        }
+    }
+
+    /// The structured error an agent failed with, if the status carries one
+    pub fn agent_error(&self) -> Option<AgentError> {
+        match self.root_cause() {
+            Error::Grpc(status) => AgentError::from_status(status),
+            _ => None,
+        }
+    }
+
+    /// Whether retrying the same operation may succeed: transport failures,
+    /// timeouts, unavailable servers and exhausted resources. An
+    /// [`AgentError`] in the status decides on its own.
+    pub fn is_retryable(&self) -> bool {
+        if let Some(agent_error) = self.agent_error() {
+            return agent_error.retryable;
+        }
+        matches!(
+            self.code(),
+            ErrorCode::Connection
+                | ErrorCode::Timeout
+                | ErrorCode::Unavailable
+                | ErrorCode::ResourceExhausted
+        )
+    }
+
+    /// The underlying error, with any attached context stripped
+    pub fn root_cause(&self) -> &Error {
+        match self {
+            Error::Context { source, .. } => source.root_cause(),
+            other => other,
+        }
+    }
+}
+
+/// Attach context to the error side of a `Result`
+pub trait ResultExt<T> {
+    fn with_context(self, key: impl Into<String>, value: impl Into<String>) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> ResultExt<T> for std::result::Result<T, E> {
+    fn with_context(self, key: impl Into<String>, value: impl Into<String>) -> Result<T> {
+        self.map_err(|error| error.into().with_context(key, value))
+    }
+}
+
+/// Error mapper for service RPCs, tagging the method and endpoint
+pub(crate) fn rpc_error<'a, E: Into<Error>>(
+    method: &'static str,
+    endpoint: &'a str,
+) -> impl FnOnce(E) -> Error + 'a {
+    move |error| {
+        error
+            .into()
+            .with_context("method", method)
+            .with_context("endpoint", endpoint)
+    }
+}
+
+/// Add `key=value` to the context bracket ending `message`, opening one if
+/// there is none
+fn append_context(mut message: String, (key, value): &(String, String)) -> String {
+    if message.ends_with(']') && message.contains(" [") {
+        message.pop();
+        message.push_str(&format!(", {}={}]", key, value));
+    } else {
+        message.push_str(&format!(" [{}={}]", key, value));
+    }
+    message
 }
 
-pub type Result<T> = std::result::Result<T, Error>;
\ No newline at end of file
+fn render_context(context: &[(String, String)]) -> String {
+    context
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_renders_context_chain() {
+        let error = Error::NotFound("Pattern not found: consensus".to_string())
+            .with_context("method", "PatternService::get")
+            .with_context("name", "consensus");
+
+        assert_eq!(
+            error.to_string(),
+            "Not found: Pattern not found: consensus [method=PatternService::get, name=consensus]"
+        );
+        assert!(matches!(error, Error::NotFound(_)));
+
+        let error = Error::from(serde_json::from_str::<u32>("x").unwrap_err())
+            .with_context("execution_id", "exec-1")
+            .with_context("pattern", "consensus");
+        assert!(error.to_string().ends_with(" [execution_id=exec-1, pattern=consensus]"));
+        assert_eq!(error.context().len(), 2);
+        assert!(matches!(error.root_cause(), Error::Serialization(_)));
+    }
+
+    #[test]
+    fn test_result_ext_keeps_the_status() {
+        let status = AgentError::retryable("MODEL_OVERLOADED", "model is at capacity").to_status();
+        let result: std::result::Result<(), tonic::Status> = Err(status);
+        let error = result.with_context("endpoint", "http://localhost:8080").unwrap_err();
+
+        match &error {
+            Error::Grpc(status) => {
+                assert_eq!(status.code(), tonic::Code::Unavailable);
+                assert!(status.message().ends_with(" [endpoint=http://localhost:8080]"));
+            }
+            other => panic!("expected Grpc, got {:?}", other),
+        }
+        assert_eq!(error.agent_error().unwrap().code, "MODEL_OVERLOADED");
+    }
+
+    #[test]
+    fn test_status_conversion_classifies_codes() {
+        let cases = [
+            (tonic::Status::unavailable("down"), ErrorCode::Unavailable, true),
+            (tonic::Status::deadline_exceeded("slow"), ErrorCode::Timeout, true),
+            (tonic::Status::resource_exhausted("busy"), ErrorCode::ResourceExhausted, true),
+            (tonic::Status::not_found("missing"), ErrorCode::NotFound, false),
+            (tonic::Status::invalid_argument("bad"), ErrorCode::InvalidArgument, false),
+            (tonic::Status::permission_denied("nope"), ErrorCode::Authentication, false),
+            (tonic::Status::unimplemented("later"), ErrorCode::Unimplemented, false),
+            (tonic::Status::internal("boom"), ErrorCode::Internal, false),
+        ];
+
+        for (status, code, retryable) in cases {
+            let error = Error::from(status);
+            assert_eq!(error.code(), code, "{}", error);
+            assert_eq!(error.is_retryable(), retryable, "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_agent_error_round_trips_through_status() {
+        let agent_error = AgentError::retryable("MODEL_OVERLOADED", "model is at capacity");
+        let status = agent_error.to_status();
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+        assert_eq!(AgentError::from_status(&status), Some(agent_error));
+
+        // The details are a google.rpc.Status wrapping the detail in an Any
+        let details: RpcStatus = prost::Message::decode(status.details()).unwrap();
+        assert_eq!(details.code, tonic::Code::Unavailable as i32);
+        assert_eq!(details.message, status.message());
+        assert_eq!(details.details[0].type_url, ERROR_DETAIL_TYPE_URL);
+
+        // Plain statuses carry no agent error
+        assert_eq!(AgentError::from_status(&tonic::Status::internal("boom")), None);
+        assert_eq!(Error::from(tonic::Status::internal("boom")).agent_error(), None);
+
+        // The detail decides retryability, whatever the status code says
+        let status = AgentError::new("QUOTA_SPENT", "monthly quota used up").to_status();
+        let status = tonic::Status::with_details(
+            tonic::Code::Unavailable,
+            "analysis failed",
+            status.details().to_vec().into(),
+        );
+        let error = Error::from(status).with_context("method", "analyze");
+        assert!(!error.is_retryable());
+        assert_eq!(error.agent_error().unwrap().code, "QUOTA_SPENT");
+    }
+
+    #[test]
+    fn test_status_conversion_uses_specific_variants() {
+        let error = Error::from(tonic::Status::not_found("Pattern not found"));
+        assert!(matches!(&error, Error::NotFound(message) if message == "Pattern not found"));
+
+        let error = Error::from(tonic::Status::deadline_exceeded("slow"));
+        assert!(matches!(error, Error::Timeout(_)));
+        let error = Error::from(tonic::Status::cancelled("Timeout expired"));
+        assert!(matches!(error, Error::Timeout(_)));
+        let error = Error::from(tonic::Status::cancelled("client went away"));
+        assert!(matches!(error, Error::Grpc(_)));
+
+        // Codes without a dedicated variant keep the original status
+        let error = Error::from(tonic::Status::unavailable("down"));
+        assert!(matches!(error, Error::Grpc(_)));
+    }
+
+    #[test]
+    fn test_code_looks_through_context() {
+        let error = Error::from(tonic::Status::unavailable("down"))
+            .with_context("method", "PatternService::list");
+
+        assert_eq!(error.code(), ErrorCode::Unavailable);
+        assert!(error.is_retryable());
+        assert!(!Error::InvalidArgument("bad".to_string()).is_retryable());
+    }
+}
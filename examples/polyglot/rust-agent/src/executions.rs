@@ -1,42 +1,151 @@
 use crate::{
-    error::Result,
+    circuit_breaker::{self, CircuitBreaker},
+    client::{InterceptedChannel, InterceptorChain},
+    clock::{self, calculate_reconnect_delay, Clock, SystemClock},
+    conversions::{canonical_json, JsonLimits, NonFinitePolicy},
+    error::{rpc_error, Error, ErrorCode, Result, ResultExt},
     generated::parallax::executions::{
         execution_service_client::ExecutionServiceClient, Execution, ExecutionStatus,
-        GetExecutionRequest, ListExecutionsRequest, StreamExecutionRequest,
+        ExportExecutionsRequest, GetExecutionRequest, ListExecutionsRequest, StreamExecutionRequest,
+        StreamExecutionResponse,
+    },
+    transport::Transport,
+    types::{
+        AgentContribution, ExecutionEvent, ExecutionEventKind, ExecutionFilter,
+        ExecutionStatus as LocalStatus, Metadata, Page, PatternExecution,
     },
-    types::{ExecutionEvent, ExecutionStatus as LocalStatus, PatternExecution},
 };
-use futures::{Stream, StreamExt};
-use prost_types::{value::Kind, Struct, Value as ProtoValue};
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt, TryStreamExt};
 use serde_json::Value;
-use std::pin::Pin;
-use tonic::transport::Channel;
-use tracing::debug;
+use std::{pin::Pin, sync::Arc, time::Duration};
+use tonic::{codec::CompressionEncoding, Code, Streaming};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+/// Reconnect attempts made by `stream_with_resume` before giving up
+const MAX_RESUME_ATTEMPTS: u32 = 5;
+/// Initial delay before a resume attempt in milliseconds
+const INITIAL_RESUME_DELAY_MS: u64 = 250;
+/// Maximum delay between resume attempts in milliseconds
+const MAX_RESUME_DELAY_MS: u64 = 5000;
+/// Page size used by `list_all` when walking the full execution history
+const LIST_ALL_PAGE_SIZE: i32 = 100;
 
 /// Service for execution operations
 #[derive(Clone)]
 pub struct ExecutionService {
-    channel: Channel,
+    channel: Transport,
+    endpoint: String,
+    compression: Option<CompressionEncoding>,
+    interceptors: InterceptorChain,
+    closing: CancellationToken,
+    clock: Arc<dyn Clock>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    result_limits: JsonLimits,
 }
 
 impl ExecutionService {
-    pub(crate) fn new(channel: Channel) -> Self {
-        Self { channel }
+    pub(crate) fn new(channel: Transport, endpoint: String) -> Self {
+        Self {
+            channel,
+            endpoint,
+            compression: None,
+            interceptors: InterceptorChain::default(),
+            closing: CancellationToken::new(),
+            clock: Arc::new(SystemClock),
+            circuit_breaker: None,
+            result_limits: JsonLimits::default(),
+        }
+    }
+
+    /// Reject executions whose input, result, metrics or event data nest
+    /// structs and lists more than `max_depth` levels deep, or hold more
+    /// than `max_nodes` values in one field (defaults: 64 and 1,000,000).
+    /// Such executions fail with `Error::Internal` instead of being decoded.
+    pub fn with_result_limits(mut self, max_depth: usize, max_nodes: usize) -> Self {
+        self.result_limits = JsonLimits {
+            max_depth,
+            max_nodes,
+            ..self.result_limits
+        };
+        self
+    }
+
+    /// How NaN and infinite numbers in execution input, results, metrics and
+    /// event data are decoded. The default, [`NonFinitePolicy::NullOnNonFinite`], turns
+    /// them into `null`; [`NonFinitePolicy::ErrorOnNonFinite`] fails the call instead,
+    /// so bad data is noticed.
+    pub fn with_non_finite_policy(mut self, policy: NonFinitePolicy) -> Self {
+        self.result_limits.non_finite = policy;
+        self
+    }
+
+    /// Compress requests and accept compressed responses with `compression`
+    pub(crate) fn with_compression(mut self, compression: Option<CompressionEncoding>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Run every request through `interceptors`
+    pub(crate) fn with_interceptors(mut self, interceptors: InterceptorChain) -> Self {
+        self.interceptors = interceptors;
+        self
+    }
+
+    /// Fail calls with `client closed` once `closing` is cancelled
+    pub(crate) fn with_closing(mut self, closing: CancellationToken) -> Self {
+        self.closing = closing;
+        self
+    }
+
+    /// Drive timeouts and reconnect backoff from `clock`
+    pub(crate) fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Fail calls fast while `circuit_breaker` is open
+    pub(crate) fn with_circuit_breaker(
+        mut self,
+        circuit_breaker: Option<Arc<CircuitBreaker>>,
+    ) -> Self {
+        self.circuit_breaker = circuit_breaker;
+        self
+    }
+
+    fn client(&self) -> Result<ExecutionServiceClient<InterceptedChannel>> {
+        if self.closing.is_cancelled() {
+            return Err(Error::Connection("client closed".to_string()));
+        }
+        let channel = circuit_breaker::guard(
+            self.channel.clone(),
+            self.circuit_breaker.as_ref(),
+            &self.clock,
+        )?;
+        let client = ExecutionServiceClient::with_interceptor(channel, self.interceptors.clone());
+        Ok(match self.compression {
+            Some(encoding) => client.send_compressed(encoding).accept_compressed(encoding),
+            None => client,
+        })
     }
 
     /// Get a specific execution by id
     pub async fn get(&self, execution_id: &str) -> Result<PatternExecution> {
         debug!("Getting execution: {}", execution_id);
 
-        let mut client = ExecutionServiceClient::new(self.channel.clone());
+        let mut client = self.client()?;
         let response = client
             .get_execution(GetExecutionRequest {
                 execution_id: execution_id.to_string(),
             })
-            .await?
+            .await
+            .map_err(rpc_error("ExecutionService::get", &self.endpoint))
+            .with_context("execution_id", execution_id)?
             .into_inner();
 
-        Ok(execution_from_proto_opt(response.execution))
+        execution_from_proto(response.execution.unwrap_or_default(), self.result_limits)
+            .with_context("execution_id", execution_id)
     }
 
     /// List executions
@@ -48,21 +157,176 @@ impl ExecutionService {
     ) -> Result<Vec<PatternExecution>> {
         debug!("Listing executions");
 
-        let mut client = ExecutionServiceClient::new(self.channel.clone());
-        let response = client
-            .list_executions(ListExecutionsRequest {
+        let (executions, _) = self
+            .fetch_page(ListExecutionsRequest {
                 limit,
                 offset,
                 status: status.unwrap_or_default(),
+                ..Default::default()
+            })
+            .await?;
+        Ok(executions)
+    }
+
+    /// List executions matching a filter
+    pub async fn list_filtered(
+        &self,
+        limit: i32,
+        offset: i32,
+        filter: &ExecutionFilter,
+    ) -> Result<Vec<PatternExecution>> {
+        debug!("Listing executions");
+
+        let (executions, _) = self
+            .fetch_page(ListExecutionsRequest {
+                offset,
+                ..list_request(limit, filter)?
             })
-            .await?
+            .await?;
+        Ok(executions)
+    }
+
+    /// Fetch one page, returning its executions and the server's cursor for
+    /// the next one
+    async fn fetch_page(
+        &self,
+        request: ListExecutionsRequest,
+    ) -> Result<(Vec<PatternExecution>, Option<String>)> {
+        let (limit, offset) = (request.limit, request.offset);
+
+        let mut client = self.client()?;
+        let response = client
+            .list_executions(request)
+            .await
+            .map_err(rpc_error("ExecutionService::list", &self.endpoint))
+            .with_context("limit", limit.to_string())
+            .with_context("offset", offset.to_string())?
             .into_inner();
 
-        Ok(response
+        let executions = response
             .executions
             .into_iter()
-            .map(execution_from_proto)
-            .collect())
+            .map(|execution| execution_from_proto(execution, self.result_limits))
+            .collect::<Result<_>>()?;
+        let next_cursor = (!response.next_cursor.is_empty()).then_some(response.next_cursor);
+        Ok((executions, next_cursor))
+    }
+
+    /// List one page of executions starting at `cursor` (or the beginning).
+    ///
+    /// Cursors are issued by the control plane and opaque to the client:
+    /// pass back the `next_cursor` of the previous page. The last page has
+    /// no `next_cursor`.
+    pub async fn list_paginated(
+        &self,
+        limit: i32,
+        cursor: Option<&str>,
+        filter: &ExecutionFilter,
+    ) -> Result<Page<PatternExecution>> {
+        let (items, next_cursor) = self
+            .fetch_page(ListExecutionsRequest {
+                cursor: cursor.unwrap_or_default().to_string(),
+                ..list_request(limit, filter)?
+            })
+            .await?;
+        Ok(Page { items, next_cursor })
+    }
+
+    /// Stream every execution matching `filter`, following page cursors
+    /// lazily so only one page is held in memory at a time
+    pub fn list_all(
+        &self,
+        filter: ExecutionFilter,
+    ) -> Pin<Box<dyn Stream<Item = Result<PatternExecution>> + Send>> {
+        self.list_all_with_page_size(filter, LIST_ALL_PAGE_SIZE)
+    }
+
+    fn list_all_with_page_size(
+        &self,
+        filter: ExecutionFilter,
+        page_size: i32,
+    ) -> Pin<Box<dyn Stream<Item = Result<PatternExecution>> + Send>> {
+        let service = self.clone();
+
+        // State: (cursor of the next page, whether another page exists)
+        let pages = futures::stream::unfold((None::<String>, true), move |(cursor, more)| {
+            let service = service.clone();
+            let filter = filter.clone();
+            async move {
+                if !more {
+                    return None;
+                }
+                match service
+                    .list_paginated(page_size, cursor.as_deref(), &filter)
+                    .await
+                {
+                    Ok(page) => {
+                        let more = page.next_cursor.is_some();
+                        let items: Vec<Result<PatternExecution>> =
+                            page.items.into_iter().map(Ok).collect();
+                        Some((futures::stream::iter(items), (page.next_cursor, more)))
+                    }
+                    Err(error) => Some((futures::stream::iter(vec![Err(error)]), (None, false))),
+                }
+            }
+        });
+
+        Box::pin(pages.flatten())
+    }
+
+    /// Export every execution matching `filter`
+    ///
+    /// Unlike [`list_all`](Self::list_all), which re-reads offset pages, this
+    /// runs a single server-streaming call with the filter applied on the
+    /// server. Executions are decoded one at a time as the stream is polled,
+    /// and HTTP/2 flow control stops the server from sending further ahead
+    /// than the transport's window, so memory use does not grow with the
+    /// size of the export.
+    ///
+    /// Control planes without the export RPC answer `Unimplemented`; the
+    /// export then falls back to [`list_all`](Self::list_all) with the same
+    /// filter.
+    pub fn export(
+        &self,
+        filter: ExecutionFilter,
+    ) -> Pin<Box<dyn Stream<Item = Result<PatternExecution>> + Send>> {
+        let service = self.clone();
+        let opened = futures::stream::once(async move {
+            let inbound = match service.open_export(&filter).await {
+                Ok(inbound) => inbound,
+                Err(error) if error.code() == ErrorCode::Unimplemented => {
+                    debug!("ExportExecutions is unimplemented, listing page by page");
+                    return Ok(service.list_all(filter));
+                }
+                Err(error) => return Err(error),
+            };
+            let executions = inbound.map(move |message| match message {
+                Ok(execution) => execution_from_proto(execution, service.result_limits),
+                Err(status) => {
+                    Err(rpc_error("ExecutionService::export", &service.endpoint)(status))
+                }
+            });
+            Ok::<_, Error>(Box::pin(executions) as Pin<Box<dyn Stream<Item = _> + Send>>)
+        });
+
+        Box::pin(opened.try_flatten())
+    }
+
+    async fn open_export(&self, filter: &ExecutionFilter) -> Result<Streaming<Execution>> {
+        filter.validate()?;
+        let request = ExportExecutionsRequest {
+            status: wire_status(filter.status),
+            pattern_name: filter.pattern.clone().unwrap_or_default(),
+            started_after: filter.started_after.map(datetime_to_timestamp),
+            started_before: filter.started_before.map(datetime_to_timestamp),
+        };
+
+        let mut client = self.client()?;
+        Ok(client
+            .export_executions(request)
+            .await
+            .map_err(rpc_error("ExecutionService::export", &self.endpoint))?
+            .into_inner())
     }
 
     /// Stream execution updates
@@ -72,17 +336,22 @@ impl ExecutionService {
     ) -> Result<Pin<Box<dyn Stream<Item = Result<PatternExecution>> + Send>>> {
         debug!("Streaming execution: {}", execution_id);
 
-        let mut client = ExecutionServiceClient::new(self.channel.clone());
+        let mut client = self.client()?;
         let stream = client
             .stream_execution(StreamExecutionRequest {
                 execution_id: execution_id.to_string(),
             })
-            .await?
+            .await
+            .map_err(rpc_error("ExecutionService::stream", &self.endpoint))
+            .with_context("execution_id", execution_id)?
             .into_inner();
 
-        let mapped = stream.filter_map(|event| async move {
+        let limits = self.result_limits;
+        let mapped = stream.filter_map(move |event| async move {
             match event {
-                Ok(event) => event.execution.map(|execution| Ok(execution_from_proto(execution))),
+                Ok(event) => event
+                    .execution
+                    .map(|execution| execution_from_proto(execution, limits)),
                 Err(error) => Some(Err(error.into())),
             }
         });
@@ -97,28 +366,333 @@ impl ExecutionService {
     ) -> Result<Pin<Box<dyn Stream<Item = Result<ExecutionEvent>> + Send>>> {
         debug!("Streaming execution events: {}", execution_id);
 
-        let mut client = ExecutionServiceClient::new(self.channel.clone());
+        let mut client = self.client()?;
         let stream = client
             .stream_execution(StreamExecutionRequest {
                 execution_id: execution_id.to_string(),
             })
-            .await?
+            .await
+            .map_err(rpc_error("ExecutionService::stream_events", &self.endpoint))
+            .with_context("execution_id", execution_id)?
             .into_inner();
 
-        let mapped = stream.map(|event| match event {
-            Ok(event) => Ok(event_from_proto(event)),
+        let limits = self.result_limits;
+        let mapped = stream.map(move |event| match event {
+            Ok(event) => event_from_proto(event, limits),
             Err(error) => Err(error.into()),
         });
 
         Ok(Box::pin(mapped))
     }
+
+    /// Stream execution events, transparently reconnecting when the stream
+    /// drops before the execution reaches a terminal state.
+    ///
+    /// After a reconnect, events the server replays are skipped so callers
+    /// see each event once: anything older than the last delivered
+    /// `event_time` is dropped, as are events at that same time matching
+    /// (type, status and data) one already delivered. Skipping stops at the
+    /// first event not delivered before. Events older than `resume_from` are
+    /// dropped the same way on the first connect. Nothing is skipped on a
+    /// connection that hasn't dropped, so distinct events sharing a
+    /// timestamp all come through. The stream ends after the first terminal
+    /// event.
+    pub async fn stream_with_resume(
+        &self,
+        execution_id: &str,
+        resume_from: Option<DateTime<Utc>>,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ExecutionEvent>> + Send>>> {
+        debug!("Streaming execution events with resume: {}", execution_id);
+
+        let state = ResumeState {
+            client: Some(self.client()?),
+            service: self.clone(),
+            clock: self.clock.clone(),
+            limits: self.result_limits,
+            execution_id: execution_id.to_string(),
+            inbound: None,
+            last_seen: resume_from,
+            seen_at_last: Vec::new(),
+            replay: resume_from.map(|_| Vec::new()),
+            attempt: 0,
+            done: false,
+        };
+
+        Ok(Box::pin(futures::stream::unfold(state, ResumeState::next_event)))
+    }
+
+    /// Wait until an execution reaches a terminal status and return it.
+    ///
+    /// Resolves immediately if the execution has already finished; otherwise
+    /// follows the execution stream. Returns `Error::Timeout` if `timeout`
+    /// elapses first.
+    pub async fn wait_for_completion(
+        &self,
+        execution_id: &str,
+        timeout: Option<Duration>,
+    ) -> Result<PatternExecution> {
+        debug!("Waiting for execution: {}", execution_id);
+
+        let wait = self.follow_until_terminal(execution_id);
+        match timeout {
+            Some(limit) => clock::timeout(&*self.clock, limit, wait).await.ok_or_else(|| {
+                Error::Timeout(format!(
+                    "execution {} did not finish within {}ms",
+                    execution_id,
+                    limit.as_millis()
+                ))
+            })?,
+            None => wait.await,
+        }
+    }
+
+    async fn follow_until_terminal(&self, execution_id: &str) -> Result<PatternExecution> {
+        let current = self.get(execution_id).await?;
+        if current.status.is_terminal() {
+            return Ok(current);
+        }
+
+        let mut updates = self.stream(execution_id).await?;
+        while let Some(update) = updates.next().await {
+            let execution = update?;
+            if execution.status.is_terminal() {
+                return Ok(execution);
+            }
+        }
+
+        // The stream can close right as the execution finishes
+        let current = self.get(execution_id).await?;
+        if current.status.is_terminal() {
+            return Ok(current);
+        }
+
+        Err(Error::Connection(format!(
+            "execution stream for {} ended before completion",
+            execution_id
+        )))
+    }
+}
+
+/// Connection state behind `ExecutionService::stream_with_resume`
+struct ResumeState {
+    // Client for the first connect; reconnects ask the service for a fresh
+    // one, so closing the client or an open circuit stops the resuming
+    client: Option<ExecutionServiceClient<InterceptedChannel>>,
+    service: ExecutionService,
+    clock: Arc<dyn Clock>,
+    limits: JsonLimits,
+    execution_id: String,
+    inbound: Option<Streaming<StreamExecutionResponse>>,
+    last_seen: Option<DateTime<Utc>>,
+    // Events delivered at exactly `last_seen`; timestamps are only
+    // second-granular, so time alone can't tell replays from new events
+    seen_at_last: Vec<EventFingerprint>,
+    // While skipping a replay after a reconnect, the events at `last_seen`
+    // not yet matched by a replayed one
+    replay: Option<Vec<EventFingerprint>>,
+    attempt: u32,
+    done: bool,
+}
+
+impl ResumeState {
+    async fn next_event(mut self) -> Option<(Result<ExecutionEvent>, Self)> {
+        loop {
+            if self.done {
+                return None;
+            }
+
+            let inbound = match self.inbound.as_mut() {
+                Some(inbound) => inbound,
+                None => {
+                    let request = StreamExecutionRequest {
+                        execution_id: self.execution_id.clone(),
+                    };
+                    let client = match self.client.take() {
+                        Some(client) => Ok(client),
+                        None => self.service.client(),
+                    };
+                    let mut client = match client {
+                        Ok(client) => client,
+                        Err(error) => {
+                            self.done = true;
+                            return Some((Err(error), self));
+                        }
+                    };
+                    match client.stream_execution(request).await {
+                        Ok(response) => self.inbound.insert(response.into_inner()),
+                        Err(status) => {
+                            if self.backoff(&status).await {
+                                continue;
+                            }
+                            return Some((Err(status.into()), self));
+                        }
+                    }
+                }
+            };
+
+            match inbound.message().await {
+                Ok(Some(message)) => {
+                    self.attempt = 0;
+                    let event = match event_from_proto(message, self.limits) {
+                        Ok(event) => event,
+                        Err(error) => return Some((Err(error), self)),
+                    };
+
+                    if let Some(event_time) = event.event_time {
+                        let fingerprint = EventFingerprint::of(&event);
+                        if let Some(replay) = self.replay.as_mut() {
+                            match self.last_seen {
+                                Some(seen) if event_time < seen => continue,
+                                Some(seen) if event_time == seen => {
+                                    if let Some(index) =
+                                        replay.iter().position(|seen| *seen == fingerprint)
+                                    {
+                                        replay.remove(index);
+                                        continue;
+                                    }
+                                }
+                                _ => {}
+                            }
+                            // The first event not delivered before ends the replay
+                            self.replay = None;
+                        }
+                        match self.last_seen {
+                            Some(seen) if event_time < seen => {}
+                            Some(seen) if event_time == seen => self.seen_at_last.push(fingerprint),
+                            _ => {
+                                self.last_seen = Some(event_time);
+                                self.seen_at_last = vec![fingerprint];
+                            }
+                        }
+                    }
+
+                    self.done = is_terminal_event(&event);
+                    return Some((Ok(event), self));
+                }
+                Ok(None) => {
+                    // Server closed the stream before a terminal event
+                    let status = tonic::Status::unavailable("execution stream closed");
+                    if !self.backoff(&status).await {
+                        return Some((Err(status.into()), self));
+                    }
+                }
+                Err(status) => {
+                    if !self.backoff(&status).await {
+                        return Some((Err(status.into()), self));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Drop the current stream and wait before reconnecting. Returns false
+    /// (and marks the stream done) when the error is not worth retrying.
+    async fn backoff(&mut self, status: &tonic::Status) -> bool {
+        self.inbound = None;
+
+        let transient = matches!(
+            status.code(),
+            Code::Unavailable | Code::Unknown | Code::Internal | Code::Aborted
+        );
+        if !transient || self.attempt >= MAX_RESUME_ATTEMPTS {
+            self.done = true;
+            return false;
+        }
+
+        let delay =
+            calculate_reconnect_delay(self.attempt, INITIAL_RESUME_DELAY_MS, MAX_RESUME_DELAY_MS);
+        self.attempt += 1;
+        // A replay already in progress still owes the same events
+        if self.replay.is_none() {
+            self.replay = Some(self.seen_at_last.clone());
+        }
+        warn!(
+            execution_id = %self.execution_id,
+            attempt = self.attempt,
+            delay_ms = delay,
+            "Execution stream dropped, resuming: {}",
+            status.message()
+        );
+        self.clock.sleep(Duration::from_millis(delay)).await;
+        true
+    }
+}
+
+/// What tells a replayed event from a new one sharing its timestamp
+#[derive(Debug, Clone, PartialEq)]
+struct EventFingerprint {
+    event_type: String,
+    status: Option<LocalStatus>,
+    event_data: Option<String>,
 }
 
-fn execution_from_proto_opt(execution: Option<Execution>) -> PatternExecution {
-    execution_from_proto(execution.unwrap_or_default())
+impl EventFingerprint {
+    fn of(event: &ExecutionEvent) -> Self {
+        Self {
+            event_type: event.event_type.clone(),
+            status: event.execution.as_ref().map(|execution| execution.status),
+            event_data: event.event_data.as_ref().map(canonical_json),
+        }
+    }
 }
 
-fn execution_from_proto(execution: Execution) -> PatternExecution {
+/// Filters over a stream of execution events, such as the one
+/// [`ExecutionService::stream_events`] returns
+///
+/// Each filter is lazy and returns an event stream, so they chain in any
+/// order. Errors pass through `only_kind` and `since`, and so does the
+/// terminal event through `only_kind`, so that
+/// `only_kind(ExecutionEventKind::Progress).until_terminal()` still ends.
+pub trait ExecutionEventStreamExt: Stream<Item = Result<ExecutionEvent>> + Send + Sized {
+    /// Only events of `kind`, plus the terminal event that ends the execution
+    fn only_kind(
+        self,
+        kind: ExecutionEventKind,
+    ) -> impl Stream<Item = Result<ExecutionEvent>> + Send {
+        self.try_filter(move |event| {
+            futures::future::ready(event.kind() == kind || is_terminal_event(event))
+        })
+    }
+
+    /// Only events at or after `time`; events without an `event_time` are
+    /// dropped
+    fn since(self, time: DateTime<Utc>) -> impl Stream<Item = Result<ExecutionEvent>> + Send {
+        self.try_filter(move |event| {
+            futures::future::ready(event.event_time.is_some_and(|at| at >= time))
+        })
+    }
+
+    /// End the stream after the first `Completed`, `Failed` or `Cancelled`
+    /// event, or error, without polling the events that follow
+    fn until_terminal(self) -> impl Stream<Item = Result<ExecutionEvent>> + Send {
+        until_terminal(self)
+    }
+}
+
+impl<S> ExecutionEventStreamExt for S where S: Stream<Item = Result<ExecutionEvent>> + Send {}
+
+/// End `events` after the first terminal event or error
+pub(crate) fn until_terminal<S>(events: S) -> impl Stream<Item = Result<ExecutionEvent>> + Send
+where
+    S: Stream<Item = Result<ExecutionEvent>> + Send,
+{
+    futures::stream::unfold(Some(Box::pin(events)), |events| async move {
+        let mut events = events?;
+        let event = events.next().await?;
+        let finished = event.as_ref().map_or(true, is_terminal_event);
+        Some((event, (!finished).then_some(events)))
+    })
+}
+
+fn is_terminal_event(event: &ExecutionEvent) -> bool {
+    event.kind().is_terminal()
+        || event
+            .execution
+            .as_ref()
+            .is_some_and(|execution| execution.status.is_terminal())
+}
+
+fn execution_from_proto(execution: Execution, limits: JsonLimits) -> Result<PatternExecution> {
     let start_time = execution
         .start_time
         .map(timestamp_to_datetime)
@@ -127,13 +701,28 @@ fn execution_from_proto(execution: Execution) -> PatternExecution {
     let duration_ms = end_time
         .map(|end| (end - start_time).num_milliseconds().max(0) as u64);
 
-    PatternExecution {
+    let convert = |value| limits.struct_to_json(value);
+    Ok(PatternExecution {
         id: execution.id,
         pattern: execution.pattern_name,
         status: status_from_proto(execution.status),
-        input: execution.input.map(struct_to_json).unwrap_or(Value::Null),
-        output: execution.result.map(struct_to_json),
-        agents: Vec::new(),
+        input: execution.input.map(convert).transpose()?.unwrap_or(Value::Null),
+        output: execution.result.map(convert).transpose()?,
+        agents: execution
+            .agents
+            .iter()
+            .map(|agent| agent.agent_id.clone())
+            .collect(),
+        agent_contributions: execution
+            .agents
+            .into_iter()
+            .filter_map(|agent| {
+                Some(AgentContribution {
+                    confidence: agent.confidence?,
+                    agent_id: agent.agent_id,
+                })
+            })
+            .collect(),
         start_time,
         end_time,
         duration_ms,
@@ -143,27 +732,30 @@ fn execution_from_proto(execution: Execution) -> PatternExecution {
         } else {
             Some(execution.error)
         },
-        metadata: execution
-            .metrics
-            .map(struct_to_json)
-            .unwrap_or(Value::Null)
-            .as_object()
-            .map(|map| map.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
-            .unwrap_or_default(),
-    }
+        metadata: match execution.metrics.map(convert).transpose()? {
+            Some(Value::Object(metrics)) => metrics.into_iter().collect(),
+            _ => Metadata::new(),
+        },
+    })
 }
 
-fn event_from_proto(event: crate::generated::parallax::executions::StreamExecutionResponse) -> ExecutionEvent {
-    let execution = event.execution.map(execution_from_proto);
+fn event_from_proto(event: StreamExecutionResponse, limits: JsonLimits) -> Result<ExecutionEvent> {
+    let execution = event
+        .execution
+        .map(|execution| execution_from_proto(execution, limits))
+        .transpose()?;
     let event_time = event.event_time.map(timestamp_to_datetime);
-    let event_data = event.event_data.map(struct_to_json);
+    let event_data = event
+        .event_data
+        .map(|data| limits.struct_to_json(data))
+        .transpose()?;
 
-    ExecutionEvent {
+    Ok(ExecutionEvent {
         event_type: event.event_type,
         execution,
         event_time,
         event_data,
-    }
+    })
 }
 
 fn status_from_proto(status: i32) -> LocalStatus {
@@ -171,40 +763,37 @@ fn status_from_proto(status: i32) -> LocalStatus {
         ExecutionStatus::Completed => LocalStatus::Completed,
         ExecutionStatus::Failed => LocalStatus::Failed,
         ExecutionStatus::Running => LocalStatus::Running,
-        ExecutionStatus::Cancelled => LocalStatus::Failed,
+        ExecutionStatus::Cancelled => LocalStatus::Cancelled,
         ExecutionStatus::Pending => LocalStatus::Pending,
         ExecutionStatus::Unknown => LocalStatus::Pending,
     }
 }
 
-fn struct_to_json(value: Struct) -> Value {
-    let map: serde_json::Map<String, Value> = value
-        .fields
-        .into_iter()
-        .map(|(key, value)| (key, value_to_json(value)))
-        .collect();
-    Value::Object(map)
-}
-
-fn value_to_json(value: ProtoValue) -> Value {
-    match value.kind {
-        Some(Kind::NullValue(_)) => Value::Null,
-        Some(Kind::BoolValue(value)) => Value::Bool(value),
-        Some(Kind::NumberValue(value)) => serde_json::Number::from_f64(value)
-            .map(Value::Number)
-            .unwrap_or(Value::Null),
-        Some(Kind::StringValue(value)) => Value::String(value),
-        Some(Kind::ListValue(list)) => Value::Array(
-            list.values
-                .into_iter()
-                .map(value_to_json)
-                .collect(),
-        ),
-        Some(Kind::StructValue(struct_value)) => struct_to_json(struct_value),
-        None => Value::Null,
+fn datetime_to_timestamp(time: DateTime<Utc>) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: time.timestamp(),
+        nanos: time.timestamp_subsec_nanos() as i32,
     }
 }
 
+/// List request carrying every criterion of `filter`
+fn list_request(limit: i32, filter: &ExecutionFilter) -> Result<ListExecutionsRequest> {
+    filter.validate()?;
+    Ok(ListExecutionsRequest {
+        limit,
+        status: wire_status(filter.status),
+        pattern_name: filter.pattern.clone().unwrap_or_default(),
+        started_after: filter.started_after.map(datetime_to_timestamp),
+        started_before: filter.started_before.map(datetime_to_timestamp),
+        ..Default::default()
+    })
+}
+
+/// Status filter as the server expects it; empty matches every status
+fn wire_status(status: Option<LocalStatus>) -> String {
+    status.map(|status| status.to_string()).unwrap_or_default()
+}
+
 fn timestamp_to_datetime(timestamp: prost_types::Timestamp) -> chrono::DateTime<chrono::Utc> {
     use chrono::TimeZone;
     let nanos = timestamp.nanos as u32;
@@ -214,3 +803,869 @@ fn timestamp_to_datetime(timestamp: prost_types::Timestamp) -> chrono::DateTime<
         .single()
         .unwrap_or_else(|| chrono::Utc.timestamp_opt(0, 0).single().unwrap())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generated::parallax::executions::{
+        execution_service_server::{self, ExecutionServiceServer},
+        ExecutionAgent, GetExecutionResponse, ListExecutionsResponse,
+    };
+    use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use tonic::{Request, Response, Status};
+
+    type ScriptedStream = Vec<std::result::Result<StreamExecutionResponse, Status>>;
+
+    fn execution(status: ExecutionStatus) -> Execution {
+        Execution {
+            id: "exec-1".to_string(),
+            status: status as i32,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_event_filters_compose_lazily() {
+        let event = |event_type: &str, seconds: i64| {
+            Ok(ExecutionEvent {
+                event_type: event_type.to_string(),
+                execution: None,
+                event_time: DateTime::from_timestamp(seconds, 0),
+                event_data: None,
+            })
+        };
+        let events = || {
+            futures::stream::iter([
+                event("started", 0),
+                event("progress", 1),
+                event("updated", 2),
+                event("progress", 3),
+                event("completed", 4),
+            ])
+            .chain(futures::stream::once(async {
+                panic!("polled past the terminal event")
+            }))
+        };
+        let times = |events: Vec<Result<ExecutionEvent>>| {
+            events
+                .into_iter()
+                .map(|event| {
+                    let event = event.unwrap();
+                    (event.event_type, event.event_time.unwrap().timestamp())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let progress = events()
+            .until_terminal()
+            .only_kind(ExecutionEventKind::Progress)
+            .collect()
+            .await;
+        let expected = [
+            ("progress".to_string(), 1),
+            ("progress".to_string(), 3),
+            ("completed".to_string(), 4),
+        ];
+        assert_eq!(times(progress), expected);
+
+        // The terminal event passes the kind filter, so until_terminal can
+        // come after it and still stop the stream
+        let progress = events()
+            .only_kind(ExecutionEventKind::Progress)
+            .until_terminal()
+            .collect()
+            .await;
+        assert_eq!(times(progress), expected);
+
+        let recent = events()
+            .since(DateTime::from_timestamp(3, 0).unwrap())
+            .until_terminal()
+            .collect()
+            .await;
+        assert_eq!(
+            times(recent),
+            [("progress".to_string(), 3), ("completed".to_string(), 4)]
+        );
+    }
+
+    fn stream_message(seconds: i64, status: ExecutionStatus) -> StreamExecutionResponse {
+        let event_type = match status {
+            ExecutionStatus::Completed => "completed",
+            ExecutionStatus::Failed => "failed",
+            _ => "updated",
+        };
+        StreamExecutionResponse {
+            event_type: event_type.to_string(),
+            execution: Some(execution(status)),
+            event_time: Some(prost_types::Timestamp { seconds, nanos: 0 }),
+            event_data: None,
+        }
+    }
+
+    /// Execution service that answers `get` with a fixed execution and plays
+    /// back one scripted stream per `stream_execution` call
+    #[derive(Default)]
+    struct FakeExecutions {
+        execution: Execution,
+        streams: Mutex<VecDeque<ScriptedStream>>,
+        stream_calls: AtomicUsize,
+        // Keep streams open after the script runs out instead of closing them
+        hold_open: bool,
+        // Executions served page by page from `list_executions`
+        listed: Vec<Execution>,
+        list_calls: AtomicUsize,
+        // The last `list_executions` request
+        last_list: Mutex<Option<ListExecutionsRequest>>,
+        // Executions generated on demand by `export_executions`, and how
+        // many of them it has produced so far
+        export_size: usize,
+        exported: Arc<AtomicUsize>,
+        last_export: Mutex<Option<ExportExecutionsRequest>>,
+        // Answer `export_executions` like a control plane without it
+        export_unimplemented: bool,
+    }
+
+    impl FakeExecutions {
+        fn new(execution: Execution, streams: Vec<ScriptedStream>) -> Arc<Self> {
+            Arc::new(Self {
+                execution,
+                streams: Mutex::new(streams.into()),
+                ..Default::default()
+            })
+        }
+    }
+
+    #[tonic::async_trait]
+    impl execution_service_server::ExecutionService for FakeExecutions {
+        async fn get_execution(
+            &self,
+            _request: Request<GetExecutionRequest>,
+        ) -> std::result::Result<Response<GetExecutionResponse>, Status> {
+            Ok(Response::new(GetExecutionResponse {
+                execution: Some(self.execution.clone()),
+            }))
+        }
+
+        async fn list_executions(
+            &self,
+            request: Request<ListExecutionsRequest>,
+        ) -> std::result::Result<Response<ListExecutionsResponse>, Status> {
+            self.list_calls.fetch_add(1, Ordering::SeqCst);
+            let request = request.into_inner();
+            *self.last_list.lock().unwrap() = Some(request.clone());
+            let start = match request.cursor.strip_prefix("page-") {
+                Some(start) => start
+                    .parse::<usize>()
+                    .map_err(|_| Status::invalid_argument("invalid page cursor"))?,
+                None if request.cursor.is_empty() => request.offset as usize,
+                None => return Err(Status::invalid_argument("invalid page cursor")),
+            };
+            let matching: Vec<_> = self
+                .listed
+                .iter()
+                .filter(|execution| {
+                    let started = execution.start_time.as_ref().map_or(0, |time| time.seconds);
+                    (request.status.is_empty()
+                        || serde_json::to_value(status_from_proto(execution.status)).unwrap()
+                            == request.status.as_str())
+                        && (request.pattern_name.is_empty()
+                            || execution.pattern_name == request.pattern_name)
+                        && request
+                            .started_after
+                            .as_ref()
+                            .is_none_or(|after| started >= after.seconds)
+                        && request
+                            .started_before
+                            .as_ref()
+                            .is_none_or(|before| started <= before.seconds)
+                })
+                .cloned()
+                .collect();
+            let total = matching.len();
+            let page: Vec<_> = matching
+                .into_iter()
+                .skip(start)
+                .take(request.limit as usize)
+                .collect();
+            let end = start + page.len();
+            Ok(Response::new(ListExecutionsResponse {
+                total: total as i32,
+                next_cursor: if end < total {
+                    format!("page-{}", end)
+                } else {
+                    String::new()
+                },
+                executions: page,
+            }))
+        }
+
+        type StreamExecutionStream = futures::stream::BoxStream<
+            'static,
+            std::result::Result<StreamExecutionResponse, Status>,
+        >;
+
+        async fn stream_execution(
+            &self,
+            _request: Request<StreamExecutionRequest>,
+        ) -> std::result::Result<Response<Self::StreamExecutionStream>, Status> {
+            self.stream_calls.fetch_add(1, Ordering::SeqCst);
+            let messages = self.streams.lock().unwrap().pop_front().unwrap_or_default();
+            let stream = futures::stream::iter(messages);
+            if self.hold_open {
+                Ok(Response::new(
+                    stream.chain(futures::stream::pending()).boxed(),
+                ))
+            } else {
+                Ok(Response::new(stream.boxed()))
+            }
+        }
+
+        type ExportExecutionsStream =
+            futures::stream::BoxStream<'static, std::result::Result<Execution, Status>>;
+
+        async fn export_executions(
+            &self,
+            request: Request<ExportExecutionsRequest>,
+        ) -> std::result::Result<Response<Self::ExportExecutionsStream>, Status> {
+            *self.last_export.lock().unwrap() = Some(request.into_inner());
+            if self.export_unimplemented {
+                return Err(Status::unimplemented("ExportExecutions"));
+            }
+            let exported = self.exported.clone();
+            let stream = futures::stream::iter(0..self.export_size).map(move |index| {
+                exported.fetch_add(1, Ordering::SeqCst);
+                Ok(Execution {
+                    id: format!("exec-{}", index),
+                    pattern_name: "nightly-audit".to_string(),
+                    status: ExecutionStatus::Completed as i32,
+                    start_time: Some(prost_types::Timestamp {
+                        seconds: 1_000 + index as i64,
+                        nanos: 0,
+                    }),
+                    // Roughly a kilobyte per execution
+                    error: "x".repeat(1024),
+                    ..Default::default()
+                })
+            });
+            Ok(Response::new(stream.boxed()))
+        }
+    }
+
+    async fn service_for(fake: Arc<FakeExecutions>) -> ExecutionService {
+        let channel = crate::test_support::serve(
+            tonic::transport::Server::builder().add_service(ExecutionServiceServer::from_arc(fake)),
+        )
+        .await;
+        ExecutionService::new(channel, "http://fake".to_string())
+    }
+
+    #[test]
+    fn test_cancelled_execution_keeps_cancelled_status() {
+        let mapped =
+            execution_from_proto(execution(ExecutionStatus::Cancelled), JsonLimits::default()).unwrap();
+
+        assert_eq!(mapped.status, LocalStatus::Cancelled);
+        assert!(mapped.status.is_terminal());
+        assert_eq!(serde_json::to_value(mapped.status).unwrap(), "cancelled");
+    }
+
+    #[tokio::test]
+    async fn test_numeric_metrics_survive_round_trip_as_numbers() {
+        let (client, control_plane) = crate::Client::mock();
+        let mut metadata = Metadata::new();
+        metadata.insert("latency_ms", 12.5);
+        metadata.insert("cached", true);
+        metadata.insert("model", "small");
+        control_plane.add_execution(PatternExecution {
+            id: "exec-1".to_string(),
+            pattern: "review".to_string(),
+            status: LocalStatus::Completed,
+            input: Value::Null,
+            output: None,
+            agents: Vec::new(),
+            agent_contributions: Vec::new(),
+            start_time: Utc::now(),
+            end_time: None,
+            duration_ms: None,
+            confidence: None,
+            error: None,
+            metadata: metadata.clone(),
+        });
+
+        let fetched = client.executions().get("exec-1").await.unwrap().metadata;
+
+        assert_eq!(fetched, metadata);
+        assert_eq!(fetched["latency_ms"], serde_json::json!(12.5));
+        assert_eq!(fetched.get_f64("latency_ms"), Some(12.5));
+        assert_eq!(fetched.get_bool("cached"), Some(true));
+        assert_eq!(fetched.get_str("model"), Some("small"));
+        assert_eq!(fetched.get_str("latency_ms"), None);
+    }
+
+    #[tokio::test]
+    async fn test_stream_with_resume_reconnects_without_duplicates() {
+        let fake = FakeExecutions::new(
+            execution(ExecutionStatus::Running),
+            vec![
+                vec![
+                    Ok(stream_message(1, ExecutionStatus::Pending)),
+                    Ok(stream_message(2, ExecutionStatus::Running)),
+                    Err(Status::unavailable("control plane restarting")),
+                ],
+                vec![
+                    Ok(stream_message(1, ExecutionStatus::Pending)),
+                    Ok(stream_message(2, ExecutionStatus::Running)),
+                    Ok(stream_message(2, ExecutionStatus::Completed)),
+                    Ok(stream_message(2, ExecutionStatus::Completed)),
+                ],
+            ],
+        );
+        let service = service_for(fake.clone()).await;
+
+        let events: Vec<_> = service
+            .stream_with_resume("exec-1", None)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        let seen: Vec<(i64, String)> = events
+            .iter()
+            .map(|event| {
+                let event = event.as_ref().unwrap();
+                (
+                    event.event_time.unwrap().timestamp(),
+                    event.event_type.clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            seen,
+            vec![
+                (1, "updated".to_string()),
+                (2, "updated".to_string()),
+                (2, "completed".to_string()),
+            ]
+        );
+        assert_eq!(fake.stream_calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stream_with_resume_keeps_distinct_events_in_the_same_second() {
+        let agent_completed = |agent_id: &str| StreamExecutionResponse {
+            event_type: "agent_completed".to_string(),
+            event_data: Some(crate::conversions::json_to_struct(
+                serde_json::json!({ "agentId": agent_id }),
+            )),
+            ..stream_message(5, ExecutionStatus::Running)
+        };
+        let fake = FakeExecutions::new(
+            execution(ExecutionStatus::Running),
+            vec![vec![
+                Ok(agent_completed("agent-a")),
+                Ok(agent_completed("agent-b")),
+                Ok(stream_message(5, ExecutionStatus::Completed)),
+            ]],
+        );
+        let service = service_for(fake.clone()).await;
+
+        let events: Vec<_> = service
+            .stream_with_resume("exec-1", None)
+            .await
+            .unwrap()
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+
+        let agents: Vec<_> = events
+            .iter()
+            .filter_map(|event| event.event_data.as_ref()?.get("agentId")?.as_str())
+            .collect();
+        assert_eq!(agents, ["agent-a", "agent-b"]);
+        assert_eq!(events.len(), 3);
+        assert_eq!(fake.stream_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_participating_agents_are_mapped_from_proto() {
+        let proto = Execution {
+            agents: vec![
+                ExecutionAgent {
+                    agent_id: "agent-a".to_string(),
+                    confidence: Some(0.9),
+                },
+                ExecutionAgent {
+                    agent_id: "agent-b".to_string(),
+                    confidence: Some(0.6),
+                },
+                ExecutionAgent {
+                    agent_id: "agent-c".to_string(),
+                    confidence: None,
+                },
+            ],
+            ..execution(ExecutionStatus::Completed)
+        };
+
+        let execution = execution_from_proto(proto, JsonLimits::default()).unwrap();
+
+        assert_eq!(execution.agents, ["agent-a", "agent-b", "agent-c"]);
+        assert_eq!(
+            execution.agent_contributions,
+            [
+                AgentContribution {
+                    agent_id: "agent-a".to_string(),
+                    confidence: 0.9,
+                },
+                AgentContribution {
+                    agent_id: "agent-b".to_string(),
+                    confidence: 0.6,
+                },
+            ]
+        );
+    }
+
+    /// A struct nesting `levels` structs inside one another
+    fn nested_struct(levels: usize) -> prost_types::Struct {
+        let mut value = prost_types::Struct::default();
+        for _ in 1..levels {
+            let inner = prost_types::Value {
+                kind: Some(prost_types::value::Kind::StructValue(value)),
+            };
+            value = prost_types::Struct {
+                fields: [("inner".to_string(), inner)].into(),
+            };
+        }
+        value
+    }
+
+    #[tokio::test]
+    async fn test_overly_nested_or_large_results_are_rejected() {
+        let fake = FakeExecutions::new(
+            Execution {
+                result: Some(nested_struct(20)),
+                ..execution(ExecutionStatus::Completed)
+            },
+            vec![],
+        );
+        let service = service_for(fake.clone()).await;
+        service.get("exec-1").await.unwrap();
+
+        let error = service
+            .clone()
+            .with_result_limits(10, 1_000)
+            .get("exec-1")
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&error, Error::Internal(message) if message.starts_with("nesting too deep")),
+            "{}",
+            error
+        );
+
+        let error = service.with_result_limits(64, 10).get("exec-1").await.unwrap_err();
+        assert!(matches!(&error, Error::Internal(message) if message.starts_with("too many values")));
+
+        // Event payloads are held to the same limits
+        let event = StreamExecutionResponse {
+            event_data: Some(nested_struct(3)),
+            ..stream_message(1, ExecutionStatus::Running)
+        };
+        let limits = JsonLimits {
+            max_depth: 2,
+            max_nodes: 100,
+            ..JsonLimits::default()
+        };
+        assert!(event_from_proto(event.clone(), limits).is_err());
+        assert!(event_from_proto(event, JsonLimits::default()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_non_finite_numbers_follow_the_policy() {
+        let metrics = [f64::NAN, f64::INFINITY, f64::NEG_INFINITY]
+            .into_iter()
+            .enumerate()
+            .map(|(i, number)| {
+                let value = prost_types::Value {
+                    kind: Some(prost_types::value::Kind::NumberValue(number)),
+                };
+                (format!("m{}", i), value)
+            })
+            .collect();
+        let fake = FakeExecutions::new(
+            Execution {
+                metrics: Some(prost_types::Struct { fields: metrics }),
+                ..execution(ExecutionStatus::Completed)
+            },
+            vec![],
+        );
+        let service = service_for(fake.clone()).await;
+
+        let nulled = service.get("exec-1").await.unwrap();
+        assert!(nulled.metadata.values().all(Value::is_null));
+        assert_eq!(nulled.metadata.len(), 3);
+
+        let error = service
+            .clone()
+            .with_non_finite_policy(NonFinitePolicy::ErrorOnNonFinite)
+            .get("exec-1")
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&error, Error::Internal(message) if message.starts_with("non-finite number")),
+            "{}",
+            error
+        );
+
+        let stringified = service
+            .with_non_finite_policy(NonFinitePolicy::StringifyNonFinite)
+            .get("exec-1")
+            .await
+            .unwrap();
+        assert_eq!(stringified.metadata["m0"], "NaN");
+        assert_eq!(stringified.metadata["m1"], "Infinity");
+        assert_eq!(stringified.metadata["m2"], "-Infinity");
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_completion_resolves_already_finished_execution() {
+        let fake = FakeExecutions::new(execution(ExecutionStatus::Completed), vec![]);
+        let service = service_for(fake.clone()).await;
+
+        let finished = service
+            .wait_for_completion("exec-1", Some(Duration::from_secs(5)))
+            .await
+            .unwrap();
+
+        assert_eq!(finished.status, LocalStatus::Completed);
+        assert_eq!(fake.stream_calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_completion_follows_stream_until_terminal() {
+        let fake = FakeExecutions::new(
+            execution(ExecutionStatus::Running),
+            vec![vec![
+                Ok(stream_message(1, ExecutionStatus::Running)),
+                Ok(stream_message(2, ExecutionStatus::Failed)),
+            ]],
+        );
+        let service = service_for(fake).await;
+
+        let finished = service.wait_for_completion("exec-1", None).await.unwrap();
+        assert_eq!(finished.status, LocalStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_completion_times_out() {
+        let fake = Arc::new(FakeExecutions {
+            execution: execution(ExecutionStatus::Running),
+            streams: Mutex::new(vec![vec![Ok(stream_message(1, ExecutionStatus::Running))]].into()),
+            hold_open: true,
+            ..Default::default()
+        });
+        let clock = crate::clock::MockClock::new();
+        let service = service_for(fake).await.with_clock(Arc::new(clock.clone()));
+
+        let wait = tokio::spawn(async move {
+            service
+                .wait_for_completion("exec-1", Some(Duration::from_secs(3600)))
+                .await
+        });
+        while clock.pending_sleeps() == 0 {
+            tokio::task::yield_now().await;
+        }
+        clock.advance(Duration::from_secs(3600));
+
+        let error = tokio::time::timeout(Duration::from_secs(5), wait)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap_err();
+        assert!(matches!(error, Error::Timeout(message) if message.contains("3600000ms")));
+    }
+
+    fn listed_executions(count: usize) -> Arc<FakeExecutions> {
+        Arc::new(FakeExecutions {
+            listed: (0..count)
+                .map(|index| Execution {
+                    id: format!("exec-{}", index),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_list_paginated_returns_cursor_until_last_page() {
+        let service = service_for(listed_executions(5)).await;
+
+        let first = service
+            .list_paginated(3, None, &ExecutionFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(first.items.len(), 3);
+        // The server's token is handed back untouched
+        let cursor = first.next_cursor.expect("second page");
+        assert_eq!(cursor, "page-3");
+
+        let second = service
+            .list_paginated(3, Some(&cursor), &ExecutionFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(second.items.len(), 2);
+        assert!(second.next_cursor.is_none());
+
+        let error = service
+            .list_paginated(3, Some("bogus"), &ExecutionFilter::default())
+            .await
+            .unwrap_err();
+        assert_eq!(error.code(), crate::ErrorCode::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_list_all_follows_cursors_across_pages() {
+        let fake = listed_executions(7);
+        let service = service_for(fake.clone()).await;
+
+        let ids: Vec<String> = service
+            .list_all_with_page_size(ExecutionFilter::default(), 3)
+            .map(|execution| execution.unwrap().id)
+            .collect()
+            .await;
+
+        let expected: Vec<String> = (0..7).map(|index| format!("exec-{}", index)).collect();
+        assert_eq!(ids, expected);
+        assert_eq!(fake.list_calls.load(Ordering::SeqCst), 3);
+    }
+
+    /// Three executions across two patterns, started at t=10s, 20s and 30s
+    fn varied_executions() -> Arc<FakeExecutions> {
+        let listed = [
+            ("exec-a", "consensus", ExecutionStatus::Completed, 10),
+            ("exec-b", "map-reduce", ExecutionStatus::Failed, 20),
+            ("exec-c", "consensus", ExecutionStatus::Running, 30),
+        ]
+        .into_iter()
+        .map(|(id, pattern, status, seconds)| Execution {
+            id: id.to_string(),
+            pattern_name: pattern.to_string(),
+            status: status as i32,
+            start_time: Some(prost_types::Timestamp { seconds, nanos: 0 }),
+            ..Default::default()
+        })
+        .collect();
+        Arc::new(FakeExecutions {
+            listed,
+            ..Default::default()
+        })
+    }
+
+    async fn filtered_ids(service: &ExecutionService, filter: ExecutionFilter) -> Vec<String> {
+        service
+            .list_filtered(10, 0, &filter)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|execution| execution.id)
+            .collect()
+    }
+
+    fn at(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(seconds, 0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_export_streams_with_bounded_buffering() {
+        const TOTAL: usize = 20_000;
+        let fake = Arc::new(FakeExecutions {
+            export_size: TOTAL,
+            ..Default::default()
+        });
+        let service = service_for(fake.clone()).await;
+        let filter = ExecutionFilter {
+            status: Some(LocalStatus::Completed),
+            pattern: Some("nightly-audit".to_string()),
+            started_after: Some(at(1_000)),
+            ..Default::default()
+        };
+
+        let mut exported = service.export(filter);
+        for _ in 0..10 {
+            exported.next().await.unwrap().unwrap();
+        }
+        // However long the consumer stalls, flow control holds the server back
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let produced = fake.exported.load(Ordering::SeqCst);
+        assert!(produced < TOTAL / 4, "server produced {} executions ahead", produced);
+
+        let remaining = exported.map(|execution| execution.unwrap()).count().await;
+        assert_eq!(10 + remaining, TOTAL);
+
+        // Filters travel with the request
+        let request = fake.last_export.lock().unwrap().take().unwrap();
+        assert_eq!(request.status, "completed");
+        assert_eq!(request.pattern_name, "nightly-audit");
+        assert_eq!(request.started_after.unwrap().seconds, 1_000);
+        assert_eq!(request.started_before, None);
+    }
+
+    #[tokio::test]
+    async fn test_export_falls_back_to_listing_when_unimplemented() {
+        let mut fake = Arc::into_inner(varied_executions()).unwrap();
+        fake.export_unimplemented = true;
+        let fake = Arc::new(fake);
+        let service = service_for(fake.clone()).await;
+        let filter = ExecutionFilter {
+            pattern: Some("consensus".to_string()),
+            ..Default::default()
+        };
+
+        let ids: Vec<String> = service
+            .export(filter)
+            .map(|execution| execution.unwrap().id)
+            .collect()
+            .await;
+
+        assert_eq!(ids, vec!["exec-a", "exec-c"]);
+        assert!(fake.last_export.lock().unwrap().is_some());
+        let request = fake.last_list.lock().unwrap().take().unwrap();
+        assert_eq!(request.pattern_name, "consensus");
+    }
+
+    #[tokio::test]
+    async fn test_export_rejects_inverted_time_range() {
+        let fake = Arc::new(FakeExecutions::default());
+        let service = service_for(fake.clone()).await;
+        let filter = ExecutionFilter {
+            started_after: Some(at(20)),
+            started_before: Some(at(10)),
+            ..Default::default()
+        };
+
+        let results: Vec<_> = service.export(filter).collect().await;
+
+        assert!(matches!(results.as_slice(), [Err(Error::InvalidArgument(_))]));
+        assert!(fake.last_export.lock().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_filtered_forwards_status() {
+        let fake = varied_executions();
+        let service = service_for(fake.clone()).await;
+
+        let filter = ExecutionFilter {
+            status: Some(LocalStatus::Failed),
+            ..Default::default()
+        };
+        assert_eq!(filtered_ids(&service, filter).await, vec!["exec-b"]);
+        let request = fake.last_list.lock().unwrap().take().unwrap();
+        assert_eq!(request.status, "failed");
+
+        // The string-based wrapper passes the status through as given
+        let listed = service
+            .list(10, 0, Some("running".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, "exec-c");
+        let listed = service
+            .list(10, 0, Some("archived".to_string()))
+            .await
+            .unwrap();
+        assert!(listed.is_empty());
+        let request = fake.last_list.lock().unwrap().take().unwrap();
+        assert_eq!(request.status, "archived");
+    }
+
+    #[tokio::test]
+    async fn test_list_filtered_by_pattern() {
+        let fake = varied_executions();
+        let service = service_for(fake.clone()).await;
+
+        let filter = ExecutionFilter {
+            pattern: Some("consensus".to_string()),
+            started_after: Some(at(5)),
+            ..Default::default()
+        };
+        assert_eq!(
+            filtered_ids(&service, filter).await,
+            vec!["exec-a", "exec-c"]
+        );
+
+        // Every criterion travels with the request
+        let request = fake.last_list.lock().unwrap().take().unwrap();
+        assert_eq!(request.pattern_name, "consensus");
+        assert_eq!(request.started_after.unwrap().seconds, 5);
+        assert_eq!(request.started_before, None);
+    }
+
+    #[tokio::test]
+    async fn test_list_paginated_fills_pages_with_filtered_executions() {
+        let service = service_for(varied_executions()).await;
+        let filter = ExecutionFilter {
+            pattern: Some("consensus".to_string()),
+            ..Default::default()
+        };
+
+        // The server filters before paginating, so no page comes back short
+        let first = service.list_paginated(1, None, &filter).await.unwrap();
+        assert_eq!(first.items.len(), 1);
+        assert_eq!(first.items[0].id, "exec-a");
+        let second = service
+            .list_paginated(1, first.next_cursor.as_deref(), &filter)
+            .await
+            .unwrap();
+        assert_eq!(second.items.len(), 1);
+        assert_eq!(second.items[0].id, "exec-c");
+        assert!(second.next_cursor.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_list_filtered_by_start_time_range() {
+        let service = service_for(varied_executions()).await;
+
+        let after = ExecutionFilter {
+            started_after: Some(at(20)),
+            ..Default::default()
+        };
+        assert_eq!(
+            filtered_ids(&service, after).await,
+            vec!["exec-b", "exec-c"]
+        );
+
+        let before = ExecutionFilter {
+            started_before: Some(at(20)),
+            ..Default::default()
+        };
+        assert_eq!(
+            filtered_ids(&service, before).await,
+            vec!["exec-a", "exec-b"]
+        );
+
+        let window = ExecutionFilter {
+            started_after: Some(at(15)),
+            started_before: Some(at(25)),
+            ..Default::default()
+        };
+        assert_eq!(filtered_ids(&service, window).await, vec!["exec-b"]);
+    }
+
+    #[tokio::test]
+    async fn test_list_filtered_rejects_inverted_range() {
+        let fake = varied_executions();
+        let service = service_for(fake.clone()).await;
+
+        let filter = ExecutionFilter {
+            started_after: Some(at(30)),
+            started_before: Some(at(10)),
+            ..Default::default()
+        };
+        let error = service.list_filtered(10, 0, &filter).await.unwrap_err();
+
+        assert!(matches!(error, Error::InvalidArgument(_)));
+        assert_eq!(fake.list_calls.load(Ordering::SeqCst), 0);
+    }
+}
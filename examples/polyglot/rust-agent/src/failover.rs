@@ -0,0 +1,121 @@
+//! Connection-level failover across control plane replicas
+
+use crate::error::{Error, Result};
+use futures::future::BoxFuture;
+use hyper_util::rt::TokioIo;
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::net::TcpStream;
+use tonic::transport::Uri;
+use tracing::{debug, warn};
+
+/// Connector that dials replicas in turn, starting from the last one that
+/// answered. The channel calls it whenever it (re)connects, so a replica
+/// going down moves traffic to the next reachable one.
+#[derive(Clone)]
+pub(crate) struct FailoverConnector {
+    targets: Arc<Vec<Uri>>,
+    active: Arc<AtomicUsize>,
+    connect_timeout: Duration,
+}
+
+impl FailoverConnector {
+    /// Dial `endpoints`, recording the index of the one that answered in
+    /// `active`
+    pub(crate) fn new(
+        endpoints: &[String],
+        connect_timeout: Duration,
+        active: Arc<AtomicUsize>,
+    ) -> Result<Self> {
+        let targets = endpoints
+            .iter()
+            .map(|endpoint| {
+                endpoint.parse::<Uri>().map_err(|error| {
+                    Error::InvalidArgument(format!("invalid endpoint {}: {}", endpoint, error))
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if targets.is_empty() {
+            return Err(Error::InvalidArgument(
+                "at least one endpoint is required".to_string(),
+            ));
+        }
+
+        Ok(Self {
+            targets: Arc::new(targets),
+            active,
+            connect_timeout,
+        })
+    }
+
+    async fn connect(self) -> io::Result<TokioIo<TcpStream>> {
+        let start = self.active.load(Ordering::Relaxed);
+        let mut last_error = None;
+
+        for offset in 0..self.targets.len() {
+            let index = (start + offset) % self.targets.len();
+            let target = &self.targets[index];
+
+            match tokio::time::timeout(self.connect_timeout, dial(target)).await {
+                Ok(Ok(stream)) => {
+                    if index != start {
+                        warn!("Failing over to control plane replica {}", target);
+                    }
+                    self.active.store(index, Ordering::Relaxed);
+                    return Ok(TokioIo::new(stream));
+                }
+                Ok(Err(error)) => {
+                    debug!("Control plane replica {} unreachable: {}", target, error);
+                    last_error = Some(error);
+                }
+                Err(_) => {
+                    debug!("Timed out connecting to control plane replica {}", target);
+                    last_error = Some(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("timed out connecting to {}", target),
+                    ));
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::NotConnected, "no control plane replicas")
+        }))
+    }
+}
+
+impl tower::Service<Uri> for FailoverConnector {
+    type Response = TokioIo<TcpStream>;
+    type Error = io::Error;
+    type Future = BoxFuture<'static, io::Result<Self::Response>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        Box::pin(self.clone().connect())
+    }
+}
+
+async fn dial(target: &Uri) -> io::Result<TcpStream> {
+    let host = target
+        .host()
+        .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "endpoint has no host"))?;
+    let port = target
+        .port_u16()
+        .unwrap_or(if target.scheme_str() == Some("https") { 443 } else { 80 });
+
+    let stream = TcpStream::connect((host, port)).await?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
@@ -0,0 +1,178 @@
+//! Scatter-gather analysis across several agents
+//!
+//! [`AgentFanout`] sends one task to a set of agents at once and reports a
+//! running aggregate confidence as their results arrive, so callers can act
+//! on a partial consensus without waiting for the slowest agent.
+
+use crate::{
+    confidence::ConfidenceAggregator,
+    conversions::json_to_struct,
+    error::{Error, Result},
+    generated::{confidence_agent_client::ConfidenceAgentClient, AgentRequest},
+};
+use futures::{stream::BoxStream, StreamExt};
+use serde_json::Value;
+use std::time::Duration;
+use tonic::transport::Endpoint;
+use tracing::warn;
+
+/// Dispatches a task to several agents and aggregates their confidence
+#[derive(Debug, Clone)]
+pub struct AgentFanout {
+    endpoints: Vec<String>,
+    strategy: String,
+    deadline: Option<Duration>,
+}
+
+impl AgentFanout {
+    /// Fan out to the agents listening at `endpoints`, averaging their
+    /// confidence
+    pub fn new(endpoints: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            endpoints: endpoints.into_iter().map(Into::into).collect(),
+            strategy: "avg".to_string(),
+            deadline: None,
+        }
+    }
+
+    /// Combine confidences with `strategy`, as understood by
+    /// [`ConfidenceAggregator::combine`]
+    pub fn with_strategy(mut self, strategy: impl Into<String>) -> Self {
+        self.strategy = strategy.into();
+        self
+    }
+
+    /// Stop waiting for agents that have not responded within `deadline`
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Analyze `task` on every agent concurrently
+    ///
+    /// Yields `(responded_count, aggregated_confidence)` each time another
+    /// agent answers successfully. Agents that cannot be reached or fail are
+    /// logged and skipped. The stream ends once every agent has answered or
+    /// failed, or when the deadline passes.
+    ///
+    /// Fails with `Internal` when called outside a Tokio runtime, which the
+    /// calls and the deadline timer need.
+    pub fn analyze(
+        &self,
+        task: &str,
+        data: Option<Value>,
+    ) -> Result<BoxStream<'static, (usize, f64)>> {
+        if tokio::runtime::Handle::try_current().is_err() {
+            return Err(Error::Internal(
+                "AgentFanout::analyze must be called within a Tokio runtime".to_string(),
+            ));
+        }
+        let request = AgentRequest {
+            task_description: task.to_string(),
+            data: data.map(json_to_struct),
+            timeout_ms: self
+                .deadline
+                .map_or(0, |deadline| deadline.as_millis().min(i32::MAX as u128) as i32),
+            ..Default::default()
+        };
+
+        let calls: futures::stream::FuturesUnordered<_> = self
+            .endpoints
+            .iter()
+            .cloned()
+            .map(|endpoint| analyze_on(endpoint, request.clone()))
+            .collect();
+
+        let strategy = self.strategy.clone();
+        let progress = calls
+            .filter_map(|confidence| async move { confidence })
+            .scan(Vec::new(), move |confidences, confidence| {
+                confidences.push(confidence);
+                let aggregate = ConfidenceAggregator::combine(confidences, &strategy, None);
+                futures::future::ready(Some((confidences.len(), aggregate)))
+            });
+
+        Ok(match self.deadline {
+            Some(deadline) => Box::pin(progress.take_until(tokio::time::sleep(deadline))),
+            None => Box::pin(progress),
+        })
+    }
+}
+
+/// Confidence reported by the agent at `endpoint`, or `None` if it failed
+async fn analyze_on(endpoint: String, request: AgentRequest) -> Option<f64> {
+    let result = async {
+        let channel = Endpoint::from_shared(endpoint.clone())?.connect().await?;
+        let response = ConfidenceAgentClient::new(channel).analyze(request).await?;
+        Ok::<_, Box<dyn std::error::Error + Send + Sync>>(response.into_inner().confidence)
+    }
+    .await;
+
+    result
+        .map_err(|e| warn!("Skipping agent at {}: {}", endpoint, e))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        generated::confidence_agent_server::ConfidenceAgentServer,
+        test_support::{serve_at, unused_endpoint},
+        AgentResult, ParallaxAgent,
+    };
+    use std::{collections::HashMap, sync::Arc};
+    use tonic::transport::Server;
+
+    async fn serve_fixed(confidence: f64, delay: Duration) -> String {
+        let agent = ParallaxAgent::new("fixed", "Fixed", vec![], HashMap::new()).set_analyze_fn(
+            move |_task: &str, _data: Option<Value>| async move {
+                tokio::time::sleep(delay).await;
+                Ok(AgentResult {
+                    confidence,
+                    ..Default::default()
+                })
+            },
+        );
+        serve_at(Server::builder().add_service(ConfidenceAgentServer::new(Arc::new(agent)))).await
+    }
+
+    #[tokio::test]
+    async fn test_fanout_aggregates_as_agents_respond() {
+        let fast = serve_fixed(0.6, Duration::ZERO).await;
+        let slow = serve_fixed(0.9, Duration::from_millis(200)).await;
+        let fanout = AgentFanout::new([fast, unused_endpoint().await, slow]);
+
+        let progress: Vec<(usize, f64)> = fanout.analyze("review", None).unwrap().collect().await;
+
+        assert_eq!(progress.len(), 2);
+        assert_eq!(progress[0], (1, 0.6));
+        assert_eq!(progress[1].0, 2);
+        assert!((progress[1].1 - 0.75).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_fanout_stops_at_deadline() {
+        let fast = serve_fixed(0.6, Duration::ZERO).await;
+        let stalled = serve_fixed(0.9, Duration::from_secs(5)).await;
+        let fanout = AgentFanout::new([fast, stalled])
+            .with_strategy("max")
+            .with_deadline(Duration::from_millis(300));
+
+        let started = std::time::Instant::now();
+        let progress: Vec<(usize, f64)> = fanout.analyze("review", None).unwrap().collect().await;
+
+        assert_eq!(progress, [(1, 0.6)]);
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_fanout_outside_a_runtime_is_an_error() {
+        let fanout = AgentFanout::new(["http://127.0.0.1:1".to_string()])
+            .with_deadline(Duration::from_millis(300));
+
+        let error = fanout.analyze("review", None).err().unwrap();
+
+        assert!(matches!(error, Error::Internal(_)), "{}", error);
+    }
+}
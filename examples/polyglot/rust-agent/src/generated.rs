@@ -1,3 +1,23 @@
+//! Protobuf types and gRPC stubs generated from `proto/*.proto`
+//!
+//! `build.rs` runs `tonic_build` over the protos in the repository's `proto/`
+//! directory and writes one file per protobuf package into `generated/`,
+//! named after the package:
+//!
+//! | Proto file          | Generated file                       | Module                         |
+//! |---------------------|--------------------------------------|--------------------------------|
+//! | `confidence.proto`  | `generated/parallax.confidence.rs`   | [`parallax::confidence`]       |
+//! | `registry.proto`    | `generated/parallax.registry.rs`     | [`parallax::registry`]         |
+//! | `patterns.proto`    | `generated/parallax.patterns.rs`     | [`parallax::patterns`]         |
+//! | `coordinator.proto` | `generated/parallax.coordinator.rs`  | [`parallax::coordinator`]      |
+//! | `executions.proto`  | `generated/parallax.executions.rs`   | [`parallax::executions`]       |
+//! | `gateway.proto`     | `generated/parallax.gateway.rs`      | [`parallax::gateway`]          |
+//!
+//! Every package is reachable as `generated::parallax::<package>` and, for
+//! short paths, as `generated::<package>`. The confidence and registry
+//! packages, which the agent uses most, are also glob re-exported here; the
+//! others are not, as several of their type names overlap.
+
 // Include generated protobuf files
 pub mod parallax {
     pub mod confidence {
@@ -21,5 +41,52 @@ pub mod parallax {
 }
 
 // Re-export for convenience
+pub use parallax::{confidence, coordinator, executions, gateway, patterns, registry};
 pub use parallax::confidence::*;
 pub use parallax::registry::*;
+
+#[cfg(test)]
+mod tests {
+    // Every generated path the services import, so a regenerated tree that
+    // renames or drops one fails to compile here rather than deep inside a
+    // service module
+    #[allow(unused_imports)]
+    use super::{
+        agent_registration,
+        confidence_agent_client::ConfidenceAgentClient,
+        confidence_agent_server::{ConfidenceAgent, ConfidenceAgentServer},
+        coordinator::coordinator_client::CoordinatorClient,
+        executions::{
+            execution_service_client::ExecutionServiceClient,
+            execution_service_server::ExecutionServiceServer, Execution, ExecutionAgent,
+            ExportExecutionsRequest, GetExecutionRequest, ListExecutionsRequest,
+            StreamExecutionRequest, StreamExecutionResponse,
+        },
+        gateway::{
+            agent_gateway_client::AgentGatewayClient, agent_to_control_plane,
+            control_plane_to_agent, AgentHeartbeat, AgentHello, AgentToControlPlane, TaskError,
+            TaskResult,
+        },
+        health::Status as HealthStatus,
+        patterns::{
+            execute_pattern_request::Options, execute_pattern_response::Status as PatternStatus,
+            pattern_catalog_event::EventType as CatalogEventType,
+            pattern_service_client::PatternServiceClient,
+            pattern_service_server::PatternServiceServer, ExecutePatternRequest, GetPatternRequest,
+            ListPatternsRequest, Pattern, PatternCatalogEvent, WatchPatternsRequest,
+        },
+        registry::{
+            registry_client::RegistryClient, registry_server::RegistryServer, watch_event,
+            ListAgentsRequest, WatchEvent, WatchRequest,
+        },
+        AgentRegistration, AgentRequest, Capabilities, ConfidenceResult, ErrorDetail, Health,
+        LogEvent, RegisterRequest, RenewRequest,
+    };
+
+    #[test]
+    fn test_service_import_paths_resolve() {
+        // Both spellings of a package path name the same module
+        let _: super::executions::Execution = super::parallax::executions::Execution::default();
+        let _: super::AgentRequest = super::parallax::confidence::AgentRequest::default();
+    }
+}
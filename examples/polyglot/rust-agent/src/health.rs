@@ -0,0 +1,103 @@
+//! Agent health reporting
+//!
+//! An agent's [`HealthStatus`] is what its `HealthCheck` RPC and gateway
+//! heartbeats report. Agents start healthy and may switch to degraded or
+//! unhealthy at runtime with
+//! [`ParallaxAgent::set_health`](crate::ParallaxAgent::set_health).
+
+use crate::generated::health::Status as HealthStatusProto;
+use std::collections::HashMap;
+
+/// Overall health of an agent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum HealthState {
+    #[default]
+    Healthy,
+    /// Serving, but with reduced quality or capacity
+    Degraded,
+    Unhealthy,
+}
+
+impl HealthState {
+    /// Lowercase name, as sent in gateway heartbeats
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HealthState::Healthy => "healthy",
+            HealthState::Degraded => "degraded",
+            HealthState::Unhealthy => "unhealthy",
+        }
+    }
+}
+
+impl From<HealthState> for HealthStatusProto {
+    fn from(state: HealthState) -> Self {
+        match state {
+            HealthState::Healthy => HealthStatusProto::Healthy,
+            HealthState::Degraded => HealthStatusProto::Degraded,
+            HealthState::Unhealthy => HealthStatusProto::Unhealthy,
+        }
+    }
+}
+
+/// Health an agent reports, with a human-readable reason
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthStatus {
+    pub state: HealthState,
+    pub message: String,
+    /// Extra key/value pairs merged into the health check's details
+    pub details: HashMap<String, String>,
+}
+
+impl HealthStatus {
+    /// Fully operational
+    pub fn healthy() -> Self {
+        Self::new(HealthState::Healthy, "Agent is operational")
+    }
+
+    /// Still serving, but impaired for the given reason
+    pub fn degraded(message: impl Into<String>) -> Self {
+        Self::new(HealthState::Degraded, message)
+    }
+
+    /// Unable to serve for the given reason
+    pub fn unhealthy(message: impl Into<String>) -> Self {
+        Self::new(HealthState::Unhealthy, message)
+    }
+
+    fn new(state: HealthState, message: impl Into<String>) -> Self {
+        Self {
+            state,
+            message: message.into(),
+            details: HashMap::new(),
+        }
+    }
+
+    /// Add a detail reported alongside the status
+    pub fn with_detail(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.details.insert(key.into(), value.into());
+        self
+    }
+}
+
+impl Default for HealthStatus {
+    fn default() -> Self {
+        Self::healthy()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_state_maps_to_proto_status() {
+        let cases = [
+            (HealthState::Healthy, 1),
+            (HealthState::Unhealthy, 2),
+            (HealthState::Degraded, 3),
+        ];
+        for (state, proto) in cases {
+            assert_eq!(HealthStatusProto::from(state) as i32, proto, "{:?}", state);
+        }
+    }
+}
@@ -2,6 +2,9 @@
 //! 
 //! Official Rust SDK for the Parallax AI Orchestration Platform.
 
+// `Error` embeds `tonic::Status`, which is larger than clippy's default threshold
+#![allow(clippy::result_large_err)]
+
 pub mod client;
 pub mod types;
 pub mod patterns;
@@ -9,26 +12,100 @@ pub mod agent_service;
 pub mod executions;
 pub mod error;
 pub mod generated;
+pub mod conversions;
+pub mod circuit_breaker;
+pub mod clock;
+pub mod connection;
+pub mod health;
+pub mod result_chunks;
+
+mod telemetry;
+mod transport;
+
+// Hosting an agent needs a gRPC server and the tokio runtime, so only the
+// client half of the SDK is built for the browser.
+#[cfg(feature = "native")]
 pub mod parallax_agent;
+#[cfg(feature = "native")]
 pub mod confidence;
+#[cfg(feature = "native")]
+pub mod middleware;
+#[cfg(feature = "native")]
+pub mod cache;
+#[cfg(feature = "native")]
+pub mod fanout;
+#[cfg(feature = "native")]
+pub mod log_sink;
+#[cfg(feature = "native")]
+pub mod retry;
+
+#[cfg(feature = "native")]
+mod failover;
+#[cfg(feature = "native")]
+mod metrics;
+#[cfg(feature = "native")]
+mod rate_limit;
+#[cfg(feature = "native")]
+mod scheduler;
+#[cfg(feature = "native")]
+mod registry_connection;
+#[cfg(feature = "native")]
+mod request_context;
 
-pub use client::{Client, ClientConfig};
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+#[cfg(test)]
+mod test_support;
+
+pub use client::{Client, ClientBuilder, ClientConfig, TlsConfig};
+pub use circuit_breaker::{CircuitBreakerConfig, CircuitState};
 pub use types::*;
 
-pub use error::{Error, Result};
+pub use error::{AgentError, Error, ErrorCode, Result, ResultExt};
+pub use tonic::codec::CompressionEncoding;
+pub use tokio_util::sync::CancellationToken;
 
 // Re-export commonly used items
 pub use patterns::PatternService;
 pub use agent_service::AgentService;
-pub use executions::ExecutionService;
-pub use parallax_agent::{ParallaxAgent, AgentResult, GatewayOptions};
+pub use executions::{ExecutionEventStreamExt, ExecutionService};
+pub use conversions::NonFinitePolicy;
+#[cfg(feature = "native")]
+pub use parallax_agent::{
+    ParallaxAgent, AgentResult, AnalyzeContext, AnalyzeFn, AnalyzeFuture, GatewayOptions, RegistrationState,
+    ReasoningStep, DEADLINE_HEADER, DEFAULT_CONFIDENCE_LABEL, PRIORITY_HEADER,
+    REASONING_STEPS_KEY,
+};
+#[cfg(feature = "native")]
+pub use middleware::{AnalyzeMiddleware, LoggingMiddleware, Next};
+#[cfg(feature = "native")]
+pub use retry::{with_retry, RetryFuture};
+#[cfg(feature = "native")]
+pub use cache::{with_cache, CacheConfig, CACHE_BYPASS_KEY};
+pub use clock::{Clock, SystemClock};
+#[cfg(any(test, feature = "testing"))]
+pub use clock::MockClock;
+#[cfg(feature = "native")]
+pub use fanout::AgentFanout;
+pub use connection::{ConnectionState, DisconnectHandler};
+pub use health::{HealthState, HealthStatus};
+#[cfg(feature = "native")]
+pub use log_sink::{LogLevel, LogSink};
+pub use result_chunks::ResultAssembler;
 
 // Re-export confidence utilities
+#[cfg(feature = "native")]
 pub use confidence::{
     ConfidenceConfig,
+    DEFAULT_CONFIDENCE_FIELDS,
     ConfidenceExtractor,
     ExtractionStrategy,
+    CustomExtractor,
+    KeywordLexicon,
     ConfidenceAggregator,
+    CalibrationModel,
+    CalibrationParams,
     with_confidence,
     WithConfidence,
 };
@@ -0,0 +1,97 @@
+//! Log lines streamed from an analysis back to its caller
+//!
+//! An analyze function set with
+//! [`set_analyze_fn_with_logs`](crate::ParallaxAgent::set_analyze_fn_with_logs)
+//! receives a [`LogSink`]. Lines written to it during a `StreamAnalyze` call
+//! reach the caller as `ConfidenceResult` messages with `log` set, ahead of
+//! the final result. For `Analyze` and gateway tasks the sink is disabled and
+//! lines are discarded.
+
+use crate::generated::LogEvent;
+use tokio::sync::mpsc;
+use tracing::debug;
+
+/// Severity of a streamed log line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogLevel {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    /// Name sent in `LogEvent.level`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Debug => "debug",
+            LogLevel::Info => "info",
+            LogLevel::Warn => "warn",
+            LogLevel::Error => "error",
+        }
+    }
+}
+
+/// Handle an analyze function writes log lines to
+///
+/// Writing never blocks: if the caller reads the stream too slowly and the
+/// buffer fills up, further lines are dropped. Clones write to the same
+/// stream.
+#[derive(Debug, Clone, Default)]
+pub struct LogSink {
+    events: Option<mpsc::Sender<LogEvent>>,
+}
+
+impl LogSink {
+    pub(crate) fn new(events: mpsc::Sender<LogEvent>) -> Self {
+        Self {
+            events: Some(events),
+        }
+    }
+
+    /// A sink that discards every line, e.g. for calling an analyze
+    /// function directly
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Whether lines still reach a caller, to skip building expensive ones
+    pub fn is_enabled(&self) -> bool {
+        self.events.as_ref().is_some_and(|events| !events.is_closed())
+    }
+
+    /// Write a line at `level`
+    pub fn log(&self, level: LogLevel, message: impl Into<String>) {
+        let Some(events) = &self.events else {
+            return;
+        };
+        let event = LogEvent {
+            level: level.as_str().to_string(),
+            message: message.into(),
+            timestamp: Some(prost_types::Timestamp::from(std::time::SystemTime::now())),
+        };
+        if let Err(mpsc::error::TrySendError::Full(event)) = events.try_send(event) {
+            debug!("Log stream buffer full, dropping line: {}", event.message);
+        }
+    }
+
+    /// Write a line at [`LogLevel::Debug`]
+    pub fn debug(&self, message: impl Into<String>) {
+        self.log(LogLevel::Debug, message);
+    }
+
+    /// Write a line at [`LogLevel::Info`]
+    pub fn info(&self, message: impl Into<String>) {
+        self.log(LogLevel::Info, message);
+    }
+
+    /// Write a line at [`LogLevel::Warn`]
+    pub fn warn(&self, message: impl Into<String>) {
+        self.log(LogLevel::Warn, message);
+    }
+
+    /// Write a line at [`LogLevel::Error`]
+    pub fn error(&self, message: impl Into<String>) {
+        self.log(LogLevel::Error, message);
+    }
+}
@@ -0,0 +1,148 @@
+//! Prometheus metrics for agents
+//!
+//! With the `metrics` feature enabled, each [`ParallaxAgent`](crate::ParallaxAgent)
+//! owns a registry with analyze request/error counters and a latency
+//! histogram. Without it, [`AgentMetrics`] is an empty recorder and every
+//! call compiles away.
+
+use std::time::Duration;
+
+#[cfg(feature = "metrics")]
+pub(crate) use enabled::AgentMetrics;
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) use disabled::AgentMetrics;
+
+#[cfg(feature = "metrics")]
+mod enabled {
+    use super::Duration;
+    use prometheus::{Encoder, Histogram, HistogramOpts, IntCounter, Registry, TextEncoder};
+    use std::collections::HashMap;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use tracing::{debug, info, warn};
+
+    /// Pause after a failed accept, so running out of file descriptors
+    /// doesn't turn the accept loop into a busy loop
+    const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+    /// Upper bounds, in seconds, of the analyze latency histogram buckets
+    const LATENCY_BUCKETS: &[f64] = &[0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+    /// Analyze metrics registered under the agent's id
+    #[derive(Clone)]
+    pub(crate) struct AgentMetrics {
+        registry: Registry,
+        requests: IntCounter,
+        errors: IntCounter,
+        latency: Histogram,
+    }
+
+    impl AgentMetrics {
+        pub(crate) fn new(agent_id: &str) -> Self {
+            let labels = HashMap::from([("agent_id".to_string(), agent_id.to_string())]);
+            let registry = Registry::new_custom(Some("parallax_agent".to_string()), Some(labels))
+                .expect("static registry prefix is valid");
+
+            let requests = IntCounter::new("analyze_requests_total", "Analyze requests received")
+                .expect("static counter definition is valid");
+            let errors = IntCounter::new("analyze_errors_total", "Analyze requests that failed")
+                .expect("static counter definition is valid");
+            let latency = Histogram::with_opts(
+                HistogramOpts::new("analyze_duration_seconds", "Analyze request latency")
+                    .buckets(LATENCY_BUCKETS.to_vec()),
+            )
+            .expect("static histogram definition is valid");
+
+            for collector in [
+                Box::new(requests.clone()) as Box<dyn prometheus::core::Collector>,
+                Box::new(errors.clone()),
+                Box::new(latency.clone()),
+            ] {
+                registry
+                    .register(collector)
+                    .expect("metrics are registered once on a fresh registry");
+            }
+
+            Self {
+                registry,
+                requests,
+                errors,
+                latency,
+            }
+        }
+
+        pub(crate) fn record(&self, success: bool, latency: Duration) {
+            self.requests.inc();
+            if !success {
+                self.errors.inc();
+            }
+            self.latency.observe(latency.as_secs_f64());
+        }
+
+        pub(crate) fn registry(&self) -> &Registry {
+            &self.registry
+        }
+
+        /// Current metrics in the Prometheus text exposition format
+        pub(crate) fn encode(&self) -> String {
+            let mut buffer = Vec::new();
+            if let Err(error) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+                debug!("Failed to encode agent metrics: {}", error);
+            }
+            String::from_utf8(buffer).unwrap_or_default()
+        }
+
+        /// Answer every HTTP request on `port` with the current metrics
+        ///
+        /// Only binding the port can fail; a connection that can't be
+        /// accepted is logged and skipped.
+        pub(crate) async fn serve(self, port: u16) -> std::io::Result<()> {
+            let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+            info!("Serving agent metrics on port {}", port);
+
+            loop {
+                let mut socket = match listener.accept().await {
+                    Ok((socket, _)) => socket,
+                    Err(error) => {
+                        warn!("Failed to accept metrics connection: {}", error);
+                        tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                        continue;
+                    }
+                };
+                let body = self.encode();
+                tokio::spawn(async move {
+                    // The path is irrelevant; read the request head and reply
+                    let mut request = [0u8; 1024];
+                    let _ = socket.read(&mut request).await;
+                    let response = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        prometheus::TEXT_FORMAT,
+                        body.len(),
+                        body
+                    );
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+mod disabled {
+    use super::Duration;
+
+    /// No-op stand-in used when the `metrics` feature is disabled
+    #[derive(Clone)]
+    pub(crate) struct AgentMetrics;
+
+    impl AgentMetrics {
+        pub(crate) fn new(_agent_id: &str) -> Self {
+            Self
+        }
+
+        pub(crate) fn record(&self, _success: bool, _latency: Duration) {}
+    }
+}
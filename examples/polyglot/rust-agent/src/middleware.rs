@@ -0,0 +1,235 @@
+//! Middleware wrapping an agent's analyze function
+//!
+//! Middleware registered with [`ParallaxAgent::with_middleware`](crate::ParallaxAgent::with_middleware)
+//! runs in registration order around the agent's `analyze_fn`. Each one
+//! receives the task, its data and a [`Next`] continuation; it may inspect or
+//! rewrite the inputs, skip `next` entirely to short-circuit, or transform
+//! the result it returns.
+
+use crate::log_sink::LogSink;
+use crate::parallax_agent::{AgentResult, AnalyzeContext, AnalyzeFn, AnalyzeFuture};
+use async_trait::async_trait;
+use serde_json::Value;
+use std::{borrow::Cow, sync::Arc, time::Instant};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, warn};
+
+/// Cross-cutting behaviour around an agent's analyze function
+#[async_trait]
+pub trait AnalyzeMiddleware: Send + Sync {
+    /// Handle a task, calling `next.run(..)` to continue down the chain
+    async fn handle(
+        &self,
+        task: &str,
+        data: Option<Value>,
+        next: Next<'_>,
+    ) -> Result<AgentResult, Box<dyn std::error::Error>>;
+}
+
+/// The rest of the middleware chain, ending in the agent's analyze function
+pub struct Next<'a> {
+    middleware: &'a [Arc<dyn AnalyzeMiddleware>],
+    analyze_fn: &'a AnalyzeFn,
+    context: AnalyzeContext,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(
+        middleware: &'a [Arc<dyn AnalyzeMiddleware>],
+        analyze_fn: &'a AnalyzeFn,
+        context: AnalyzeContext,
+    ) -> Self {
+        Self {
+            middleware,
+            analyze_fn,
+            context,
+        }
+    }
+
+    /// Token cancelled once the caller has gone away
+    pub fn cancellation(&self) -> &CancellationToken {
+        self.context.cancellation()
+    }
+
+    /// Sink for log lines streamed to the caller
+    pub fn logs(&self) -> &LogSink {
+        self.context.logs()
+    }
+
+    /// Run the remaining middleware and the analyze function
+    ///
+    /// `task` may be the `&str` the middleware was given or a `String` it
+    /// rewrote, such as a redacted copy.
+    pub fn run(self, task: impl Into<Cow<'a, str>>, data: Option<Value>) -> AnalyzeFuture<'a> {
+        let task = task.into();
+        match self.middleware.split_first() {
+            Some((middleware, rest)) => {
+                let next = Next::new(rest, self.analyze_fn, self.context);
+                Box::pin(async move { middleware.handle(&task, data, next).await })
+            }
+            // The analyze function is called, and reads the context, only
+            // once the scoped future is polled
+            None => {
+                let analyze_fn = self.analyze_fn;
+                self.context
+                    .scope(Box::pin(async move { analyze_fn(&task, data).await }))
+            }
+        }
+    }
+}
+
+/// Logs each task with its outcome, confidence and duration
+#[derive(Debug, Clone, Default)]
+pub struct LoggingMiddleware;
+
+#[async_trait]
+impl AnalyzeMiddleware for LoggingMiddleware {
+    async fn handle(
+        &self,
+        task: &str,
+        data: Option<Value>,
+        next: Next<'_>,
+    ) -> Result<AgentResult, Box<dyn std::error::Error>> {
+        info!("Analyzing task: {}", task);
+        let started = Instant::now();
+
+        let result = next.run(task, data).await;
+        match &result {
+            Ok(result) => info!(
+                "Analyzed task in {:?} with confidence {:.2}",
+                started.elapsed(),
+                result.confidence
+            ),
+            Err(error) => warn!("Analysis failed after {:?}: {}", started.elapsed(), error),
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ParallaxAgent;
+    use std::{
+        collections::HashMap,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    /// Rejects any task mentioning a forbidden word
+    struct RejectForbidden;
+
+    #[async_trait]
+    impl AnalyzeMiddleware for RejectForbidden {
+        async fn handle(
+            &self,
+            task: &str,
+            data: Option<Value>,
+            next: Next<'_>,
+        ) -> Result<AgentResult, Box<dyn std::error::Error>> {
+            if task.contains("forbidden") {
+                return Err("task rejected by policy".into());
+            }
+            next.run(task, data).await
+        }
+    }
+
+    /// Halves the confidence of whatever the rest of the chain returns
+    struct HalveConfidence;
+
+    #[async_trait]
+    impl AnalyzeMiddleware for HalveConfidence {
+        async fn handle(
+            &self,
+            task: &str,
+            data: Option<Value>,
+            next: Next<'_>,
+        ) -> Result<AgentResult, Box<dyn std::error::Error>> {
+            let mut result = next.run(task, data).await?;
+            result.confidence /= 2.0;
+            Ok(result)
+        }
+    }
+
+    /// Replaces every digit in the task before passing it on
+    struct RedactDigits;
+
+    #[async_trait]
+    impl AnalyzeMiddleware for RedactDigits {
+        async fn handle(
+            &self,
+            task: &str,
+            data: Option<Value>,
+            next: Next<'_>,
+        ) -> Result<AgentResult, Box<dyn std::error::Error>> {
+            let redacted: String = task
+                .chars()
+                .map(|c| if c.is_ascii_digit() { '#' } else { c })
+                .collect();
+            next.run(redacted, data).await
+        }
+    }
+
+    fn counting_agent(calls: Arc<AtomicUsize>) -> ParallaxAgent {
+        ParallaxAgent::new("mw-agent", "Middleware Agent", vec![], HashMap::new()).set_analyze_fn(
+            move |_task: &str, _data: Option<Value>| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                async {
+                    Ok(AgentResult {
+                        confidence: 0.8,
+                        ..Default::default()
+                    })
+                }
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_middleware_short_circuits_before_analyze_fn() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let agent = counting_agent(calls.clone())
+            .with_middleware(LoggingMiddleware)
+            .with_middleware(RejectForbidden);
+
+        let error = agent
+            .run_analyze("a forbidden task", None, CancellationToken::new(), LogSink::disabled())
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.to_string(), "task rejected by policy");
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_middleware_transforms_result() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let agent = counting_agent(calls.clone())
+            .with_middleware(RejectForbidden)
+            .with_middleware(HalveConfidence);
+
+        let result = agent
+            .run_analyze("a routine task", None, CancellationToken::new(), LogSink::disabled())
+            .await
+            .unwrap();
+
+        assert_eq!(result.confidence, 0.4);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_middleware_passes_on_a_rewritten_task() {
+        let agent = ParallaxAgent::new("mw-agent", "Middleware Agent", vec![], HashMap::new())
+            .set_analyze_fn(|task: &str, _data: Option<Value>| {
+                let task = task.to_string();
+                async move { Ok(AgentResult::new(Value::String(task), 0.8)) }
+            })
+            .with_middleware(RedactDigits)
+            .with_middleware(LoggingMiddleware);
+
+        let result = agent
+            .run_analyze("card 4111 1111", None, CancellationToken::new(), LogSink::disabled())
+            .await
+            .unwrap();
+
+        assert_eq!(result.value, "card #### ####");
+    }
+}
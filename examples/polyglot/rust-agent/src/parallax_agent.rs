@@ -1,22 +1,41 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use async_trait::async_trait;
+use futures::StreamExt;
 use tokio::signal;
-use tokio::sync::Mutex;
-use tokio::time::interval;
+use tokio::sync::{watch, Mutex};
+use tokio_util::sync::CancellationToken;
+use tonic::{codec::CompressionEncoding, codegen::http, service::interceptor::InterceptedService};
 use tonic::{transport::Server, Request, Response, Status};
-use tracing::{error, info, warn};
+use tracing::{debug, error, info, warn, Instrument};
+
+use crate::clock::{self, calculate_reconnect_delay, Clock, SystemClock};
+use crate::confidence::{with_confidence, ConfidenceAggregator, ConfidenceConfig};
+use crate::conversions::{json_to_struct, struct_to_json, struct_to_json_within};
+use crate::error::AgentError;
+use crate::health::{HealthState, HealthStatus};
+use crate::log_sink::LogSink;
+use crate::metrics::AgentMetrics;
+use crate::middleware::{AnalyzeMiddleware, Next};
+use crate::patterns::schema_violations;
+use crate::rate_limit::RateLimiter;
+use crate::result_chunks::{is_chunked, result_frames};
+use crate::scheduler::{PrioritySlots, Slot};
+use crate::types::{checked_capabilities, normalize_capabilities};
+use crate::registry_connection::RegistryConnection;
+use crate::request_context::{self, RequestContext, REQUEST_ID_HEADER};
 
 // Import generated proto types
 use crate::generated::{
     confidence_agent_server::{ConfidenceAgent, ConfidenceAgentServer},
-    registry_client::RegistryClient,
     agent_registration,
     health::Status as HealthStatusProto,
-    AgentRequest, AgentRegistration, Capabilities, ConfidenceResult, Health,
+    AgentRequest, AgentRegistration, Capabilities, ConfidenceResult, Health, LogEvent,
     RegisterRequest, RenewRequest,
 };
 
@@ -30,15 +49,310 @@ use crate::generated::parallax::gateway::{
 };
 
 /// Result of an agent's analysis
-#[derive(Debug, Clone)]
+///
+/// Build one with [`AgentResult::new`] and the `with_*` methods. Fields may
+/// be added in later releases, so a struct literal should end in
+/// `..Default::default()`.
+#[derive(Debug, Clone, Default)]
 pub struct AgentResult {
     pub value: serde_json::Value,
     pub confidence: f64,
     pub reasoning: Option<String>,
+    /// Step-by-step reasoning, sent to callers as JSON under the
+    /// `reasoning_steps` metadata key
+    pub reasoning_steps: Vec<ReasoningStep>,
     pub uncertainties: Vec<String>,
     pub metadata: HashMap<String, String>,
+    /// How long the coordinator may reuse this result for identical requests.
+    /// Sent as a `cache-control: max-age=<secs>` response header; leave `None`
+    /// for time-sensitive results.
+    pub cache_ttl: Option<Duration>,
+}
+
+/// Metadata key carrying an `AgentResult`'s reasoning steps as JSON
+pub const REASONING_STEPS_KEY: &str = "reasoning_steps";
+
+/// One step of an agent's reasoning
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ReasoningStep {
+    pub description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f64>,
+}
+
+impl ReasoningStep {
+    /// Create a step without a confidence
+    pub fn new(description: impl Into<String>) -> Self {
+        Self {
+            description: description.into(),
+            confidence: None,
+        }
+    }
+
+    /// Attach the agent's confidence in this step (0.0-1.0)
+    pub fn with_confidence(mut self, confidence: f64) -> Self {
+        self.confidence = Some(confidence);
+        self
+    }
+
+    /// Decode the steps an agent sent under [`REASONING_STEPS_KEY`]. Results
+    /// without steps, or with unreadable ones, decode as no steps.
+    pub fn from_metadata(metadata: &HashMap<String, String>) -> Vec<ReasoningStep> {
+        metadata
+            .get(REASONING_STEPS_KEY)
+            .and_then(|steps| serde_json::from_str(steps).ok())
+            .unwrap_or_default()
+    }
+}
+
+impl AgentResult {
+    /// A result holding `value` with `confidence` (0.0-1.0)
+    pub fn new(value: serde_json::Value, confidence: f64) -> Self {
+        Self {
+            value,
+            confidence,
+            ..Default::default()
+        }
+    }
+
+    /// Explain how the result was reached
+    pub fn with_reasoning(mut self, reasoning: impl Into<String>) -> Self {
+        self.reasoning = Some(reasoning.into());
+        self
+    }
+
+    /// Add a step to the reasoning
+    pub fn with_reasoning_step(mut self, step: ReasoningStep) -> Self {
+        self.reasoning_steps.push(step);
+        self
+    }
+
+    /// Note something the agent is unsure about
+    pub fn with_uncertainty(mut self, uncertainty: impl Into<String>) -> Self {
+        self.uncertainties.push(uncertainty.into());
+        self
+    }
+
+    /// Attach a metadata entry for the caller
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Let the coordinator reuse the result for identical requests for `ttl`
+    pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = Some(ttl);
+        self
+    }
+
+    /// The reasoning as text: `reasoning` when set, otherwise the step
+    /// descriptions one per line
+    pub fn reasoning_text(&self) -> Option<String> {
+        if self.reasoning.is_some() || self.reasoning_steps.is_empty() {
+            return self.reasoning.clone();
+        }
+
+        Some(
+            self.reasoning_steps
+                .iter()
+                .map(|step| step.description.as_str())
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    }
+
+    /// Metadata sent to callers, including the encoded reasoning steps
+    fn wire_metadata(&self) -> HashMap<String, String> {
+        let mut metadata = self.metadata.clone();
+        if !self.reasoning_steps.is_empty() {
+            if let Ok(steps) = serde_json::to_string(&self.reasoning_steps) {
+                metadata.insert(REASONING_STEPS_KEY.to_string(), steps);
+            }
+        }
+        metadata
+    }
+
+    /// Merge results from several agents into one view
+    ///
+    /// Confidences are fused with [`ConfidenceAggregator::combine`] using
+    /// `conf_strategy`, `value` becomes a JSON array of the individual values,
+    /// reasoning steps are concatenated in input order, uncertainties are
+    /// unioned in first-seen order and metadata maps are
+    /// merged with later results winning on key collisions. The merged result
+    /// is only cacheable for the shortest TTL, and not at all if any input is
+    /// uncacheable. An empty slice yields an empty, zero-confidence result.
+    pub fn merge(results: &[AgentResult], conf_strategy: &str) -> AgentResult {
+        if results.is_empty() {
+            return AgentResult {
+                value: serde_json::Value::Array(Vec::new()),
+                confidence: 0.0,
+                ..Default::default()
+            };
+        }
+
+        let confidences: Vec<f64> = results.iter().map(|result| result.confidence).collect();
+
+        let mut uncertainties: Vec<String> = Vec::new();
+        let mut metadata = HashMap::new();
+        for result in results {
+            for uncertainty in &result.uncertainties {
+                if !uncertainties.contains(uncertainty) {
+                    uncertainties.push(uncertainty.clone());
+                }
+            }
+            for (key, value) in &result.metadata {
+                if let Some(previous) = metadata.insert(key.clone(), value.clone()) {
+                    if previous != *value {
+                        debug!(
+                            "Metadata key {} collided while merging results: {:?} replaced by {:?}",
+                            key, previous, value
+                        );
+                    }
+                }
+            }
+        }
+
+        let reasoning_steps = results
+            .iter()
+            .flat_map(|result| result.reasoning_steps.iter().cloned())
+            .collect();
+
+        let reasoning: Vec<&str> = results
+            .iter()
+            .filter_map(|result| result.reasoning.as_deref())
+            .filter(|reasoning| !reasoning.is_empty())
+            .collect();
+
+        let cache_ttl = results
+            .iter()
+            .map(|result| result.cache_ttl)
+            .collect::<Option<Vec<_>>>()
+            .and_then(|ttls| ttls.into_iter().min());
+
+        AgentResult {
+            value: serde_json::Value::Array(
+                results.iter().map(|result| result.value.clone()).collect(),
+            ),
+            confidence: ConfidenceAggregator::combine(&confidences, conf_strategy, None),
+            reasoning: (!reasoning.is_empty()).then(|| reasoning.join("\n")),
+            reasoning_steps,
+            uncertainties,
+            metadata,
+            cache_ttl,
+        }
+    }
+}
+
+/// Where an agent stands with the control plane registry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RegistrationState {
+    /// Not registered, e.g. before `serve` or after the lease was lost
+    #[default]
+    Unregistered,
+    /// Attempting to register, retrying with backoff
+    Registering,
+    /// Registered and renewing the lease
+    Registered,
 }
 
+impl RegistrationState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RegistrationState::Unregistered => "unregistered",
+            RegistrationState::Registering => "registering",
+            RegistrationState::Registered => "registered",
+        }
+    }
+}
+
+/// Expertise reported by agents that declare no capability scores
+const DEFAULT_EXPERTISE_LEVEL: f64 = 0.8;
+
+/// Metadata label `ParallaxAgent::new` reads the default confidence from
+pub const DEFAULT_CONFIDENCE_LABEL: &str = "default_confidence";
+
+/// How often a registered agent renews its lease
+const LEASE_RENEWAL_INTERVAL: Duration = Duration::from_secs(30);
+/// Consecutive failed renewals after which the lease is treated as lost
+const MAX_RENEWAL_FAILURES: u32 = 3;
+/// How long `serve` waits for in-flight analyses after a shutdown signal
+const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+/// Deepest nesting of structs and lists accepted in request data
+const DEFAULT_MAX_DATA_DEPTH: usize = 64;
+/// Waiting time worth one priority level to a queued analyze call
+const DEFAULT_PRIORITY_AGING: Duration = Duration::from_secs(1);
+
+/// Metadata keys read for a request's priority (an integer) and deadline
+/// (RFC 3339) when the `AgentRequest` leaves them unset
+pub const PRIORITY_HEADER: &str = "x-priority";
+pub const DEADLINE_HEADER: &str = "x-deadline";
+
+/// Environment variable holding an explicit id for `stable_id` to return
+const AGENT_ID_ENV: &str = "PARALLAX_AGENT_ID";
+
+/// File in the temp directory keeping a random host id for `stable_id` on
+/// machines whose hostname can't be found
+const HOST_ID_FILE: &str = "parallax-host-id";
+
+/// Room left for the rest of an `AgentRequest` when capping decoded message
+/// size at `max_request_bytes`
+const REQUEST_ENVELOPE_BYTES: usize = 64 * 1024;
+
+/// Boxed future returned by an agent's analyze function
+pub type AnalyzeFuture<'a> =
+    futures::future::BoxFuture<'a, Result<AgentResult, Box<dyn std::error::Error>>>;
+
+/// Type-erased analyze function stored on the agent
+pub type AnalyzeFn = Arc<dyn Fn(&str, Option<serde_json::Value>) -> AnalyzeFuture<'_> + Send + Sync>;
+
+tokio::task_local! {
+    static ANALYZE_CONTEXT: AnalyzeContext;
+}
+
+/// Per-call state the agent hands an analyze function alongside the task
+///
+/// The setters that take a [`CancellationToken`] or [`LogSink`] pass them in
+/// from here. An [`AnalyzeFn`] assigned to
+/// [`analyze_fn`](ParallaxAgent::analyze_fn) directly can read them with
+/// [`current`](Self::current) when it is called.
+#[derive(Debug, Clone, Default)]
+pub struct AnalyzeContext {
+    cancellation: CancellationToken,
+    logs: LogSink,
+}
+
+impl AnalyzeContext {
+    pub(crate) fn new(cancellation: CancellationToken, logs: LogSink) -> Self {
+        Self { cancellation, logs }
+    }
+
+    /// Context of the analyze call in progress. Outside one, such as when
+    /// an analyze function is called directly, the token is never cancelled
+    /// and the sink is disabled.
+    pub fn current() -> Self {
+        ANALYZE_CONTEXT.try_with(Clone::clone).unwrap_or_default()
+    }
+
+    /// Token cancelled once the caller stops waiting for the result
+    pub fn cancellation(&self) -> &CancellationToken {
+        &self.cancellation
+    }
+
+    /// Sink for log lines streamed to `StreamAnalyze` callers
+    pub fn logs(&self) -> &LogSink {
+        &self.logs
+    }
+
+    /// Run `analyze` with this as the current context
+    pub(crate) fn scope<'a>(self, analyze: AnalyzeFuture<'a>) -> AnalyzeFuture<'a> {
+        Box::pin(ANALYZE_CONTEXT.scope(self, analyze))
+    }
+}
+
+/// Log lines buffered for a `StreamAnalyze` caller before further ones are
+/// dropped
+const LOG_STREAM_BUFFER: usize = 256;
+
 /// Options for gateway connection
 #[derive(Debug, Clone)]
 pub struct GatewayOptions {
@@ -69,39 +383,108 @@ impl Default for GatewayOptions {
     }
 }
 
-/// Calculate reconnect delay with exponential backoff
-fn calculate_reconnect_delay(
-    attempt: u32,
-    initial_delay_ms: u64,
-    max_delay_ms: u64,
-) -> u64 {
-    let delay = initial_delay_ms.saturating_mul(2u64.saturating_pow(attempt));
-    delay.min(max_delay_ms)
+/// `prefix` and a hash of `host` and `capabilities`. FNV-1a, unlike the
+/// standard library's hasher, gives the same hash on every Rust release.
+fn derive_stable_id(prefix: &str, capabilities: &[String], host: &str) -> String {
+    let mut capabilities = normalize_capabilities(capabilities, false);
+    capabilities.sort();
+
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let parts = std::iter::once(host).chain(capabilities.iter().map(String::as_str));
+    for byte in parts.flat_map(|part| part.bytes().chain([0])) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{}-{:016x}", prefix, hash)
 }
 
-/// Convert prost Value to serde_json Value
-fn prost_value_to_json(value: prost_types::Value) -> serde_json::Value {
-    use prost_types::value::Kind;
-    match value.kind {
-        Some(Kind::NullValue(_)) => serde_json::Value::Null,
-        Some(Kind::NumberValue(n)) => serde_json::json!(n),
-        Some(Kind::StringValue(s)) => serde_json::json!(s),
-        Some(Kind::BoolValue(b)) => serde_json::json!(b),
-        Some(Kind::StructValue(s)) => {
-            let map: serde_json::Map<String, serde_json::Value> = s.fields
-                .into_iter()
-                .map(|(k, v)| (k, prost_value_to_json(v)))
-                .collect();
-            serde_json::Value::Object(map)
-        }
-        Some(Kind::ListValue(list)) => {
-            let vec: Vec<serde_json::Value> = list.values
-                .into_iter()
-                .map(prost_value_to_json)
-                .collect();
-            serde_json::Value::Array(vec)
+/// Something identifying this machine: its hostname, or failing that a
+/// random id kept in the temp directory
+fn host_key() -> String {
+    hostname().unwrap_or_else(|| persisted_host_id(&std::env::temp_dir().join(HOST_ID_FILE)))
+}
+
+/// This machine's hostname, unless it can't be found or is only `localhost`
+fn hostname() -> Option<String> {
+    let read = |path: &str| std::fs::read_to_string(path).ok();
+    [
+        std::env::var("HOSTNAME").ok(),
+        std::env::var("COMPUTERNAME").ok(),
+        read("/proc/sys/kernel/hostname"),
+        read("/etc/hostname"),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|host| host.trim().to_string())
+    .find(|host| !host.is_empty() && host != "localhost" && host != "localhost.localdomain")
+}
+
+/// The id stored at `path`, creating it if there is none. Agents on
+/// different machines without a hostname then still get different ids.
+fn persisted_host_id(path: &std::path::Path) -> String {
+    if let Some(id) = std::fs::read_to_string(path)
+        .ok()
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+    {
+        return id;
+    }
+    let id = uuid::Uuid::new_v4().simple().to_string();
+    if let Err(error) = std::fs::write(path, &id) {
+        warn!(
+            "Could not save host id to {}, so stable_id will change on restart: {}",
+            path.display(),
+            error
+        );
+    }
+    id
+}
+
+/// Gateway `TaskError` code for a task the agent refused or failed
+fn gateway_error_code(code: tonic::Code) -> &'static str {
+    match code {
+        tonic::Code::InvalidArgument => "INVALID_ARGUMENT",
+        tonic::Code::ResourceExhausted => "RESOURCE_EXHAUSTED",
+        tonic::Code::Unavailable => "UNAVAILABLE",
+        _ => "INTERNAL",
+    }
+}
+
+/// Status a failed analysis is reported with, carrying an [`AgentError`]
+/// in its details when the analyze function returned one
+fn analysis_failure(error: Box<dyn std::error::Error>) -> Status {
+    match error.downcast_ref::<AgentError>() {
+        Some(agent_error) => agent_error.to_status(),
+        None => Status::internal(format!("analysis failed: {}", error)),
+    }
+}
+
+/// Strip a `{ "data": { ... } }` envelope from request data
+///
+/// Some callers wrap a task's payload in a `data` key inside the request's
+/// own `data`. An object whose only key is `data`, holding an object, is
+/// replaced by that inner object. Agents opt in with
+/// [`ParallaxAgent::with_unwrap_data_envelope`], as a payload can also have
+/// `data` as its only key on purpose.
+fn unwrap_data_envelope(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(mut map)
+            if map.len() == 1 && map.get("data").is_some_and(serde_json::Value::is_object) =>
+        {
+            map.remove("data").unwrap_or_default()
         }
-        None => serde_json::Value::Null,
+        other => other,
+    }
+}
+
+impl AgentRequest {
+    /// Deserialize the request's `data` into `T`
+    ///
+    /// Missing data deserializes from `null`, which suits `Option` and unit
+    /// types.
+    pub fn data_as<T: serde::de::DeserializeOwned>(&self) -> crate::Result<T> {
+        let data = self.data.clone().map(struct_to_json).unwrap_or_default();
+        Ok(serde_json::from_value(data)?)
     }
 }
 
@@ -109,24 +492,89 @@ fn prost_value_to_json(value: prost_types::Value) -> serde_json::Value {
 pub struct ParallaxAgent {
     pub id: String,
     pub name: String,
-    pub capabilities: Vec<String>,
+    // Swapped as a whole by `update_capabilities`; see `capabilities()`
+    capabilities: std::sync::RwLock<Vec<String>>,
+    // Whether capabilities are lowercased as well as trimmed and deduplicated
+    lowercase_capabilities: bool,
     pub metadata: HashMap<String, String>,
+    /// Declared competency per capability (0.0-1.0), reported to the
+    /// control plane for quality-weighted routing
+    pub capability_scores: HashMap<String, f64>,
+    // Confidence reported when nothing better is known (None = unset)
+    default_confidence: Option<f64>,
+    // Declared shape of result values, and whether results are checked
+    // against it before being returned
+    output_schema: Option<(serde_json::Value, Arc<jsonschema::Validator>)>,
+    validate_output: bool,
     
     // Internal state
-    registry_addr: String,
+    registry: RegistryConnection,
     lease_id: Arc<Mutex<Option<String>>>,
+    // Port the agent last registered with, reused when re-registering
+    registered_port: std::sync::Mutex<Option<u16>>,
+    registration: watch::Sender<RegistrationState>,
+    // Initial and maximum delay between registration attempts
+    registration_backoff: (Duration, Duration),
     shutdown_tx: Arc<Mutex<Option<tokio::sync::oneshot::Sender<()>>>>,
     
     // Gateway state
     gateway_shutdown_tx: Arc<Mutex<Option<tokio::sync::oneshot::Sender<()>>>>,
     gateway_reconnecting: Arc<Mutex<bool>>,
 
+    // Upper bound on a single analyze call (None = unbounded)
+    analyze_timeout: Option<Duration>,
+
+    // Time source for lease renewal, heartbeats, backoff and timeouts
+    clock: Arc<dyn Clock>,
+
+    // Concurrency limiting for analyze calls
+    concurrency_limit: Option<Arc<PrioritySlots>>,
+    reject_when_full: bool,
+    priority_aging: Duration,
+    in_flight: AtomicUsize,
+    // Per-client token buckets for gRPC analyze calls (None = unlimited)
+    rate_limit: Option<RateLimiter>,
+
+    // Set once shutdown starts; new analyze calls are refused from then on
+    draining: AtomicBool,
+    drain_timeout: Duration,
+    // Flipped when the drain timeout elapses, abandoning remaining analyses
+    force_closed: watch::Sender<bool>,
+
+    // Encoding accepted from and used towards callers (None = uncompressed)
+    compression: Option<CompressionEncoding>,
+    // Largest piece of a result value sent per `StreamAnalyze` message
+    // (None = whole results)
+    stream_chunk_size: Option<usize>,
+
+    // Limits on incoming request data (None = tonic's default message limit)
+    max_request_bytes: Option<usize>,
+    max_data_depth: usize,
+    omit_unset_fields: bool,
+    unwrap_data_envelope: bool,
+
+    // Request counters reported via the health check
+    stats: RequestStats,
+    health: std::sync::RwLock<HealthStatus>,
+
+    // Prometheus metrics (no-op without the `metrics` feature)
+    metrics: AgentMetrics,
+    #[cfg(feature = "metrics")]
+    metrics_port: Option<u16>,
+
+    // Middleware wrapped around analyze_fn, outermost first
+    middleware: Vec<Arc<dyn AnalyzeMiddleware>>,
+
     // The analysis function that subclasses implement
-    pub analyze_fn: Arc<dyn Fn(&str, Option<serde_json::Value>) -> futures::future::BoxFuture<'_, Result<AgentResult, Box<dyn std::error::Error>>> + Send + Sync>,
+    pub analyze_fn: AnalyzeFn,
 }
 
 impl ParallaxAgent {
     /// Create a new ParallaxAgent
+    ///
+    /// Capabilities are trimmed and deduplicated, and blank ones dropped;
+    /// use [`with_capabilities`](Self::with_capabilities) to reject blanks
+    /// instead.
     pub fn new(
         id: impl Into<String>,
         name: impl Into<String>,
@@ -135,22 +583,77 @@ impl ParallaxAgent {
     ) -> Self {
         let registry_addr = std::env::var("PARALLAX_REGISTRY")
             .unwrap_or_else(|_| "http://localhost:50051".to_string());
+        let id = id.into();
+        let default_confidence = metadata
+            .get(DEFAULT_CONFIDENCE_LABEL)
+            .and_then(|label| label.parse().ok());
             
         Self {
-            id: id.into(),
+            metrics: AgentMetrics::new(&id),
+            #[cfg(feature = "metrics")]
+            metrics_port: None,
+            id,
             name: name.into(),
-            capabilities,
+            capabilities: std::sync::RwLock::new(normalize_capabilities(capabilities, false)),
+            lowercase_capabilities: false,
             metadata,
-            registry_addr,
+            capability_scores: HashMap::new(),
+            default_confidence,
+            output_schema: None,
+            validate_output: false,
+            registry: RegistryConnection::new(registry_addr),
             lease_id: Arc::new(Mutex::new(None)),
+            registered_port: std::sync::Mutex::new(None),
+            registration: watch::Sender::new(RegistrationState::Unregistered),
+            registration_backoff: (Duration::from_secs(1), Duration::from_secs(30)),
             shutdown_tx: Arc::new(Mutex::new(None)),
             gateway_shutdown_tx: Arc::new(Mutex::new(None)),
             gateway_reconnecting: Arc::new(Mutex::new(false)),
+            analyze_timeout: None,
+            clock: Arc::new(SystemClock),
+            concurrency_limit: None,
+            reject_when_full: false,
+            priority_aging: DEFAULT_PRIORITY_AGING,
+            in_flight: AtomicUsize::new(0),
+            rate_limit: None,
+            draining: AtomicBool::new(false),
+            drain_timeout: DEFAULT_DRAIN_TIMEOUT,
+            force_closed: watch::Sender::new(false),
+            compression: None,
+            stream_chunk_size: None,
+            max_request_bytes: None,
+            max_data_depth: DEFAULT_MAX_DATA_DEPTH,
+            omit_unset_fields: false,
+            unwrap_data_envelope: false,
+            stats: RequestStats::default(),
+            health: std::sync::RwLock::new(HealthStatus::healthy()),
+            middleware: Vec::new(),
             analyze_fn: Arc::new(|_, _| Box::pin(async {
                 Err("analyze function not set".into())
             })),
         }
     }
+
+    /// An agent id that stays the same across restarts on this host, so a
+    /// restarted agent reclaims its lease and keeps its metrics
+    ///
+    /// `prefix` followed by a hash of the hostname and the capabilities,
+    /// which are normalized as by [`new`](Self::new) and compared in any
+    /// order. The capabilities are part of the id so that agents with
+    /// different roles on one host don't share an id, and an agent whose
+    /// capabilities change registers afresh rather than taking over a lease
+    /// that advertises the old ones; pass the same list given to `new`.
+    ///
+    /// The hostname comes from `$HOSTNAME`, `$COMPUTERNAME`,
+    /// `/proc/sys/kernel/hostname` or `/etc/hostname`. Where none gives one
+    /// (macOS, some containers) a random id saved in the temp directory
+    /// stands in for it. Set `PARALLAX_AGENT_ID` to use that id instead.
+    pub fn stable_id(prefix: &str, capabilities: &[String]) -> String {
+        match std::env::var(AGENT_ID_ENV) {
+            Ok(id) if !id.trim().is_empty() => id.trim().to_string(),
+            _ => derive_stable_id(prefix, capabilities, &host_key()),
+        }
+    }
     
     /// Set the analyze function
     pub fn set_analyze_fn<F, Fut>(mut self, f: F) -> Self 
@@ -161,668 +664,3476 @@ impl ParallaxAgent {
         self.analyze_fn = Arc::new(move |task, data| Box::pin(f(task, data)));
         self
     }
-    
-    /// Start the gRPC server and register with control plane
-    pub async fn serve(self: Arc<Self>, port: u16) -> Result<(), Box<dyn std::error::Error>> {
-        let addr = format!("0.0.0.0:{}", port).parse::<SocketAddr>()?;
-        
-        info!(
-            agent_id = %self.id,
-            agent_name = %self.name,
-            "Starting agent gRPC server on {}",
-            addr
-        );
-        
-        // Create shutdown channel
-        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
-        {
-            let mut tx = self.shutdown_tx.lock().await;
-            *tx = Some(shutdown_tx);
-        }
-        
-        // Register with control plane
-        let self_clone = Arc::clone(&self);
-        tokio::spawn(async move {
-            if let Err(e) = self_clone.register(port).await {
-                error!("Failed to register with control plane: {}", e);
-            }
+
+    /// Set an analyze function that also receives a [`CancellationToken`].
+    ///
+    /// The token is cancelled when the caller goes away (it cancels the RPC
+    /// or drops the connection), the request times out, or the agent
+    /// force-closes. The returned future is dropped at that point, so the
+    /// token matters for work it hands off, such as `spawn_blocking` loops
+    /// or threads, which should check it and stop early.
+    pub fn set_analyze_fn_cancellable<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(&str, Option<serde_json::Value>, CancellationToken) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<AgentResult, Box<dyn std::error::Error>>> + Send + 'static,
+    {
+        self.analyze_fn = Arc::new(move |task, data| {
+            let context = AnalyzeContext::current();
+            Box::pin(f(task, data, context.cancellation))
         });
-        
-        // Start lease renewal
-        let self_clone = Arc::clone(&self);
-        tokio::spawn(async move {
-            self_clone.start_lease_renewal().await;
+        self
+    }
+
+    /// Set an analyze function that also receives a [`LogSink`], alongside
+    /// the [`CancellationToken`] described for
+    /// [`set_analyze_fn_cancellable`](Self::set_analyze_fn_cancellable).
+    ///
+    /// Lines written to the sink during a `StreamAnalyze` call are streamed
+    /// to the caller ahead of the result; for other calls they are dropped.
+    pub fn set_analyze_fn_with_logs<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(&str, Option<serde_json::Value>, CancellationToken, LogSink) -> Fut
+            + Send
+            + Sync
+            + 'static,
+        Fut: std::future::Future<Output = Result<AgentResult, Box<dyn std::error::Error>>> + Send + 'static,
+    {
+        self.analyze_fn = Arc::new(move |task, data| {
+            let context = AnalyzeContext::current();
+            Box::pin(f(task, data, context.cancellation, context.logs))
         });
-        
-        // Create gRPC service
-        let service = ConfidenceAgentServer::new(self);
-        
-        // Start server with graceful shutdown
-        Server::builder()
-            .add_service(service)
-            .serve_with_shutdown(addr, async {
-                let _ = shutdown_rx.await;
-                info!("Shutting down gRPC server");
-            })
-            .await?;
-            
-        Ok(())
+        self
     }
-    
-    /// Register with the control plane
-    async fn register(&self, port: u16) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let channel = tonic::transport::Endpoint::from_shared(self.registry_addr.clone())?
-            .connect()
-            .await?;
-        let mut client = RegistryClient::new(channel);
-        
-        let agent_reg = AgentRegistration {
-            id: self.id.clone(),
-            name: self.name.clone(),
-            endpoint: format!("localhost:{}", port),
-            capabilities: self.capabilities.clone(),
-            metadata: Some(agent_registration::Metadata {
-                labels: self.metadata.clone(),
-                version: String::new(),
-                region: String::new(),
-                default_confidence: 0.0,
-            }),
+
+    /// Set an analyze function returning a bare value, scoring its
+    /// confidence with [`with_confidence`] under
+    /// [`confidence_config`](Self::confidence_config). Set the default
+    /// confidence first, as the configuration is taken when this is called.
+    pub fn set_analyze_fn_with_confidence<F, Fut>(mut self, f: F) -> Self
+    where
+        F: Fn(&str, Option<serde_json::Value>) -> Fut + Send + Sync + 'static,
+        Fut: std::future::Future<Output = Result<serde_json::Value, Box<dyn std::error::Error>>> + Send + 'static,
+    {
+        let analyze = with_confidence(f, Some(self.confidence_config()));
+        self.analyze_fn = Arc::new(analyze);
+        self
+    }
+
+    /// Wrap the analyze function in `middleware`. Middleware runs in the
+    /// order it is added, so the first one added sees each task first.
+    pub fn with_middleware(mut self, middleware: impl AnalyzeMiddleware + 'static) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Run a task through the middleware chain and the analyze function
+    pub(crate) fn run_analyze<'a>(
+        &'a self,
+        task: &'a str,
+        data: Option<serde_json::Value>,
+        cancellation: CancellationToken,
+        logs: LogSink,
+    ) -> AnalyzeFuture<'a> {
+        let context = AnalyzeContext::new(cancellation, logs);
+        Next::new(&self.middleware, &self.analyze_fn, context).run(task, data)
+    }
+
+    /// Run a task through the same pipeline as an `Analyze` call, minus the
+    /// network: request validation, concurrency limits, middleware, the
+    /// timeout, stats and metrics all apply. This is the way to test an
+    /// agent without serving it.
+    ///
+    /// Failures come back as they would to a gRPC caller, so an
+    /// [`AgentError`] is available through [`Error::agent_error`](crate::Error::agent_error).
+    pub async fn analyze_local(
+        &self,
+        task: &str,
+        data: Option<serde_json::Value>,
+    ) -> crate::Result<AgentResult> {
+        let request = AgentRequest {
+            task_description: task.to_string(),
+            data: data.map(json_to_struct),
             ..Default::default()
         };
-        
-        let request = Request::new(RegisterRequest {
-            agent: Some(agent_reg),
-            auto_renew: true,
-        });
-        
-        let response = client.register(request).await?;
-        let resp = response.into_inner();
-        
-        if !resp.lease_id.is_empty() {
-            let mut lid = self.lease_id.lock().await;
-            *lid = Some(resp.lease_id.clone());
-            info!(
-                agent_id = %self.id,
-                lease_id = %resp.lease_id,
-                "Agent registered with control plane"
-            );
+        let response = self
+            .handle_analyze(Request::new(request), LogSink::disabled(), None)
+            .await?;
+        agent_result_from_response(response)
+    }
+
+    /// Declare the agent's competency (0.0-1.0) for a capability. Scores are
+    /// validated when the agent starts serving.
+    pub fn capability_score(mut self, capability: impl Into<String>, score: f64) -> Self {
+        self.capability_scores.insert(capability.into(), score);
+        self
+    }
+
+    /// Average of the declared capability scores, or
+    /// `DEFAULT_EXPERTISE_LEVEL` when none are declared
+    pub fn expertise_level(&self) -> f64 {
+        if self.capability_scores.is_empty() {
+            return DEFAULT_EXPERTISE_LEVEL;
         }
-        
-        Ok(())
+        self.capability_scores.values().sum::<f64>() / self.capability_scores.len() as f64
     }
-    
-    /// Start lease renewal loop
-    async fn start_lease_renewal(&self) {
-        let mut interval = interval(Duration::from_secs(30));
-        
-        loop {
-            interval.tick().await;
-            
-            let lease_id = {
-                let lid = self.lease_id.lock().await;
-                lid.clone()
-            };
-            
-            if let Some(lease_id) = lease_id {
-                match self.renew_lease(&lease_id).await {
-                    Ok(true) => {
-                        // Lease renewed successfully
-                    }
-                    Ok(false) => {
-                        warn!("Lease renewal failed, re-registering");
-                        if let Err(e) = self.register(0).await { // Use 0 to keep same port
-                            error!("Failed to re-register: {}", e);
-                        }
-                    }
-                    Err(e) => {
-                        error!("Error renewing lease: {}", e);
-                    }
-                }
-            }
+
+    /// Set the confidence (0.0-1.0) the agent registers with and falls back
+    /// to when none can be extracted from a result, overriding the
+    /// `default_confidence` metadata label
+    pub fn with_default_confidence(mut self, confidence: f64) -> crate::Result<Self> {
+        if !(0.0..=1.0).contains(&confidence) {
+            return Err(crate::Error::InvalidArgument(format!(
+                "default confidence must be between 0 and 1, got {}",
+                confidence
+            )));
         }
+        self.default_confidence = Some(confidence);
+        Ok(self)
     }
-    
-    /// Renew lease with registry
-    async fn renew_lease(&self, lease_id: &str) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
-        let channel = tonic::transport::Endpoint::from_shared(self.registry_addr.clone())?
-            .connect()
-            .await?;
-        let mut client = RegistryClient::new(channel);
-        
-        let request = Request::new(RenewRequest {
-            lease_id: lease_id.to_string(),
-            ttl: None,
-        });
-        
-        let response = client.renew(request).await?;
-        Ok(response.into_inner().success)
+
+    /// The configured default confidence, if any
+    pub fn default_confidence(&self) -> Option<f64> {
+        self.default_confidence
     }
-    
-    /// Connect to the control plane via the Agent Gateway (bidirectional stream).
-    /// Use this instead of serve() for agents behind NAT or without a public endpoint.
-    /// The agent opens an outbound connection; tasks are received through the stream.
-    /// Boxed wrapper: the connection task recursively re-enters this fn on
-    /// reconnect, so the future must be boxed for Send inference to resolve
-    pub fn connect_via_gateway<'a>(
-        self: &'a Arc<Self>,
-        endpoint: &'a str,
-        options: Option<GatewayOptions>,
-    ) -> futures::future::BoxFuture<'a, Result<(), Box<dyn std::error::Error + Send + Sync>>>
+
+    /// Declare the JSON schema result values follow, advertised to callers
+    /// through `GetCapabilities`. The schema must be a JSON object.
+    pub fn with_output_schema(mut self, schema: serde_json::Value) -> crate::Result<Self> {
+        if !schema.is_object() {
+            return Err(crate::Error::InvalidArgument(
+                "output schema must be a JSON object".to_string(),
+            ));
+        }
+        let validator = jsonschema::validator_for(&schema).map_err(|error| {
+            crate::Error::InvalidArgument(format!("invalid output schema: {}", error))
+        })?;
+        self.output_schema = Some((schema, Arc::new(validator)));
+        Ok(self)
+    }
+
+    /// Check each result value against the output schema before returning
+    /// it, failing the call with `INTERNAL` if it doesn't match. Off by
+    /// default; has no effect without an output schema.
+    pub fn with_output_validation(mut self, enabled: bool) -> Self {
+        self.validate_output = enabled;
+        self
+    }
+
+    /// The declared output schema, if any
+    pub fn output_schema(&self) -> Option<&serde_json::Value> {
+        self.output_schema.as_ref().map(|(schema, _)| schema)
+    }
+
+    /// Fail a result value that breaks the agent's own output schema, when
+    /// output validation is on
+    fn check_output(&self, value: &serde_json::Value) -> Result<(), Status> {
+        let Some((_, validator)) = self.output_schema.as_ref().filter(|_| self.validate_output)
+        else {
+            return Ok(());
+        };
+        let problems = schema_violations(validator, value);
+        if problems.is_empty() {
+            return Ok(());
+        }
+        error!(
+            "Agent {} returned a value that breaks its output schema: {}",
+            self.id,
+            problems.join("; ")
+        );
+        Err(Status::internal(format!(
+            "result does not match the output schema of agent {}: {}",
+            self.id,
+            problems.join("; ")
+        )))
+    }
+
+    /// Confidence extraction settings using the agent's default confidence
+    /// as the fallback, for use with [`with_confidence`]
+    pub fn confidence_config(&self) -> ConfidenceConfig {
+        let mut config = ConfidenceConfig::default();
+        if let Some(confidence) = self.default_confidence {
+            config.default_confidence = confidence;
+        }
+        config
+    }
+
+    /// What the agent advertises, as a JSON document for discovery tooling
+    /// and CI checks
+    ///
+    /// Holds `id`, `name`, `capabilities` (in declared order),
+    /// `capability_scores`, `metadata` and `default_confidence` (`null` when
+    /// unset). Map keys are sorted, so the same agent always produces the
+    /// same document.
+    pub fn manifest(&self) -> serde_json::Value {
+        // Serialized from sorted maps, so the order holds even when
+        // serde_json preserves insertion order
+        let capability_scores: BTreeMap<_, _> = self.capability_scores.iter().collect();
+        let metadata: BTreeMap<_, _> = self.metadata.iter().collect();
+
+        serde_json::json!({
+            "id": self.id,
+            "name": self.name,
+            "capabilities": self.capabilities(),
+            "capability_scores": capability_scores,
+            "metadata": metadata,
+            "default_confidence": self.default_confidence,
+        })
+    }
+
+    /// Check the default confidence and every declared capability score
+    /// are within 0.0-1.0
+    fn validate_capability_scores(&self) -> Result<(), String> {
+        if let Some(confidence) = self.default_confidence {
+            if !(0.0..=1.0).contains(&confidence) {
+                return Err(format!(
+                    "default confidence must be between 0 and 1, got {}",
+                    confidence
+                ));
+            }
+        }
+
+        let mut invalid: Vec<String> = self
+            .capability_scores
+            .iter()
+            .filter(|(_, score)| !(0.0..=1.0).contains(*score))
+            .map(|(capability, score)| format!("{}={}", capability, score))
+            .collect();
+        if invalid.is_empty() {
+            return Ok(());
+        }
+        invalid.sort();
+        Err(format!(
+            "capability scores must be between 0 and 1: {}",
+            invalid.join(", ")
+        ))
+    }
+
+    /// Replace the capabilities, normalized as by [`new`](Self::new).
+    /// Returns `Error::InvalidArgument` if any is blank.
+    pub fn with_capabilities<I, S>(self, capabilities: I) -> crate::Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
     {
-        Box::pin(self.connect_via_gateway_inner(endpoint, options))
+        let capabilities = checked_capabilities(capabilities, self.lowercase_capabilities)?;
+        *self.capabilities.write().unwrap() = capabilities;
+        Ok(self)
+    }
+
+    /// Lowercase capabilities, merging ones that differ only in case
+    /// (default: false). Applies to the current set and later updates.
+    pub fn with_lowercase_capabilities(mut self, lowercase: bool) -> Self {
+        self.lowercase_capabilities = lowercase;
+        let capabilities = self.capabilities.get_mut().unwrap();
+        *capabilities = normalize_capabilities(capabilities.iter(), lowercase);
+        self
+    }
+
+    /// Register with the registry at `registry_addr` instead of the
+    /// `PARALLAX_REGISTRY` environment variable
+    pub fn with_registry_addr(mut self, registry_addr: impl Into<String>) -> Self {
+        self.registry = RegistryConnection::new(registry_addr.into());
+        self
+    }
+
+    /// Set the initial and maximum delay between registration attempts
+    /// (default: 1s doubling up to 30s)
+    pub fn with_registration_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.registration_backoff = (initial, max);
+        self
+    }
+
+    /// Drive lease renewal, heartbeats, reconnect backoff and timeouts from
+    /// `clock` instead of real time, e.g. a [`MockClock`](crate::MockClock)
+    /// in tests
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Current registration state with the control plane
+    pub fn registration_state(&self) -> RegistrationState {
+        *self.registration.borrow()
+    }
+
+    /// Observe registration state changes
+    pub fn watch_registration(&self) -> watch::Receiver<RegistrationState> {
+        self.registration.subscribe()
+    }
+
+    /// Set the timeout applied to each analyze call. A non-zero `timeout_ms`
+    /// on the incoming `AgentRequest` takes precedence over this value.
+    pub fn with_analyze_timeout(mut self, timeout: Duration) -> Self {
+        self.analyze_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit how many analyze calls may run at once. Calls beyond the limit
+    /// wait for a free slot unless `with_reject_when_full(true)` is set.
+    /// Waiting calls are started in order of their request's `priority`,
+    /// highest first, each gaining a level for every second it waits (see
+    /// [`with_priority_aging`](Self::with_priority_aging)), and dropped with
+    /// `DEADLINE_EXCEEDED` if their `deadline` passes first.
+    pub fn with_max_concurrent_requests(mut self, max_concurrent_requests: NonZeroUsize) -> Self {
+        self.concurrency_limit = Some(PrioritySlots::new(max_concurrent_requests.get(), self.priority_aging));
+        self
+    }
+
+    /// Set how long a call waiting for a concurrency slot must wait to gain
+    /// one priority level (default: 1s), so low-priority calls are not
+    /// starved by a steady stream of higher ones. `Duration::MAX` turns
+    /// aging off; `Duration::ZERO` serves waiting calls in arrival order.
+    pub fn with_priority_aging(mut self, aging: Duration) -> Self {
+        self.priority_aging = aging;
+        if let Some(slots) = &self.concurrency_limit {
+            self.concurrency_limit = Some(PrioritySlots::new(slots.capacity(), aging));
+        }
+        self
+    }
+
+    /// Reject analyze calls with `RESOURCE_EXHAUSTED` instead of queueing
+    /// them when the concurrency limit is reached
+    pub fn with_reject_when_full(mut self, reject_when_full: bool) -> Self {
+        self.reject_when_full = reject_when_full;
+        self
+    }
+
+    /// Limit each client to `rate` analyze calls per second, in bursts of up
+    /// to `burst`. Clients are told apart by their IP address, so callers
+    /// behind one proxy or NAT share a budget; up to 1024 clients are tracked
+    /// at once. Calls over the limit fail with `RESOURCE_EXHAUSTED` and a
+    /// `retry-after` entry giving the seconds to wait. Gateway tasks are not
+    /// limited.
+    pub fn with_rate_limit(mut self, rate: f64, burst: u32) -> crate::Result<Self> {
+        if !(rate.is_finite() && rate > 0.0) {
+            return Err(crate::Error::InvalidArgument(format!(
+                "rate limit must be a positive number of calls per second, got {}",
+                rate
+            )));
+        }
+        self.rate_limit = Some(RateLimiter::new(rate, burst));
+        Ok(self)
+    }
+
+    /// Set how long `serve` waits for in-flight analyses to finish after a
+    /// shutdown signal before closing anyway (default: 30s)
+    pub fn with_drain_timeout(mut self, drain_timeout: Duration) -> Self {
+        self.drain_timeout = drain_timeout;
+        self
+    }
+
+    /// Accept requests compressed with `encoding` and compress responses with
+    /// it when the caller advertises support. Uncompressed requests are still
+    /// accepted.
+    pub fn with_compression(mut self, encoding: CompressionEncoding) -> Self {
+        self.compression = Some(encoding);
+        self
+    }
+
+    /// Split `StreamAnalyze` results whose JSON value is longer than `bytes`
+    /// across several messages, so a large value isn't encoded as one
+    /// message. The control plane puts them back together itself; other
+    /// callers reassemble them with a
+    /// [`ResultAssembler`](crate::ResultAssembler).
+    pub fn with_stream_chunk_size(mut self, bytes: usize) -> Self {
+        self.stream_chunk_size = Some(bytes);
+        self
+    }
+
+    /// Messages sending `result` to a `StreamAnalyze` caller
+    fn result_frames(
+        &self,
+        result: ConfidenceResult,
+    ) -> impl futures::Stream<Item = ConfidenceResult> + Send {
+        let chunk_size = self.stream_chunk_size.unwrap_or(usize::MAX);
+        futures::stream::iter(result_frames(result, chunk_size))
+    }
+
+    /// Reject requests whose `data` encodes to more than `bytes` with
+    /// `RESOURCE_EXHAUSTED`. Messages far beyond the limit are refused by
+    /// the transport before they are buffered, with the same code.
+    pub fn with_max_request_bytes(mut self, bytes: usize) -> Self {
+        self.max_request_bytes = Some(bytes);
+        self
+    }
+
+    /// Reject request data whose structs and lists nest more than `depth`
+    /// levels deep with `INVALID_ARGUMENT` (default: 64)
+    pub fn with_max_data_depth(mut self, depth: usize) -> Self {
+        self.max_data_depth = depth;
+        self
+    }
+
+    /// Leave request data fields whose protobuf value has no kind out of the
+    /// JSON passed to `analyze_fn`, instead of passing them as `null`
+    /// (default: false). Agents that validate strictly can then tell a
+    /// missing field from an explicit `null`.
+    pub fn with_omit_unset_fields(mut self, omit: bool) -> Self {
+        self.omit_unset_fields = omit;
+        self
+    }
+
+    /// Pass request data shaped `{ "data": { ... } }`, with nothing beside
+    /// the inner object, to `analyze_fn` as that inner object (default:
+    /// false). For callers that wrap the payload a second time; the control
+    /// plane sends a pattern input's `data` as the request data itself.
+    pub fn with_unwrap_data_envelope(mut self, unwrap: bool) -> Self {
+        self.unwrap_data_envelope = unwrap;
+        self
+    }
+
+    /// Serve Prometheus metrics over HTTP on `port` alongside the gRPC server
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics_port(mut self, port: u16) -> Self {
+        self.metrics_port = Some(port);
+        self
+    }
+
+    /// Registry holding this agent's metrics, for registering extra collectors
+    #[cfg(feature = "metrics")]
+    pub fn metrics_registry(&self) -> &prometheus::Registry {
+        self.metrics.registry()
+    }
+
+    /// Current metrics in the Prometheus text exposition format
+    #[cfg(feature = "metrics")]
+    pub fn metrics_handle(&self) -> String {
+        self.metrics.encode()
+    }
+
+    /// Capabilities the agent currently advertises
+    pub fn capabilities(&self) -> Vec<String> {
+        self.capabilities.read().unwrap().clone()
+    }
+
+    /// Replace the advertised capabilities at runtime
+    ///
+    /// The set is normalized as by [`new`](Self::new), and rejected if any
+    /// capability is blank. `GetCapabilities` reports the new set
+    /// immediately. A registered agent also re-registers so the control
+    /// plane routes by it; agents connected through the gateway advertise it
+    /// when they next connect.
+    pub async fn update_capabilities(
+        &self,
+        capabilities: Vec<String>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let capabilities = checked_capabilities(capabilities, self.lowercase_capabilities)?;
+        info!(agent_id = %self.id, "Updating capabilities to {:?}", capabilities);
+        *self.capabilities.write().unwrap() = capabilities;
+
+        let port = *self.registered_port.lock().unwrap();
+        match port {
+            Some(port) if self.registration_state() == RegistrationState::Registered => {
+                self.register(port).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Report `health` from the health check and gateway heartbeats
+    pub fn set_health(&self, health: HealthStatus) {
+        *self.health.write().unwrap() = health;
+    }
+
+    /// Health currently reported by the agent
+    pub fn health(&self) -> HealthStatus {
+        self.health.read().unwrap().clone()
+    }
+
+    /// Number of analyze calls currently executing
+    pub fn in_flight_requests(&self) -> usize {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Check request data against the configured limits and convert it to JSON
+    fn decode_data(
+        &self,
+        data: Option<prost_types::Struct>,
+    ) -> Result<Option<serde_json::Value>, Status> {
+        let Some(data) = data else {
+            return Ok(None);
+        };
+
+        if let Some(limit) = self.max_request_bytes {
+            let size = prost::Message::encoded_len(&data);
+            if size > limit {
+                return Err(Status::resource_exhausted(format!(
+                    "request data is {} bytes, the limit is {} bytes",
+                    size, limit
+                )));
+            }
+        }
+
+        struct_to_json_within(data, self.max_data_depth, self.omit_unset_fields)
+            .map(|data| {
+                Some(if self.unwrap_data_envelope {
+                    unwrap_data_envelope(data)
+                } else {
+                    data
+                })
+            })
+            .ok_or_else(|| {
+                Status::invalid_argument(format!(
+                    "request data is nested more than {} levels deep",
+                    self.max_data_depth
+                ))
+            })
+    }
+
+    /// Run the analyze pipeline for a decoded request. With a `chunk_size`,
+    /// the result is bound for `StreamAnalyze` and a value that will be split
+    /// at it is sent as JSON only.
+    async fn analyze_request(
+        &self,
+        req: AgentRequest,
+        logs: LogSink,
+        chunk_size: Option<usize>,
+    ) -> Result<Response<ConfidenceResult>, Status> {
+        if req.task_description.is_empty() {
+            return Err(Status::invalid_argument("task description is required"));
+        }
+        if self.draining.load(Ordering::Relaxed) {
+            return Err(Status::unavailable("agent is shutting down"));
+        }
+        
+        let data = self.decode_data(req.data)?;
+        let deadline = match req.deadline {
+            Some(deadline) => {
+                let remaining = std::time::SystemTime::try_from(deadline)
+                    .map_err(|_| Status::invalid_argument("request deadline is out of range"))?
+                    .duration_since(std::time::SystemTime::now())
+                    .unwrap_or_default();
+                if remaining.is_zero() {
+                    return Err(Status::deadline_exceeded("request deadline has already passed"));
+                }
+                Some(self.clock.now() + remaining)
+            }
+            None => None,
+        };
+        
+        // Hold a concurrency slot for the duration of the call
+        let _slot = self.acquire_slot(req.priority, deadline).await?;
+        let _in_flight = InFlightGuard::new(&self.in_flight);
+
+        // Cancel the token however this call ends, including tonic dropping
+        // the handler because the client went away
+        let cancellation = CancellationToken::new();
+        let _cancel_on_drop = cancellation.clone().drop_guard();
+
+        // Call the analyze function, bounded by the effective timeout and
+        // abandoned if the agent force-closes first. The deadline only
+        // bounded the wait for a slot; once started, the analysis runs to
+        // its timeout.
+        let timeout = self.effective_timeout(req.timeout_ms);
+        let analysis = self.run_analyze(&req.task_description, data, cancellation, logs);
+        let bounded = async {
+            match timeout {
+                Some(limit) => clock::timeout(&*self.clock, limit, analysis).await.ok_or_else(|| {
+                    Status::deadline_exceeded(format!(
+                        "analysis exceeded timeout of {}ms",
+                        limit.as_millis()
+                    ))
+                }),
+                None => Ok(analysis.await),
+            }
+        };
+        let mut force_closed = self.force_closed.subscribe();
+        let result = tokio::select! {
+            result = bounded => result?,
+            _ = force_closed.wait_for(|closed| *closed) => {
+                return Err(Status::unavailable("agent shut down before the analysis finished"));
+            }
+        }
+        .map_err(analysis_failure)?;
+        self.check_output(&result.value)?;
+
+        let cache_ttl = result.cache_ttl;
+
+        // Build response, with the value both as JSON text and structured
+        // unless it is about to be split into chunks
+        let value_json = serde_json::to_string(&result.value)
+            .map_err(|e| Status::internal(format!("failed to serialize result: {}", e)))?;
+        let value_struct = match chunk_size {
+            Some(chunk_size) if is_chunked(value_json.len(), chunk_size) => None,
+            _ => Some(json_to_struct(result.value.clone())),
+        };
+        let response = ConfidenceResult {
+            value_json,
+            value_struct,
+            confidence: result.confidence,
+            agent_id: self.id.clone(),
+            timestamp: Some(prost_types::Timestamp::from(std::time::SystemTime::now())),
+            reasoning: result.reasoning_text().unwrap_or_default(),
+            metadata: result.wire_metadata(),
+            uncertainties: result.uncertainties,
+            log: None,
+            more_chunks: false,
+        };
+
+        let mut response = Response::new(response);
+        if let Some(ttl) = cache_ttl {
+            if let Ok(value) = format!("max-age={}", ttl.as_secs()).parse() {
+                response.metadata_mut().insert("cache-control", value);
+            }
+        }
+
+        Ok(response)
+    }
+
+    /// Acquire a concurrency slot, if a limit is configured, queueing by
+    /// `priority` and giving up once `deadline` passes
+    async fn acquire_slot(
+        &self,
+        priority: i32,
+        deadline: Option<Instant>,
+    ) -> Result<Option<Slot>, Status> {
+        let Some(slots) = &self.concurrency_limit else {
+            return Ok(None);
+        };
+
+        if self.reject_when_full {
+            return slots
+                .try_acquire()
+                .map(Some)
+                .ok_or_else(|| Status::resource_exhausted("agent is at its concurrent request limit"));
+        }
+        let now = self.clock.now();
+        let Some(deadline) = deadline else {
+            return Ok(Some(slots.acquire(priority, now).await));
+        };
+        let remaining = deadline.saturating_duration_since(now);
+        clock::timeout(&*self.clock, remaining, slots.acquire(priority, now))
+            .await
+            .map(Some)
+            .ok_or_else(|| Status::deadline_exceeded("request deadline passed while queued"))
+    }
+
+    /// Take a call from the caller's rate limit bucket, if a limit is configured
+    fn check_rate_limit<T>(&self, request: &Request<T>) -> Result<(), Status> {
+        let Some(limiter) = &self.rate_limit else {
+            return Ok(());
+        };
+
+        let client = request.remote_addr().map(|addr| addr.ip());
+        limiter.acquire(client, self.clock.now()).map_err(|wait| {
+            let mut status = match client {
+                Some(client) => Status::resource_exhausted(format!(
+                    "client {} is over the agent's rate limit",
+                    client
+                )),
+                None => Status::resource_exhausted("over the agent's rate limit"),
+            };
+            let seconds = wait.as_secs_f64().ceil().max(1.0) as u64;
+            if let Ok(value) = seconds.to_string().parse() {
+                status.metadata_mut().insert("retry-after", value);
+            }
+            status
+        })
+    }
+
+    /// Resolve the timeout for a request, preferring the request's own deadline
+    fn effective_timeout(&self, request_timeout_ms: i32) -> Option<Duration> {
+        if request_timeout_ms > 0 {
+            Some(Duration::from_millis(request_timeout_ms as u64))
+        } else {
+            self.analyze_timeout
+        }
+    }
+    
+    /// Start the gRPC server and register with control plane
+    ///
+    /// On shutdown the server stops accepting requests, waits up to the
+    /// drain timeout for in-flight analyses, then closes and unregisters.
+    pub async fn serve(self: Arc<Self>, port: u16) -> Result<(), Box<dyn std::error::Error>> {
+        self.validate_capability_scores()?;
+        let addr = format!("0.0.0.0:{}", port).parse::<SocketAddr>()?;
+        
+        info!(
+            agent_id = %self.id,
+            agent_name = %self.name,
+            "Starting agent gRPC server on {}",
+            addr
+        );
+        
+        // Create shutdown channel
+        self.draining.store(false, Ordering::Relaxed);
+        self.force_closed.send_replace(false);
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+        {
+            let mut tx = self.shutdown_tx.lock().await;
+            *tx = Some(shutdown_tx);
+        }
+        
+        // Register with control plane and keep the lease alive
+        let self_clone = Arc::clone(&self);
+        let supervisor = tokio::spawn(async move {
+            self_clone.supervise_registration(port).await;
+        });
+        
+        #[cfg(feature = "metrics")]
+        let metrics_server = self.metrics_port.map(|metrics_port| {
+            let metrics = self.metrics.clone();
+            tokio::spawn(async move {
+                if let Err(e) = metrics.serve(metrics_port).await {
+                    error!("Metrics server failed: {}", e);
+                }
+            })
+        });
+
+        // Create gRPC service
+        let service = Arc::clone(&self).into_service();
+        
+        // Start server with graceful shutdown, draining in-flight analyses
+        let (drain_tx, drain_rx) = tokio::sync::oneshot::channel();
+        let agent = Arc::clone(&self);
+        let server = Server::builder()
+            .add_service(service)
+            .serve_with_shutdown(addr, async move {
+                let _ = shutdown_rx.await;
+                info!(
+                    "Shutting down gRPC server, draining {} in-flight analyses",
+                    agent.in_flight_requests()
+                );
+                let _ = drain_tx.send(());
+            });
+        let drain_deadline = async {
+            match drain_rx.await {
+                Ok(()) => self.clock.sleep(self.drain_timeout).await,
+                Err(_) => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            result = server => result?,
+            _ = drain_deadline => {
+                warn!(
+                    "Drain timeout of {:?} elapsed with {} analyses in flight, closing",
+                    self.drain_timeout,
+                    self.in_flight_requests()
+                );
+                self.force_closed.send_replace(true);
+            }
+        }
+
+        supervisor.abort();
+        #[cfg(feature = "metrics")]
+        if let Some(metrics_server) = metrics_server {
+            metrics_server.abort();
+        }
+
+        if self.registration_state() == RegistrationState::Registered {
+            if let Err(e) = self.unregister().await {
+                warn!("Failed to unregister from control plane: {}", e);
+            }
+        }
+        *self.lease_id.lock().await = None;
+        self.registration.send_replace(RegistrationState::Unregistered);
+            
+        Ok(())
+    }
+    
+    /// Wrap the agent in its gRPC service, applying the configured compression
+    /// and request size limit, behind the request context interceptor
+    fn into_service(
+        self: Arc<Self>,
+    ) -> OversizeAsExhausted<InterceptedService<ConfidenceAgentServer<Arc<Self>>, ContextInterceptor>> {
+        let compression = self.compression;
+        let max_request_bytes = self.max_request_bytes;
+        let mut service = ConfidenceAgentServer::new(self);
+        if let Some(limit) = max_request_bytes {
+            service = service
+                .max_decoding_message_size(limit.saturating_add(REQUEST_ENVELOPE_BYTES));
+        }
+        if let Some(encoding) = compression {
+            service = service.accept_compressed(encoding).send_compressed(encoding);
+        }
+        OversizeAsExhausted(InterceptedService::new(
+            service,
+            request_context::extract as ContextInterceptor,
+        ))
+    }
+
+    /// Register with the control plane
+    async fn register(&self, port: u16) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let agent_reg = AgentRegistration {
+            id: self.id.clone(),
+            name: self.name.clone(),
+            endpoint: format!("localhost:{}", port),
+            capabilities: self.capabilities(),
+            metadata: Some(agent_registration::Metadata {
+                labels: self.metadata.clone(),
+                version: String::new(),
+                region: String::new(),
+                default_confidence: self.default_confidence.unwrap_or(0.0),
+            }),
+            ..Default::default()
+        };
+        
+        let request = Request::new(RegisterRequest {
+            agent: Some(agent_reg),
+            auto_renew: true,
+        });
+        
+        let resp = self
+            .registry
+            .call(|mut client| async move { client.register(request).await })
+            .await?
+            .into_inner();
+        *self.registered_port.lock().unwrap() = Some(port);
+        
+        if !resp.lease_id.is_empty() {
+            let mut lid = self.lease_id.lock().await;
+            *lid = Some(resp.lease_id.clone());
+            info!(
+                agent_id = %self.id,
+                lease_id = %resp.lease_id,
+                "Agent registered with control plane"
+            );
+        }
+        
+        Ok(())
     }
+    
+    /// Keep the agent registered: retry registration with backoff until it
+    /// succeeds, renew the lease, and start over if the lease is lost
+    async fn supervise_registration(&self, port: u16) {
+        let (initial_delay, max_delay) = self.registration_backoff;
+
+        loop {
+            self.registration.send_replace(RegistrationState::Registering);
+
+            let mut attempt = 0;
+            while let Err(e) = self.register(port).await {
+                let delay = calculate_reconnect_delay(
+                    attempt,
+                    initial_delay.as_millis() as u64,
+                    max_delay.as_millis() as u64,
+                );
+                warn!(
+                    "Failed to register with control plane (attempt {}), retrying in {}ms: {}",
+                    attempt + 1,
+                    delay,
+                    e
+                );
+                self.clock.sleep(Duration::from_millis(delay)).await;
+                attempt = attempt.saturating_add(1);
+            }
+
+            self.registration.send_replace(RegistrationState::Registered);
+            self.maintain_lease().await;
+
+            *self.lease_id.lock().await = None;
+            self.registration.send_replace(RegistrationState::Unregistered);
+            warn!("Lease lost, re-registering with control plane");
+        }
+    }
+
+    /// Renew the lease periodically, returning once it is lost: the registry
+    /// rejects a renewal or no longer knows the lease, or renewals fail
+    /// `MAX_RENEWAL_FAILURES` times in a row
+    async fn maintain_lease(&self) {
+        let mut ticks = self.clock.interval(LEASE_RENEWAL_INTERVAL);
+        ticks.next().await;
+        let mut failures = 0;
+
+        loop {
+            ticks.next().await;
+
+            let lease_id = {
+                let lid = self.lease_id.lock().await;
+                lid.clone()
+            };
+            let Some(lease_id) = lease_id else {
+                continue;
+            };
+
+            match self.renew_lease(&lease_id).await {
+                Ok(true) => failures = 0,
+                Ok(false) => {
+                    warn!("Registry rejected lease renewal");
+                    return;
+                }
+                Err(e) => {
+                    // An evicted lease won't come back; renewing it again is pointless
+                    if let Some(status) = e.downcast_ref::<Status>().filter(|status| {
+                        matches!(status.code(), tonic::Code::NotFound | tonic::Code::Unauthenticated)
+                    }) {
+                        warn!("Registry no longer recognizes lease {}: {}", lease_id, status.message());
+                        return;
+                    }
+                    failures += 1;
+                    error!("Error renewing lease ({}/{}): {}", failures, MAX_RENEWAL_FAILURES, e);
+                    if failures >= MAX_RENEWAL_FAILURES {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+    
+    /// Remove this agent from the registry
+    async fn unregister(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let request = Request::new(AgentRegistration {
+            id: self.id.clone(),
+            ..Default::default()
+        });
+
+        self.registry
+            .call(|mut client| async move { client.unregister(request).await })
+            .await?;
+        info!(agent_id = %self.id, "Unregistered from control plane");
+        Ok(())
+    }
+
+    /// Renew lease with registry
+    async fn renew_lease(&self, lease_id: &str) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let request = Request::new(RenewRequest {
+            lease_id: lease_id.to_string(),
+            ttl: None,
+        });
+        
+        let response = self
+            .registry
+            .call(|mut client| async move { client.renew(request).await })
+            .await?;
+        Ok(response.into_inner().success)
+    }
+    
+    /// Connect to the control plane via the Agent Gateway (bidirectional stream).
+    /// Use this instead of serve() for agents behind NAT or without a public endpoint.
+    /// The agent opens an outbound connection; tasks are received through the stream.
+    /// Boxed wrapper: the connection task recursively re-enters this fn on
+    /// reconnect, so the future must be boxed for Send inference to resolve
+    pub fn connect_via_gateway<'a>(
+        self: &'a Arc<Self>,
+        endpoint: &'a str,
+        options: Option<GatewayOptions>,
+    ) -> futures::future::BoxFuture<'a, Result<(), Box<dyn std::error::Error + Send + Sync>>>
+    {
+        Box::pin(self.connect_via_gateway_inner(endpoint, options))
+    }
+
+    async fn connect_via_gateway_inner(
+        self: &Arc<Self>,
+        endpoint: &str,
+        options: Option<GatewayOptions>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let opts = options.unwrap_or_default();
+        let heartbeat_interval_ms = opts.heartbeat_interval_ms;
+        let endpoint_owned = endpoint.to_string();
+
+        // Build channel with optional TLS
+        let channel = if let Some(tls_config) = &opts.credentials {
+            tonic::transport::Channel::from_shared(endpoint_owned.clone())?
+                .tls_config(tls_config.clone())?
+                .connect()
+                .await?
+        } else {
+            tonic::transport::Channel::from_shared(endpoint_owned.clone())?
+                .connect()
+                .await?
+        };
+
+        let mut client = AgentGatewayClient::new(channel);
+
+        // Create a channel for sending messages to the stream
+        let (tx, rx) = tokio::sync::mpsc::channel::<AgentToControlPlane>(32);
+        let rx_stream = tokio_stream::wrappers::ReceiverStream::new(rx);
+
+        // Open bidirectional stream
+        let response = client.connect(rx_stream).await?;
+        let mut inbound = response.into_inner();
+
+        // Send AgentHello
+        let hello = AgentToControlPlane {
+            request_id: format!("hello-{}", self.id),
+            payload: Some(agent_to_control_plane::Payload::Hello(AgentHello {
+                agent_id: self.id.clone(),
+                agent_name: self.name.clone(),
+                capabilities: self.capabilities(),
+                metadata: self.metadata.clone(),
+                heartbeat_interval_ms: heartbeat_interval_ms as i32,
+            })),
+        };
+        tx.send(hello).await.map_err(|e| format!("Failed to send hello: {}", e))?;
+
+        info!(
+            agent_id = %self.id,
+            agent_name = %self.name,
+            endpoint = %endpoint_owned,
+            "Connecting via gateway"
+        );
+
+        // Wait for ServerAck
+        let ack_timeout = Duration::from_secs(10);
+        let ack_msg = clock::timeout(&*self.clock, ack_timeout, inbound.message()).await
+            .ok_or("Gateway connection timed out waiting for ack")?
+            .map_err(|e| format!("Stream error waiting for ack: {}", e))?
+            .ok_or("Stream ended before receiving ack")?;
+
+        if let Some(control_plane_to_agent::Payload::Ack(ack)) = ack_msg.payload {
+            if !ack.accepted {
+                return Err(format!("Gateway rejected agent: {}", ack.message).into());
+            }
+            info!(
+                agent_id = %self.id,
+                node_id = %ack.assigned_node_id,
+                "Connected via gateway"
+            );
+        } else {
+            return Err("Expected ServerAck but received different message".into());
+        }
+
+        // Create gateway shutdown channel
+        let (gw_shutdown_tx, mut gw_shutdown_rx) = tokio::sync::oneshot::channel();
+        {
+            let mut shutdown = self.gateway_shutdown_tx.lock().await;
+            *shutdown = Some(gw_shutdown_tx);
+        }
+
+        // Spawn heartbeat task
+        let tx_heartbeat = tx.clone();
+        let agent = Arc::clone(self);
+        let heartbeat_handle = tokio::spawn(async move {
+            let mut ticks = agent.clock.interval(Duration::from_millis(heartbeat_interval_ms));
+            loop {
+                ticks.next().await;
+                let heartbeat = AgentToControlPlane {
+                    request_id: String::new(),
+                    payload: Some(agent_to_control_plane::Payload::Heartbeat(AgentHeartbeat {
+                        agent_id: agent.id.clone(),
+                        load: 0.0,
+                        status: agent.health().state.as_str().to_string(),
+                        extra: HashMap::new(),
+                    })),
+                };
+                if tx_heartbeat.send(heartbeat).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        // Spawn message receiver task
+        let self_clone = Arc::clone(self);
+        let tx_response = tx.clone();
+        let endpoint_for_reconnect = endpoint_owned.clone();
+        let opts_for_reconnect = opts.clone();
+        let mut receiver_handle = tokio::spawn(async move {
+            // Tasks in flight on this connection, cancelled by CancelTask or,
+            // through the guard, when the connection goes away
+            let connection = CancellationToken::new();
+            let cancel_on_disconnect = connection.clone().drop_guard();
+            let in_flight: Arc<std::sync::Mutex<HashMap<String, CancellationToken>>> =
+                Arc::default();
+            loop {
+                match inbound.message().await {
+                    Ok(Some(msg)) => {
+                        let request_id = msg.request_id.clone();
+                        match msg.payload {
+                            Some(control_plane_to_agent::Payload::TaskRequest(task_req)) => {
+                                let agent = Arc::clone(&self_clone);
+                                let tx_task = tx_response.clone();
+                                let task_id = task_req.task_id.clone();
+                                let cancellation = connection.child_token();
+                                in_flight
+                                    .lock()
+                                    .unwrap()
+                                    .insert(task_id.clone(), cancellation.clone());
+                                let in_flight = Arc::clone(&in_flight);
+
+                                tokio::spawn(async move {
+                                    let analysis = async {
+                                        match agent.decode_data(task_req.data) {
+                                            Ok(data) => agent
+                                                .run_analyze(
+                                                    &task_req.task_description,
+                                                    data,
+                                                    cancellation.clone(),
+                                                    LogSink::disabled(),
+                                                )
+                                                .await
+                                                .map_err(analysis_failure)
+                                                .and_then(|result| {
+                                                    agent.check_output(&result.value).map(|()| result)
+                                                }),
+                                            Err(status) => Err(status),
+                                        }
+                                        .map_err(|status| {
+                                            (
+                                                status.message().to_string(),
+                                                gateway_error_code(status.code()),
+                                            )
+                                        })
+                                    };
+                                    // A cancelled task gets no reply; the control
+                                    // plane has already given up on it
+                                    let outcome = tokio::select! {
+                                        biased;
+                                        _ = cancellation.cancelled() => None,
+                                        outcome = analysis => Some(outcome),
+                                    };
+                                    in_flight.lock().unwrap().remove(&task_id);
+                                    let Some(outcome) = outcome else {
+                                        return;
+                                    };
+                                    match outcome {
+                                        Ok(result) => {
+                                            let value_json = serde_json::to_string(&result.value)
+                                                .unwrap_or_else(|_| "null".to_string());
+                                            let msg = AgentToControlPlane {
+                                                request_id,
+                                                payload: Some(agent_to_control_plane::Payload::TaskResult(
+                                                    GatewayTaskResult {
+                                                        task_id,
+                                                        value_json,
+                                                        confidence: result.confidence,
+                                                        reasoning: result.reasoning_text().unwrap_or_default(),
+                                                        metadata: result.wire_metadata(),
+                                                    },
+                                                )),
+                                            };
+                                            let _ = tx_task.send(msg).await;
+                                        }
+                                        Err((error_message, error_code)) => {
+                                            let msg = AgentToControlPlane {
+                                                request_id,
+                                                payload: Some(agent_to_control_plane::Payload::TaskError(
+                                                    GatewayTaskError {
+                                                        task_id,
+                                                        error_message,
+                                                        error_code: error_code.to_string(),
+                                                    },
+                                                )),
+                                            };
+                                            let _ = tx_task.send(msg).await;
+                                        }
+                                    }
+                                });
+                            }
+                            Some(control_plane_to_agent::Payload::CancelTask(cancel)) => {
+                                info!(
+                                    task_id = %cancel.task_id,
+                                    reason = %cancel.reason,
+                                    "Task cancelled"
+                                );
+                                if let Some(task) = in_flight.lock().unwrap().remove(&cancel.task_id) {
+                                    task.cancel();
+                                }
+                            }
+                            Some(control_plane_to_agent::Payload::Ping(_)) => {
+                                let heartbeat = AgentToControlPlane {
+                                    request_id: String::new(),
+                                    payload: Some(agent_to_control_plane::Payload::Heartbeat(
+                                        AgentHeartbeat {
+                                            agent_id: self_clone.id.clone(),
+                                            load: 0.0,
+                                            status: self_clone.health().state.as_str().to_string(),
+                                            extra: HashMap::new(),
+                                        },
+                                    )),
+                                };
+                                if tx_response.send(heartbeat).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Some(control_plane_to_agent::Payload::Ack(_)) => {
+                                // Already handled during initial connection
+                            }
+                            Some(_) => {
+                                // Thread orchestration payloads (ThreadSpawn/ThreadInput/
+                                // ThreadStop) target CLI runtime agents; this example only
+                                // serves analyze tasks
+                            }
+                            None => {}
+                        }
+                    }
+                    Ok(None) => {
+                        info!("Gateway stream ended");
+                        break;
+                    }
+                    Err(e) => {
+                        error!("Gateway stream error: {}", e);
+                        break;
+                    }
+                }
+            }
+
+            // Stream disconnected - cancel its tasks and attempt reconnect
+            drop(cancel_on_disconnect);
+            heartbeat_handle.abort();
+            Self::handle_gateway_reconnect(
+                self_clone,
+                &endpoint_for_reconnect,
+                opts_for_reconnect,
+            )
+            .await;
+        });
+
+        // Wait for shutdown signal or receiver to finish
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = &mut gw_shutdown_rx => {
+                    receiver_handle.abort();
+                }
+                _ = &mut receiver_handle => {
+                    // Receiver ended on its own (disconnect/error)
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Handle gateway disconnection with auto-reconnect using exponential backoff
+    async fn handle_gateway_reconnect(
+        agent: Arc<Self>,
+        endpoint: &str,
+        options: GatewayOptions,
+    ) {
+        if !options.auto_reconnect {
+            return;
+        }
+
+        {
+            let mut reconnecting = agent.gateway_reconnecting.lock().await;
+            if *reconnecting {
+                return;
+            }
+            *reconnecting = true;
+        }
+
+        let max_attempts = options.max_reconnect_attempts;
+        let initial_delay = options.initial_reconnect_delay_ms;
+        let max_delay = options.max_reconnect_delay_ms;
+        let mut attempt: u32 = 0;
+
+        loop {
+            if let Some(max) = max_attempts {
+                if attempt >= max {
+                    error!(
+                        agent_id = %agent.id,
+                        attempts = attempt,
+                        "Gateway reconnect failed after max attempts"
+                    );
+                    break;
+                }
+            }
+
+            let delay = calculate_reconnect_delay(attempt, initial_delay, max_delay);
+            attempt += 1;
+
+            info!(
+                agent_id = %agent.id,
+                delay_ms = delay,
+                attempt = attempt,
+                "Gateway reconnecting"
+            );
+
+            agent.clock.sleep(Duration::from_millis(delay)).await;
+
+            match agent
+                .connect_via_gateway(endpoint, Some(options.clone()))
+                .await
+            {
+                Ok(()) => {
+                    info!(agent_id = %agent.id, "Gateway reconnected successfully");
+                    break;
+                }
+                Err(e) => {
+                    error!(
+                        agent_id = %agent.id,
+                        attempt = attempt,
+                        error = %e,
+                        "Gateway reconnect attempt failed"
+                    );
+                }
+            }
+        }
+
+        let mut reconnecting = agent.gateway_reconnecting.lock().await;
+        *reconnecting = false;
+    }
+
+    /// Shutdown the agent
+    pub async fn shutdown(&self) -> Result<(), Box<dyn std::error::Error>> {
+        info!(agent_id = %self.id, "Shutting down agent");
+
+        // Prevent reconnect during shutdown
+        {
+            let mut reconnecting = self.gateway_reconnecting.lock().await;
+            *reconnecting = true;
+        }
+
+        // Shut down gateway connection
+        if let Some(tx) = self.gateway_shutdown_tx.lock().await.take() {
+            let _ = tx.send(());
+        }
+
+        // Refuse new analyses, then trigger server shutdown; `serve` drains
+        // in-flight ones and unregisters
+        self.draining.store(true, Ordering::Relaxed);
+        if let Some(tx) = self.shutdown_tx.lock().await.take() {
+            let _ = tx.send(());
+        }
+
+        Ok(())
+    }
+}
+
+/// Weight given to the newest sample in the rolling latency average
+const LATENCY_SMOOTHING: f64 = 0.2;
+
+/// Lock-free analyze counters, kept off the request path's critical section
+#[derive(Debug, Default)]
+struct RequestStats {
+    total: AtomicU64,
+    succeeded: AtomicU64,
+    failed: AtomicU64,
+    // f64 bits of the exponentially weighted average latency in milliseconds
+    avg_latency_ms: AtomicU64,
+}
+
+impl RequestStats {
+    fn record(&self, success: bool, latency: Duration) {
+        let previous_total = self.total.fetch_add(1, Ordering::Relaxed);
+        if success {
+            self.succeeded.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let sample = latency.as_secs_f64() * 1000.0;
+        let _ = self
+            .avg_latency_ms
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                let average = if previous_total == 0 {
+                    sample
+                } else {
+                    let current = f64::from_bits(bits);
+                    current + LATENCY_SMOOTHING * (sample - current)
+                };
+                Some(average.to_bits())
+            });
+    }
+
+    fn write_details(&self, details: &mut HashMap<String, String>) {
+        details.insert("requests_total".to_string(), self.total.load(Ordering::Relaxed).to_string());
+        details.insert(
+            "requests_succeeded".to_string(),
+            self.succeeded.load(Ordering::Relaxed).to_string(),
+        );
+        details.insert(
+            "requests_failed".to_string(),
+            self.failed.load(Ordering::Relaxed).to_string(),
+        );
+        details.insert(
+            "avg_latency_ms".to_string(),
+            format!("{:.2}", f64::from_bits(self.avg_latency_ms.load(Ordering::Relaxed))),
+        );
+    }
+}
+
+/// Tracks an executing analyze call in the agent's in-flight counter
+struct InFlightGuard<'a>(&'a AtomicUsize);
+
+impl<'a> InFlightGuard<'a> {
+    fn new(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Self(counter)
+    }
+}
+
+impl Drop for InFlightGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Interceptor installed by [`ParallaxAgent::into_service`]
+type ContextInterceptor = fn(Request<()>) -> Result<Request<()>, Status>;
+
+/// Service answering `RESOURCE_EXHAUSTED` where tonic refuses a message over
+/// the decoding limit with `OUT_OF_RANGE`, so requests too large for the
+/// transport fail with the same code as those caught by `max_request_bytes`
+#[derive(Clone)]
+struct OversizeAsExhausted<S>(S);
+
+impl<S: tonic::server::NamedService> tonic::server::NamedService for OversizeAsExhausted<S> {
+    const NAME: &'static str = S::NAME;
+}
+
+impl<S> tower::Service<http::Request<tonic::body::BoxBody>> for OversizeAsExhausted<S>
+where
+    S: tower::Service<
+        http::Request<tonic::body::BoxBody>,
+        Response = http::Response<tonic::body::BoxBody>,
+    >,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = futures::future::BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<tonic::body::BoxBody>) -> Self::Future {
+        let response = self.0.call(request);
+        Box::pin(async move {
+            let mut response = response.await?;
+            // Decoding errors arrive in the headers of a trailers-only response
+            let oversized = Status::from_header_map(response.headers()).filter(|status| {
+                status.code() == tonic::Code::OutOfRange
+                    && status.message().contains("message length too large")
+            });
+            if let Some(status) = oversized {
+                let _ = Status::resource_exhausted(status.message())
+                    .add_header(response.headers_mut());
+            }
+            Ok(response)
+        })
+    }
+}
+
+impl ParallaxAgent {
+    /// Serve one analyze call, writing the analysis's log lines to `logs`
+    async fn handle_analyze(
+        &self,
+        request: Request<AgentRequest>,
+        logs: LogSink,
+        chunk_size: Option<usize>,
+    ) -> Result<Response<ConfidenceResult>, Status> {
+        let context = RequestContext::of(&request);
+        let span = tracing::info_span!(
+            "parallax.analyze",
+            agent_id = %self.id,
+            request_id = %context.request_id,
+            trace_id = context.trace_id.as_deref().unwrap_or(""),
+        );
+
+        let started = Instant::now();
+        let mut result = match self.check_rate_limit(&request) {
+            Ok(()) => match scheduling_from_metadata(request) {
+                Ok(req) => {
+                    self.analyze_request(req, logs, chunk_size)
+                        .instrument(span.clone())
+                        .await
+                }
+                Err(status) => Err(status),
+            },
+            Err(status) => Err(status),
+        };
+        let elapsed = started.elapsed();
+        self.stats.record(result.is_ok(), elapsed);
+        self.metrics.record(result.is_ok(), elapsed);
+
+        span.in_scope(|| match &result {
+            Ok(_) => info!(duration_ms = elapsed.as_millis() as u64, "Analyze succeeded"),
+            Err(status) => info!(
+                duration_ms = elapsed.as_millis() as u64,
+                code = ?status.code(),
+                "Analyze failed: {}",
+                status.message()
+            ),
+        });
+
+        // Echo the request id so callers can correlate logs
+        if let Ok(request_id) = context.request_id.parse() {
+            let metadata = match &mut result {
+                Ok(response) => response.metadata_mut(),
+                Err(status) => status.metadata_mut(),
+            };
+            metadata.insert(REQUEST_ID_HEADER, request_id);
+        }
+        result
+    }
+}
+
+/// The request, with its priority and deadline taken from the
+/// `x-priority` and `x-deadline` metadata entries where the message leaves
+/// them unset
+fn scheduling_from_metadata(request: Request<AgentRequest>) -> Result<AgentRequest, Status> {
+    let (metadata, _, mut req) = request.into_parts();
+    let entry = |key: &str| {
+        metadata
+            .get(key)
+            .map(|value| value.to_str().map(str::trim).unwrap_or_default().to_string())
+    };
+
+    if req.priority == 0 {
+        if let Some(priority) = entry(PRIORITY_HEADER) {
+            req.priority = priority.parse().map_err(|_| {
+                Status::invalid_argument(format!("{} must be an integer", PRIORITY_HEADER))
+            })?;
+        }
+    }
+    if req.deadline.is_none() {
+        if let Some(deadline) = entry(DEADLINE_HEADER) {
+            let deadline = chrono::DateTime::parse_from_rfc3339(&deadline).map_err(|_| {
+                Status::invalid_argument(format!("{} must be an RFC 3339 time", DEADLINE_HEADER))
+            })?;
+            req.deadline = Some(prost_types::Timestamp::from(std::time::SystemTime::from(
+                deadline,
+            )));
+        }
+    }
+    Ok(req)
+}
+
+/// Read an `Analyze` response back into the result the agent returned
+fn agent_result_from_response(response: Response<ConfidenceResult>) -> crate::Result<AgentResult> {
+    let cache_ttl = response
+        .metadata()
+        .get("cache-control")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("max-age="))
+        .and_then(|seconds| seconds.parse().ok())
+        .map(Duration::from_secs);
+    let result = response.into_inner();
+    let value = match result.value_struct {
+        Some(value) if result.value_json.is_empty() => struct_to_json(value),
+        _ => serde_json::from_str(&result.value_json)?,
+    };
+    let mut metadata = result.metadata;
+    let reasoning_steps = ReasoningStep::from_metadata(&metadata);
+    metadata.remove(REASONING_STEPS_KEY);
+    // Steps without reasoning are sent as their joined descriptions
+    let stepped = AgentResult {
+        reasoning_steps: reasoning_steps.clone(),
+        ..Default::default()
+    }
+    .reasoning_text();
+    let reasoning = Some(result.reasoning)
+        .filter(|reasoning| !reasoning.is_empty() && Some(reasoning) != stepped.as_ref());
+
+    Ok(AgentResult {
+        value,
+        confidence: result.confidence,
+        reasoning,
+        reasoning_steps,
+        uncertainties: result.uncertainties,
+        metadata,
+        cache_ttl,
+    })
+}
+
+/// Progress of a `StreamAnalyze` call
+enum AnalysisUpdate {
+    Log(LogEvent),
+    Done(Box<Result<Response<ConfidenceResult>, Status>>),
+}
+
+/// Drives an analysis, yielding the log lines it writes as they arrive and
+/// then its outcome. Lines written before the analysis finished always come
+/// before the outcome.
+struct LiveAnalysis {
+    analysis: Option<futures::future::BoxFuture<'static, Result<Response<ConfidenceResult>, Status>>>,
+    outcome: Option<Result<Response<ConfidenceResult>, Status>>,
+    logs: tokio::sync::mpsc::Receiver<LogEvent>,
+}
+
+impl futures::Stream for LiveAnalysis {
+    type Item = AnalysisUpdate;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<AnalysisUpdate>> {
+        use std::task::Poll;
+
+        let this = &mut *self;
+        if let Some(analysis) = this.analysis.as_mut() {
+            if let Poll::Ready(outcome) = analysis.as_mut().poll(cx) {
+                this.analysis = None;
+                this.outcome = Some(outcome);
+                // Lines already buffered are still delivered
+                this.logs.close();
+            }
+        }
+        if this.analysis.is_some() || this.outcome.is_some() {
+            if let Poll::Ready(Some(event)) = this.logs.poll_recv(cx) {
+                return Poll::Ready(Some(AnalysisUpdate::Log(event)));
+            }
+        }
+        match this.outcome.take() {
+            Some(outcome) => Poll::Ready(Some(AnalysisUpdate::Done(Box::new(outcome)))),
+            None if this.analysis.is_none() => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Stream message carrying a log line rather than a result
+fn log_message(agent_id: &str, event: LogEvent) -> ConfidenceResult {
+    ConfidenceResult {
+        agent_id: agent_id.to_string(),
+        timestamp: event.timestamp,
+        log: Some(event),
+        ..Default::default()
+    }
+}
+
+#[async_trait]
+impl ConfidenceAgent for Arc<ParallaxAgent> {
+    async fn analyze(
+        &self,
+        request: Request<AgentRequest>,
+    ) -> Result<Response<ConfidenceResult>, Status> {
+        self.handle_analyze(request, LogSink::disabled(), None).await
+    }
+
+    type StreamAnalyzeStream = std::pin::Pin<
+        Box<dyn futures::Stream<Item = Result<ConfidenceResult, Status>> + Send>,
+    >;
+
+    /// Streams the analysis's log lines as `ConfidenceResult`s with `log`
+    /// set, then the result, split into chunks if a stream chunk size is
+    /// set. Response headers go out with the first message:
+    /// if the analysis finishes without logging, they carry the result's
+    /// headers (such as `cache-control`) as `analyze` would; otherwise only
+    /// the request id.
+    async fn stream_analyze(
+        &self,
+        mut request: Request<AgentRequest>,
+    ) -> Result<Response<Self::StreamAnalyzeStream>, Status> {
+        // Pin the request id so the headers sent early match the handler's
+        let context = RequestContext::of(&request);
+        request.extensions_mut().insert(context.clone());
+
+        let (log_tx, logs) = tokio::sync::mpsc::channel(LOG_STREAM_BUFFER);
+        let agent = Arc::clone(self);
+        let mut updates = LiveAnalysis {
+            analysis: Some(Box::pin(async move {
+                let chunk_size = agent.stream_chunk_size;
+                agent.handle_analyze(request, LogSink::new(log_tx), chunk_size).await
+            })),
+            outcome: None,
+            logs,
+        };
+
+        let first_log = match updates.next().await {
+            Some(AnalysisUpdate::Log(event)) => event,
+            Some(AnalysisUpdate::Done(outcome)) => {
+                let (metadata, result, extensions) = (*outcome)?.into_parts();
+                let stream: Self::StreamAnalyzeStream = Box::pin(self.result_frames(result).map(Ok));
+                return Ok(Response::from_parts(metadata, stream, extensions));
+            }
+            None => return Err(Status::internal("analysis ended without a result")),
+        };
+
+        let agent = Arc::clone(self);
+        let stream = futures::stream::once(async move { AnalysisUpdate::Log(first_log) })
+            .chain(updates)
+            .flat_map(move |update| match update {
+                AnalysisUpdate::Log(event) => {
+                    futures::stream::once(futures::future::ready(Ok(log_message(&agent.id, event))))
+                        .boxed()
+                }
+                AnalysisUpdate::Done(outcome) => match *outcome {
+                    Ok(result) => agent.result_frames(result.into_inner()).map(Ok).boxed(),
+                    Err(status) => futures::stream::once(futures::future::ready(Err(status))).boxed(),
+                },
+            });
+        let mut response = Response::new(Box::pin(stream) as Self::StreamAnalyzeStream);
+        if let Ok(request_id) = context.request_id.parse() {
+            response.metadata_mut().insert(REQUEST_ID_HEADER, request_id);
+        }
+        Ok(response)
+    }
+    
+    async fn get_capabilities(
+        &self,
+        _request: Request<()>,
+    ) -> Result<Response<Capabilities>, Status> {
+        Ok(Response::new(Capabilities {
+            agent_id: self.id.clone(),
+            name: self.name.clone(),
+            capabilities: self.capabilities(),
+            expertise_level: self.expertise_level(),
+            capability_scores: self.capability_scores.clone(),
+            output_schema: self.output_schema().cloned().map(json_to_struct),
+        }))
+    }
+    
+    async fn health_check(
+        &self,
+        _request: Request<()>,
+    ) -> Result<Response<Health>, Status> {
+        let health = self.health();
+        let mut details = health.details;
+        details.insert("health_state".to_string(), health.state.as_str().to_string());
+        if health.state != HealthState::Healthy {
+            details.insert("reason".to_string(), health.message.clone());
+        }
+        details.insert(
+            "in_flight_requests".to_string(),
+            self.in_flight_requests().to_string(),
+        );
+        self.stats.write_details(&mut details);
+        details.insert(
+            "registration_state".to_string(),
+            self.registration_state().as_str().to_string(),
+        );
+
+        Ok(Response::new(Health {
+            status: HealthStatusProto::from(health.state) as i32,
+            message: health.message,
+            last_check: Some(prost_types::Timestamp::from(std::time::SystemTime::now())),
+            details,
+        }))
+    }
+}
+
+/// Helper function to serve an agent
+pub async fn serve_agent(agent: Arc<ParallaxAgent>, port: u16) -> Result<(), Box<dyn std::error::Error>> {
+    // Handle shutdown signals
+    let agent_clone = Arc::clone(&agent);
+    tokio::spawn(async move {
+        match signal::ctrl_c().await {
+            Ok(()) => {
+                info!("Received shutdown signal");
+                let _ = agent_clone.shutdown().await;
+            }
+            Err(err) => {
+                error!("Unable to listen for shutdown signal: {}", err);
+            }
+        }
+    });
+    
+    agent.serve(port).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generated::parallax::gateway::{
+        agent_gateway_server::{AgentGateway, AgentGatewayServer},
+        CancelTask, ControlPlaneToAgent, ServerAck, TaskRequest,
+    };
+
+    #[test]
+    fn test_gateway_options_default() {
+        let opts = GatewayOptions::default();
+        assert!(opts.credentials.is_none());
+        assert_eq!(opts.heartbeat_interval_ms, 10000);
+        assert!(opts.auto_reconnect);
+        assert!(opts.max_reconnect_attempts.is_none());
+        assert_eq!(opts.initial_reconnect_delay_ms, 1000);
+        assert_eq!(opts.max_reconnect_delay_ms, 30000);
+    }
+
+    #[test]
+    fn test_gateway_options_custom() {
+        let opts = GatewayOptions {
+            credentials: None,
+            heartbeat_interval_ms: 5000,
+            auto_reconnect: false,
+            max_reconnect_attempts: Some(5),
+            initial_reconnect_delay_ms: 500,
+            max_reconnect_delay_ms: 60000,
+        };
+        assert_eq!(opts.heartbeat_interval_ms, 5000);
+        assert!(!opts.auto_reconnect);
+        assert_eq!(opts.max_reconnect_attempts, Some(5));
+        assert_eq!(opts.initial_reconnect_delay_ms, 500);
+        assert_eq!(opts.max_reconnect_delay_ms, 60000);
+    }
+
+    #[test]
+    fn test_reconnect_delay_exponential_backoff() {
+        let initial = 1000;
+        let max = 30000;
+
+        assert_eq!(calculate_reconnect_delay(0, initial, max), 1000);
+        assert_eq!(calculate_reconnect_delay(1, initial, max), 2000);
+        assert_eq!(calculate_reconnect_delay(2, initial, max), 4000);
+        assert_eq!(calculate_reconnect_delay(3, initial, max), 8000);
+        assert_eq!(calculate_reconnect_delay(4, initial, max), 16000);
+    }
+
+    #[test]
+    fn test_reconnect_delay_capped_at_max() {
+        let initial = 1000;
+        let max = 10000;
+
+        assert_eq!(calculate_reconnect_delay(0, initial, max), 1000);
+        assert_eq!(calculate_reconnect_delay(3, initial, max), 8000);
+        assert_eq!(calculate_reconnect_delay(4, initial, max), 10000);
+        assert_eq!(calculate_reconnect_delay(5, initial, max), 10000);
+        assert_eq!(calculate_reconnect_delay(10, initial, max), 10000);
+    }
+
+    #[test]
+    fn test_reconnect_delay_no_overflow() {
+        let initial = 1000;
+        let max = 30000;
+
+        // Very large attempt number should not panic
+        let delay = calculate_reconnect_delay(100, initial, max);
+        assert_eq!(delay, max);
+    }
+
+    #[test]
+    fn test_agent_has_gateway_fields() {
+        let agent = ParallaxAgent::new(
+            "test-agent",
+            "Test Agent",
+            vec!["analysis".to_string()],
+            HashMap::new(),
+        );
+        // Verify gateway fields are initialized
+        assert!(agent.gateway_shutdown_tx.try_lock().is_ok());
+        assert!(agent.gateway_reconnecting.try_lock().is_ok());
+    }
+
+    fn slow_agent() -> ParallaxAgent {
+        ParallaxAgent::new("slow-agent", "Slow Agent", vec![], HashMap::new()).set_analyze_fn(
+            |_task: &str, _data: Option<serde_json::Value>| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok(AgentResult {
+                    value: serde_json::json!("late"),
+                    confidence: 0.9,
+                    reasoning: None,
+                    reasoning_steps: Vec::new(),
+                    uncertainties: vec![],
+                    metadata: HashMap::new(),
+                    cache_ttl: None,
+                })
+            },
+        )
+    }
+
+    fn agent_request(timeout_ms: i32) -> Request<AgentRequest> {
+        Request::new(AgentRequest {
+            task_description: "analyze".to_string(),
+            timeout_ms,
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_analyze_timeout_returns_deadline_exceeded() {
+        let clock = crate::clock::MockClock::new();
+        let agent = Arc::new(
+            slow_agent()
+                .with_analyze_timeout(Duration::from_secs(60))
+                .with_clock(clock.clone()),
+        );
+
+        let call = tokio::spawn({
+            let agent = Arc::clone(&agent);
+            async move { agent.analyze(agent_request(0)).await }
+        });
+        while clock.pending_sleeps() == 0 {
+            tokio::task::yield_now().await;
+        }
+        clock.advance(Duration::from_secs(60));
+
+        let status = tokio::time::timeout(Duration::from_secs(1), call)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::DeadlineExceeded);
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout_overrides_agent_timeout() {
+        let agent = Arc::new(slow_agent().with_analyze_timeout(Duration::from_secs(60)));
+
+        let status = agent.analyze(agent_request(50)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::DeadlineExceeded);
+    }
+
+    #[tokio::test]
+    async fn test_deadline_does_not_bound_a_started_analysis() {
+        let clock = crate::clock::MockClock::new();
+        let release = Arc::new(tokio::sync::Notify::new());
+        let started = Arc::new(AtomicBool::new(false));
+        let agent = Arc::new(
+            ParallaxAgent::new("late-agent", "Late Agent", vec![], HashMap::new())
+                .set_analyze_fn({
+                    let release = Arc::clone(&release);
+                    let started = Arc::clone(&started);
+                    move |_task: &str, _data: Option<serde_json::Value>| {
+                        started.store(true, Ordering::SeqCst);
+                        let release = Arc::clone(&release);
+                        async move {
+                            release.notified().await;
+                            Ok(AgentResult::new(serde_json::json!("done"), 0.8))
+                        }
+                    }
+                })
+                .with_clock(clock.clone()),
+        );
+        let mut request = agent_request(0);
+        request.get_mut().deadline = Some(prost_types::Timestamp::from(
+            std::time::SystemTime::now() + Duration::from_secs(1),
+        ));
+
+        let call = tokio::spawn({
+            let agent = Arc::clone(&agent);
+            async move { agent.analyze(request).await }
+        });
+        while !started.load(Ordering::SeqCst) {
+            tokio::task::yield_now().await;
+        }
+        clock.advance(Duration::from_secs(5));
+        tokio::task::yield_now().await;
+        release.notify_one();
+
+        let response = tokio::time::timeout(Duration::from_secs(1), call)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(response.into_inner().value_json, "\"done\"");
+    }
+
+    fn tracking_agent(active: Arc<AtomicUsize>, peak: Arc<AtomicUsize>) -> ParallaxAgent {
+        ParallaxAgent::new("busy-agent", "Busy Agent", vec![], HashMap::new()).set_analyze_fn(
+            move |_task: &str, _data: Option<serde_json::Value>| {
+                let active = Arc::clone(&active);
+                let peak = Arc::clone(&peak);
+                async move {
+                    let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    active.fetch_sub(1, Ordering::SeqCst);
+                    Ok(AgentResult {
+                        value: serde_json::json!("done"),
+                        confidence: 0.8,
+                        reasoning: None,
+                        reasoning_steps: Vec::new(),
+                        uncertainties: vec![],
+                        metadata: HashMap::new(),
+                        cache_ttl: None,
+                    })
+                }
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit_caps_in_flight_analyses() {
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let agent = Arc::new(
+            tracking_agent(Arc::clone(&active), Arc::clone(&peak))
+                .with_max_concurrent_requests(NonZeroUsize::new(2).unwrap()),
+        );
+
+        let calls = (0..6).map(|_| {
+            let agent = Arc::clone(&agent);
+            async move { agent.analyze(agent_request(0)).await }
+        });
+        let results = futures::future::join_all(calls).await;
+
+        assert!(results.iter().all(|r| r.is_ok()));
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!(agent.in_flight_requests(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_reject_when_full_returns_resource_exhausted() {
+        let agent = Arc::new(
+            slow_agent()
+                .with_max_concurrent_requests(NonZeroUsize::new(1).unwrap())
+                .with_reject_when_full(true),
+        );
+
+        let busy = Arc::clone(&agent);
+        let first = tokio::spawn(async move { busy.analyze(agent_request(200)).await });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let status = agent.analyze(agent_request(0)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+
+        let health = agent.health_check(Request::new(())).await.unwrap().into_inner();
+        assert_eq!(health.details.get("in_flight_requests").map(String::as_str), Some("1"));
+
+        first.abort();
+    }
+
+    #[tokio::test]
+    async fn test_output_schema_is_advertised_and_enforced() {
+        let schema = serde_json::json!({
+            "type": "object",
+            "required": ["label", "score"],
+            "properties": {"label": {"type": "string"}, "score": {"type": "number"}}
+        });
+        let agent = Arc::new(
+            ParallaxAgent::new("schema-agent", "Schema Agent", vec![], HashMap::new())
+                .set_analyze_fn(|task: &str, _data: Option<serde_json::Value>| {
+                let value = match task {
+                    "complete" => serde_json::json!({"label": "positive", "score": 0.9}),
+                    _ => serde_json::json!({"score": 0.9}),
+                };
+                async move {
+                    Ok(AgentResult {
+                        value,
+                        confidence: 0.8,
+                        reasoning: None,
+                        reasoning_steps: Vec::new(),
+                        uncertainties: vec![],
+                        metadata: HashMap::new(),
+                        cache_ttl: None,
+                    })
+                }
+            })
+                .with_output_schema(schema.clone())
+                .unwrap()
+                .with_output_validation(true),
+        );
+
+        let capabilities = agent.get_capabilities(Request::new(())).await.unwrap().into_inner();
+        assert_eq!(capabilities.output_schema.map(struct_to_json), Some(schema));
+
+        assert!(agent.analyze_local("complete", None).await.is_ok());
+        let error = agent.analyze_local("incomplete", None).await.unwrap_err();
+        assert_eq!(error.code(), crate::error::ErrorCode::Internal);
+        assert!(error.to_string().contains("output schema"), "{}", error);
+        assert!(error.to_string().contains("label"), "{}", error);
+
+        let invalid = ParallaxAgent::new("a", "A", vec![], HashMap::new())
+            .with_output_schema(serde_json::json!({"type": 7}));
+        assert!(matches!(invalid, Err(crate::Error::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_stable_id_is_deterministic_per_host_and_capabilities() {
+        let capabilities = vec!["sentiment".to_string(), "analysis".to_string()];
+        let id = derive_stable_id("sentiment-agent", &capabilities, "host-a");
+
+        assert!(id.starts_with("sentiment-agent-"));
+        assert_eq!(id, derive_stable_id("sentiment-agent", &capabilities, "host-a"));
+        let reordered = vec!["analysis".to_string(), " sentiment ".to_string()];
+        assert_eq!(id, derive_stable_id("sentiment-agent", &reordered, "host-a"));
+        assert_ne!(id, derive_stable_id("sentiment-agent", &capabilities, "host-b"));
+        assert_ne!(id, derive_stable_id("sentiment-agent", &capabilities[..1], "host-a"));
+        assert_eq!(
+            ParallaxAgent::stable_id("sentiment-agent", &capabilities),
+            ParallaxAgent::stable_id("sentiment-agent", &capabilities)
+        );
+    }
+
+    #[test]
+    fn test_host_id_without_hostname_is_kept_across_restarts() {
+        let path = std::env::temp_dir().join(format!("parallax-host-id-{}", uuid::Uuid::new_v4()));
+
+        let id = persisted_host_id(&path);
+        assert_eq!(id, persisted_host_id(&path));
+        std::fs::remove_file(&path).unwrap();
+        assert_ne!(id, persisted_host_id(&path));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_queued_requests_start_by_priority() {
+        let started = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let gate = Arc::new(tokio::sync::Notify::new());
+        let agent = Arc::new(
+            ParallaxAgent::new("queue-agent", "Queue Agent", vec![], HashMap::new())
+                .set_analyze_fn({
+                    let started = Arc::clone(&started);
+                    let gate = Arc::clone(&gate);
+                    move |task: &str, _data: Option<serde_json::Value>| {
+                        started.lock().unwrap().push(task.to_string());
+                        let gate = Arc::clone(&gate);
+                        let blocks = task == "blocker";
+                        async move {
+                            if blocks {
+                                gate.notified().await;
+                            }
+                            Ok(AgentResult {
+                                value: serde_json::json!("done"),
+                                confidence: 0.8,
+                                reasoning: None,
+                                reasoning_steps: Vec::new(),
+                                uncertainties: vec![],
+                                metadata: HashMap::new(),
+                                cache_ttl: None,
+                            })
+                        }
+                    }
+                })
+                .with_clock(crate::clock::MockClock::new())
+                .with_max_concurrent_requests(NonZeroUsize::new(1).unwrap()),
+        );
+        let request = |task: &str, priority: i32| {
+            Request::new(AgentRequest {
+                task_description: task.to_string(),
+                priority,
+                ..Default::default()
+            })
+        };
+        let queue = |request: Request<AgentRequest>| {
+            let agent = Arc::clone(&agent);
+            tokio::spawn(async move { agent.analyze(request).await })
+        };
+
+        let blocker = queue(request("blocker", 0));
+        while started.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        let mut queued = vec![queue(request("low", 1)), queue(request("mid", 5))];
+        let mut high = request("high", 0);
+        high.metadata_mut().insert(PRIORITY_HEADER, "10".parse().unwrap());
+        queued.push(queue(high));
+        // A request whose deadline has passed is dropped rather than queued
+        let mut expired = request("expired", 100);
+        expired.get_mut().deadline = Some(prost_types::Timestamp::from(
+            std::time::SystemTime::now() - Duration::from_secs(1),
+        ));
+        let status = agent.analyze(expired).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::DeadlineExceeded);
+        // Time stands still, so the queued calls don't age past each other
+        let slots = agent.concurrency_limit.as_ref().unwrap();
+        while slots.waiting() < 3 {
+            tokio::task::yield_now().await;
+        }
+
+        gate.notify_one();
+        blocker.await.unwrap().unwrap();
+        for call in queued {
+            call.await.unwrap().unwrap();
+        }
+        assert_eq!(*started.lock().unwrap(), ["blocker", "high", "mid", "low"]);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_throttles_each_client() {
+        use crate::generated::confidence_agent_client::ConfidenceAgentClient;
+
+        let clock = crate::clock::MockClock::new();
+        let agent = Arc::new(
+            echo_agent()
+                .with_clock(clock.clone())
+                .with_rate_limit(2.0, 3)
+                .unwrap(),
+        );
+        let from = |ip: [u8; 4]| {
+            let mut request = agent_request(0);
+            request.extensions_mut().insert(tonic::transport::server::TcpConnectInfo {
+                local_addr: None,
+                remote_addr: Some((ip, 40000).into()),
+            });
+            request
+        };
+        let greedy = [10, 0, 0, 1];
+
+        let results = futures::future::join_all((0..5).map(|_| agent.analyze(from(greedy)))).await;
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 3);
+        let status = results.into_iter().find_map(Result::err).unwrap();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        assert_eq!(status.metadata().get("retry-after").unwrap(), "1");
+
+        // Other clients keep their own budget, and the greedy one refills
+        assert!(agent.analyze(from([10, 0, 0, 2])).await.is_ok());
+        clock.advance(Duration::from_millis(500));
+        assert!(agent.analyze(from(greedy)).await.is_ok());
+        assert!(agent.analyze(from(greedy)).await.is_err());
+
+        // Over a real connection the peer address is the key, and a client
+        // cannot escape its budget by naming itself in metadata
+        let channel = crate::test_support::serve(
+            Server::builder().add_service(Arc::clone(&agent).into_service()),
+        )
+        .await;
+        let mut client = ConfidenceAgentClient::new(channel);
+        for _ in 0..3 {
+            client.analyze(data_request(serde_json::json!({}))).await.unwrap();
+        }
+        let mut renamed = Request::new(data_request(serde_json::json!({})));
+        renamed.metadata_mut().insert("x-client-id", "someone-else".parse().unwrap());
+        let status = client.analyze(renamed).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+
+        assert!(echo_agent().with_rate_limit(0.0, 1).is_err());
+    }
+
+    fn cached_agent(cache_ttl: Option<Duration>) -> Arc<ParallaxAgent> {
+        Arc::new(
+            ParallaxAgent::new("cache-agent", "Cache Agent", vec![], HashMap::new()).set_analyze_fn(
+                move |_task: &str, _data: Option<serde_json::Value>| async move {
+                    Ok(AgentResult {
+                        value: serde_json::json!({ "answer": 42 }),
+                        confidence: 0.9,
+                        cache_ttl,
+                        ..Default::default()
+                    })
+                },
+            ),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_cache_ttl_sets_cache_control_header() {
+        let agent = cached_agent(Some(Duration::from_secs(300)));
+
+        let response = agent.analyze(agent_request(0)).await.unwrap();
+        assert_eq!(
+            response.metadata().get("cache-control").and_then(|v| v.to_str().ok()),
+            Some("max-age=300")
+        );
+
+        let stream = agent.stream_analyze(agent_request(0)).await.unwrap();
+        assert!(stream.metadata().get("cache-control").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_health_details_report_request_counts() {
+        let agent = Arc::new(
+            ParallaxAgent::new("stats-agent", "Stats Agent", vec![], HashMap::new()).set_analyze_fn(
+                |task: &str, _data: Option<serde_json::Value>| {
+                    let fail = task == "fail";
+                    async move {
+                        if fail {
+                            return Err("boom".into());
+                        }
+                        Ok(AgentResult {
+                            value: serde_json::json!("ok"),
+                            confidence: 0.7,
+                            ..Default::default()
+                        })
+                    }
+                },
+            ),
+        );
+
+        for task in ["analyze", "analyze", "fail"] {
+            let _ = agent
+                .analyze(Request::new(AgentRequest {
+                    task_description: task.to_string(),
+                    ..Default::default()
+                }))
+                .await;
+        }
+
+        let details = agent.health_check(Request::new(())).await.unwrap().into_inner().details;
+        assert_eq!(details["requests_total"], "3");
+        assert_eq!(details["requests_succeeded"], "2");
+        assert_eq!(details["requests_failed"], "1");
+        assert!(details["avg_latency_ms"].parse::<f64>().unwrap() >= 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_health_check_reports_degraded_with_reason() {
+        let agent = Arc::new(ParallaxAgent::new("model-agent", "Model Agent", vec![], HashMap::new()));
+        let health = agent.health_check(Request::new(())).await.unwrap().into_inner();
+        assert_eq!(health.status, HealthStatusProto::Healthy as i32);
+        assert!(!health.details.contains_key("reason"));
+
+        agent.set_health(
+            HealthStatus::degraded("falling back to the small model").with_detail("model", "small"),
+        );
+        let health = agent.health_check(Request::new(())).await.unwrap().into_inner();
+
+        assert_eq!(health.status, HealthStatusProto::Degraded as i32);
+        assert_eq!(health.message, "falling back to the small model");
+        assert_eq!(health.details["health_state"], "degraded");
+        assert_eq!(health.details["reason"], "falling back to the small model");
+        assert_eq!(health.details["model"], "small");
+        assert_eq!(health.details["in_flight_requests"], "0");
+    }
+
+    #[tokio::test]
+    async fn test_gzip_round_trips_large_payload() {
+        use crate::generated::confidence_agent_client::ConfidenceAgentClient;
+
+        let agent = ParallaxAgent::new("echo-agent", "Echo Agent", vec![], HashMap::new())
+            .set_analyze_fn(|_task: &str, data: Option<serde_json::Value>| async move {
+                Ok(AgentResult {
+                    value: data.unwrap_or_default(),
+                    confidence: 0.9,
+                    ..Default::default()
+                })
+            })
+            .with_compression(CompressionEncoding::Gzip);
+        let channel = crate::test_support::serve(
+            Server::builder().add_service(Arc::new(agent).into_service()),
+        )
+        .await;
+        let mut client = ConfidenceAgentClient::new(channel)
+            .send_compressed(CompressionEncoding::Gzip)
+            .accept_compressed(CompressionEncoding::Gzip);
+
+        let data = serde_json::json!({ "document": "evidence ".repeat(64 * 1024) });
+        let response = client
+            .analyze(AgentRequest {
+                task_description: "echo".to_string(),
+                data: Some(crate::conversions::json_to_struct(data.clone())),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            response.metadata().get("grpc-encoding").and_then(|v| v.to_str().ok()),
+            Some("gzip")
+        );
+        let value: serde_json::Value =
+            serde_json::from_str(&response.into_inner().value_json).unwrap();
+        assert_eq!(value, data);
+    }
+
+    fn echo_agent() -> ParallaxAgent {
+        ParallaxAgent::new("echo-agent", "Echo Agent", vec![], HashMap::new()).set_analyze_fn(
+            |_task: &str, data: Option<serde_json::Value>| async move {
+                Ok(AgentResult {
+                    value: data.unwrap_or_default(),
+                    confidence: 0.9,
+                    ..Default::default()
+                })
+            },
+        )
+    }
+
+    fn data_request(data: serde_json::Value) -> AgentRequest {
+        AgentRequest {
+            task_description: "echo".to_string(),
+            data: Some(crate::conversions::json_to_struct(data)),
+            ..Default::default()
+        }
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct ReviewParams {
+        content: String,
+        #[serde(default)]
+        strict: bool,
+    }
+
+    #[test]
+    fn test_data_as_deserializes_typed_params() {
+        let request = data_request(serde_json::json!({ "content": "fn main() {}", "strict": true }));
+        assert_eq!(
+            request.data_as::<ReviewParams>().unwrap(),
+            ReviewParams {
+                content: "fn main() {}".to_string(),
+                strict: true,
+            }
+        );
+
+        assert!(data_request(serde_json::json!({ "strict": true }))
+            .data_as::<ReviewParams>()
+            .is_err());
+        let missing = AgentRequest::default();
+        assert_eq!(missing.data_as::<Option<ReviewParams>>().unwrap(), None);
+    }
+
+    #[test]
+    fn test_unwrap_data_envelope_only_strips_sole_object() {
+        assert_eq!(
+            unwrap_data_envelope(serde_json::json!({ "data": { "content": "x" } })),
+            serde_json::json!({ "content": "x" })
+        );
+        for kept in [
+            serde_json::json!({ "data": { "content": "x" }, "mode": "fast" }),
+            serde_json::json!({ "data": "x" }),
+            serde_json::json!({ "content": "x" }),
+        ] {
+            assert_eq!(unwrap_data_envelope(kept.clone()), kept);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_analyze_fn_receives_payload_as_sent() {
+        use crate::generated::confidence_agent_client::ConfidenceAgentClient;
+
+        let serve = |agent: ParallaxAgent| async {
+            let channel = crate::test_support::serve(
+                Server::builder().add_service(Arc::new(agent).into_service()),
+            )
+            .await;
+            ConfidenceAgentClient::new(channel)
+        };
+        let enveloped = serde_json::json!({ "data": { "content": "review me" } });
+
+        // No extra level is added, and none is taken away by default
+        let mut client = serve(echo_agent()).await;
+        for data in [enveloped.clone(), serde_json::json!({ "content": "review me" })] {
+            let response = client.analyze(data_request(data.clone())).await.unwrap();
+            assert_eq!(agent_result_from_response(response).unwrap().value, data);
+        }
+
+        let mut client = serve(echo_agent().with_unwrap_data_envelope(true)).await;
+        let response = client.analyze(data_request(enveloped)).await.unwrap();
+        assert_eq!(
+            agent_result_from_response(response).unwrap().value,
+            serde_json::json!({ "content": "review me" })
+        );
+
+        // Other keys next to `data` mean it is part of the payload
+        let data = serde_json::json!({ "data": { "content": "review me" }, "mode": "fast" });
+        let response = client.analyze(data_request(data.clone())).await.unwrap();
+        assert_eq!(agent_result_from_response(response).unwrap().value, data);
+    }
+
+    #[tokio::test]
+    async fn test_analyze_local_matches_grpc_round_trip() {
+        use crate::generated::confidence_agent_client::ConfidenceAgentClient;
+
+        let agent = Arc::new(
+            ParallaxAgent::new("local-agent", "Local Agent", vec![], HashMap::new())
+                .set_analyze_fn(|task: &str, data: Option<serde_json::Value>| {
+                    let task = task.to_string();
+                    async move {
+                        Ok(AgentResult {
+                            value: serde_json::json!({ "task": task, "data": data }),
+                            confidence: 0.75,
+                            reasoning_steps: vec![
+                                ReasoningStep::new("Read the input"),
+                                ReasoningStep::new("Echoed it").with_confidence(0.9),
+                            ],
+                            uncertainties: vec!["input may be partial".to_string()],
+                            metadata: HashMap::from([("model".to_string(), "echo".to_string())]),
+                            cache_ttl: Some(Duration::from_secs(60)),
+                            ..Default::default()
+                        })
+                    }
+                })
+                .with_middleware(crate::middleware::LoggingMiddleware),
+        );
+        let channel = crate::test_support::serve(
+            Server::builder().add_service(Arc::clone(&agent).into_service()),
+        )
+        .await;
+        let mut client = ConfidenceAgentClient::new(channel);
+        let data = serde_json::json!({ "items": [1, 2.5, "three"], "nested": { "ok": true } });
+
+        let local = agent.analyze_local("echo", Some(data.clone())).await.unwrap();
+        let remote = client.analyze(data_request(data)).await.unwrap();
+        let remote = agent_result_from_response(remote).unwrap();
+
+        assert_eq!(local.value, remote.value);
+        assert_eq!(local.confidence, remote.confidence);
+        assert_eq!(local.reasoning, None);
+        assert_eq!(local.reasoning, remote.reasoning);
+        assert_eq!(local.reasoning_steps, remote.reasoning_steps);
+        assert_eq!(local.reasoning_steps.len(), 2);
+        assert_eq!(local.uncertainties, remote.uncertainties);
+        assert_eq!(local.metadata, remote.metadata);
+        assert_eq!(local.cache_ttl, Some(Duration::from_secs(60)));
+        assert_eq!(local.cache_ttl, remote.cache_ttl);
+
+        // Both calls went through the agent's request accounting
+        let details = agent.health_check(Request::new(())).await.unwrap().into_inner().details;
+        assert_eq!(details["requests_total"], "2");
+
+        let error = agent.analyze_local("", None).await.unwrap_err();
+        assert_eq!(error.code(), crate::error::ErrorCode::InvalidArgument);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_request_data_is_rejected() {
+        use crate::generated::confidence_agent_client::ConfidenceAgentClient;
+
+        let agent = Arc::new(echo_agent().with_max_request_bytes(1024));
+        let channel = crate::test_support::serve(
+            Server::builder().add_service(Arc::clone(&agent).into_service()),
+        )
+        .await;
+        let mut client = ConfidenceAgentClient::new(channel);
+
+        let small = serde_json::json!({ "document": "short" });
+        client.analyze(data_request(small)).await.unwrap();
+
+        let large = serde_json::json!({ "document": "x".repeat(8 * 1024) });
+        let status = client.analyze(data_request(large)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+
+        // Far beyond the limit, the transport refuses the message outright,
+        // with the same code
+        let huge = serde_json::json!({ "document": "x".repeat(1024 * 1024) });
+        let status = client.analyze(data_request(huge)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        assert!(status.message().contains("too large"), "{}", status.message());
+        assert_eq!(agent.in_flight_requests(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_stream_analyze_streams_log_lines_before_result() {
+        use crate::generated::confidence_agent_client::ConfidenceAgentClient;
+
+        let release = Arc::new(tokio::sync::Notify::new());
+        let agent = Arc::new(
+            ParallaxAgent::new("chatty-agent", "Chatty Agent", vec![], HashMap::new())
+                .set_analyze_fn_with_logs({
+                    let release = Arc::clone(&release);
+                    move |_task: &str, _data: Option<serde_json::Value>, _cancellation, logs: LogSink| {
+                        let release = Arc::clone(&release);
+                        async move {
+                            logs.info("loading model");
+                            logs.warn("falling back to the small model");
+                            release.notified().await;
+                            Ok(AgentResult {
+                                value: serde_json::json!("done"),
+                                confidence: 0.9,
+                                ..Default::default()
+                            })
+                        }
+                    }
+                }),
+        );
+        let channel = crate::test_support::serve(
+            Server::builder().add_service(Arc::clone(&agent).into_service()),
+        )
+        .await;
+        let mut client = ConfidenceAgentClient::new(channel);
+
+        let response = client.stream_analyze(agent_request(0)).await.unwrap();
+        assert!(response.metadata().get(REQUEST_ID_HEADER).is_some());
+        let mut stream = response.into_inner();
+
+        // Log lines arrive while the analysis is still running
+        let mut lines = Vec::new();
+        for _ in 0..2 {
+            let message = stream.message().await.unwrap().unwrap();
+            assert_eq!(message.agent_id, "chatty-agent");
+            let log = message.log.unwrap();
+            lines.push((log.level, log.message));
+        }
+        assert_eq!(
+            lines,
+            [
+                ("info".to_string(), "loading model".to_string()),
+                ("warn".to_string(), "falling back to the small model".to_string()),
+            ]
+        );
+
+        release.notify_one();
+        let result = stream.message().await.unwrap().unwrap();
+        assert!(result.log.is_none());
+        assert_eq!(result.value_json, "\"done\"");
+        assert_eq!(result.confidence, 0.9);
+        assert!(stream.message().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stream_analyze_chunks_large_values() {
+        use crate::generated::confidence_agent_client::ConfidenceAgentClient;
+
+        let value = serde_json::json!({
+            "rows": (0..2000).map(|row| format!("row {} ✓", row)).collect::<Vec<_>>()
+        });
+        let agent = Arc::new(
+            ParallaxAgent::new("bulk-agent", "Bulk Agent", vec![], HashMap::new())
+                .set_analyze_fn({
+                    let value = value.clone();
+                    move |_task: &str, _data: Option<serde_json::Value>| {
+                        let value = value.clone();
+                        async move {
+                            Ok(AgentResult {
+                                value,
+                                confidence: 0.9,
+                                ..Default::default()
+                            })
+                        }
+                    }
+                })
+                .with_stream_chunk_size(1024),
+        );
+        let channel = crate::test_support::serve(
+            Server::builder().add_service(Arc::clone(&agent).into_service()),
+        )
+        .await;
+        let mut client = ConfidenceAgentClient::new(channel);
+
+        let messages: Vec<_> = client
+            .stream_analyze(agent_request(0))
+            .await
+            .unwrap()
+            .into_inner()
+            .collect()
+            .await;
+        assert!(messages.len() > 20, "{} messages", messages.len());
+        assert!(messages
+            .iter()
+            .all(|message| message.as_ref().unwrap().value_json.len() <= 1024));
+
+        let result = crate::ResultAssembler::new()
+            .collect(futures::stream::iter(messages))
+            .await
+            .unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&result.value_json).unwrap(), value);
+        assert_eq!(result.confidence, 0.9);
+        assert!(result.value_struct.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_result_value_is_also_sent_structured() {
+        let agent = Arc::new(echo_agent());
+        let data = serde_json::json!({
+            "summary": "naïve “quotes” ✓",
+            "scores": [0.1, 0.25, 1e-9, 12345.678, -3.0],
+            "findings": [
+                { "file": "src/lib.rs", "lines": [10.0, 42.0], "fixed": false },
+                { "file": "README.md", "notes": null, "tags": [[], {}] }
+            ],
+            "meta": { "nested": { "deeper": { "ok": true } } }
+        });
+
+        let response = agent
+            .analyze(Request::new(data_request(data.clone())))
+            .await
+            .unwrap()
+            .into_inner();
+
+        let structured = crate::conversions::struct_to_json(response.value_struct.unwrap());
+        assert_eq!(structured, data);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&response.value_json).unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn test_unset_request_fields_are_null_unless_omitted() {
+        use prost_types::value::Kind;
+
+        let request = || {
+            let mut data = crate::conversions::json_to_struct(serde_json::json!({ "explicit": null }));
+            data.fields.insert("missing".to_string(), prost_types::Value { kind: None });
+            AgentRequest {
+                task_description: "echo".to_string(),
+                data: Some(data),
+                ..Default::default()
+            }
+        };
+        assert!(matches!(
+            request().data.unwrap().fields["explicit"].kind,
+            Some(Kind::NullValue(_))
+        ));
+        let echoed = |response: Response<ConfidenceResult>| {
+            serde_json::from_str::<serde_json::Value>(&response.into_inner().value_json).unwrap()
+        };
+
+        let lenient = Arc::new(echo_agent());
+        let response = lenient.analyze(Request::new(request())).await.unwrap();
+        assert_eq!(echoed(response), serde_json::json!({ "explicit": null, "missing": null }));
 
-    async fn connect_via_gateway_inner(
-        self: &Arc<Self>,
-        endpoint: &str,
-        options: Option<GatewayOptions>,
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let opts = options.unwrap_or_default();
-        let heartbeat_interval_ms = opts.heartbeat_interval_ms;
-        let endpoint_owned = endpoint.to_string();
+        let strict = Arc::new(echo_agent().with_omit_unset_fields(true));
+        let response = strict.analyze(Request::new(request())).await.unwrap();
+        assert_eq!(echoed(response), serde_json::json!({ "explicit": null }));
+    }
 
-        // Build channel with optional TLS
-        let channel = if let Some(tls_config) = &opts.credentials {
-            tonic::transport::Channel::from_shared(endpoint_owned.clone())?
-                .tls_config(tls_config.clone())?
-                .connect()
-                .await?
-        } else {
-            tonic::transport::Channel::from_shared(endpoint_owned.clone())?
-                .connect()
-                .await?
+    #[tokio::test]
+    async fn test_deeply_nested_request_data_is_rejected() {
+        let agent = Arc::new(echo_agent().with_max_data_depth(8));
+        let nested = |levels: usize| {
+            (1..levels).fold(serde_json::json!({ "leaf": true }), |inner, level| {
+                if level % 2 == 1 {
+                    serde_json::json!({ "child": inner })
+                } else {
+                    serde_json::json!([inner])
+                }
+            })
         };
 
-        let mut client = AgentGatewayClient::new(channel);
+        let shallow = agent.analyze(Request::new(data_request(nested(8)))).await.unwrap();
+        assert!(shallow.into_inner().value_json.contains("leaf"));
 
-        // Create a channel for sending messages to the stream
-        let (tx, rx) = tokio::sync::mpsc::channel::<AgentToControlPlane>(32);
-        let rx_stream = tokio_stream::wrappers::ReceiverStream::new(rx);
+        let status = agent
+            .analyze(Request::new(data_request(nested(200))))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        assert!(status.message().contains("8 levels"));
+    }
 
-        // Open bidirectional stream
-        let response = client.connect(rx_stream).await?;
-        let mut inbound = response.into_inner();
+    #[tokio::test]
+    async fn test_request_id_is_echoed_in_response_metadata() {
+        use crate::generated::confidence_agent_client::ConfidenceAgentClient;
 
-        // Send AgentHello
-        let hello = AgentToControlPlane {
-            request_id: format!("hello-{}", self.id),
-            payload: Some(agent_to_control_plane::Payload::Hello(AgentHello {
-                agent_id: self.id.clone(),
-                agent_name: self.name.clone(),
-                capabilities: self.capabilities.clone(),
-                metadata: self.metadata.clone(),
-                heartbeat_interval_ms: heartbeat_interval_ms as i32,
-            })),
+        let channel = crate::test_support::serve(
+            Server::builder().add_service(Arc::new(echo_agent()).into_service()),
+        )
+        .await;
+        let mut client = ConfidenceAgentClient::new(channel);
+
+        let mut request = Request::new(data_request(serde_json::json!({ "n": 1 })));
+        request.metadata_mut().insert(REQUEST_ID_HEADER, "req-123".parse().unwrap());
+        request.metadata_mut().insert(
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".parse().unwrap(),
+        );
+        let response = client.analyze(request).await.unwrap();
+        assert_eq!(response.metadata().get(REQUEST_ID_HEADER).unwrap(), "req-123");
+
+        // Callers without an id get a generated one, on failures too
+        let status = client
+            .analyze(AgentRequest::default())
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        let generated = status.metadata().get(REQUEST_ID_HEADER).unwrap();
+        assert!(uuid::Uuid::parse_str(generated.to_str().unwrap()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_agent_error_reaches_client_in_status_details() {
+        use crate::generated::confidence_agent_client::ConfidenceAgentClient;
+
+        let agent = ParallaxAgent::new("flaky-agent", "Flaky Agent", vec![], HashMap::new())
+            .set_analyze_fn(|task: &str, _data: Option<serde_json::Value>| {
+                let error = if task == "overloaded" {
+                    AgentError::retryable("MODEL_OVERLOADED", "model is at capacity")
+                } else {
+                    AgentError::new("UNSUPPORTED_LANGUAGE", "cannot analyze COBOL")
+                };
+                async move { Err(error.into()) }
+            });
+        let channel = crate::test_support::serve(
+            Server::builder().add_service(Arc::new(agent).into_service()),
+        )
+        .await;
+        let mut client = ConfidenceAgentClient::new(channel);
+        let request = |task: &str| AgentRequest {
+            task_description: task.to_string(),
+            ..Default::default()
         };
-        tx.send(hello).await.map_err(|e| format!("Failed to send hello: {}", e))?;
 
-        info!(
-            agent_id = %self.id,
-            agent_name = %self.name,
-            endpoint = %endpoint_owned,
-            "Connecting via gateway"
+        let status = client.analyze(request("overloaded")).await.unwrap_err();
+        let error = crate::Error::from(status);
+        assert!(error.is_retryable());
+        assert_eq!(
+            error.agent_error(),
+            Some(AgentError::retryable("MODEL_OVERLOADED", "model is at capacity"))
         );
 
-        // Wait for ServerAck
-        let ack_timeout = Duration::from_secs(10);
-        let ack_msg = tokio::time::timeout(ack_timeout, inbound.message()).await
-            .map_err(|_| "Gateway connection timed out waiting for ack")?
-            .map_err(|e| format!("Stream error waiting for ack: {}", e))?
-            .ok_or("Stream ended before receiving ack")?;
+        let status = client.analyze(request("cobol")).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Internal);
+        let error = crate::Error::from(status);
+        assert!(!error.is_retryable());
+        assert_eq!(error.agent_error().unwrap().code, "UNSUPPORTED_LANGUAGE");
+    }
 
-        if let Some(control_plane_to_agent::Payload::Ack(ack)) = ack_msg.payload {
-            if !ack.accepted {
-                return Err(format!("Gateway rejected agent: {}", ack.message).into());
-            }
-            info!(
-                agent_id = %self.id,
-                node_id = %ack.assigned_node_id,
-                "Connected via gateway"
-            );
-        } else {
-            return Err("Expected ServerAck but received different message".into());
+    #[tokio::test]
+    async fn test_dropped_call_cancels_analysis_token() {
+        use crate::generated::confidence_agent_client::ConfidenceAgentClient;
+
+        // The analyze fn hands its token out, then runs until cancelled
+        let (token_tx, mut token_rx) = tokio::sync::mpsc::unbounded_channel();
+        let agent = ParallaxAgent::new("long-agent", "Long Agent", vec![], HashMap::new())
+            .set_analyze_fn_cancellable(move |_task: &str, _data, cancellation: CancellationToken| {
+                let _ = token_tx.send(cancellation.clone());
+                async move {
+                    cancellation.cancelled().await;
+                    Err("cancelled".into())
+                }
+            });
+        let channel = crate::test_support::serve(
+            Server::builder().add_service(Arc::new(agent).into_service()),
+        )
+        .await;
+
+        let call = tokio::spawn(async move {
+            let mut client = ConfidenceAgentClient::new(channel);
+            client.analyze(data_request(serde_json::json!({}))).await
+        });
+        let token = token_rx.recv().await.unwrap();
+        assert!(!token.is_cancelled());
+        call.abort();
+
+        tokio::time::timeout(Duration::from_secs(5), token.cancelled())
+            .await
+            .expect("token was not cancelled after the client dropped the call");
+    }
+
+    type GatewayOutbound = tokio::sync::mpsc::UnboundedSender<Result<ControlPlaneToAgent, Status>>;
+
+    /// Gateway that hands each agent connection to the test
+    struct TestGateway {
+        connections:
+            tokio::sync::mpsc::UnboundedSender<(tonic::Streaming<AgentToControlPlane>, GatewayOutbound)>,
+    }
+
+    #[tonic::async_trait]
+    impl AgentGateway for TestGateway {
+        type ConnectStream = tokio_stream::wrappers::UnboundedReceiverStream<
+            Result<ControlPlaneToAgent, Status>,
+        >;
+
+        async fn connect(
+            &self,
+            request: Request<tonic::Streaming<AgentToControlPlane>>,
+        ) -> Result<Response<Self::ConnectStream>, Status> {
+            let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+            tx.send(Ok(ControlPlaneToAgent {
+                request_id: String::new(),
+                payload: Some(control_plane_to_agent::Payload::Ack(ServerAck {
+                    accepted: true,
+                    message: String::new(),
+                    assigned_node_id: "node-1".to_string(),
+                })),
+            }))
+            .unwrap();
+            let _ = self.connections.send((request.into_inner(), tx));
+            Ok(Response::new(tokio_stream::wrappers::UnboundedReceiverStream::new(rx)))
         }
+    }
 
-        // Create gateway shutdown channel
-        let (gw_shutdown_tx, mut gw_shutdown_rx) = tokio::sync::oneshot::channel();
-        {
-            let mut shutdown = self.gateway_shutdown_tx.lock().await;
-            *shutdown = Some(gw_shutdown_tx);
+    #[tokio::test]
+    async fn test_gateway_cancel_task_cancels_the_analyze_token() {
+        let (token_tx, mut token_rx) = tokio::sync::mpsc::unbounded_channel();
+        let agent = Arc::new(
+            ParallaxAgent::new("gateway-agent", "Gateway Agent", vec![], HashMap::new())
+                .set_analyze_fn_cancellable(move |_task: &str, _data, cancellation: CancellationToken| {
+                    let _ = token_tx.send(cancellation.clone());
+                    async move {
+                        cancellation.cancelled().await;
+                        Err("cancelled".into())
+                    }
+                }),
+        );
+        let (connections, mut connected) = tokio::sync::mpsc::unbounded_channel();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(
+            Server::builder()
+                .add_service(AgentGatewayServer::new(TestGateway { connections }))
+                .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+        );
+
+        let options = GatewayOptions { auto_reconnect: false, ..Default::default() };
+        agent.connect_via_gateway(&endpoint, Some(options)).await.unwrap();
+        let (mut inbound, outbound) = connected.recv().await.unwrap();
+
+        outbound
+            .send(Ok(ControlPlaneToAgent {
+                request_id: "req-1".to_string(),
+                payload: Some(control_plane_to_agent::Payload::TaskRequest(TaskRequest {
+                    task_id: "task-1".to_string(),
+                    task_description: "long task".to_string(),
+                    ..Default::default()
+                })),
+            }))
+            .unwrap();
+        let token = token_rx.recv().await.unwrap();
+        assert!(!token.is_cancelled());
+
+        outbound
+            .send(Ok(ControlPlaneToAgent {
+                request_id: "req-2".to_string(),
+                payload: Some(control_plane_to_agent::Payload::CancelTask(CancelTask {
+                    task_id: "task-1".to_string(),
+                    reason: "caller went away".to_string(),
+                })),
+            }))
+            .unwrap();
+        tokio::time::timeout(Duration::from_secs(5), token.cancelled())
+            .await
+            .expect("CancelTask did not cancel the analyze token");
+
+        // The cancelled task sends nothing back; only the hello arrives
+        drop(outbound);
+        while let Some(message) = inbound.message().await.ok().flatten() {
+            assert!(matches!(
+                message.payload,
+                Some(agent_to_control_plane::Payload::Hello(_))
+                    | Some(agent_to_control_plane::Payload::Heartbeat(_))
+            ));
         }
+    }
 
-        // Spawn heartbeat task
-        let tx_heartbeat = tx.clone();
-        let agent_id = self.id.clone();
-        let heartbeat_handle = tokio::spawn(async move {
-            let mut interval = interval(Duration::from_millis(heartbeat_interval_ms));
-            loop {
-                interval.tick().await;
-                let heartbeat = AgentToControlPlane {
-                    request_id: String::new(),
-                    payload: Some(agent_to_control_plane::Payload::Heartbeat(AgentHeartbeat {
-                        agent_id: agent_id.clone(),
-                        load: 0.0,
-                        status: "healthy".to_string(),
-                        extra: HashMap::new(),
-                    })),
-                };
-                if tx_heartbeat.send(heartbeat).await.is_err() {
-                    break;
-                }
+    #[tokio::test]
+    async fn test_gateway_task_error_carries_the_failure_code() {
+        let agent = Arc::new(
+            ParallaxAgent::new("gateway-agent", "Gateway Agent", vec![], HashMap::new())
+                .set_analyze_fn(|_task: &str, _data: Option<serde_json::Value>| async {
+                    Err(AgentError::retryable("MODEL_OVERLOADED", "model is at capacity").into())
+                }),
+        );
+        let (connections, mut connected) = tokio::sync::mpsc::unbounded_channel();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(
+            Server::builder()
+                .add_service(AgentGatewayServer::new(TestGateway { connections }))
+                .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+        );
+
+        let options = GatewayOptions { auto_reconnect: false, ..Default::default() };
+        agent.connect_via_gateway(&endpoint, Some(options)).await.unwrap();
+        let (mut inbound, outbound) = connected.recv().await.unwrap();
+        outbound
+            .send(Ok(ControlPlaneToAgent {
+                request_id: "req-1".to_string(),
+                payload: Some(control_plane_to_agent::Payload::TaskRequest(TaskRequest {
+                    task_id: "task-1".to_string(),
+                    task_description: "busy task".to_string(),
+                    ..Default::default()
+                })),
+            }))
+            .unwrap();
+
+        let error = loop {
+            let message = inbound.message().await.unwrap().expect("connection closed");
+            if let Some(agent_to_control_plane::Payload::TaskError(error)) = message.payload {
+                break error;
             }
-        });
+        };
+        assert_eq!(error.task_id, "task-1");
+        assert_eq!(error.error_code, "UNAVAILABLE");
+        assert!(error.error_message.contains("MODEL_OVERLOADED"), "{}", error.error_message);
+    }
 
-        // Spawn message receiver task
-        let self_clone = Arc::clone(self);
-        let tx_response = tx.clone();
-        let endpoint_for_reconnect = endpoint_owned.clone();
-        let opts_for_reconnect = opts.clone();
-        let mut receiver_handle = tokio::spawn(async move {
-            loop {
-                match inbound.message().await {
-                    Ok(Some(msg)) => {
-                        let request_id = msg.request_id.clone();
-                        match msg.payload {
-                            Some(control_plane_to_agent::Payload::TaskRequest(task_req)) => {
-                                let analyze_fn = Arc::clone(&self_clone.analyze_fn);
-                                let tx_task = tx_response.clone();
-                                let task_id = task_req.task_id.clone();
+    fn result(value: serde_json::Value, confidence: f64, uncertainties: &[&str]) -> AgentResult {
+        AgentResult {
+            value,
+            confidence,
+            uncertainties: uncertainties.iter().map(|u| u.to_string()).collect(),
+            ..Default::default()
+        }
+    }
 
-                                // Parse data from Struct
-                                let data = task_req.data.map(|s| {
-                                    let map: serde_json::Map<String, serde_json::Value> = s
-                                        .fields
-                                        .into_iter()
-                                        .map(|(k, v)| (k, prost_value_to_json(v)))
-                                        .collect();
-                                    serde_json::Value::Object(map)
-                                });
+    #[test]
+    fn test_merge_dedupes_uncertainties_and_merges_metadata() {
+        let mut first = result(serde_json::json!("yes"), 0.9, &["sparse data", "stale index"]);
+        first.metadata.insert("model".to_string(), "a".to_string());
+        first.metadata.insert("region".to_string(), "eu".to_string());
+        let mut second = result(serde_json::json!("no"), 0.5, &["stale index", "ambiguous task"]);
+        second.metadata.insert("model".to_string(), "b".to_string());
 
-                                tokio::spawn(async move {
-                                    match analyze_fn(&task_req.task_description, data)
-                                        .await
-                                        .map_err(|e| e.to_string())
-                                    {
-                                        Ok(result) => {
-                                            let value_json = serde_json::to_string(&result.value)
-                                                .unwrap_or_else(|_| "null".to_string());
-                                            let msg = AgentToControlPlane {
-                                                request_id,
-                                                payload: Some(agent_to_control_plane::Payload::TaskResult(
-                                                    GatewayTaskResult {
-                                                        task_id,
-                                                        value_json,
-                                                        confidence: result.confidence,
-                                                        reasoning: result.reasoning.unwrap_or_default(),
-                                                        metadata: result.metadata,
-                                                    },
-                                                )),
-                                            };
-                                            let _ = tx_task.send(msg).await;
-                                        }
-                                        Err(e) => {
-                                            let msg = AgentToControlPlane {
-                                                request_id,
-                                                payload: Some(agent_to_control_plane::Payload::TaskError(
-                                                    GatewayTaskError {
-                                                        task_id,
-                                                        error_message: e.to_string(),
-                                                        error_code: "INTERNAL".to_string(),
-                                                    },
-                                                )),
-                                            };
-                                            let _ = tx_task.send(msg).await;
-                                        }
-                                    }
-                                });
-                            }
-                            Some(control_plane_to_agent::Payload::CancelTask(cancel)) => {
-                                info!(
-                                    task_id = %cancel.task_id,
-                                    reason = %cancel.reason,
-                                    "Task cancelled"
-                                );
-                            }
-                            Some(control_plane_to_agent::Payload::Ping(_)) => {
-                                let heartbeat = AgentToControlPlane {
-                                    request_id: String::new(),
-                                    payload: Some(agent_to_control_plane::Payload::Heartbeat(
-                                        AgentHeartbeat {
-                                            agent_id: self_clone.id.clone(),
-                                            load: 0.0,
-                                            status: "healthy".to_string(),
-                                            extra: HashMap::new(),
-                                        },
-                                    )),
-                                };
-                                if tx_response.send(heartbeat).await.is_err() {
-                                    break;
-                                }
-                            }
-                            Some(control_plane_to_agent::Payload::Ack(_)) => {
-                                // Already handled during initial connection
-                            }
-                            Some(_) => {
-                                // Thread orchestration payloads (ThreadSpawn/ThreadInput/
-                                // ThreadStop) target CLI runtime agents; this example only
-                                // serves analyze tasks
-                            }
-                            None => {}
+        let merged = AgentResult::merge(&[first, second], "avg");
+
+        assert_eq!(merged.value, serde_json::json!(["yes", "no"]));
+        assert_eq!(
+            merged.uncertainties,
+            vec!["sparse data", "stale index", "ambiguous task"]
+        );
+        assert_eq!(merged.metadata["model"], "b");
+        assert_eq!(merged.metadata["region"], "eu");
+    }
+
+    #[test]
+    fn test_merge_uses_confidence_strategy() {
+        let results = [
+            result(serde_json::json!(1), 0.9, &[]),
+            result(serde_json::json!(2), 0.5, &[]),
+        ];
+
+        assert_eq!(AgentResult::merge(&results, "min").confidence, 0.5);
+        assert_eq!(AgentResult::merge(&results, "max").confidence, 0.9);
+        assert!((AgentResult::merge(&results, "avg").confidence - 0.7).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_merge_empty_returns_low_confidence_result() {
+        let merged = AgentResult::merge(&[], "avg");
+
+        assert_eq!(merged.confidence, 0.0);
+        assert_eq!(merged.value, serde_json::json!([]));
+        assert!(merged.uncertainties.is_empty());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[tokio::test]
+    async fn test_metrics_count_analyze_requests_and_errors() {
+        let agent = Arc::new(
+            ParallaxAgent::new("metrics-agent", "Metrics Agent", vec![], HashMap::new())
+                .set_analyze_fn(|task: &str, _data: Option<serde_json::Value>| {
+                    let fail = task == "fail";
+                    async move {
+                        if fail {
+                            return Err("boom".into());
                         }
+                        Ok(AgentResult::default())
                     }
-                    Ok(None) => {
-                        info!("Gateway stream ended");
-                        break;
-                    }
-                    Err(e) => {
-                        error!("Gateway stream error: {}", e);
-                        break;
-                    }
-                }
-            }
+                }),
+        );
 
-            // Stream disconnected - attempt reconnect
-            heartbeat_handle.abort();
-            Self::handle_gateway_reconnect(
-                self_clone,
-                &endpoint_for_reconnect,
-                opts_for_reconnect,
-            )
-            .await;
+        for task in ["analyze", "analyze", "fail"] {
+            let _ = agent
+                .analyze(Request::new(AgentRequest {
+                    task_description: task.to_string(),
+                    ..Default::default()
+                }))
+                .await;
+        }
+
+        let metrics = agent.metrics_handle();
+        assert!(metrics
+            .contains("parallax_agent_analyze_requests_total{agent_id=\"metrics-agent\"} 3"));
+        assert!(metrics
+            .contains("parallax_agent_analyze_errors_total{agent_id=\"metrics-agent\"} 1"));
+        assert!(metrics
+            .contains("parallax_agent_analyze_duration_seconds_count{agent_id=\"metrics-agent\"} 3"));
+    }
+
+    #[tokio::test]
+    async fn test_capabilities_are_normalized_and_blanks_rejected() {
+        let raw = ["a", "a", "", "A"].map(String::from).to_vec();
+        let agent = ParallaxAgent::new("caps-agent", "Caps Agent", raw.clone(), HashMap::new());
+        assert_eq!(agent.capabilities(), ["a", "A"]);
+
+        let agent = agent.with_lowercase_capabilities(true);
+        assert_eq!(agent.capabilities(), ["a"]);
+        assert!(agent.update_capabilities(raw.clone()).await.is_err());
+        agent
+            .update_capabilities(vec![" Review ".to_string(), "review".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(agent.capabilities(), ["review"]);
+
+        assert!(echo_agent().with_capabilities(&raw).is_err());
+        let agent = echo_agent().with_capabilities(["b", "b "]).unwrap();
+        assert_eq!(agent.capabilities(), ["b"]);
+    }
+
+    #[tokio::test]
+    async fn test_update_capabilities_reregisters_and_reports_new_set() {
+        use crate::generated::confidence_agent_client::ConfidenceAgentClient;
+
+        let control_plane = crate::testing::MockControlPlane::new();
+        let registry_addr = crate::test_support::serve_at(control_plane.router()).await;
+        let capabilities = vec!["summarize".to_string()];
+        let agent = Arc::new(
+            ParallaxAgent::new("model-agent", "Model Agent", capabilities, HashMap::new())
+                .with_registry_addr(registry_addr),
+        );
+        let mut registration = agent.watch_registration();
+        let supervisor = tokio::spawn({
+            let agent = Arc::clone(&agent);
+            async move { agent.supervise_registration(50051).await }
         });
+        registration
+            .wait_for(|state| *state == RegistrationState::Registered)
+            .await
+            .unwrap();
+        let channel = crate::test_support::serve(
+            Server::builder().add_service(Arc::clone(&agent).into_service()),
+        )
+        .await;
+        let mut client = ConfidenceAgentClient::new(channel);
 
-        // Wait for shutdown signal or receiver to finish
-        tokio::spawn(async move {
-            tokio::select! {
-                _ = &mut gw_shutdown_rx => {
-                    receiver_handle.abort();
-                }
-                _ = &mut receiver_handle => {
-                    // Receiver ended on its own (disconnect/error)
-                }
-            }
+        agent
+            .update_capabilities(vec!["summarize".to_string(), "translate".to_string()])
+            .await
+            .unwrap();
+        let capabilities = client.get_capabilities(()).await.unwrap().into_inner().capabilities;
+        supervisor.abort();
+
+        assert_eq!(capabilities, ["summarize", "translate"]);
+        control_plane.assert_called("Register", 2);
+        assert_eq!(control_plane.agents()[0].capabilities, ["summarize", "translate"]);
+    }
+
+    #[tokio::test]
+    async fn test_default_confidence_is_registered_and_used_as_fallback() {
+        let control_plane = crate::testing::MockControlPlane::new();
+        let registry_addr = crate::test_support::serve_at(control_plane.router()).await;
+        let agent = Arc::new(
+            ParallaxAgent::new("hedging-agent", "Hedging Agent", vec![], HashMap::new())
+                .with_registry_addr(registry_addr)
+                .with_default_confidence(0.6)
+                .unwrap()
+                .set_analyze_fn_with_confidence(|_task: &str, _data| async {
+                    Ok(serde_json::json!({ "answer": "42" }))
+                }),
+        );
+        let mut registration = agent.watch_registration();
+        let supervisor = tokio::spawn({
+            let agent = Arc::clone(&agent);
+            async move { agent.supervise_registration(50051).await }
         });
+        registration
+            .wait_for(|state| *state == RegistrationState::Registered)
+            .await
+            .unwrap();
+        supervisor.abort();
 
-        Ok(())
+        assert_eq!(control_plane.agents()[0].confidence, 0.6);
+        let result = agent
+            .run_analyze("answer", None, CancellationToken::new(), LogSink::disabled())
+            .await
+            .unwrap();
+        assert!((result.confidence - 0.6).abs() < 1e-9);
     }
 
-    /// Handle gateway disconnection with auto-reconnect using exponential backoff
-    async fn handle_gateway_reconnect(
-        agent: Arc<Self>,
-        endpoint: &str,
-        options: GatewayOptions,
-    ) {
-        if !options.auto_reconnect {
-            return;
+    #[test]
+    fn test_default_confidence_outside_unit_range_is_rejected() {
+        for confidence in [-0.1, 1.5, f64::NAN] {
+            let agent = ParallaxAgent::new("agent", "Agent", vec![], HashMap::new());
+            assert!(matches!(
+                agent.with_default_confidence(confidence),
+                Err(crate::Error::InvalidArgument(_))
+            ));
         }
 
-        {
-            let mut reconnecting = agent.gateway_reconnecting.lock().await;
-            if *reconnecting {
-                return;
-            }
-            *reconnecting = true;
-        }
+        let labels = HashMap::from([(DEFAULT_CONFIDENCE_LABEL.to_string(), "0.7".to_string())]);
+        let agent = ParallaxAgent::new("agent", "Agent", vec![], labels);
+        assert_eq!(agent.default_confidence(), Some(0.7));
+        assert_eq!(agent.confidence_config().default_confidence, 0.7);
+    }
 
-        let max_attempts = options.max_reconnect_attempts;
-        let initial_delay = options.initial_reconnect_delay_ms;
-        let max_delay = options.max_reconnect_delay_ms;
-        let mut attempt: u32 = 0;
+    #[tokio::test]
+    async fn test_supervisor_registers_once_registry_comes_up() {
+        let registry_addr = crate::test_support::unused_endpoint().await;
+        let agent = Arc::new(
+            ParallaxAgent::new("late-agent", "Late Agent", vec![], HashMap::new())
+                .with_registry_addr(registry_addr.clone())
+                .with_registration_backoff(Duration::from_millis(10), Duration::from_millis(50)),
+        );
+        let mut registration = agent.watch_registration();
 
-        loop {
-            if let Some(max) = max_attempts {
-                if attempt >= max {
-                    error!(
-                        agent_id = %agent.id,
-                        attempts = attempt,
-                        "Gateway reconnect failed after max attempts"
-                    );
-                    break;
-                }
-            }
+        let supervisor = tokio::spawn({
+            let agent = Arc::clone(&agent);
+            async move { agent.supervise_registration(50051).await }
+        });
 
-            let delay = calculate_reconnect_delay(attempt, initial_delay, max_delay);
-            attempt += 1;
+        registration
+            .wait_for(|state| *state == RegistrationState::Registering)
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(agent.registration_state(), RegistrationState::Registering);
 
-            info!(
-                agent_id = %agent.id,
-                delay_ms = delay,
-                attempt = attempt,
-                "Gateway reconnecting"
-            );
+        let control_plane = crate::testing::MockControlPlane::new();
+        let listener = tokio::net::TcpListener::bind(registry_addr.trim_start_matches("http://"))
+            .await
+            .unwrap();
+        tokio::spawn(control_plane.router().serve_with_incoming(
+            tokio_stream::wrappers::TcpListenerStream::new(listener),
+        ));
 
-            tokio::time::sleep(Duration::from_millis(delay)).await;
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            registration.wait_for(|state| *state == RegistrationState::Registered),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        supervisor.abort();
 
-            match agent
-                .connect_via_gateway(endpoint, Some(options.clone()))
-                .await
-            {
-                Ok(()) => {
-                    info!(agent_id = %agent.id, "Gateway reconnected successfully");
-                    break;
+        assert_eq!(control_plane.agents()[0].id, "late-agent");
+        let details = agent.health_check(Request::new(())).await.unwrap().into_inner().details;
+        assert_eq!(details["registration_state"], "registered");
+    }
+
+    #[tokio::test]
+    async fn test_lease_renews_on_each_interval_of_virtual_time() {
+        let control_plane = crate::testing::MockControlPlane::new();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let registry_addr = format!("http://{}", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let incoming = tokio_stream::wrappers::TcpListenerStream::new(listener).inspect({
+            let connections = Arc::clone(&connections);
+            move |_| {
+                connections.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        tokio::spawn(control_plane.router().serve_with_incoming(incoming));
+        let clock = crate::clock::MockClock::new();
+        let agent = Arc::new(
+            ParallaxAgent::new("renewing-agent", "Renewing Agent", vec![], HashMap::new())
+                .with_registry_addr(registry_addr)
+                .with_clock(clock.clone()),
+        );
+
+        let supervisor = tokio::spawn({
+            let agent = Arc::clone(&agent);
+            async move { agent.supervise_registration(50051).await }
+        });
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            for renewals in 1..=3 {
+                // Wait for the renewal loop to park on its next tick
+                while clock.pending_sleeps() == 0 {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
                 }
-                Err(e) => {
-                    error!(
-                        agent_id = %agent.id,
-                        attempt = attempt,
-                        error = %e,
-                        "Gateway reconnect attempt failed"
-                    );
+                clock.advance(LEASE_RENEWAL_INTERVAL);
+                while control_plane.calls_to("Renew").len() < renewals {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
                 }
             }
-        }
-
-        let mut reconnecting = agent.gateway_reconnecting.lock().await;
-        *reconnecting = false;
-    }
+        })
+        .await
+        .unwrap();
+        supervisor.abort();
 
-    /// Shutdown the agent
-    pub async fn shutdown(&self) -> Result<(), Box<dyn std::error::Error>> {
-        info!(agent_id = %self.id, "Shutting down agent");
+        control_plane.assert_called("Register", 1);
+        control_plane.assert_called("Renew", 3);
+        assert_eq!(agent.registration_state(), RegistrationState::Registered);
 
-        // Prevent reconnect during shutdown
-        {
-            let mut reconnecting = self.gateway_reconnecting.lock().await;
-            *reconnecting = true;
-        }
+        // Registering, every renewal and unregistering share one connection
+        agent.unregister().await.unwrap();
+        control_plane.assert_called("Unregister", 1);
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+    }
 
-        // Shut down gateway connection
-        if let Some(tx) = self.gateway_shutdown_tx.lock().await.take() {
-            let _ = tx.send(());
-        }
+    #[tokio::test]
+    async fn test_evicted_lease_re_registers_within_one_cycle() {
+        let control_plane = crate::testing::MockControlPlane::new();
+        let registry_addr = crate::test_support::serve_at(control_plane.router()).await;
+        let clock = crate::clock::MockClock::new();
+        let agent = Arc::new(
+            ParallaxAgent::new("evicted-agent", "Evicted Agent", vec![], HashMap::new())
+                .with_registry_addr(registry_addr)
+                .with_clock(clock.clone()),
+        );
 
-        // Unregister from control plane
-        // Note: In a real implementation, we'd have an unregister method in the Registry service
+        let supervisor = tokio::spawn({
+            let agent = Arc::clone(&agent);
+            async move { agent.supervise_registration(50051).await }
+        });
 
-        // Trigger server shutdown
-        if let Some(tx) = self.shutdown_tx.lock().await.take() {
-            let _ = tx.send(());
-        }
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while clock.pending_sleeps() == 0 {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            control_plane.fail_next("Renew", Status::not_found("lease evicted"));
+            clock.advance(LEASE_RENEWAL_INTERVAL);
+            while control_plane.calls_to("Register").len() < 2 {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+            // Parked on the next renewal tick of the fresh lease
+            while clock.pending_sleeps() == 0 {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+        supervisor.abort();
 
-        Ok(())
+        control_plane.assert_called("Renew", 1);
+        assert_eq!(agent.registration_state(), RegistrationState::Registered);
+        assert!(agent.lease_id.lock().await.is_some());
     }
-}
 
-#[async_trait]
-impl ConfidenceAgent for Arc<ParallaxAgent> {
-    async fn analyze(
-        &self,
-        request: Request<AgentRequest>,
-    ) -> Result<Response<ConfidenceResult>, Status> {
-        let req = request.into_inner();
-        
-        if req.task_description.is_empty() {
-            return Err(Status::invalid_argument("task description is required"));
-        }
-        
-        // Parse data if provided
-        let data = if let Some(data_struct) = req.data {
-            let json_value = serde_json::Value::Object(
-                data_struct.fields.into_iter()
-                    .map(|(k, v)| (k, prost_value_to_json(v)))
-                    .collect()
-            );
-            Some(json_value)
-        } else {
-            None
-        };
-        
-        // Call the analyze function
-        let result = (self.analyze_fn)(&req.task_description, data)
+    /// Serve `agent` on a free port and return the port with the serve task
+    async fn spawn_serve(
+        agent: &Arc<ParallaxAgent>,
+    ) -> (u16, tokio::task::JoinHandle<Result<(), String>>) {
+        let port = tokio::net::TcpListener::bind("127.0.0.1:0")
             .await
-            .map_err(|e| Status::internal(format!("analysis failed: {}", e)))?;
-        
-        // Build response
-        let response = ConfidenceResult {
-            value_json: serde_json::to_string(&result.value)
-                .map_err(|e| Status::internal(format!("failed to serialize result: {}", e)))?,
-            confidence: result.confidence,
-            agent_id: self.id.clone(),
-            timestamp: Some(prost_types::Timestamp::from(std::time::SystemTime::now())),
-            reasoning: result.reasoning.unwrap_or_default(),
-            uncertainties: result.uncertainties,
-            metadata: result.metadata,
-        };
-        
-        Ok(Response::new(response))
-    }
-    
-    type StreamAnalyzeStream = tokio_stream::wrappers::ReceiverStream<Result<ConfidenceResult, Status>>;
-    
-    async fn stream_analyze(
-        &self,
-        request: Request<AgentRequest>,
-    ) -> Result<Response<Self::StreamAnalyzeStream>, Status> {
-        // For now, just analyze once and stream the result
-        let result = self.analyze(request).await?;
-        
-        let (tx, rx) = tokio::sync::mpsc::channel(1);
-        tx.send(Ok(result.into_inner())).await.unwrap();
-        
-        Ok(Response::new(tokio_stream::wrappers::ReceiverStream::new(rx)))
-    }
-    
-    async fn get_capabilities(
-        &self,
-        _request: Request<()>,
-    ) -> Result<Response<Capabilities>, Status> {
-        Ok(Response::new(Capabilities {
-            agent_id: self.id.clone(),
-            name: self.name.clone(),
-            capabilities: self.capabilities.clone(),
-            expertise_level: 0.8,
-            capability_scores: HashMap::new(),
-        }))
-    }
-    
-    async fn health_check(
-        &self,
-        _request: Request<()>,
-    ) -> Result<Response<Health>, Status> {
-        Ok(Response::new(Health {
-            status: HealthStatusProto::Healthy as i32,
-            message: "Agent is operational".to_string(),
-            last_check: Some(prost_types::Timestamp::from(std::time::SystemTime::now())),
-            details: HashMap::new(),
-        }))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let serve = tokio::spawn({
+            let agent = Arc::clone(agent);
+            async move { agent.serve(port).await.map_err(|e| e.to_string()) }
+        });
+        (port, serve)
     }
-}
 
-/// Helper function to serve an agent
-pub async fn serve_agent(agent: Arc<ParallaxAgent>, port: u16) -> Result<(), Box<dyn std::error::Error>> {
-    // Handle shutdown signals
-    let agent_clone = Arc::clone(&agent);
-    tokio::spawn(async move {
-        match signal::ctrl_c().await {
-            Ok(()) => {
-                info!("Received shutdown signal");
-                let _ = agent_clone.shutdown().await;
-            }
-            Err(err) => {
-                error!("Unable to listen for shutdown signal: {}", err);
+    /// Start a slow analyze call against the agent on `port` once it is up
+    async fn start_slow_analysis(
+        port: u16,
+        agent: &Arc<ParallaxAgent>,
+    ) -> tokio::task::JoinHandle<Result<Response<ConfidenceResult>, Status>> {
+        use crate::generated::confidence_agent_client::ConfidenceAgentClient;
+
+        let endpoint = format!("http://127.0.0.1:{}", port);
+        let mut client = loop {
+            let endpoint = tonic::transport::Endpoint::from_shared(endpoint.clone()).unwrap();
+            if let Ok(channel) = endpoint.connect().await {
+                break ConfidenceAgentClient::new(channel);
             }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        let call = tokio::spawn(async move { client.analyze(agent_request(0)).await });
+        while agent.in_flight_requests() == 0 {
+            tokio::time::sleep(Duration::from_millis(5)).await;
         }
-    });
-    
-    agent.serve(port).await
-}
+        call
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[tokio::test]
+    async fn test_shutdown_drains_in_flight_analysis_then_unregisters() {
+        let control_plane = crate::testing::MockControlPlane::new();
+        let registry_addr = crate::test_support::serve_at(control_plane.router()).await;
+        let agent = Arc::new(
+            ParallaxAgent::new("draining-agent", "Draining Agent", vec![], HashMap::new())
+                .set_analyze_fn(|_task: &str, _data: Option<serde_json::Value>| async {
+                    tokio::time::sleep(Duration::from_millis(300)).await;
+                    Ok(AgentResult {
+                        value: serde_json::json!("finished"),
+                        confidence: 0.8,
+                        ..Default::default()
+                    })
+                })
+                .with_registry_addr(registry_addr)
+                .with_drain_timeout(Duration::from_secs(5)),
+        );
+        let mut registration = agent.watch_registration();
+        let (port, serve) = spawn_serve(&agent).await;
+        registration
+            .wait_for(|state| *state == RegistrationState::Registered)
+            .await
+            .unwrap();
 
-    #[test]
-    fn test_gateway_options_default() {
-        let opts = GatewayOptions::default();
-        assert!(opts.credentials.is_none());
-        assert_eq!(opts.heartbeat_interval_ms, 10000);
-        assert!(opts.auto_reconnect);
-        assert!(opts.max_reconnect_attempts.is_none());
-        assert_eq!(opts.initial_reconnect_delay_ms, 1000);
-        assert_eq!(opts.max_reconnect_delay_ms, 30000);
+        let call = start_slow_analysis(port, &agent).await;
+        agent.shutdown().await.unwrap();
+        // Calls arriving during the drain are refused
+        let status = agent.analyze(agent_request(0)).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unavailable);
+
+        tokio::time::timeout(Duration::from_secs(5), serve)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(call.is_finished());
+        let response = call.await.unwrap().unwrap().into_inner();
+        assert_eq!(response.value_json, "\"finished\"");
+        control_plane.assert_called("Unregister", 1);
+        assert!(control_plane.agents().is_empty());
+        assert_eq!(agent.registration_state(), RegistrationState::Unregistered);
     }
 
-    #[test]
-    fn test_gateway_options_custom() {
-        let opts = GatewayOptions {
-            credentials: None,
-            heartbeat_interval_ms: 5000,
-            auto_reconnect: false,
-            max_reconnect_attempts: Some(5),
-            initial_reconnect_delay_ms: 500,
-            max_reconnect_delay_ms: 60000,
-        };
-        assert_eq!(opts.heartbeat_interval_ms, 5000);
-        assert!(!opts.auto_reconnect);
-        assert_eq!(opts.max_reconnect_attempts, Some(5));
-        assert_eq!(opts.initial_reconnect_delay_ms, 500);
-        assert_eq!(opts.max_reconnect_delay_ms, 60000);
+    #[tokio::test]
+    async fn test_drain_timeout_force_closes_server() {
+        let agent = Arc::new(
+            slow_agent()
+                .with_registry_addr(crate::test_support::unused_endpoint().await)
+                .with_drain_timeout(Duration::from_millis(100)),
+        );
+        let (port, serve) = spawn_serve(&agent).await;
+
+        let call = start_slow_analysis(port, &agent).await;
+        let started = Instant::now();
+        agent.shutdown().await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), serve)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(call.await.unwrap().is_err());
     }
 
-    #[test]
-    fn test_reconnect_delay_exponential_backoff() {
-        let initial = 1000;
-        let max = 30000;
+    fn reasoning_agent(reasoning: Option<&str>, steps: Vec<ReasoningStep>) -> Arc<ParallaxAgent> {
+        let reasoning = reasoning.map(str::to_string);
+        Arc::new(
+            ParallaxAgent::new("reasoning-agent", "Reasoning Agent", vec![], HashMap::new())
+                .set_analyze_fn(move |_task: &str, _data: Option<serde_json::Value>| {
+                    let result = AgentResult {
+                        reasoning: reasoning.clone(),
+                        reasoning_steps: steps.clone(),
+                        ..Default::default()
+                    };
+                    async move { Ok(result) }
+                }),
+        )
+    }
 
-        assert_eq!(calculate_reconnect_delay(0, initial, max), 1000);
-        assert_eq!(calculate_reconnect_delay(1, initial, max), 2000);
-        assert_eq!(calculate_reconnect_delay(2, initial, max), 4000);
-        assert_eq!(calculate_reconnect_delay(3, initial, max), 8000);
-        assert_eq!(calculate_reconnect_delay(4, initial, max), 16000);
+    #[tokio::test]
+    async fn test_reasoning_steps_are_sent_and_derive_reasoning() {
+        let steps = vec![
+            ReasoningStep::new("Parsed the diff").with_confidence(0.95),
+            ReasoningStep::new("No tests cover the new branch"),
+        ];
+        let agent = reasoning_agent(None, steps.clone());
+
+        let response = agent.analyze(agent_request(0)).await.unwrap().into_inner();
+
+        assert_eq!(response.reasoning, "Parsed the diff\nNo tests cover the new branch");
+        assert_eq!(ReasoningStep::from_metadata(&response.metadata), steps);
     }
 
-    #[test]
-    fn test_reconnect_delay_capped_at_max() {
-        let initial = 1000;
-        let max = 10000;
+    #[tokio::test]
+    async fn test_plain_reasoning_is_unchanged() {
+        let agent = reasoning_agent(Some("Looks correct"), vec![]);
 
-        assert_eq!(calculate_reconnect_delay(0, initial, max), 1000);
-        assert_eq!(calculate_reconnect_delay(3, initial, max), 8000);
-        assert_eq!(calculate_reconnect_delay(4, initial, max), 10000);
-        assert_eq!(calculate_reconnect_delay(5, initial, max), 10000);
-        assert_eq!(calculate_reconnect_delay(10, initial, max), 10000);
+        let response = agent.analyze(agent_request(0)).await.unwrap().into_inner();
+
+        assert_eq!(response.reasoning, "Looks correct");
+        assert!(!response.metadata.contains_key(REASONING_STEPS_KEY));
+        assert!(ReasoningStep::from_metadata(&response.metadata).is_empty());
     }
 
     #[test]
-    fn test_reconnect_delay_no_overflow() {
-        let initial = 1000;
-        let max = 30000;
+    fn test_manifest_lists_what_the_agent_advertises() {
+        let metadata = HashMap::from([
+            ("region".to_string(), "eu".to_string()),
+            ("language".to_string(), "rust".to_string()),
+        ]);
+        let agent = ParallaxAgent::new(
+            "manifest-agent",
+            "Manifest Agent",
+            vec!["review".to_string(), "security".to_string()],
+            metadata,
+        )
+        .capability_score("security", 0.9)
+        .capability_score("review", 0.6)
+        .with_default_confidence(0.7)
+        .unwrap();
 
-        // Very large attempt number should not panic
-        let delay = calculate_reconnect_delay(100, initial, max);
-        assert_eq!(delay, max);
+        let manifest = agent.manifest();
+        assert_eq!(
+            manifest,
+            serde_json::json!({
+                "id": "manifest-agent",
+                "name": "Manifest Agent",
+                "capabilities": ["review", "security"],
+                "capability_scores": { "review": 0.6, "security": 0.9 },
+                "metadata": { "language": "rust", "region": "eu" },
+                "default_confidence": 0.7,
+            })
+        );
+        let rendered = serde_json::to_string(&manifest).unwrap();
+        assert!(rendered.find("\"review\":0.6").unwrap() < rendered.find("\"security\":0.9").unwrap());
+        assert!(rendered.find("\"language\"").unwrap() < rendered.find("\"region\"").unwrap());
+        for _ in 0..3 {
+            assert_eq!(serde_json::to_string(&agent.manifest()).unwrap(), rendered);
+        }
+
+        let plain = ParallaxAgent::new("plain", "Plain", vec![], HashMap::new());
+        assert!(plain.manifest()["default_confidence"].is_null());
     }
 
-    #[test]
-    fn test_agent_has_gateway_fields() {
+    #[tokio::test]
+    async fn test_capability_scores_surface_through_get_capabilities() {
+        use crate::generated::confidence_agent_client::ConfidenceAgentClient;
+
         let agent = ParallaxAgent::new(
-            "test-agent",
-            "Test Agent",
-            vec!["analysis".to_string()],
+            "scored-agent",
+            "Scored Agent",
+            vec!["security".to_string(), "review".to_string()],
             HashMap::new(),
+        )
+        .capability_score("security", 0.9)
+        .capability_score("review", 0.6);
+        let channel = crate::test_support::serve(
+            Server::builder().add_service(Arc::new(agent).into_service()),
+        )
+        .await;
+
+        let capabilities = ConfidenceAgentClient::new(channel)
+            .get_capabilities(())
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(capabilities.capability_scores["security"], 0.9);
+        assert_eq!(capabilities.capability_scores["review"], 0.6);
+        assert!((capabilities.expertise_level - 0.75).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_serve_rejects_out_of_range_capability_scores() {
+        let agent = Arc::new(
+            ParallaxAgent::new("bad-agent", "Bad Agent", vec![], HashMap::new())
+                .capability_score("security", 1.5),
         );
-        // Verify gateway fields are initialized
-        assert!(agent.gateway_shutdown_tx.try_lock().is_ok());
-        assert!(agent.gateway_reconnecting.try_lock().is_ok());
+
+        let error = agent.serve(0).await.unwrap_err();
+        assert!(error.to_string().contains("security=1.5"));
+        assert_eq!(
+            ParallaxAgent::new("plain", "Plain", vec![], HashMap::new()).expertise_level(),
+            DEFAULT_EXPERTISE_LEVEL
+        );
+    }
+
+    #[tokio::test]
+    async fn test_no_cache_ttl_omits_cache_control_header() {
+        let agent = cached_agent(None);
+
+        let response = agent.analyze(agent_request(0)).await.unwrap();
+        assert!(response.metadata().get("cache-control").is_none());
     }
 }
\ No newline at end of file
@@ -1,60 +1,206 @@
 use crate::{
-    error::{Error, Result},
+    circuit_breaker::{self, CircuitBreaker},
+    client::{InterceptedChannel, InterceptorChain},
+    clock::{calculate_reconnect_delay, Clock, SystemClock},
+    conversions::{json_to_struct, struct_to_json, JsonLimits, NonFinitePolicy},
+    error::{rpc_error, Error, Result, ResultExt},
+    executions::until_terminal,
     generated::parallax::patterns::{
-        pattern_service_client::PatternServiceClient, ExecutePatternRequest, ListPatternsRequest,
+        pattern_catalog_event::EventType as CatalogEventType,
+        pattern_service_client::PatternServiceClient, ExecutePatternRequest, GetPatternRequest,
+        ListPatternsRequest, Pattern as PatternProto,
+        PatternCatalogEvent as PatternCatalogEventProto, WatchPatternsRequest,
+    },
+    telemetry::traced,
+    transport::Transport,
+    types::{
+        AgentSelector, ExecuteOptions, ExecutionEvent, ExecutionEventKind, ExecutionStatus,
+        Metadata, Pattern, PatternCatalogEvent, PatternConfig, PatternExecution,
     },
-    types::{ExecuteOptions, Pattern, PatternExecution},
 };
-use futures::Stream;
-use prost_types::{value::Kind, ListValue, Struct, Value as ProtoValue};
+use futures::{Stream, StreamExt};
 use serde_json::Value;
-use std::{collections::{BTreeMap, HashMap}, pin::Pin};
-use tonic::transport::Channel;
-use tracing::{debug, info};
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    pin::Pin,
+    sync::Arc,
+    time::Duration,
+};
+use tonic::{
+    codec::{CompressionEncoding, Streaming},
+    metadata::{Ascii, MetadataValue},
+    Code, Status,
+};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
 
 /// Service for pattern operations
 #[derive(Clone)]
 pub struct PatternService {
-    _channel: Channel,
+    _channel: Transport,
+    endpoint: String,
+    compression: Option<CompressionEncoding>,
+    interceptors: InterceptorChain,
+    closing: CancellationToken,
+    clock: Arc<dyn Clock>,
+    circuit_breaker: Option<Arc<CircuitBreaker>>,
+    batch_concurrency: usize,
+    result_limits: JsonLimits,
 }
 
+/// Executions `execute_batch` keeps in flight unless configured otherwise
+const DEFAULT_BATCH_CONCURRENCY: usize = 8;
+
+/// gRPC metadata entry carrying `ExecuteOptions::idempotency_key`
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Consecutive attempts `subscribe_catalog` makes to re-open a broken watch
+const CATALOG_RECONNECT_ATTEMPTS: u32 = 5;
+/// Delay before re-opening a broken catalog watch, doubled per failed attempt
+const CATALOG_RECONNECT_DELAY_MS: u64 = 100;
+const CATALOG_RECONNECT_MAX_DELAY_MS: u64 = 5_000;
+
 impl PatternService {
-    pub(crate) fn new(channel: Channel) -> Self {
-        Self { _channel: channel }
+    pub(crate) fn new(channel: Transport, endpoint: String) -> Self {
+        Self {
+            _channel: channel,
+            endpoint,
+            compression: None,
+            interceptors: InterceptorChain::default(),
+            closing: CancellationToken::new(),
+            clock: Arc::new(SystemClock),
+            circuit_breaker: None,
+            batch_concurrency: DEFAULT_BATCH_CONCURRENCY,
+            result_limits: JsonLimits::default(),
+        }
+    }
+
+    /// Set how many executions `execute_batch` runs at once (default: 8)
+    pub fn with_batch_concurrency(mut self, batch_concurrency: usize) -> Self {
+        self.batch_concurrency = batch_concurrency;
+        self
+    }
+
+    /// How NaN and infinite numbers in execution results and event data are
+    /// decoded; see [`ExecutionService::with_non_finite_policy`]
+    ///
+    /// [`ExecutionService::with_non_finite_policy`]: crate::ExecutionService::with_non_finite_policy
+    pub fn with_non_finite_policy(mut self, policy: NonFinitePolicy) -> Self {
+        self.result_limits.non_finite = policy;
+        self
+    }
+
+    /// Compress requests and accept compressed responses with `compression`
+    pub(crate) fn with_compression(mut self, compression: Option<CompressionEncoding>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Run every request through `interceptors`
+    pub(crate) fn with_interceptors(mut self, interceptors: InterceptorChain) -> Self {
+        self.interceptors = interceptors;
+        self
+    }
+
+    /// Fail calls with `client closed` once `closing` is cancelled
+    pub(crate) fn with_closing(mut self, closing: CancellationToken) -> Self {
+        self.closing = closing;
+        self
+    }
+
+    /// Drive timeouts and reconnect backoff from `clock`
+    pub(crate) fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Fail calls fast while `circuit_breaker` is open
+    pub(crate) fn with_circuit_breaker(
+        mut self,
+        circuit_breaker: Option<Arc<CircuitBreaker>>,
+    ) -> Self {
+        self.circuit_breaker = circuit_breaker;
+        self
+    }
+
+    fn client(&self) -> Result<PatternServiceClient<InterceptedChannel>> {
+        if self.closing.is_cancelled() {
+            return Err(Error::Connection("client closed".to_string()));
+        }
+        let channel = circuit_breaker::guard(
+            self._channel.clone(),
+            self.circuit_breaker.as_ref(),
+            &self.clock,
+        )?;
+        let client = PatternServiceClient::with_interceptor(channel, self.interceptors.clone());
+        Ok(match self.compression {
+            Some(encoding) => client.send_compressed(encoding).accept_compressed(encoding),
+            None => client,
+        })
     }
 
     /// List all available patterns
     pub async fn list(&self) -> Result<Vec<Pattern>> {
         debug!("Listing patterns");
 
-        let mut client = PatternServiceClient::new(self._channel.clone());
-        let response = client
-            .list_patterns(ListPatternsRequest {
-                tags: vec![],
-                include_definitions: false,
-            })
+        Ok(self
+            .list_protos(false)
             .await?
-            .into_inner();
-
-        Ok(response
-            .patterns
             .into_iter()
             .map(pattern_from_proto)
             .collect())
     }
 
+    async fn list_protos(&self, include_definitions: bool) -> Result<Vec<PatternProto>> {
+        let mut client = self.client()?;
+        let response = traced(
+            "PatternService::list",
+            &self.endpoint,
+            None,
+            ListPatternsRequest {
+                tags: vec![],
+                include_definitions,
+            },
+            |request| client.list_patterns(request),
+        )
+        .await
+        .map_err(rpc_error("PatternService::list", &self.endpoint))?
+        .into_inner();
+        Ok(response.patterns)
+    }
+
     /// Get a specific pattern by name
     pub async fn get(&self, name: &str) -> Result<Pattern> {
         debug!("Getting pattern: {}", name);
-        
-        let patterns = self.list().await?;
-        patterns
-            .into_iter()
-            .find(|p| p.name == name)
-            .ok_or_else(|| crate::error::Error::NotFound(format!("Pattern not found: {}", name)))
+
+        let mut client = self.client()?;
+        let pattern = traced(
+            "PatternService::get",
+            &self.endpoint,
+            None,
+            GetPatternRequest {
+                name: name.to_string(),
+                version: String::new(),
+            },
+            |request| client.get_pattern(request),
+        )
+        .await
+        .map_err(rpc_error("PatternService::get", &self.endpoint))
+        .with_context("name", name)?
+        .into_inner();
+
+        Ok(pattern_from_proto(pattern))
     }
 
     /// Execute a pattern
+    ///
+    /// A `timeout_ms` in `options` also becomes the call's gRPC deadline, so
+    /// the client gives up with [`Error::Timeout`] once it passes even if the
+    /// control plane never answers.
+    ///
+    /// With an `idempotency_key`, a control plane that already ran an
+    /// execution for the key answers with that execution, which is returned
+    /// like a fresh one.
     pub async fn execute(
         &self,
         pattern: &str,
@@ -64,25 +210,257 @@ impl PatternService {
         info!("Executing pattern: {}", pattern);
         
         let options = options.unwrap_or_default();
+        if let Some(config) = config_overrides(&options) {
+            config.validate()?;
+        }
+        options.validate_min_confidence()?;
+        if options.validate == Some(true) {
+            self.validate_input(pattern, &input).await?;
+        }
+        let request = execute_request(pattern, &input, &options)?;
+        let idempotency_key = idempotency_key(&options)?;
+        let min_confidence = options.min_confidence;
 
-        let input_struct = json_to_struct(input.clone());
-        let mut client = PatternServiceClient::new(self._channel.clone());
-        let response = client
-            .execute_pattern(ExecutePatternRequest {
-                pattern_name: pattern.to_string(),
-                pattern_version: String::new(),
-                input: Some(input_struct),
-                options: Some(crate::generated::parallax::patterns::execute_pattern_request::Options {
-                    timeout_ms: options.timeout_ms.unwrap_or(30000) as i32,
-                    max_parallel: 0,
-                    cache_results: false,
-                    context: HashMap::new(),
-                }),
+        let mut client = self.client()?;
+        let response = traced(
+            "PatternService::execute",
+            &self.endpoint,
+            options.trace_id.as_deref(),
+            request,
+            |mut request| {
+                if let Some(timeout_ms) = options.timeout_ms {
+                    request.set_timeout(Duration::from_millis(timeout_ms));
+                }
+                if let Some(key) = idempotency_key {
+                    request.metadata_mut().insert(IDEMPOTENCY_KEY_HEADER, key);
+                }
+                client.execute_pattern(request)
+            },
+        )
+        .await
+        .map_err(rpc_error("PatternService::execute", &self.endpoint))
+        .with_context("pattern", pattern)?
+        .into_inner();
+
+        let execution = execution_from_response(response, input, options.metadata, self.result_limits)
+            .with_context("pattern", pattern)?;
+        if let Some(required) = min_confidence {
+            check_confidence(&execution, required)?;
+        }
+        Ok(execution)
+    }
+
+    /// Execute a pattern and follow its progress
+    ///
+    /// Yields the pattern engine's events for the execution as the control
+    /// plane forwards them, from `"started"` through per-agent progress, and
+    /// ends after the terminal `"completed"` or `"failed"` event, which
+    /// carries the result. Control planes that don't forward progress send
+    /// only that terminal event.
+    pub async fn execute_streaming(
+        &self,
+        pattern: &str,
+        input: Value,
+        options: Option<ExecuteOptions>,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ExecutionEvent>> + Send>>> {
+        info!("Executing pattern with streaming: {}", pattern);
+
+        let options = options.unwrap_or_default();
+        if options.validate == Some(true) {
+            self.validate_input(pattern, &input).await?;
+        }
+        let request = execute_request(pattern, &input, &options)?;
+        let idempotency_key = idempotency_key(&options)?;
+
+        let mut client = self.client()?;
+        let responses = traced(
+            "PatternService::execute_streaming",
+            &self.endpoint,
+            options.trace_id.as_deref(),
+            request,
+            |mut request| {
+                if let Some(key) = idempotency_key {
+                    request.metadata_mut().insert(IDEMPOTENCY_KEY_HEADER, key);
+                }
+                client.stream_execute_pattern(request)
+            },
+        )
+        .await
+        .map_err(rpc_error("PatternService::execute_streaming", &self.endpoint))
+        .with_context("pattern", pattern)?
+        .into_inner();
+
+        let endpoint = self.endpoint.clone();
+        let pattern = pattern.to_string();
+        let metadata = options.metadata;
+        let limits = self.result_limits;
+        let events = responses.map(move |response| match response {
+            Ok(response) => event_from_response(response, &input, &metadata, limits)
+                .with_context("pattern", pattern.as_str()),
+            Err(status) => Err(rpc_error("PatternService::execute_streaming", &endpoint)(status)
+                .with_context("pattern", pattern.as_str())),
+        });
+        Ok(Box::pin(until_terminal(events)))
+    }
+
+    /// Execute several patterns concurrently
+    ///
+    /// Each request is `(pattern, input, options)`. At most the configured
+    /// batch concurrency run at once, and the output holds one result per
+    /// request in input order, so a failed execution doesn't affect the rest.
+    pub async fn execute_batch(
+        &self,
+        requests: Vec<(String, Value, Option<ExecuteOptions>)>,
+    ) -> Result<Vec<Result<PatternExecution>>> {
+        if self.batch_concurrency == 0 {
+            return Err(Error::InvalidArgument(
+                "batch concurrency must be at least 1".to_string(),
+            ));
+        }
+        info!("Executing batch of {} patterns", requests.len());
+
+        Ok(futures::stream::iter(requests)
+            .map(|(pattern, input, options)| async move {
+                self.execute(&pattern, input, options).await
             })
+            .buffered(self.batch_concurrency)
+            .collect()
+            .await)
+    }
+
+    /// Follow changes to the pattern catalog
+    ///
+    /// Yields an event whenever the control plane adds, updates or removes
+    /// a pattern. If the watch breaks it is re-opened, with backoff, and the
+    /// catalog compared with the one the stream started from plus the
+    /// changes it has yielded since: only the differences are yielded. The
+    /// stream ends with an error once re-opening has failed repeatedly
+    /// or the control plane rejects the watch. Control planes that predate
+    /// the watch RPC fail the subscription with an `Unimplemented` status;
+    /// poll [`list`](Self::list) against those instead.
+    pub async fn subscribe_catalog(
+        &self,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<PatternCatalogEvent>> + Send>>> {
+        debug!("Subscribing to pattern catalog");
+
+        // Opened before listing, so no change falls between the two
+        let events = self.watch_patterns(false).await?;
+        let known = self
+            .list_protos(true)
             .await?
-            .into_inner();
+            .into_iter()
+            .map(|pattern| (pattern.name.clone(), pattern))
+            .collect();
+        let watch = CatalogWatch {
+            events: Some(events),
+            service: self.clone(),
+            failures: 0,
+            done: false,
+            known,
+            resynced: VecDeque::new(),
+        };
+        let stream = futures::stream::unfold(watch, |mut watch| async move {
+            let event = watch.next_event().await?;
+            Some((event, watch))
+        });
+
+        Ok(Box::pin(stream))
+    }
+
+    async fn watch_patterns(
+        &self,
+        include_initial: bool,
+    ) -> Result<Streaming<PatternCatalogEventProto>> {
+        let mut client = self.client()?;
+        let response = traced(
+            "PatternService::subscribe_catalog",
+            &self.endpoint,
+            None,
+            WatchPatternsRequest { include_initial },
+            |request| client.watch_patterns(request),
+        )
+        .await
+        .map_err(|status| match status.code() {
+            Code::Unimplemented => Status::unimplemented(
+                "the control plane does not support watching the pattern catalog \
+                 (WatchPatterns is unimplemented); poll PatternService::list instead",
+            ),
+            _ => status,
+        })
+        .map_err(rpc_error("PatternService::subscribe_catalog", &self.endpoint))?;
+        Ok(response.into_inner())
+    }
 
-        Ok(execution_from_response(response, input, options.metadata))
+    /// Check `input` against the pattern's declared input schema
+    ///
+    /// Patterns without a schema accept any input. Mismatches are reported
+    /// as `Error::InvalidArgument` naming the offending input paths.
+    pub async fn validate_input(&self, pattern: &str, input: &Value) -> Result<()> {
+        match self.get(pattern).await?.input_schema {
+            Some(schema) => check_input(pattern, &schema, input),
+            None => Ok(()),
+        }
+    }
+
+    /// Execute a pattern, re-running it with more agents until the aggregate
+    /// confidence reaches `min_confidence`.
+    ///
+    /// The first attempt requests the pattern's declared `min_agents` (or one
+    /// agent if none is declared); each subsequent attempt raises
+    /// `AgentSelector.min_count` by `agent_increment`. After `max_attempts`
+    /// the last execution is returned as-is, so callers should still check
+    /// its confidence.
+    pub async fn execute_until_confident(
+        &self,
+        pattern: &str,
+        input: Value,
+        min_confidence: f64,
+        max_attempts: u32,
+        agent_increment: u32,
+    ) -> Result<PatternExecution> {
+        if !(0.0..=1.0).contains(&min_confidence) {
+            return Err(Error::InvalidArgument(
+                "min_confidence must be between 0 and 1".to_string(),
+            ));
+        }
+        if max_attempts == 0 {
+            return Err(Error::InvalidArgument(
+                "max_attempts must be at least 1".to_string(),
+            ));
+        }
+
+        let base_count = self
+            .get(pattern)
+            .await?
+            .config
+            .min_agents
+            .filter(|count| *count > 0)
+            .unwrap_or(1);
+
+        let mut attempt = 0;
+        loop {
+            let min_count = escalated_min_count(base_count, attempt, agent_increment);
+            let options = ExecuteOptions {
+                agent_selector: Some(AgentSelector {
+                    min_count: Some(min_count),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            };
+
+            let execution = self.execute(pattern, input.clone(), Some(options)).await?;
+            let confidence = execution.confidence.unwrap_or(0.0);
+            attempt += 1;
+
+            if confidence >= min_confidence || attempt >= max_attempts {
+                return Ok(execution);
+            }
+
+            info!(
+                "Pattern {} reached confidence {:.2} with {} agents (need {:.2}), escalating",
+                pattern, confidence, min_count, min_confidence
+            );
+        }
     }
 
     /// Get execution status
@@ -117,8 +495,283 @@ impl PatternService {
     }
 }
 
+/// Agent count requested on a given (zero-based) escalation attempt
+fn escalated_min_count(base: u32, attempt: u32, increment: u32) -> u32 {
+    base.saturating_add(attempt.saturating_mul(increment))
+}
+
+/// The execute request for `pattern`. The selector's agent counts go in
+/// the request's `min_agents` / `max_agents`, which the control plane uses
+/// in place of the pattern's own.
+fn execute_request(
+    pattern: &str,
+    input: &Value,
+    options: &ExecuteOptions,
+) -> Result<ExecutePatternRequest> {
+    let selector = options.agent_selector.as_ref();
+    Ok(ExecutePatternRequest {
+        pattern_name: pattern.to_string(),
+        pattern_version: String::new(),
+        input: Some(json_to_struct(input.clone())),
+        options: Some(crate::generated::parallax::patterns::execute_pattern_request::Options {
+            timeout_ms: options.timeout_ms.unwrap_or(30000) as i32,
+            max_parallel: 0,
+            cache_results: false,
+            context: execution_context(options)?,
+            min_agents: agent_count(selector.and_then(|selector| selector.min_count)),
+            max_agents: agent_count(selector.and_then(|selector| selector.max_count)),
+        }),
+    })
+}
+
+/// Fail with `ConfidenceBelowThreshold` if a completed execution is less
+/// confident than `required`. An execution reporting no confidence counts
+/// as 0; executions that have not completed pass through.
+fn check_confidence(execution: &PatternExecution, required: f64) -> Result<()> {
+    if execution.status != ExecutionStatus::Completed {
+        return Ok(());
+    }
+    let got = execution.confidence.unwrap_or(0.0);
+    if got < required {
+        return Err(Error::ConfidenceBelowThreshold { got, required });
+    }
+    Ok(())
+}
+
+/// Pattern configuration the options override, if they override any
+fn config_overrides(options: &ExecuteOptions) -> Option<PatternConfig> {
+    let selector = options.agent_selector.as_ref();
+    let config = PatternConfig {
+        min_agents: selector.and_then(|selector| selector.min_count),
+        max_agents: selector.and_then(|selector| selector.max_count),
+        timeout_ms: options.timeout_ms,
+        ..Default::default()
+    };
+    let overrides = config.min_agents.is_some()
+        || config.max_agents.is_some()
+        || config.timeout_ms.is_some();
+    overrides.then_some(config)
+}
+
+/// The options' idempotency key as a metadata value, rejecting keys that
+/// can't be sent as one
+fn idempotency_key(options: &ExecuteOptions) -> Result<Option<MetadataValue<Ascii>>> {
+    let Some(key) = options.idempotency_key.as_deref() else {
+        return Ok(None);
+    };
+    if key.is_empty() {
+        return Err(Error::InvalidArgument(
+            "idempotency key must not be empty".to_string(),
+        ));
+    }
+    key.parse().map(Some).map_err(|_| {
+        Error::InvalidArgument(format!(
+            "idempotency key {:?} must be visible ASCII",
+            key
+        ))
+    })
+}
+
+/// An agent count as sent on the wire, where 0 leaves the pattern's own
+fn agent_count(count: Option<u32>) -> i32 {
+    count.map_or(0, |count| count.min(i32::MAX as u32) as i32)
+}
+
+/// Request context forwarded alongside an execution, carrying the whole
+/// agent selector JSON-encoded under `agent_selector`
+fn execution_context(options: &ExecuteOptions) -> Result<HashMap<String, String>> {
+    let mut context = HashMap::new();
+    if let Some(selector) = &options.agent_selector {
+        context.insert("agent_selector".to_string(), serde_json::to_string(selector)?);
+    }
+    Ok(context)
+}
+
+/// Validate `input` against a pattern's JSON schema
+fn check_input(pattern: &str, schema: &Value, input: &Value) -> Result<()> {
+    let validator = jsonschema::validator_for(schema).map_err(|error| {
+        Error::Internal(format!("pattern {} has an invalid input schema: {}", pattern, error))
+    })?;
+
+    let problems = schema_violations(&validator, input);
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::InvalidArgument(format!(
+            "input does not match the schema for pattern {}: {}",
+            pattern,
+            problems.join("; ")
+        )))
+    }
+}
+
+/// Each way `value` breaks the schema, prefixed with the offending path
+pub(crate) fn schema_violations(validator: &jsonschema::Validator, value: &Value) -> Vec<String> {
+    validator
+        .iter_errors(value)
+        .map(|error| {
+            let path = error.instance_path.to_string();
+            let path = if path.is_empty() { "/".to_string() } else { path };
+            format!("{}: {}", path, error)
+        })
+        .collect()
+}
+
+/// State of a [`PatternService::subscribe_catalog`] stream
+struct CatalogWatch {
+    service: PatternService,
+    // The open watch, or `None` once it broke
+    events: Option<Streaming<PatternCatalogEventProto>>,
+    // Attempts to re-open the watch since the last event arrived
+    failures: u32,
+    done: bool,
+    // The catalog as of the last event yielded, by pattern name
+    known: BTreeMap<String, PatternProto>,
+    // Differences found after re-opening the watch, yielded before its events
+    resynced: VecDeque<PatternCatalogEventProto>,
+}
+
+impl CatalogWatch {
+    async fn next_event(&mut self) -> Option<Result<PatternCatalogEvent>> {
+        while !self.done {
+            if let Some(event) = self.resynced.pop_front() {
+                match self.admit(event) {
+                    Some(event) => return Some(Ok(event)),
+                    None => continue,
+                }
+            }
+            let Some(events) = self.events.as_mut() else {
+                if let Err(error) = self.reconnect().await {
+                    self.done = true;
+                    return Some(Err(error));
+                }
+                continue;
+            };
+
+            let broken = match events.message().await {
+                Ok(Some(event)) => {
+                    self.failures = 0;
+                    match self.admit(event) {
+                        Some(event) => return Some(Ok(event)),
+                        None => continue,
+                    }
+                }
+                Ok(None) => Error::Connection("pattern catalog watch closed".to_string()),
+                Err(status) => Error::from(status),
+            };
+            if !broken.is_retryable() {
+                self.done = true;
+                return Some(Err(broken));
+            }
+            warn!("Pattern catalog watch broke, re-syncing: {}", broken);
+            self.events = None;
+        }
+        None
+    }
+
+    /// The event a watch update yields, recorded in the known catalog
+    fn admit(&mut self, event: PatternCatalogEventProto) -> Option<PatternCatalogEvent> {
+        let pattern = event.pattern.as_ref()?;
+        if event.r#type == CatalogEventType::Removed as i32 {
+            self.known.remove(&pattern.name);
+        } else {
+            self.known.insert(pattern.name.clone(), pattern.clone());
+        }
+        catalog_event_from_proto(event)
+    }
+
+    /// Queue what changed between the known catalog and the control
+    /// plane's `current` one
+    fn resync(&mut self, current: Vec<PatternProto>) {
+        let mut current: BTreeMap<_, _> = current
+            .into_iter()
+            .map(|pattern| (pattern.name.clone(), pattern))
+            .collect();
+        let mut changes = Vec::new();
+        for (name, last) in &self.known {
+            match current.remove(name) {
+                None => changes.push((
+                    CatalogEventType::Removed,
+                    PatternProto {
+                        name: name.clone(),
+                        ..Default::default()
+                    },
+                )),
+                Some(pattern) if pattern != *last => {
+                    changes.push((CatalogEventType::Updated, pattern))
+                }
+                Some(_) => {}
+            }
+        }
+        changes.extend(current.into_values().map(|pattern| (CatalogEventType::Added, pattern)));
+        self.resynced
+            .extend(changes.into_iter().map(|(kind, pattern)| PatternCatalogEventProto {
+                r#type: kind as i32,
+                pattern: Some(pattern),
+                timestamp: None,
+            }));
+    }
+
+    /// Re-open the watch, then queue the changes it missed. Opening the
+    /// watch before listing means no change falls between the two.
+    async fn reconnect(&mut self) -> Result<()> {
+        loop {
+            let delay = calculate_reconnect_delay(
+                self.failures,
+                CATALOG_RECONNECT_DELAY_MS,
+                CATALOG_RECONNECT_MAX_DELAY_MS,
+            );
+            self.service.clock.sleep(Duration::from_millis(delay)).await;
+            self.failures += 1;
+
+            let reopened = match self.service.watch_patterns(false).await {
+                Ok(events) => self
+                    .service
+                    .list_protos(true)
+                    .await
+                    .map(|current| (events, current)),
+                Err(error) => Err(error),
+            };
+            match reopened {
+                Ok((events, current)) => {
+                    self.events = Some(events);
+                    self.resync(current);
+                    return Ok(());
+                }
+                Err(error) if error.is_retryable() && self.failures < CATALOG_RECONNECT_ATTEMPTS => {
+                    debug!("Re-opening pattern catalog watch failed: {}", error);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+fn catalog_event_from_proto(event: PatternCatalogEventProto) -> Option<PatternCatalogEvent> {
+    let pattern = event.pattern?;
+    match CatalogEventType::try_from(event.r#type) {
+        Ok(CatalogEventType::Added) => Some(PatternCatalogEvent::Added(pattern_from_proto(pattern))),
+        Ok(CatalogEventType::Updated) => {
+            Some(PatternCatalogEvent::Updated(pattern_from_proto(pattern)))
+        }
+        Ok(CatalogEventType::Removed) => Some(PatternCatalogEvent::Removed(pattern.name)),
+        _ => {
+            debug!("Ignoring pattern catalog event of unknown type {}", event.r#type);
+            None
+        }
+    }
+}
+
 fn pattern_from_proto(pattern: crate::generated::parallax::patterns::Pattern) -> Pattern {
     let requirements = pattern.requirements.unwrap_or_default();
+    // The schema travels in the pattern metadata under either casing
+    let input_schema = pattern.metadata.map(struct_to_json).and_then(|mut metadata| {
+        let metadata = metadata.as_object_mut()?;
+        metadata
+            .remove("input_schema")
+            .or_else(|| metadata.remove("inputSchema"))
+            .filter(|schema| !schema.is_null())
+    });
 
     Pattern {
         name: pattern.name,
@@ -131,14 +784,16 @@ fn pattern_from_proto(pattern: crate::generated::parallax::patterns::Pattern) ->
             confidence_threshold: Some(requirements.min_confidence),
             ..Default::default()
         },
+        input_schema,
     }
 }
 
 fn execution_from_response(
     response: crate::generated::parallax::patterns::ExecutePatternResponse,
     input: Value,
-    metadata: HashMap<String, Value>,
-) -> PatternExecution {
+    metadata: Metadata,
+    limits: JsonLimits,
+) -> Result<PatternExecution> {
     let metrics = response.metrics;
     let (start_time, end_time, duration_ms) = if let Some(metrics) = metrics {
         let start_time = metrics
@@ -153,13 +808,14 @@ fn execution_from_response(
         (chrono::Utc::now(), None, None)
     };
 
-    PatternExecution {
+    Ok(PatternExecution {
         id: response.execution_id,
         pattern: response.pattern_name,
         status: status_from_proto(response.status),
         input,
-        output: response.result.map(struct_to_json),
+        output: response.result.map(|result| limits.struct_to_json(result)).transpose()?,
         agents: Vec::new(),
+        agent_contributions: Vec::new(),
         start_time,
         end_time,
         duration_ms,
@@ -170,84 +826,57 @@ fn execution_from_response(
             Some(response.error_message)
         },
         metadata,
+    })
+}
+
+/// Event reported by one `StreamExecutePattern` message
+///
+/// Messages sent while the execution runs have the `UNKNOWN` status; they
+/// are read as `Running`. A control plane that predates progress events
+/// sends no event type, only the final result.
+fn event_from_response(
+    mut response: crate::generated::parallax::patterns::ExecutePatternResponse,
+    input: &Value,
+    metadata: &Metadata,
+    limits: JsonLimits,
+) -> Result<ExecutionEvent> {
+    let event_type = std::mem::take(&mut response.event_type);
+    let event_data = response
+        .event_data
+        .take()
+        .map(|data| limits.struct_to_json(data))
+        .transpose()?;
+    let mut execution = execution_from_response(response, input.clone(), metadata.clone(), limits)?;
+    let event_type = if event_type.is_empty() {
+        ExecutionEventKind::for_status(execution.status).to_string()
+    } else {
+        event_type
+    };
+    if !ExecutionEventKind::from(event_type.as_str()).is_terminal()
+        && execution.status == ExecutionStatus::Pending
+    {
+        execution.status = ExecutionStatus::Running;
     }
+
+    Ok(ExecutionEvent {
+        event_type,
+        execution: Some(execution),
+        event_time: Some(chrono::Utc::now()),
+        event_data,
+    })
 }
 
-fn status_from_proto(status: i32) -> crate::types::ExecutionStatus {
-    use crate::types::ExecutionStatus;
+fn status_from_proto(status: i32) -> ExecutionStatus {
     use crate::generated::parallax::patterns::execute_pattern_response::Status;
 
     match Status::try_from(status).unwrap_or(Status::Unknown) {
         Status::Success => ExecutionStatus::Completed,
-        Status::Failure | Status::Timeout | Status::Cancelled => ExecutionStatus::Failed,
+        Status::Failure | Status::Timeout => ExecutionStatus::Failed,
+        Status::Cancelled => ExecutionStatus::Cancelled,
         Status::Unknown => ExecutionStatus::Pending,
     }
 }
 
-fn json_to_struct(value: Value) -> Struct {
-    match value {
-        Value::Object(map) => Struct {
-            fields: map
-                .into_iter()
-                .map(|(key, value)| (key, json_to_value(value)))
-                .collect::<BTreeMap<_, _>>(),
-        },
-        other => {
-            let mut fields = BTreeMap::new();
-            fields.insert("value".to_string(), json_to_value(other));
-            Struct { fields }
-        }
-    }
-}
-
-fn json_to_value(value: Value) -> ProtoValue {
-    let kind = match value {
-        Value::Null => Kind::NullValue(0),
-        Value::Bool(value) => Kind::BoolValue(value),
-        Value::Number(value) => Kind::NumberValue(value.as_f64().unwrap_or_default()),
-        Value::String(value) => Kind::StringValue(value),
-        Value::Array(values) => Kind::ListValue(ListValue {
-            values: values.into_iter().map(json_to_value).collect(),
-        }),
-        Value::Object(values) => Kind::StructValue(Struct {
-            fields: values
-                .into_iter()
-                .map(|(key, value)| (key, json_to_value(value)))
-                .collect(),
-        }),
-    };
-
-    ProtoValue { kind: Some(kind) }
-}
-
-fn struct_to_json(value: Struct) -> Value {
-    let map: serde_json::Map<String, Value> = value
-        .fields
-        .into_iter()
-        .map(|(key, value)| (key, value_to_json(value)))
-        .collect();
-    Value::Object(map)
-}
-
-fn value_to_json(value: ProtoValue) -> Value {
-    match value.kind {
-        Some(Kind::NullValue(_)) => Value::Null,
-        Some(Kind::BoolValue(value)) => Value::Bool(value),
-        Some(Kind::NumberValue(value)) => serde_json::Number::from_f64(value)
-            .map(Value::Number)
-            .unwrap_or(Value::Null),
-        Some(Kind::StringValue(value)) => Value::String(value),
-        Some(Kind::ListValue(list)) => Value::Array(
-            list.values
-                .into_iter()
-                .map(value_to_json)
-                .collect(),
-        ),
-        Some(Kind::StructValue(struct_value)) => struct_to_json(struct_value),
-        None => Value::Null,
-    }
-}
-
 fn timestamp_to_datetime(timestamp: prost_types::Timestamp) -> chrono::DateTime<chrono::Utc> {
     use chrono::TimeZone;
     let nanos = timestamp.nanos as u32;
@@ -257,3 +886,837 @@ fn timestamp_to_datetime(timestamp: prost_types::Timestamp) -> chrono::DateTime<
         .single()
         .unwrap_or_else(|| chrono::Utc.timestamp_opt(0, 0).single().unwrap())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generated::parallax::patterns::{
+        self as proto,
+        pattern_service_server::{self, PatternServiceServer},
+        ExecutePatternResponse, ListPatternsResponse, UploadPatternRequest, UploadPatternResponse,
+    };
+    use crate::types::{ExecutionStatus, SelectionStrategy};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    };
+    use tonic::{Request, Response, Status};
+
+    /// Pattern service backed by a fixed catalog
+    #[derive(Default)]
+    struct FakePatterns {
+        patterns: Vec<proto::Pattern>,
+        // Names requested through `get_pattern`, in call order
+        get_requests: Mutex<Vec<String>>,
+        list_calls: AtomicUsize,
+        execute_calls: AtomicUsize,
+        // `min_agents` of each `execute_pattern` call
+        requested_agents: Mutex<Vec<i32>>,
+        // Answer with this much confidence per requested agent instead of 0.9
+        confidence_per_agent: Option<f64>,
+        // Execution id started for each idempotency key
+        executions_by_key: Mutex<HashMap<String, String>>,
+        // Hold each `execute_pattern` reply back this long
+        execute_delay: Option<Duration>,
+        // Result each `execute_pattern` call answers with
+        result: Option<prost_types::Struct>,
+        // Catalogs `list_patterns` answers with, in call order, before
+        // falling back to `patterns`
+        listings: Mutex<std::collections::VecDeque<Vec<proto::Pattern>>>,
+        // Events each `watch_patterns` call sends before idling, in call order
+        catalog_watches: Mutex<std::collections::VecDeque<Vec<WatchReply>>>,
+        // `include_initial` of each `watch_patterns` call
+        watch_requests: Mutex<Vec<bool>>,
+        // Answer `watch_patterns` like a control plane without it
+        watch_unimplemented: bool,
+    }
+
+    type WatchReply = std::result::Result<PatternCatalogEventProto, Status>;
+
+    fn described(name: &str, description: &str) -> proto::Pattern {
+        proto::Pattern {
+            name: name.to_string(),
+            description: description.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn catalog_event(kind: CatalogEventType, name: &str, description: &str) -> WatchReply {
+        Ok(PatternCatalogEventProto {
+            r#type: kind as i32,
+            pattern: Some(described(name, description)),
+            timestamp: None,
+        })
+    }
+
+    #[tonic::async_trait]
+    impl pattern_service_server::PatternService for FakePatterns {
+        async fn execute_pattern(
+            &self,
+            request: Request<ExecutePatternRequest>,
+        ) -> std::result::Result<Response<ExecutePatternResponse>, Status> {
+            let call = self.execute_calls.fetch_add(1, Ordering::SeqCst) + 1;
+            if let Some(delay) = self.execute_delay {
+                tokio::time::sleep(delay).await;
+            }
+            let key = request
+                .metadata()
+                .get(IDEMPOTENCY_KEY_HEADER)
+                .map(|key| key.to_str().unwrap().to_string());
+            let request = request.into_inner();
+            let min_agents = request.options.map_or(0, |options| options.min_agents);
+            self.requested_agents.lock().unwrap().push(min_agents);
+            let pattern_name = request.pattern_name;
+            if !self.patterns.iter().any(|pattern| pattern.name == pattern_name) {
+                return Err(Status::not_found("Pattern not found"));
+            }
+            let fresh_id = format!("exec-{}-{}", pattern_name, call);
+            let execution_id = match key {
+                Some(key) => self
+                    .executions_by_key
+                    .lock()
+                    .unwrap()
+                    .entry(key)
+                    .or_insert(fresh_id)
+                    .clone(),
+                None => fresh_id,
+            };
+            Ok(Response::new(ExecutePatternResponse {
+                execution_id,
+                pattern_name,
+                status: proto::execute_pattern_response::Status::Success as i32,
+                confidence: self
+                    .confidence_per_agent
+                    .map_or(0.9, |per_agent| per_agent * min_agents as f64),
+                result: self.result.clone(),
+                ..Default::default()
+            }))
+        }
+
+        type StreamExecutePatternStream = futures::stream::BoxStream<
+            'static,
+            std::result::Result<ExecutePatternResponse, Status>,
+        >;
+
+        async fn stream_execute_pattern(
+            &self,
+            _request: Request<ExecutePatternRequest>,
+        ) -> std::result::Result<Response<Self::StreamExecutePatternStream>, Status> {
+            Err(Status::unimplemented("stream_execute_pattern"))
+        }
+
+        async fn list_patterns(
+            &self,
+            _request: Request<ListPatternsRequest>,
+        ) -> std::result::Result<Response<ListPatternsResponse>, Status> {
+            self.list_calls.fetch_add(1, Ordering::SeqCst);
+            let patterns = self.listings.lock().unwrap().pop_front();
+            Ok(Response::new(ListPatternsResponse {
+                patterns: patterns.unwrap_or_else(|| self.patterns.clone()),
+            }))
+        }
+
+        async fn get_pattern(
+            &self,
+            request: Request<GetPatternRequest>,
+        ) -> std::result::Result<Response<proto::Pattern>, Status> {
+            let name = request.into_inner().name;
+            self.get_requests.lock().unwrap().push(name.clone());
+            self.patterns
+                .iter()
+                .find(|pattern| pattern.name == name)
+                .cloned()
+                .map(Response::new)
+                .ok_or_else(|| Status::not_found("Pattern not found"))
+        }
+
+        async fn upload_pattern(
+            &self,
+            _request: Request<UploadPatternRequest>,
+        ) -> std::result::Result<Response<UploadPatternResponse>, Status> {
+            Err(Status::unimplemented("upload_pattern"))
+        }
+
+        type WatchPatternsStream = futures::stream::BoxStream<'static, WatchReply>;
+
+        async fn watch_patterns(
+            &self,
+            request: Request<WatchPatternsRequest>,
+        ) -> std::result::Result<Response<Self::WatchPatternsStream>, Status> {
+            self.watch_requests.lock().unwrap().push(request.into_inner().include_initial);
+            if self.watch_unimplemented {
+                return Err(Status::unimplemented(""));
+            }
+            let events = self.catalog_watches.lock().unwrap().pop_front().unwrap_or_default();
+            // A watch that ends in an error breaks; otherwise it stays open
+            let stream = futures::stream::iter(events).chain(futures::stream::pending());
+            Ok(Response::new(stream.boxed()))
+        }
+    }
+
+    fn catalog() -> Vec<proto::Pattern> {
+        ["consensus", "map-reduce"]
+            .into_iter()
+            .map(|name| proto::Pattern {
+                name: name.to_string(),
+                description: format!("{} pattern", name),
+                requirements: Some(proto::pattern::Requirements {
+                    capabilities: vec!["analysis".to_string()],
+                    min_agents: 3,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    async fn service_for(fake: Arc<FakePatterns>) -> PatternService {
+        let channel = crate::test_support::serve(
+            tonic::transport::Server::builder().add_service(PatternServiceServer::from_arc(fake)),
+        )
+        .await;
+        PatternService::new(channel, "http://fake".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_get_fetches_single_pattern_by_name() {
+        let fake = Arc::new(FakePatterns {
+            patterns: catalog(),
+            ..Default::default()
+        });
+        let service = service_for(fake.clone()).await;
+
+        let pattern = service.get("map-reduce").await.unwrap();
+
+        assert_eq!(pattern.name, "map-reduce");
+        assert_eq!(pattern.config.min_agents, Some(3));
+        assert_eq!(*fake.get_requests.lock().unwrap(), vec!["map-reduce"]);
+        assert_eq!(fake.list_calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_get_unknown_pattern_is_not_found() {
+        let fake = Arc::new(FakePatterns {
+            patterns: catalog(),
+            ..Default::default()
+        });
+        let service = service_for(fake).await;
+
+        let error = service.get("debate").await.unwrap_err();
+        assert!(matches!(&error, Error::NotFound(_)));
+    }
+
+    #[tokio::test]
+    async fn test_execute_until_confident_asks_for_more_agents() {
+        let fake = Arc::new(FakePatterns {
+            patterns: catalog(),
+            confidence_per_agent: Some(0.15),
+            ..Default::default()
+        });
+        let service = service_for(fake.clone()).await;
+
+        let execution = service
+            .execute_until_confident("consensus", serde_json::json!({}), 0.7, 5, 2)
+            .await
+            .unwrap();
+
+        // The pattern declares 3 agents; the second attempt asks for 5
+        assert_eq!(*fake.requested_agents.lock().unwrap(), [3, 5]);
+        assert!((execution.confidence.unwrap() - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_escalated_min_count_grows_per_attempt() {
+        assert_eq!(escalated_min_count(3, 0, 2), 3);
+        assert_eq!(escalated_min_count(3, 1, 2), 5);
+        assert_eq!(escalated_min_count(3, 2, 2), 7);
+        assert_eq!(escalated_min_count(u32::MAX, 5, 2), u32::MAX);
+    }
+
+    #[test]
+    fn test_execute_request_carries_agent_selector() {
+        let options = ExecuteOptions {
+            agent_selector: Some(AgentSelector {
+                min_count: Some(4),
+                strategy: Some(SelectionStrategy::WeightedByConfidence),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let request = execute_request("consensus", &Value::Null, &options).unwrap();
+        let request_options = request.options.unwrap();
+        assert_eq!(request_options.min_agents, 4);
+        assert_eq!(request_options.max_agents, 0);
+        let selector: Value =
+            serde_json::from_str(&request_options.context["agent_selector"]).unwrap();
+        assert_eq!(selector["strategy"], "weighted_by_confidence");
+
+        let plain = execute_request("consensus", &Value::Null, &ExecuteOptions::default()).unwrap();
+        let plain_options = plain.options.unwrap();
+        assert!(plain_options.context.is_empty());
+        assert_eq!((plain_options.min_agents, plain_options.max_agents), (0, 0));
+    }
+
+    /// A pattern whose input must carry a `task` string
+    fn schema_pattern() -> proto::Pattern {
+        let schema = serde_json::json!({
+            "input_schema": {
+                "type": "object",
+                "required": ["task"],
+                "properties": { "task": { "type": "string" } }
+            }
+        });
+        proto::Pattern {
+            name: "analysis".to_string(),
+            metadata: Some(json_to_struct(schema)),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_validate_input_checks_declared_schema() {
+        let fake = Arc::new(FakePatterns {
+            patterns: vec![schema_pattern(), catalog().remove(0)],
+            ..Default::default()
+        });
+        let service = service_for(fake).await;
+
+        let pattern = service.get("analysis").await.unwrap();
+        assert_eq!(pattern.input_schema.unwrap()["required"][0], "task");
+
+        let valid = serde_json::json!({ "task": "summarize" });
+        service.validate_input("analysis", &valid).await.unwrap();
+
+        let missing = service
+            .validate_input("analysis", &serde_json::json!({}))
+            .await
+            .unwrap_err();
+        assert!(matches!(&missing, Error::InvalidArgument(message) if message.contains("task")));
+
+        let wrong_type = service
+            .validate_input("analysis", &serde_json::json!({ "task": 7 }))
+            .await
+            .unwrap_err();
+        assert!(matches!(&wrong_type, Error::InvalidArgument(message) if message.contains("/task")));
+
+        // Patterns without a schema accept anything
+        service.validate_input("consensus", &serde_json::json!(42)).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_execute_validates_input_when_requested() {
+        let fake = Arc::new(FakePatterns {
+            patterns: vec![schema_pattern()],
+            ..Default::default()
+        });
+        let service = service_for(fake.clone()).await;
+        let validated = || ExecuteOptions::builder().validate(true).build().unwrap();
+
+        let error = service
+            .execute("analysis", serde_json::json!({}), Some(validated()))
+            .await
+            .unwrap_err();
+        assert!(matches!(error, Error::InvalidArgument(_)));
+        assert_eq!(fake.execute_calls.load(Ordering::SeqCst), 0);
+
+        let execution = service
+            .execute("analysis", serde_json::json!({ "task": "go" }), Some(validated()))
+            .await
+            .unwrap();
+        assert_eq!(execution.pattern, "analysis");
+
+        // Without the option the input goes straight to the server
+        service.execute("analysis", serde_json::json!({}), None).await.unwrap();
+        assert_eq!(fake.execute_calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_execute_rejects_invalid_config_overrides() {
+        let fake = Arc::new(FakePatterns {
+            patterns: catalog(),
+            ..Default::default()
+        });
+        let service = service_for(fake.clone()).await;
+        let options = ExecuteOptions {
+            agent_selector: Some(AgentSelector {
+                min_count: Some(4),
+                max_count: Some(2),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let error = service
+            .execute("consensus", serde_json::json!({}), Some(options))
+            .await
+            .unwrap_err();
+
+        assert!(
+            matches!(&error, Error::InvalidArgument(message) if message.contains("min_agents (4)")),
+            "{}",
+            error
+        );
+        assert_eq!(fake.execute_calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_execute_timeout_sets_call_deadline() {
+        let fake = Arc::new(FakePatterns {
+            patterns: catalog(),
+            execute_delay: Some(Duration::from_secs(5)),
+            ..Default::default()
+        });
+        let service = service_for(fake.clone()).await;
+        let options = ExecuteOptions {
+            timeout_ms: Some(100),
+            ..Default::default()
+        };
+
+        let started = std::time::Instant::now();
+        let error = service
+            .execute("consensus", serde_json::json!({}), Some(options))
+            .await
+            .unwrap_err();
+
+        assert!(matches!(&error, Error::Timeout(_)), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(fake.execute_calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_execute_with_same_idempotency_key_returns_same_execution() {
+        let fake = Arc::new(FakePatterns {
+            patterns: catalog(),
+            ..Default::default()
+        });
+        let service = service_for(fake.clone()).await;
+        let keyed = |key: &str| {
+            ExecuteOptions::builder()
+                .idempotency_key(key)
+                .build()
+                .unwrap()
+        };
+        let input = serde_json::json!({ "task": "charge" });
+
+        let first = service
+            .execute("consensus", input.clone(), Some(keyed("order-42")))
+            .await
+            .unwrap();
+        let resent = service
+            .execute("consensus", input.clone(), Some(keyed("order-42")))
+            .await
+            .unwrap();
+        let other = service
+            .execute("consensus", input.clone(), Some(keyed("order-43")))
+            .await
+            .unwrap();
+        let unkeyed = service.execute("consensus", input, None).await.unwrap();
+
+        assert_eq!(resent.id, first.id);
+        assert_ne!(other.id, first.id);
+        assert_ne!(unkeyed.id, first.id);
+        assert_eq!(fake.execute_calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_execute_rejects_unsendable_idempotency_key() {
+        let fake = Arc::new(FakePatterns {
+            patterns: catalog(),
+            ..Default::default()
+        });
+        let service = service_for(fake.clone()).await;
+
+        for key in ["", "line\nbreak"] {
+            let options = ExecuteOptions {
+                idempotency_key: Some(key.to_string()),
+                ..Default::default()
+            };
+            let error = service
+                .execute("consensus", serde_json::json!({}), Some(options))
+                .await
+                .unwrap_err();
+            assert!(matches!(error, Error::InvalidArgument(_)), "{}", error);
+        }
+        assert_eq!(fake.execute_calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_execute_batch_returns_per_request_results_in_order() {
+        let fake = Arc::new(FakePatterns {
+            patterns: catalog(),
+            ..Default::default()
+        });
+        let service = service_for(fake.clone()).await.with_batch_concurrency(2);
+        let requests = ["consensus", "missing", "map-reduce", "consensus"]
+            .into_iter()
+            .map(|name| (name.to_string(), serde_json::json!({ "task": name }), None))
+            .collect();
+
+        let results = service.execute_batch(requests).await.unwrap();
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap().pattern, "consensus");
+        assert_eq!(results[1].as_ref().unwrap_err().code(), crate::ErrorCode::NotFound);
+        assert_eq!(results[2].as_ref().unwrap().pattern, "map-reduce");
+        assert_eq!(results[3].as_ref().unwrap().input, serde_json::json!({ "task": "consensus" }));
+        assert_eq!(fake.execute_calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test]
+    async fn test_execute_batch_rejects_zero_concurrency() {
+        let service = service_for(Arc::new(FakePatterns::default()))
+            .await
+            .with_batch_concurrency(0);
+
+        let error = service.execute_batch(Vec::new()).await.unwrap_err();
+        assert!(matches!(error, Error::InvalidArgument(_)));
+    }
+
+    #[tokio::test]
+    async fn test_execute_decodes_non_finite_results_by_policy() {
+        let score = prost_types::Value {
+            kind: Some(prost_types::value::Kind::NumberValue(f64::NAN)),
+        };
+        let fake = Arc::new(FakePatterns {
+            patterns: catalog(),
+            result: Some(prost_types::Struct {
+                fields: [("score".to_string(), score)].into(),
+            }),
+            ..Default::default()
+        });
+        let service = service_for(fake).await;
+
+        let nulled = service.execute("consensus", serde_json::json!({}), None).await.unwrap();
+        assert_eq!(nulled.output, Some(serde_json::json!({ "score": null })));
+
+        let error = service
+            .clone()
+            .with_non_finite_policy(NonFinitePolicy::ErrorOnNonFinite)
+            .execute("consensus", serde_json::json!({}), None)
+            .await
+            .unwrap_err();
+        assert!(matches!(&error, Error::Internal(_)), "{}", error);
+
+        let stringified = service
+            .with_non_finite_policy(NonFinitePolicy::StringifyNonFinite)
+            .execute("consensus", serde_json::json!({}), None)
+            .await
+            .unwrap();
+        assert_eq!(stringified.output, Some(serde_json::json!({ "score": "NaN" })));
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_catalog_yields_changes() {
+        let fake = Arc::new(FakePatterns::default());
+        fake.catalog_watches.lock().unwrap().push_back(vec![
+            catalog_event(CatalogEventType::Added, "triage", "Sort incoming issues"),
+            catalog_event(CatalogEventType::Updated, "triage", "Sort and label issues"),
+            catalog_event(CatalogEventType::Removed, "legacy", ""),
+        ]);
+        let service = service_for(fake.clone()).await;
+
+        let events: Vec<_> = service
+            .subscribe_catalog()
+            .await
+            .unwrap()
+            .take(3)
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+
+        assert!(matches!(&events[0], PatternCatalogEvent::Added(pattern) if pattern.name == "triage"));
+        assert!(matches!(
+            &events[1],
+            PatternCatalogEvent::Updated(pattern) if pattern.description == "Sort and label issues"
+        ));
+        assert!(matches!(&events[2], PatternCatalogEvent::Removed(name) if name == "legacy"));
+        assert_eq!(*fake.watch_requests.lock().unwrap(), [false]);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_catalog_resyncs_after_broken_watch() {
+        let fake = Arc::new(FakePatterns::default());
+        fake.listings.lock().unwrap().extend([
+            vec![described("legacy", "v1"), described("triage", "v1")],
+            // While the watch was down, legacy went, triage changed and
+            // review arrived
+            vec![described("review", "v1"), described("triage", "v3")],
+        ]);
+        fake.catalog_watches.lock().unwrap().extend([
+            vec![
+                catalog_event(CatalogEventType::Updated, "triage", "v2"),
+                Err(Status::unavailable("control plane restarting")),
+            ],
+            vec![catalog_event(CatalogEventType::Added, "audit", "v1")],
+        ]);
+        let service = service_for(fake.clone()).await;
+
+        let changes: Vec<_> = service
+            .subscribe_catalog()
+            .await
+            .unwrap()
+            .take(5)
+            .map(|event| match event.unwrap() {
+                PatternCatalogEvent::Added(pattern) => ("added", pattern.name, pattern.description),
+                PatternCatalogEvent::Updated(pattern) => {
+                    ("updated", pattern.name, pattern.description)
+                }
+                PatternCatalogEvent::Removed(name) => ("removed", name, String::new()),
+            })
+            .collect()
+            .await;
+
+        let change = |kind, name: &str, description: &str| {
+            (kind, name.to_string(), description.to_string())
+        };
+        assert_eq!(
+            changes,
+            [
+                change("updated", "triage", "v2"),
+                // Only the differences from the re-sync, not a replay
+                change("removed", "legacy", ""),
+                change("updated", "triage", "v3"),
+                change("added", "review", "v1"),
+                change("added", "audit", "v1"),
+            ]
+        );
+        assert_eq!(*fake.watch_requests.lock().unwrap(), [false, false]);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_catalog_ends_on_rejected_watch() {
+        let fake = Arc::new(FakePatterns::default());
+        fake.catalog_watches
+            .lock()
+            .unwrap()
+            .push_back(vec![Err(Status::permission_denied("not allowed"))]);
+        let service = service_for(fake.clone()).await;
+
+        let events: Vec<_> = service.subscribe_catalog().await.unwrap().collect().await;
+
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0].as_ref().unwrap_err(), Error::Authentication(_)));
+        assert_eq!(fake.watch_requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_catalog_explains_unsupported_watch() {
+        let fake = Arc::new(FakePatterns {
+            watch_unimplemented: true,
+            ..Default::default()
+        });
+        let service = service_for(fake.clone()).await;
+
+        let error = service.subscribe_catalog().await.err().unwrap();
+
+        assert_eq!(error.code(), crate::ErrorCode::Unimplemented);
+        assert!(error.to_string().contains("does not support watching the pattern catalog"));
+        assert_eq!(fake.watch_requests.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_execute_streaming_yields_progress_then_completion() {
+        use crate::types::{ExecutionEvent, PatternConfig};
+
+        let (client, control_plane) = crate::Client::mock();
+        control_plane.add_pattern(Pattern {
+            name: "consensus".to_string(),
+            description: "Consensus pattern".to_string(),
+            enabled: true,
+            required_capabilities: Vec::new(),
+            config: PatternConfig::default(),
+            input_schema: None,
+        });
+        let execution = |status: ExecutionStatus, confidence: Option<f64>| PatternExecution {
+            id: "exec-1".to_string(),
+            pattern: "consensus".to_string(),
+            status,
+            input: Value::Null,
+            output: None,
+            agents: Vec::new(),
+            agent_contributions: Vec::new(),
+            start_time: chrono::Utc::now(),
+            end_time: None,
+            duration_ms: None,
+            confidence,
+            error: None,
+            metadata: Metadata::new(),
+        };
+        let event = |event_type: &str, status: ExecutionStatus, confidence: Option<f64>, data| {
+            ExecutionEvent {
+                event_type: event_type.to_string(),
+                execution: Some(execution(status, confidence)),
+                event_time: Some(chrono::Utc::now()),
+                event_data: Some(data),
+            }
+        };
+        control_plane.enqueue_execution("consensus", execution(ExecutionStatus::Running, Some(0.2)));
+        control_plane.enqueue_events(
+            "exec-1",
+            vec![
+                event(
+                    "agent_completed",
+                    ExecutionStatus::Running,
+                    Some(0.4),
+                    serde_json::json!({ "agentId": "agent-1", "confidence": 0.8 }),
+                ),
+                event(
+                    "progress",
+                    ExecutionStatus::Running,
+                    Some(0.7),
+                    serde_json::json!({ "total": 2, "completed": 1, "failed": 0 }),
+                ),
+                event("completed", ExecutionStatus::Completed, Some(0.9), serde_json::json!({})),
+                // Never delivered: the stream ends at the terminal event
+                event("updated", ExecutionStatus::Running, None, serde_json::json!({})),
+            ],
+        );
+
+        let events: Vec<ExecutionEvent> = client
+            .patterns()
+            .execute_streaming("consensus", serde_json::json!({ "task": "review" }), None)
+            .await
+            .unwrap()
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+
+        let types: Vec<&str> = events.iter().map(|event| event.event_type.as_str()).collect();
+        assert_eq!(types, ["started", "agent_completed", "progress", "completed"]);
+        let confidences: Vec<Option<f64>> = events
+            .iter()
+            .map(|event| event.execution.as_ref().unwrap().confidence)
+            .collect();
+        assert_eq!(confidences, [Some(0.2), Some(0.4), Some(0.7), Some(0.9)]);
+        let statuses: Vec<ExecutionStatus> = events
+            .iter()
+            .map(|event| event.execution.as_ref().unwrap().status)
+            .collect();
+        assert_eq!(
+            statuses,
+            [
+                ExecutionStatus::Running,
+                ExecutionStatus::Running,
+                ExecutionStatus::Running,
+                ExecutionStatus::Completed
+            ]
+        );
+        assert_eq!(events[1].event_data.as_ref().unwrap()["agentId"], "agent-1");
+        // Everything arrives on the one call; nothing is fetched separately
+        control_plane.assert_called("StreamExecutePattern", 1);
+        control_plane.assert_called("StreamExecution", 0);
+    }
+
+    #[tokio::test]
+    async fn test_execute_streaming_ends_at_once_for_finished_execution() {
+        let (client, control_plane) = crate::Client::mock();
+        control_plane.add_pattern(Pattern {
+            name: "consensus".to_string(),
+            description: "Consensus pattern".to_string(),
+            enabled: true,
+            required_capabilities: Vec::new(),
+            config: Default::default(),
+            input_schema: None,
+        });
+        control_plane.enqueue_execution(
+            "consensus",
+            PatternExecution {
+                id: String::new(),
+                pattern: String::new(),
+                status: ExecutionStatus::Completed,
+                input: Value::Null,
+                output: Some(serde_json::json!({ "answer": 42 })),
+                agents: Vec::new(),
+                agent_contributions: Vec::new(),
+                start_time: chrono::Utc::now(),
+                end_time: Some(chrono::Utc::now()),
+                duration_ms: None,
+                confidence: Some(0.8),
+                error: None,
+                metadata: Metadata::new(),
+            },
+        );
+
+        let events: Vec<_> = client
+            .patterns()
+            .execute_streaming("consensus", serde_json::json!({}), None)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].as_ref().unwrap().event_type, "completed");
+        control_plane.assert_called("StreamExecution", 0);
+    }
+
+    #[tokio::test]
+    async fn test_execute_enforces_min_confidence() {
+        let (client, control_plane) = crate::Client::mock();
+        control_plane.add_pattern(Pattern {
+            name: "consensus".to_string(),
+            description: "Consensus pattern".to_string(),
+            enabled: true,
+            required_capabilities: Vec::new(),
+            config: Default::default(),
+            input_schema: None,
+        });
+        let completed = |confidence: f64| PatternExecution {
+            id: "exec-1".to_string(),
+            pattern: "consensus".to_string(),
+            status: ExecutionStatus::Completed,
+            input: Value::Null,
+            output: Some(serde_json::json!({ "verdict": "approve" })),
+            agents: Vec::new(),
+            agent_contributions: Vec::new(),
+            start_time: chrono::Utc::now(),
+            end_time: Some(chrono::Utc::now()),
+            duration_ms: None,
+            confidence: Some(confidence),
+            error: None,
+            metadata: Metadata::new(),
+        };
+        let options = ExecuteOptions::builder().min_confidence(0.75).build().unwrap();
+
+        control_plane.enqueue_execution("consensus", completed(0.9));
+        let execution = client
+            .patterns()
+            .execute("consensus", serde_json::json!({}), Some(options.clone()))
+            .await
+            .unwrap();
+        assert_eq!(execution.confidence, Some(0.9));
+
+        control_plane.enqueue_execution("consensus", completed(0.6));
+        let error = client
+            .patterns()
+            .execute("consensus", serde_json::json!({}), Some(options))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            Error::ConfidenceBelowThreshold { got, required } if got == 0.6 && required == 0.75
+        ));
+        assert!(!error.is_retryable());
+    }
+
+    #[tokio::test]
+    async fn test_execute_rejects_out_of_range_min_confidence() {
+        let (client, control_plane) = crate::Client::mock();
+        assert!(ExecuteOptions::builder().min_confidence(1.5).build().is_err());
+
+        for min_confidence in [-0.1, 1.5, f64::NAN] {
+            let options = ExecuteOptions {
+                min_confidence: Some(min_confidence),
+                ..Default::default()
+            };
+            let error = client
+                .patterns()
+                .execute("consensus", serde_json::json!({}), Some(options))
+                .await
+                .unwrap_err();
+            assert!(matches!(error, Error::InvalidArgument(_)), "{}", error);
+        }
+        control_plane.assert_called("ExecutePattern", 0);
+    }
+}
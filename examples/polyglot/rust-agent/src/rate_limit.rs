@@ -0,0 +1,108 @@
+//! Per-client rate limiting for calls served by an agent
+//!
+//! Each client gets a token bucket holding up to `burst` tokens, refilled at
+//! `rate` tokens per second. A call takes one token; a client with an empty
+//! bucket is told how long until the next token arrives. Clients are keyed by
+//! peer IP address, which the caller cannot choose the way it can a header.
+
+use lru::LruCache;
+use std::net::IpAddr;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Buckets kept at once; past this the least recently seen client's bucket
+/// is dropped
+const MAX_TRACKED_CLIENTS: NonZeroUsize = match NonZeroUsize::new(1024) {
+    Some(max) => max,
+    None => unreachable!(),
+};
+
+/// Token buckets keyed by peer address. Calls with no peer address, such as
+/// in-process ones, share one bucket.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    buckets: Mutex<LruCache<Option<IpAddr>, Bucket>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    /// Allow `rate` calls per second per client, in bursts of up to `burst`
+    pub(crate) fn new(rate: f64, burst: u32) -> Self {
+        Self {
+            rate,
+            burst: f64::from(burst.max(1)),
+            buckets: Mutex::new(LruCache::new(MAX_TRACKED_CLIENTS)),
+        }
+    }
+
+    /// Take a token from `client`'s bucket, or return how long until one is
+    /// available
+    pub(crate) fn acquire(&self, client: Option<IpAddr>, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.get_or_insert_mut(client, || Bucket {
+            tokens: self.burst,
+            refilled_at: now,
+        });
+        *bucket = self.refill(*bucket, now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+    }
+
+    fn refill(&self, bucket: Bucket, now: Instant) -> Bucket {
+        let elapsed = now.saturating_duration_since(bucket.refilled_at);
+        Bucket {
+            tokens: (bucket.tokens + elapsed.as_secs_f64() * self.rate).min(self.burst),
+            refilled_at: now.max(bucket.refilled_at),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer(last: u8) -> Option<IpAddr> {
+        Some(IpAddr::from([10, 0, 0, last]))
+    }
+
+    #[test]
+    fn test_buckets_refill_per_client() {
+        let limiter = RateLimiter::new(2.0, 2);
+        let start = Instant::now();
+
+        assert!(limiter.acquire(peer(1), start).is_ok());
+        assert!(limiter.acquire(peer(1), start).is_ok());
+        assert_eq!(limiter.acquire(peer(1), start), Err(Duration::from_millis(500)));
+        assert!(limiter.acquire(peer(2), start).is_ok());
+
+        let later = start + Duration::from_millis(500);
+        assert!(limiter.acquire(peer(1), later).is_ok());
+        assert!(limiter.acquire(peer(1), later).is_err());
+    }
+
+    #[test]
+    fn test_tracked_clients_are_capped_by_evicting_the_least_recent() {
+        let limiter = RateLimiter::new(1.0, 1);
+        let now = Instant::now();
+
+        assert!(limiter.acquire(None, now).is_ok());
+        for client in 0..MAX_TRACKED_CLIENTS.get() as u32 {
+            assert!(limiter.acquire(Some(IpAddr::from(client.to_be_bytes())), now).is_ok());
+        }
+
+        assert_eq!(limiter.buckets.lock().unwrap().len(), MAX_TRACKED_CLIENTS.get());
+        // The first client's empty bucket was the one evicted
+        assert!(limiter.acquire(None, now).is_ok());
+    }
+}
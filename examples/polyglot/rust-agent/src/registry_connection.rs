@@ -0,0 +1,58 @@
+//! Registry connection reused across an agent's registry calls
+
+use crate::generated::registry_client::RegistryClient;
+use std::future::Future;
+use tokio::sync::Mutex;
+use tonic::{transport::Channel, Code, Status};
+use tracing::debug;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Lazily connected channel to the registry
+///
+/// Registering, renewing and unregistering all go through the same channel,
+/// so lease renewals reuse a warm connection. The channel is only dropped
+/// when a call fails as if the registry were unreachable; the next call
+/// then connects afresh.
+#[derive(Debug)]
+pub(crate) struct RegistryConnection {
+    addr: String,
+    channel: Mutex<Option<Channel>>,
+}
+
+impl RegistryConnection {
+    pub(crate) fn new(addr: String) -> Self {
+        Self {
+            addr,
+            channel: Mutex::new(None),
+        }
+    }
+
+    /// Run `call` against the registry, connecting first if needed
+    pub(crate) async fn call<T, F, Fut>(&self, call: F) -> Result<T, BoxError>
+    where
+        F: FnOnce(RegistryClient<Channel>) -> Fut,
+        Fut: Future<Output = Result<T, Status>>,
+    {
+        let channel = self.channel().await?;
+        let result = call(RegistryClient::new(channel)).await;
+        if let Err(status) = &result {
+            if matches!(status.code(), Code::Unavailable | Code::Unknown) {
+                debug!("Dropping registry connection after failed call: {}", status);
+                *self.channel.lock().await = None;
+            }
+        }
+        Ok(result?)
+    }
+
+    async fn channel(&self) -> Result<Channel, BoxError> {
+        let mut cached = self.channel.lock().await;
+        if let Some(channel) = cached.as_ref() {
+            return Ok(channel.clone());
+        }
+        let channel = tonic::transport::Endpoint::from_shared(self.addr.clone())?
+            .connect()
+            .await?;
+        Ok(cached.insert(channel).clone())
+    }
+}
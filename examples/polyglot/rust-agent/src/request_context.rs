@@ -0,0 +1,95 @@
+//! Request ids and trace context for calls served by an agent
+//!
+//! [`extract`] runs as a tonic interceptor in front of the agent's gRPC
+//! service. It reads the caller's `x-request-id` (generating one when absent)
+//! and the trace id from a W3C `traceparent` header, and stores them in the
+//! request extensions for the handler's tracing span. The handler echoes the
+//! request id back in the response metadata.
+
+use tonic::{metadata::MetadataMap, Request, Status};
+
+/// Metadata key carrying the request id in both directions
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Identifiers of an incoming request
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RequestContext {
+    pub(crate) request_id: String,
+    /// Trace id from the caller's `traceparent`, if it sent a valid one
+    pub(crate) trace_id: Option<String>,
+}
+
+impl RequestContext {
+    fn from_metadata(metadata: &MetadataMap) -> Self {
+        let request_id = metadata
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| !id.is_empty())
+            .map(str::to_string)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let trace_id = metadata
+            .get("traceparent")
+            .and_then(|value| value.to_str().ok())
+            .and_then(trace_id_from_traceparent);
+
+        Self { request_id, trace_id }
+    }
+
+    /// Context attached by [`extract`], or a fresh one for requests that
+    /// bypassed it
+    pub(crate) fn of<T>(request: &Request<T>) -> Self {
+        request
+            .extensions()
+            .get::<Self>()
+            .cloned()
+            .unwrap_or_else(|| Self::from_metadata(request.metadata()))
+    }
+}
+
+/// Interceptor attaching a [`RequestContext`] to every request
+pub(crate) fn extract(mut request: Request<()>) -> Result<Request<()>, Status> {
+    let context = RequestContext::from_metadata(request.metadata());
+    request.extensions_mut().insert(context);
+    Ok(request)
+}
+
+/// The trace id of a `version-traceid-spanid-flags` header
+fn trace_id_from_traceparent(traceparent: &str) -> Option<String> {
+    let mut parts = traceparent.trim().split('-');
+    let (_version, trace_id) = (parts.next()?, parts.next()?);
+    let valid = trace_id.len() == 32
+        && trace_id.chars().all(|c| c.is_ascii_hexdigit())
+        && trace_id.chars().any(|c| c != '0');
+    valid.then(|| trace_id.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_reads_request_id_and_trace_id() {
+        let mut request = Request::new(());
+        request.metadata_mut().insert(REQUEST_ID_HEADER, "req-42".parse().unwrap());
+        request.metadata_mut().insert(
+            "traceparent",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01".parse().unwrap(),
+        );
+
+        let context = RequestContext::of(&extract(request).unwrap());
+
+        assert_eq!(context.request_id, "req-42");
+        assert_eq!(context.trace_id.as_deref(), Some("4bf92f3577b34da6a3ce929d0e0e4736"));
+    }
+
+    #[test]
+    fn test_extract_generates_missing_request_id() {
+        let mut request = Request::new(());
+        request.metadata_mut().insert("traceparent", "garbage".parse().unwrap());
+
+        let context = RequestContext::of(&extract(request).unwrap());
+
+        assert!(uuid::Uuid::parse_str(&context.request_id).is_ok());
+        assert_eq!(context.trace_id, None);
+    }
+}
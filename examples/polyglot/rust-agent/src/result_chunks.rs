@@ -0,0 +1,194 @@
+//! Large results split across `StreamAnalyze` messages
+//!
+//! With [`with_stream_chunk_size`](crate::ParallaxAgent::with_stream_chunk_size)
+//! set, a `StreamAnalyze` result whose `value_json` is longer than the chunk
+//! size is sent as several `ConfidenceResult` messages. Every message but the
+//! last carries only the next piece of `value_json`, with `more_chunks` set.
+//! The last carries the final piece along with the rest of the result;
+//! `value_struct` is left unset, as it would repeat the whole value.
+//!
+//! Callers put the pieces back together with a [`ResultAssembler`], which
+//! refuses values larger than its limit.
+
+use crate::generated::ConfidenceResult;
+use futures::{Stream, StreamExt};
+use tonic::Status;
+
+/// Smallest chunk size used, so every chunk can hold a whole UTF-8 character
+#[cfg(feature = "native")]
+const MIN_CHUNK_BYTES: usize = 4;
+
+/// Largest value a [`ResultAssembler`] puts back together unless told
+/// otherwise
+const DEFAULT_MAX_RESULT_BYTES: usize = 64 * 1024 * 1024;
+
+/// Whether a `value_json` of `len` bytes is split at `chunk_size`
+#[cfg(feature = "native")]
+pub(crate) fn is_chunked(len: usize, chunk_size: usize) -> bool {
+    len > chunk_size.max(MIN_CHUNK_BYTES)
+}
+
+/// Messages carrying `result`, its `value_json` split into pieces of at
+/// most `chunk_size` bytes when longer
+///
+/// The pieces are split off the serialized value from the back, so the
+/// first keeps the original buffer and every other piece is copied once.
+#[cfg(feature = "native")]
+pub(crate) fn result_frames(
+    mut result: ConfidenceResult,
+    chunk_size: usize,
+) -> impl Iterator<Item = ConfidenceResult> + Send {
+    if !is_chunked(result.value_json.len(), chunk_size) {
+        return vec![result].into_iter();
+    }
+    let chunk_size = chunk_size.max(MIN_CHUNK_BYTES);
+    let mut value = std::mem::take(&mut result.value_json);
+    result.value_struct = None;
+
+    let mut starts = Vec::with_capacity(value.len() / chunk_size + 1);
+    let mut start = 0;
+    while start < value.len() {
+        starts.push(start);
+        let mut end = (start + chunk_size).min(value.len());
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        start = end;
+    }
+
+    let mut frames = Vec::with_capacity(starts.len());
+    result.value_json = value.split_off(starts.pop().unwrap_or_default());
+    frames.push(result);
+    while let Some(start) = starts.pop() {
+        frames.push(ConfidenceResult {
+            agent_id: frames[0].agent_id.clone(),
+            value_json: if start == 0 { std::mem::take(&mut value) } else { value.split_off(start) },
+            more_chunks: true,
+            ..Default::default()
+        });
+    }
+    frames.reverse();
+    frames.into_iter()
+}
+
+/// Reassembles results a `StreamAnalyze` response split into chunks
+#[derive(Debug)]
+pub struct ResultAssembler {
+    value_json: String,
+    max_bytes: usize,
+}
+
+impl Default for ResultAssembler {
+    fn default() -> Self {
+        Self {
+            value_json: String::new(),
+            max_bytes: DEFAULT_MAX_RESULT_BYTES,
+        }
+    }
+}
+
+impl ResultAssembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Refuse values longer than `bytes` once reassembled (default: 64 MiB)
+    pub fn with_max_bytes(mut self, bytes: usize) -> Self {
+        self.max_bytes = bytes;
+        self
+    }
+
+    /// Take the next message of a `StreamAnalyze` response
+    ///
+    /// Log lines and unsplit results come straight back. Chunks of a split
+    /// result are held, returning `None`, until the last arrives; it comes
+    /// back with the whole `value_json`. A split value growing past the
+    /// limit is dropped with `RESOURCE_EXHAUSTED`.
+    pub fn push(&mut self, mut message: ConfidenceResult) -> Result<Option<ConfidenceResult>, Status> {
+        if message.log.is_some() {
+            return Ok(Some(message));
+        }
+        if self.value_json.len() + message.value_json.len() > self.max_bytes {
+            self.value_json = String::new();
+            return Err(Status::resource_exhausted(format!(
+                "result value is larger than the {} byte limit",
+                self.max_bytes
+            )));
+        }
+        if message.more_chunks {
+            self.value_json.push_str(&message.value_json);
+            return Ok(None);
+        }
+        if !self.value_json.is_empty() {
+            self.value_json.push_str(&message.value_json);
+            message.value_json = std::mem::take(&mut self.value_json);
+        }
+        Ok(Some(message))
+    }
+
+    /// Read a `StreamAnalyze` response through to its result, skipping log
+    /// lines and reassembling a split value
+    pub async fn collect<S>(mut self, messages: S) -> Result<ConfidenceResult, Status>
+    where
+        S: Stream<Item = Result<ConfidenceResult, Status>>,
+    {
+        let mut messages = std::pin::pin!(messages);
+        while let Some(message) = messages.next().await {
+            if let Some(result) = self.push(message?)?.filter(|result| result.log.is_none()) {
+                return Ok(result);
+            }
+        }
+        Err(Status::internal("stream ended without a result"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunks_split_on_character_boundaries() {
+        let result = ConfidenceResult {
+            agent_id: "agent".to_string(),
+            value_json: "\"añb✓c\"".to_string(),
+            confidence: 0.7,
+            ..Default::default()
+        };
+
+        let frames: Vec<_> = result_frames(result, 4).collect();
+        assert!(frames.iter().all(|frame| frame.value_json.len() <= 4));
+        assert!(frames[..frames.len() - 1].iter().all(|frame| frame.more_chunks));
+
+        let mut assembler = ResultAssembler::new();
+        let assembled: Vec<_> = frames
+            .into_iter()
+            .filter_map(|frame| assembler.push(frame).unwrap())
+            .collect();
+        assert_eq!(assembled.len(), 1);
+        assert_eq!(assembled[0].value_json, "\"añb✓c\"");
+        assert_eq!(assembled[0].confidence, 0.7);
+        assert!(!assembled[0].more_chunks);
+    }
+
+    #[test]
+    fn test_assembler_refuses_values_over_its_limit() {
+        let result = ConfidenceResult {
+            value_json: format!("\"{}\"", "x".repeat(30)),
+            ..Default::default()
+        };
+
+        let mut assembler = ResultAssembler::new().with_max_bytes(16);
+        let status = result_frames(result.clone(), 8)
+            .map(|frame| assembler.push(frame))
+            .find_map(Result::err)
+            .unwrap();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+
+        let mut assembler = ResultAssembler::new().with_max_bytes(32);
+        let assembled = result_frames(result.clone(), 8)
+            .filter_map(|frame| assembler.push(frame).unwrap())
+            .next()
+            .unwrap();
+        assert_eq!(assembled.value_json, result.value_json);
+    }
+}
@@ -0,0 +1,111 @@
+//! Retrying wrapper for analyze functions
+//!
+//! [`with_retry`] wraps any analyze-style function, so it composes with
+//! [`with_confidence`](crate::with_confidence): put the retry inside and the
+//! confidence extraction outside to retry only the flaky call itself.
+
+use crate::clock::calculate_reconnect_delay;
+use futures::future::BoxFuture;
+use serde_json::Value;
+use std::{future::Future, sync::Arc, time::Duration};
+use tracing::warn;
+
+/// Boxed future returned by a [`with_retry`] wrapper
+pub type RetryFuture<T> = BoxFuture<'static, Result<T, Box<dyn std::error::Error>>>;
+
+/// Wrap an analyze function so failed calls are retried
+///
+/// The function runs up to `max_attempts` times (at least once), waiting
+/// `backoff` before the first retry and doubling the wait after each further
+/// failure. The first success is returned; once every attempt has failed, the
+/// last error is.
+pub fn with_retry<F, Fut, T>(
+    analyze_fn: F,
+    max_attempts: u32,
+    backoff: Duration,
+) -> impl Fn(&str, Option<Value>) -> RetryFuture<T> + Send + Sync
+where
+    F: Fn(&str, Option<Value>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<T, Box<dyn std::error::Error>>> + Send + 'static,
+    T: 'static,
+{
+    let analyze_fn = Arc::new(analyze_fn);
+    let max_attempts = max_attempts.max(1);
+    let backoff_ms = backoff.as_millis().min(u64::MAX as u128) as u64;
+
+    move |task: &str, data: Option<Value>| {
+        let analyze_fn = analyze_fn.clone();
+        let task = task.to_string();
+
+        Box::pin(async move {
+            let mut attempt = 1;
+            loop {
+                match analyze_fn(&task, data.clone()).await {
+                    Ok(result) => return Ok(result),
+                    Err(error) if attempt >= max_attempts => return Err(error),
+                    Err(error) => warn!(
+                        "Analyze attempt {}/{} failed, retrying: {}",
+                        attempt, max_attempts, error
+                    ),
+                }
+
+                let delay = calculate_reconnect_delay(attempt - 1, backoff_ms, u64::MAX);
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                attempt += 1;
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{with_confidence, ConfidenceConfig, ExtractionStrategy};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Analyze function failing its first `failures` calls
+    fn flaky(
+        failures: u32,
+        calls: Arc<AtomicU32>,
+    ) -> impl Fn(&str, Option<Value>) -> RetryFuture<Value> + Send + Sync + 'static {
+        move |_task: &str, _data: Option<Value>| {
+            let call = calls.fetch_add(1, Ordering::SeqCst) + 1;
+            Box::pin(async move {
+                if call <= failures {
+                    return Err(format!("failure {}", call).into());
+                }
+                Ok(json!({ "answer": "done", "confidence": 0.9 }))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_recovers_after_transient_failures() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let analyze = with_confidence(
+            with_retry(flaky(2, calls.clone()), 3, Duration::from_millis(1)),
+            Some(ConfidenceConfig {
+                strategy: ExtractionStrategy::Llm,
+                ..Default::default()
+            }),
+        );
+
+        let result = analyze("task", None).await.unwrap();
+
+        assert_eq!(result.value["answer"], "done");
+        assert_eq!(result.confidence, 0.9);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_returns_last_error_when_attempts_run_out() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let analyze = with_retry(flaky(u32::MAX, calls.clone()), 3, Duration::from_millis(1));
+
+        let error = analyze("task", Some(json!({ "id": 1 }))).await.unwrap_err();
+
+        assert_eq!(error.to_string(), "failure 3");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,237 @@
+//! Priority-ordered concurrency slots for analyze calls
+//!
+//! [`PrioritySlots`] limits how many analyses run at once like a semaphore,
+//! but hands a freed slot to the highest-priority waiting call rather than
+//! the longest-waiting one. Calls of equal priority keep arrival order.
+//!
+//! So that a steady stream of high-priority calls cannot starve the rest, a
+//! waiting call gains one priority level for every aging interval it has
+//! waited. Every waiter ages at the same rate, so their order never changes
+//! while they wait and the queue stays a plain heap.
+
+use crate::clock::Instant;
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::sync::oneshot;
+
+/// Fixed number of slots, handed out by priority
+#[derive(Debug)]
+pub(crate) struct PrioritySlots {
+    state: Mutex<State>,
+    capacity: usize,
+    aging: Duration,
+}
+
+#[derive(Debug)]
+struct State {
+    available: usize,
+    waiting: BinaryHeap<Waiter>,
+    arrivals: u64,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    priority: i32,
+    queued_at: Instant,
+    aging: Duration,
+    arrival: u64,
+    grant: oneshot::Sender<()>,
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // How far ahead `self` is, in nanoseconds of waiting: its priority
+        // lead in aging intervals, plus how much longer it has waited
+        let levels = i128::from(self.priority) - i128::from(other.priority);
+        let waited_longer = match other.queued_at.checked_duration_since(self.queued_at) {
+            Some(longer) => longer.as_nanos() as i128,
+            None => -(self.queued_at.duration_since(other.queued_at).as_nanos() as i128),
+        };
+        let lead = levels
+            .saturating_mul(self.aging.as_nanos() as i128)
+            .saturating_add(waited_longer);
+        lead.cmp(&0)
+            .then_with(|| other.arrival.cmp(&self.arrival))
+    }
+}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+/// A held slot, returned when dropped
+#[derive(Debug)]
+pub(crate) struct Slot {
+    slots: Arc<PrioritySlots>,
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.slots.release();
+    }
+}
+
+impl PrioritySlots {
+    /// `slots` slots, with waiting calls gaining a priority level every
+    /// `aging`
+    pub(crate) fn new(slots: usize, aging: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(State {
+                available: slots,
+                waiting: BinaryHeap::new(),
+                arrivals: 0,
+            }),
+            capacity: slots,
+            aging,
+        })
+    }
+
+    /// Number of slots, free or held
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Calls waiting for a slot
+    #[cfg(test)]
+    pub(crate) fn waiting(&self) -> usize {
+        self.state.lock().unwrap().waiting.len()
+    }
+
+    /// Take a slot if one is free right now
+    pub(crate) fn try_acquire(self: &Arc<Self>) -> Option<Slot> {
+        let mut state = self.state.lock().unwrap();
+        if state.available == 0 {
+            return None;
+        }
+        state.available -= 1;
+        Some(Slot {
+            slots: Arc::clone(self),
+        })
+    }
+
+    /// Wait for a slot, served ahead of waiting calls of lower `priority`
+    /// once aged from `now`
+    pub(crate) async fn acquire(self: &Arc<Self>, priority: i32, now: Instant) -> Slot {
+        let granted = {
+            let mut state = self.state.lock().unwrap();
+            if state.available > 0 && state.waiting.is_empty() {
+                state.available -= 1;
+                return Slot {
+                    slots: Arc::clone(self),
+                };
+            }
+            let (grant, granted) = oneshot::channel();
+            state.arrivals += 1;
+            let arrival = state.arrivals;
+            state.waiting.push(Waiter {
+                priority,
+                queued_at: now,
+                aging: self.aging,
+                arrival,
+                grant,
+            });
+            granted
+        };
+
+        let mut pending = PendingGrant {
+            granted,
+            slots: Arc::clone(self),
+        };
+        // The sender is only dropped once the slot has been handed over
+        let _ = (&mut pending.granted).await;
+        Slot {
+            slots: Arc::clone(self),
+        }
+    }
+
+    /// Hand a freed slot to the highest-priority caller still waiting
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        while let Some(waiter) = state.waiting.pop() {
+            if waiter.grant.send(()).is_ok() {
+                return;
+            }
+        }
+        state.available += 1;
+    }
+}
+
+/// Grant a waiting call is owed; a slot granted after the call gave up
+/// waiting is passed on
+struct PendingGrant {
+    granted: oneshot::Receiver<()>,
+    slots: Arc<PrioritySlots>,
+}
+
+impl Drop for PendingGrant {
+    fn drop(&mut self) {
+        self.granted.close();
+        if self.granted.try_recv().is_ok() {
+            self.slots.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    #[tokio::test]
+    async fn test_abandoned_waiter_passes_its_slot_on() {
+        let slots = PrioritySlots::new(1, Duration::from_secs(1));
+        let held = slots.try_acquire().unwrap();
+        let now = Instant::now();
+
+        let mut abandoned = Box::pin(slots.acquire(5, now));
+        assert!((&mut abandoned).now_or_never().is_none());
+        let mut waiting = Box::pin(slots.acquire(0, now));
+        assert!((&mut waiting).now_or_never().is_none());
+
+        // Granted to the higher priority waiter just as it gives up
+        drop(held);
+        drop(abandoned);
+        let slot = waiting.await;
+        assert!(slots.try_acquire().is_none());
+        drop(slot);
+        assert!(slots.try_acquire().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_waiting_calls_age_past_newer_higher_priority_ones() {
+        let slots = PrioritySlots::new(1, Duration::from_secs(1));
+        let held = slots.try_acquire().unwrap();
+        let start = Instant::now();
+
+        // Five seconds of waiting outranks a priority lead of three, but not
+        // one of ten
+        let mut old = Box::pin(slots.acquire(0, start));
+        let mut newer = Box::pin(slots.acquire(3, start + Duration::from_secs(5)));
+        let mut urgent = Box::pin(slots.acquire(10, start + Duration::from_secs(5)));
+        assert!((&mut old).now_or_never().is_none());
+        assert!((&mut newer).now_or_never().is_none());
+        assert!((&mut urgent).now_or_never().is_none());
+
+        drop(held);
+        let slot = (&mut urgent).now_or_never().expect("urgent call not served first");
+        assert!((&mut newer).now_or_never().is_none());
+        drop(slot);
+        let slot = (&mut old).now_or_never().expect("aged call not served next");
+        drop(slot);
+        assert!(newer.now_or_never().is_some());
+    }
+}
@@ -0,0 +1,227 @@
+//! Tracing spans and W3C trace context for client RPCs
+//!
+//! With the `otel` feature enabled, every service RPC runs inside a
+//! `parallax.rpc` span recording the method, endpoint, trace id and
+//! resulting gRPC status code, and sends a `traceparent` header so the
+//! control plane can join the same trace. Without the feature, calls pass
+//! straight through.
+
+use std::future::Future;
+use tonic::{Request, Response, Status};
+
+/// Name of the span emitted around each RPC
+#[cfg(feature = "otel")]
+pub(crate) const RPC_SPAN: &str = "parallax.rpc";
+
+/// Send `message` through `call`, tracing the RPC when the `otel` feature is on
+///
+/// `trace_id` joins the call to an existing trace; it is normalised to the
+/// 32 hex digit W3C form, and a fresh trace is started if it is missing or
+/// cannot be represented that way.
+#[cfg(feature = "otel")]
+pub(crate) async fn traced<T, R, F, Fut>(
+    rpc: &'static str,
+    endpoint: &str,
+    trace_id: Option<&str>,
+    message: T,
+    call: F,
+) -> Result<Response<R>, Status>
+where
+    F: FnOnce(Request<T>) -> Fut,
+    Fut: Future<Output = Result<Response<R>, Status>>,
+{
+    use tracing::{field, Instrument};
+
+    let trace_id = trace_id
+        .and_then(w3c_trace_id)
+        .unwrap_or_else(|| random_hex(16));
+    let span = tracing::info_span!(
+        RPC_SPAN,
+        rpc,
+        endpoint,
+        trace_id = trace_id.as_str(),
+        status_code = field::Empty,
+    );
+
+    let mut request = Request::new(message);
+    let traceparent = format!("00-{}-{}-01", trace_id, random_hex(8));
+    if let Ok(value) = traceparent.parse() {
+        request.metadata_mut().insert("traceparent", value);
+    }
+
+    let result = call(request).instrument(span.clone()).await;
+    let code = match &result {
+        Ok(_) => tonic::Code::Ok,
+        Err(status) => status.code(),
+    };
+    span.record("status_code", format!("{:?}", code).as_str());
+
+    result
+}
+
+/// Send `message` through `call`, tracing the RPC when the `otel` feature is on
+#[cfg(not(feature = "otel"))]
+pub(crate) async fn traced<T, R, F, Fut>(
+    _rpc: &'static str,
+    _endpoint: &str,
+    _trace_id: Option<&str>,
+    message: T,
+    call: F,
+) -> Result<Response<R>, Status>
+where
+    F: FnOnce(Request<T>) -> Fut,
+    Fut: Future<Output = Result<Response<R>, Status>>,
+{
+    call(Request::new(message)).await
+}
+
+/// Normalise a caller-supplied trace id (hex, optionally hyphenated like a
+/// UUID) to the W3C form; all-zero ids are invalid
+#[cfg(feature = "otel")]
+fn w3c_trace_id(trace_id: &str) -> Option<String> {
+    let hex: String = trace_id
+        .chars()
+        .filter(|c| *c != '-')
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+
+    let valid = hex.len() == 32
+        && hex.chars().all(|c| c.is_ascii_hexdigit())
+        && hex.chars().any(|c| c != '0');
+    valid.then_some(hex)
+}
+
+/// `bytes` random bytes as lowercase hex, never all zero
+#[cfg(feature = "otel")]
+fn random_hex(bytes: usize) -> String {
+    loop {
+        let id: Vec<u8> = (0..bytes).map(|_| rand::random::<u8>()).collect();
+        if id.iter().any(|byte| *byte != 0) {
+            return id.iter().map(|byte| format!("{:02x}", byte)).collect();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "otel"))]
+mod tests {
+    use super::*;
+    use crate::{types::ExecuteOptions, Client};
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        Subscriber,
+    };
+    use tracing_subscriber::{
+        layer::{Context, SubscriberExt},
+        registry::LookupSpan,
+        Layer,
+    };
+
+    type Fields = HashMap<String, String>;
+
+    /// Layer collecting the fields of every closed `parallax.rpc` span
+    #[derive(Clone, Default)]
+    struct SpanRecorder {
+        closed: Arc<Mutex<Vec<Fields>>>,
+    }
+
+    struct FieldVisitor<'a>(&'a mut Fields);
+
+    impl Visit for FieldVisitor<'_> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0.insert(field.name().to_string(), format!("{:?}", value));
+        }
+    }
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for SpanRecorder {
+        fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+            if attrs.metadata().name() != RPC_SPAN {
+                return;
+            }
+            let mut fields = Fields::new();
+            attrs.record(&mut FieldVisitor(&mut fields));
+            ctx.span(id).unwrap().extensions_mut().insert(fields);
+        }
+
+        fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+            if let Some(fields) = ctx.span(id).unwrap().extensions_mut().get_mut::<Fields>() {
+                values.record(&mut FieldVisitor(fields));
+            }
+        }
+
+        fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+            if let Some(fields) = ctx.span(&id).unwrap().extensions_mut().remove::<Fields>() {
+                self.closed.lock().unwrap().push(fields);
+            }
+        }
+    }
+
+    #[test]
+    fn test_w3c_trace_id_normalisation() {
+        assert_eq!(
+            w3c_trace_id("4BF92F35-77B3-4DA6-A3CE-929D0E0E4736").as_deref(),
+            Some("4bf92f3577b34da6a3ce929d0e0e4736")
+        );
+        assert_eq!(w3c_trace_id("req-42"), None);
+        assert_eq!(w3c_trace_id(&"0".repeat(32)), None);
+    }
+
+    #[tokio::test]
+    async fn test_traced_sends_traceparent_for_trace_id() {
+        let response = traced(
+            "Test::echo",
+            "http://fake",
+            Some("4bf92f3577b34da6a3ce929d0e0e4736"),
+            (),
+            |request: Request<()>| async move {
+                let traceparent = request.metadata().get("traceparent").unwrap();
+                Ok(Response::new(traceparent.to_str().unwrap().to_string()))
+            },
+        )
+        .await
+        .unwrap();
+
+        let parts: Vec<&str> = response.get_ref().split('-').collect();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(parts[0], "00");
+        assert_eq!(parts[1], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(parts[2].len(), 16);
+        assert_eq!(parts[3], "01");
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn test_service_calls_emit_rpc_spans() {
+        let recorder = SpanRecorder::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+
+        let (client, _control_plane) = Client::mock();
+        let patterns = client.patterns();
+        patterns.list().await.unwrap();
+        let options = ExecuteOptions {
+            trace_id: Some("4bf92f3577b34da6a3ce929d0e0e4736".to_string()),
+            ..Default::default()
+        };
+        patterns
+            .execute("missing", serde_json::json!({}), Some(options))
+            .await
+            .unwrap_err();
+
+        let spans = recorder.closed.lock().unwrap().clone();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0]["rpc"], "PatternService::list");
+        assert_eq!(spans[0]["endpoint"], crate::testing::MOCK_ENDPOINT);
+        assert_eq!(spans[0]["status_code"], "Ok");
+        assert_eq!(spans[1]["rpc"], "PatternService::execute");
+        assert_eq!(spans[1]["trace_id"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(spans[1]["status_code"], "NotFound");
+    }
+}
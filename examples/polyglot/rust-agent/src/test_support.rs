@@ -0,0 +1,103 @@
+//! Helpers for exercising services against in-process gRPC servers
+
+use futures::StreamExt;
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::net::TcpListener;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::{
+    transport::{server::Router, Channel, Endpoint, Server},
+    Request,
+};
+
+/// Metadata values recorded per request, in the order the names were given
+pub(crate) type RecordedMetadata = Arc<Mutex<Vec<Vec<Option<String>>>>>;
+
+/// Serve `router` on an ephemeral localhost port and return a channel to it
+pub(crate) async fn serve(router: Router) -> Channel {
+    Endpoint::from_shared(serve_at(router).await)
+        .unwrap()
+        .connect()
+        .await
+        .unwrap()
+}
+
+/// Serve `router` on an ephemeral localhost port and return its URL
+pub(crate) async fn serve_at(router: Router) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(router.serve_with_incoming(TcpListenerStream::new(listener)));
+    format!("http://{}", addr)
+}
+
+/// Serve `router` like [`serve_at`], also returning a count of the
+/// connections accepted
+pub(crate) async fn serve_counting_connections(router: Router) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let connections = Arc::new(AtomicUsize::new(0));
+    let incoming = TcpListenerStream::new(listener).inspect({
+        let connections = Arc::clone(&connections);
+        move |_| {
+            connections.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+    tokio::spawn(router.serve_with_incoming(incoming));
+    (format!("http://{}", addr), connections)
+}
+
+/// Serve `router` like [`serve_at`], recording the values of the metadata
+/// entries called `names` on every request it receives
+pub(crate) async fn serve_recording_metadata(
+    router: Router,
+    names: &'static [&'static str],
+) -> (String, RecordedMetadata) {
+    let seen = RecordedMetadata::default();
+    let recorder = tonic::service::interceptor({
+        let seen = Arc::clone(&seen);
+        move |request: Request<()>| {
+            let values = names
+                .iter()
+                .map(|name| {
+                    request
+                        .metadata()
+                        .get(*name)
+                        .map(|value| value.to_str().unwrap().to_string())
+                })
+                .collect();
+            seen.lock().unwrap().push(values);
+            Ok(request)
+        }
+    });
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let router = Server::builder().layer(recorder).add_routes(router.into_service());
+    tokio::spawn(router.serve_with_incoming(TcpListenerStream::new(listener)));
+    (format!("http://{}", addr), seen)
+}
+
+/// Serve `router` like [`serve_at`] until `shutdown` resolves, then close
+/// every connection
+pub(crate) async fn serve_until(
+    router: Router,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(router.serve_with_incoming_shutdown(TcpListenerStream::new(listener), shutdown));
+    format!("http://{}", addr)
+}
+
+/// URL of a localhost port with nothing listening on it
+pub(crate) async fn unused_endpoint() -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    drop(listener);
+    format!("http://{}", addr)
+}
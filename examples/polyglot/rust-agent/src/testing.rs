@@ -0,0 +1,957 @@
+//! In-memory control plane for testing code built on the SDK
+//!
+//! [`Client::mock`](crate::Client::mock) returns a client wired to a
+//! [`MockControlPlane`] over an in-process transport, so pattern, agent and
+//! execution calls work without a running server. Seed the patterns, agents
+//! and executions a test needs, enqueue execution results, then inspect the
+//! calls the control plane received.
+
+use crate::{
+    agent_service::{agent_from_registration, agent_to_registration},
+    generated::parallax::{
+        executions::{
+            execution_service_server::{self, ExecutionServiceServer},
+            Execution, ExecutionAgent, ExecutionStatus as ProtoExecutionStatus, ExportExecutionsRequest,
+            GetExecutionRequest, GetExecutionResponse, ListExecutionsRequest, ListExecutionsResponse,
+            StreamExecutionRequest, StreamExecutionResponse,
+        },
+        patterns::{
+            self as patterns_proto,
+            execute_pattern_response::{ExecutionMetrics, Status as ProtoPatternStatus},
+            pattern_service_server::{self, PatternServiceServer},
+            pattern_catalog_event::EventType as CatalogEventType,
+            ExecutePatternRequest, ExecutePatternResponse, GetPatternRequest,
+            ListPatternsRequest, ListPatternsResponse, PatternCatalogEvent, UploadPatternRequest,
+            UploadPatternResponse, WatchPatternsRequest,
+        },
+        registry::{
+            registry_server::{self, RegistryServer},
+            watch_event::EventType,
+            AgentRegistration, GetAgentRequest, ListAgentsRequest, ListAgentsResponse,
+            RegisterRequest, RegisterResponse, RenewRequest, WatchEvent, WatchRequest,
+        },
+    },
+    conversions::{json_to_struct, struct_to_json},
+    types::{
+        Agent, ExecutionEvent, ExecutionEventKind, ExecutionFilter, ExecutionStatus, Pattern,
+        PatternExecution,
+    },
+};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use hyper_util::rt::TokioIo;
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use tokio::{io::DuplexStream, sync::mpsc};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tonic::{
+    codec::CompressionEncoding,
+    transport::{server::Router, Channel, Endpoint, Server, Uri},
+    Request, Response, Status,
+};
+
+/// Endpoint reported by clients connected to a [`MockControlPlane`]
+pub(crate) const MOCK_ENDPOINT: &str = "http://mock.parallax";
+
+/// Buffer size of each in-memory connection
+const MOCK_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Encodings the mock control plane accepts and responds with
+const COMPRESSION_ENCODINGS: [CompressionEncoding; 2] =
+    [CompressionEncoding::Gzip, CompressionEncoding::Zstd];
+
+/// A call received by the mock control plane
+#[derive(Debug, Clone, PartialEq)]
+pub struct MockCall {
+    /// gRPC method name, e.g. `"ExecutePattern"`, `"Register"` or `"GetExecution"`
+    pub method: String,
+    /// Pattern name, agent id or execution id the call targeted, if any
+    pub target: Option<String>,
+    /// Input sent with `ExecutePattern`
+    pub input: Option<Value>,
+}
+
+/// In-memory stand-in for the Parallax control plane
+///
+/// Cloning shares the same state, so a test can keep a handle while the
+/// client talks to it.
+#[derive(Clone, Default)]
+pub struct MockControlPlane {
+    state: Arc<MockState>,
+}
+
+#[derive(Default)]
+struct MockState {
+    patterns: Mutex<BTreeMap<String, Pattern>>,
+    agents: Mutex<BTreeMap<String, AgentRegistration>>,
+    executions: Mutex<BTreeMap<String, PatternExecution>>,
+    // Results handed out by `ExecutePattern`, per pattern, in order
+    execute_results: Mutex<HashMap<String, VecDeque<Result<PatternExecution, Status>>>>,
+    // Events `StreamExecution` sends, per execution id
+    events: Mutex<HashMap<String, Vec<ExecutionEvent>>>,
+    // One-shot failures injected per method
+    failures: Mutex<HashMap<String, VecDeque<Status>>>,
+    calls: Mutex<Vec<MockCall>>,
+}
+
+impl MockControlPlane {
+    /// Create an empty control plane
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make a pattern available to `list`, `get` and `execute`
+    pub fn add_pattern(&self, pattern: Pattern) {
+        self.state
+            .patterns
+            .lock()
+            .unwrap()
+            .insert(pattern.name.clone(), pattern);
+    }
+
+    /// Register an agent as if it had called `register` itself
+    pub fn add_agent(&self, agent: Agent) {
+        self.state
+            .agents
+            .lock()
+            .unwrap()
+            .insert(agent.id.clone(), agent_to_registration(&agent));
+    }
+
+    /// Make an execution available to the execution service
+    pub fn add_execution(&self, execution: PatternExecution) {
+        self.state
+            .executions
+            .lock()
+            .unwrap()
+            .insert(execution.id.clone(), execution);
+    }
+
+    /// Queue the result of the next `execute` call for `pattern`
+    ///
+    /// Empty ids, pattern names and inputs are filled in from the request,
+    /// and the execution is then visible through the execution service.
+    pub fn enqueue_execution(&self, pattern: &str, execution: PatternExecution) {
+        self.enqueue_execute_result(pattern, Ok(execution));
+    }
+
+    /// Make the next `execute` call for `pattern` fail with `status`
+    pub fn enqueue_execute_error(&self, pattern: &str, status: Status) {
+        self.enqueue_execute_result(pattern, Err(status));
+    }
+
+    /// Set the events `StreamExecution` sends for an execution, in order,
+    /// and `StreamExecutePattern` forwards after `started`. Without queued
+    /// events `StreamExecution` sends the execution's current state once.
+    pub fn enqueue_events(&self, execution_id: &str, events: Vec<ExecutionEvent>) {
+        self.state
+            .events
+            .lock()
+            .unwrap()
+            .insert(execution_id.to_string(), events);
+    }
+
+    /// Make the next call to `method` (e.g. `"ListPatterns"`) fail with `status`
+    pub fn fail_next(&self, method: &str, status: Status) {
+        self.state
+            .failures
+            .lock()
+            .unwrap()
+            .entry(method.to_string())
+            .or_default()
+            .push_back(status);
+    }
+
+    /// Agents currently registered
+    pub fn agents(&self) -> Vec<Agent> {
+        self.state
+            .agents
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .map(agent_from_registration)
+            .collect()
+    }
+
+    /// Every call received so far, in order
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.state.calls.lock().unwrap().clone()
+    }
+
+    /// Calls received for one method, in order
+    pub fn calls_to(&self, method: &str) -> Vec<MockCall> {
+        self.calls()
+            .into_iter()
+            .filter(|call| call.method == method)
+            .collect()
+    }
+
+    /// Panic unless `method` was called exactly `times` times
+    #[track_caller]
+    pub fn assert_called(&self, method: &str, times: usize) {
+        let calls = self.calls_to(method).len();
+        assert_eq!(
+            calls, times,
+            "expected {} call(s) to {}, got {}",
+            times, method, calls
+        );
+    }
+
+    fn enqueue_execute_result(&self, pattern: &str, result: Result<PatternExecution, Status>) {
+        self.state
+            .execute_results
+            .lock()
+            .unwrap()
+            .entry(pattern.to_string())
+            .or_default()
+            .push_back(result);
+    }
+
+    /// Serve the control plane in the background and return a channel to it.
+    /// Each connection the channel opens gets its own in-memory pipe.
+    pub(crate) fn channel(&self) -> Channel {
+        let (connections, incoming) = mpsc::unbounded_channel::<DuplexStream>();
+        let router = self.router();
+
+        tokio::spawn(async move {
+            let incoming = UnboundedReceiverStream::new(incoming).map(Ok::<_, std::io::Error>);
+            let _ = router.serve_with_incoming(incoming).await;
+        });
+
+        Endpoint::from_static(MOCK_ENDPOINT)
+            .connect_with_connector_lazy(MockConnector { connections })
+    }
+
+    /// Router exposing every control plane service backed by this mock.
+    /// Accepts gzip and zstd payloads so clients configured with compression
+    /// can run against it.
+    pub(crate) fn router(&self) -> Router {
+        let patterns = PatternServiceServer::from_arc(self.state.clone());
+        let registry = RegistryServer::from_arc(self.state.clone());
+        let executions = ExecutionServiceServer::from_arc(self.state.clone());
+
+        let (patterns, registry, executions) = COMPRESSION_ENCODINGS.iter().fold(
+            (patterns, registry, executions),
+            |(patterns, registry, executions), &encoding| {
+                (
+                    patterns.accept_compressed(encoding).send_compressed(encoding),
+                    registry.accept_compressed(encoding).send_compressed(encoding),
+                    executions.accept_compressed(encoding).send_compressed(encoding),
+                )
+            },
+        );
+
+        Server::builder()
+            .add_service(patterns)
+            .add_service(registry)
+            .add_service(executions)
+    }
+}
+
+/// Connector handing the server one end of a fresh in-memory pipe per connection
+#[derive(Clone)]
+struct MockConnector {
+    connections: mpsc::UnboundedSender<DuplexStream>,
+}
+
+impl tower::Service<Uri> for MockConnector {
+    type Response = TokioIo<DuplexStream>;
+    type Error = std::io::Error;
+    type Future = std::future::Ready<Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        let (client, server) = tokio::io::duplex(MOCK_BUFFER_SIZE);
+        let connected = self
+            .connections
+            .send(server)
+            .map(|_| TokioIo::new(client))
+            .map_err(|_| {
+                std::io::Error::new(
+                    std::io::ErrorKind::BrokenPipe,
+                    "mock control plane has stopped",
+                )
+            });
+        std::future::ready(connected)
+    }
+}
+
+impl MockState {
+    /// Record a call, failing it if a failure was injected for the method
+    fn record(
+        &self,
+        method: &str,
+        target: Option<String>,
+        input: Option<Value>,
+    ) -> Result<(), Status> {
+        self.calls.lock().unwrap().push(MockCall {
+            method: method.to_string(),
+            target,
+            input,
+        });
+        match self
+            .failures
+            .lock()
+            .unwrap()
+            .get_mut(method)
+            .and_then(VecDeque::pop_front)
+        {
+            Some(status) => Err(status),
+            None => Ok(()),
+        }
+    }
+
+    /// Hand out the next queued result for an `ExecutePattern` or
+    /// `StreamExecutePattern` request and store the execution
+    fn start_execution(
+        &self,
+        method: &str,
+        request: ExecutePatternRequest,
+    ) -> Result<PatternExecution, Status> {
+        let name = request.pattern_name;
+        let input = request.input.map(struct_to_json).unwrap_or(Value::Null);
+        self.record(method, Some(name.clone()), Some(input.clone()))?;
+
+        if !self.patterns.lock().unwrap().contains_key(&name) {
+            return Err(Status::not_found("Pattern not found"));
+        }
+
+        let queued = self
+            .execute_results
+            .lock()
+            .unwrap()
+            .get_mut(&name)
+            .and_then(VecDeque::pop_front);
+        let mut execution = match queued {
+            Some(result) => result?,
+            None => {
+                return Err(Status::failed_precondition(format!(
+                    "no execution enqueued for pattern {}",
+                    name
+                )))
+            }
+        };
+
+        if execution.id.is_empty() {
+            execution.id = uuid::Uuid::new_v4().to_string();
+        }
+        if execution.pattern.is_empty() {
+            execution.pattern = name;
+        }
+        if execution.input.is_null() {
+            execution.input = input;
+        }
+        self.executions
+            .lock()
+            .unwrap()
+            .insert(execution.id.clone(), execution.clone());
+
+        Ok(execution)
+    }
+
+    fn execution(&self, id: &str) -> Result<PatternExecution, Status> {
+        self.executions
+            .lock()
+            .unwrap()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| Status::not_found("Execution not found"))
+    }
+}
+
+#[tonic::async_trait]
+impl pattern_service_server::PatternService for MockState {
+    async fn execute_pattern(
+        &self,
+        request: Request<ExecutePatternRequest>,
+    ) -> Result<Response<ExecutePatternResponse>, Status> {
+        let execution = self.start_execution("ExecutePattern", request.into_inner())?;
+        Ok(Response::new(execute_response(&execution)))
+    }
+
+    type StreamExecutePatternStream =
+        futures::stream::BoxStream<'static, Result<ExecutePatternResponse, Status>>;
+
+    /// Reports the execution the way the control plane forwards engine
+    /// events: `started` with the execution as it was enqueued, then the
+    /// events queued for it up to the first terminal one, which carries the
+    /// result. An execution enqueued finished, with no events queued, is
+    /// sent once as its terminal event.
+    async fn stream_execute_pattern(
+        &self,
+        request: Request<ExecutePatternRequest>,
+    ) -> Result<Response<Self::StreamExecutePatternStream>, Status> {
+        let execution = self.start_execution("StreamExecutePattern", request.into_inner())?;
+        let events = self.events.lock().unwrap().get(&execution.id).cloned();
+
+        let mut responses = Vec::new();
+        if events.is_none() && execution.status.is_terminal() {
+            let kind = ExecutionEventKind::for_status(execution.status);
+            responses.push(event_response(&execution, kind.as_str(), None));
+        } else {
+            responses.push(event_response(&execution, "started", None));
+        }
+        for event in events.unwrap_or_default() {
+            let current = event.execution.as_ref().unwrap_or(&execution);
+            responses.push(event_response(current, &event.event_type, event.event_data.clone()));
+            if event.kind().is_terminal() {
+                break;
+            }
+        }
+        Ok(Response::new(futures::stream::iter(responses.into_iter().map(Ok)).boxed()))
+    }
+
+    async fn list_patterns(
+        &self,
+        _request: Request<ListPatternsRequest>,
+    ) -> Result<Response<ListPatternsResponse>, Status> {
+        self.record("ListPatterns", None, None)?;
+        Ok(Response::new(ListPatternsResponse {
+            patterns: self
+                .patterns
+                .lock()
+                .unwrap()
+                .values()
+                .map(pattern_to_proto)
+                .collect(),
+        }))
+    }
+
+    async fn get_pattern(
+        &self,
+        request: Request<GetPatternRequest>,
+    ) -> Result<Response<patterns_proto::Pattern>, Status> {
+        let name = request.into_inner().name;
+        self.record("GetPattern", Some(name.clone()), None)?;
+        self.patterns
+            .lock()
+            .unwrap()
+            .get(&name)
+            .map(|pattern| Response::new(pattern_to_proto(pattern)))
+            .ok_or_else(|| Status::not_found("Pattern not found"))
+    }
+
+    async fn upload_pattern(
+        &self,
+        request: Request<UploadPatternRequest>,
+    ) -> Result<Response<UploadPatternResponse>, Status> {
+        let name = request.into_inner().pattern.map(|pattern| pattern.name);
+        self.record("UploadPattern", name, None)?;
+        Err(Status::unimplemented(
+            "the mock control plane does not accept pattern uploads",
+        ))
+    }
+
+    type WatchPatternsStream =
+        futures::stream::BoxStream<'static, Result<PatternCatalogEvent, Status>>;
+
+    /// Replays the current patterns as `ADDED` events when asked to, then
+    /// stays open without further changes
+    async fn watch_patterns(
+        &self,
+        request: Request<WatchPatternsRequest>,
+    ) -> Result<Response<Self::WatchPatternsStream>, Status> {
+        self.record("WatchPatterns", None, None)?;
+        let initial: Vec<_> = if request.into_inner().include_initial {
+            self.patterns
+                .lock()
+                .unwrap()
+                .values()
+                .map(|pattern| {
+                    Ok(PatternCatalogEvent {
+                        r#type: CatalogEventType::Added as i32,
+                        pattern: Some(pattern_to_proto(pattern)),
+                        timestamp: Some(to_timestamp(Utc::now())),
+                    })
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let stream = futures::stream::iter(initial).chain(futures::stream::pending());
+        Ok(Response::new(stream.boxed()))
+    }
+}
+
+#[tonic::async_trait]
+impl registry_server::Registry for MockState {
+    async fn register(
+        &self,
+        request: Request<RegisterRequest>,
+    ) -> Result<Response<RegisterResponse>, Status> {
+        let agent = request
+            .into_inner()
+            .agent
+            .ok_or_else(|| Status::invalid_argument("Missing agent registration"))?;
+        self.record("Register", Some(agent.id.clone()), None)?;
+
+        let lease_id = format!("lease-{}", agent.id);
+        self.agents.lock().unwrap().insert(agent.id.clone(), agent);
+        Ok(Response::new(RegisterResponse {
+            success: true,
+            message: "Agent registered".to_string(),
+            lease_id,
+        }))
+    }
+
+    async fn unregister(
+        &self,
+        request: Request<AgentRegistration>,
+    ) -> Result<Response<RegisterResponse>, Status> {
+        let id = request.into_inner().id;
+        self.record("Unregister", Some(id.clone()), None)?;
+        self.agents.lock().unwrap().remove(&id);
+        Ok(Response::new(RegisterResponse {
+            success: true,
+            message: "Agent unregistered".to_string(),
+            lease_id: String::new(),
+        }))
+    }
+
+    async fn renew(
+        &self,
+        request: Request<RenewRequest>,
+    ) -> Result<Response<RegisterResponse>, Status> {
+        let lease_id = request.into_inner().lease_id;
+        self.record("Renew", Some(lease_id.clone()), None)?;
+        Ok(Response::new(RegisterResponse {
+            success: true,
+            message: "Lease renewed".to_string(),
+            lease_id,
+        }))
+    }
+
+    async fn list_agents(
+        &self,
+        _request: Request<ListAgentsRequest>,
+    ) -> Result<Response<ListAgentsResponse>, Status> {
+        self.record("ListAgents", None, None)?;
+        let agents: Vec<_> = self.agents.lock().unwrap().values().cloned().collect();
+        Ok(Response::new(ListAgentsResponse {
+            total_count: agents.len() as i32,
+            agents,
+            next_continuation_token: String::new(),
+        }))
+    }
+
+    async fn get_agent(
+        &self,
+        request: Request<GetAgentRequest>,
+    ) -> Result<Response<AgentRegistration>, Status> {
+        let id = request.into_inner().agent_id;
+        self.record("GetAgent", Some(id.clone()), None)?;
+        self.agents
+            .lock()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .map(Response::new)
+            .ok_or_else(|| Status::not_found("Agent not found"))
+    }
+
+    type WatchStream = futures::stream::BoxStream<'static, Result<WatchEvent, Status>>;
+
+    /// Replays the current agents as `ADDED` events, then stays open
+    /// without further changes
+    async fn watch(
+        &self,
+        _request: Request<WatchRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        self.record("Watch", None, None)?;
+        let events: Vec<_> = self
+            .agents
+            .lock()
+            .unwrap()
+            .values()
+            .map(|agent| {
+                Ok(WatchEvent {
+                    r#type: EventType::Added as i32,
+                    agent: Some(agent.clone()),
+                    timestamp: Some(to_timestamp(Utc::now())),
+                })
+            })
+            .collect();
+        let stream = futures::stream::iter(events).chain(futures::stream::pending());
+        Ok(Response::new(stream.boxed()))
+    }
+}
+
+#[tonic::async_trait]
+impl execution_service_server::ExecutionService for MockState {
+    async fn get_execution(
+        &self,
+        request: Request<GetExecutionRequest>,
+    ) -> Result<Response<GetExecutionResponse>, Status> {
+        let id = request.into_inner().execution_id;
+        self.record("GetExecution", Some(id.clone()), None)?;
+        Ok(Response::new(GetExecutionResponse {
+            execution: Some(execution_to_proto(&self.execution(&id)?)),
+        }))
+    }
+
+    async fn list_executions(
+        &self,
+        request: Request<ListExecutionsRequest>,
+    ) -> Result<Response<ListExecutionsResponse>, Status> {
+        let request = request.into_inner();
+        self.record("ListExecutions", None, None)?;
+
+        // Status is compared as sent, so an unknown one matches nothing
+        let filter = ExecutionFilter {
+            status: None,
+            pattern: (!request.pattern_name.is_empty()).then_some(request.pattern_name),
+            started_after: request.started_after.map(from_timestamp),
+            started_before: request.started_before.map(from_timestamp),
+        };
+        let matching: Vec<Execution> = self
+            .executions
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|execution| {
+                (request.status.is_empty() || status_name(execution.status) == request.status)
+                    && filter.matches(execution)
+            })
+            .map(execution_to_proto)
+            .collect();
+        let total = matching.len();
+        let limit = if request.limit > 0 {
+            request.limit as usize
+        } else {
+            usize::MAX
+        };
+        // Cursors are the offset of the next page, which is enough for a mock
+        let start = if request.cursor.is_empty() {
+            request.offset.max(0) as usize
+        } else {
+            request
+                .cursor
+                .parse()
+                .map_err(|_| Status::invalid_argument("invalid page cursor"))?
+        };
+
+        let page: Vec<Execution> = matching.into_iter().skip(start).take(limit).collect();
+        let end = start + page.len();
+        Ok(Response::new(ListExecutionsResponse {
+            executions: page,
+            total: total as i32,
+            next_cursor: if end < total {
+                end.to_string()
+            } else {
+                String::new()
+            },
+        }))
+    }
+
+    type StreamExecutionStream =
+        futures::stream::BoxStream<'static, Result<StreamExecutionResponse, Status>>;
+
+    /// Sends the events queued for the execution, or else its current
+    /// state as a single event, then ends
+    async fn stream_execution(
+        &self,
+        request: Request<StreamExecutionRequest>,
+    ) -> Result<Response<Self::StreamExecutionStream>, Status> {
+        let id = request.into_inner().execution_id;
+        self.record("StreamExecution", Some(id.clone()), None)?;
+
+        if let Some(events) = self.events.lock().unwrap().get(&id) {
+            let events: Vec<_> = events.iter().map(|event| Ok(event_to_proto(event))).collect();
+            return Ok(Response::new(futures::stream::iter(events).boxed()));
+        }
+
+        let execution = self.execution(&id)?;
+        let event = StreamExecutionResponse {
+            event_type: ExecutionEventKind::for_status(execution.status).to_string(),
+            execution: Some(execution_to_proto(&execution)),
+            event_time: Some(to_timestamp(Utc::now())),
+            event_data: None,
+        };
+        Ok(Response::new(futures::stream::iter([Ok(event)]).boxed()))
+    }
+
+    type ExportExecutionsStream = futures::stream::BoxStream<'static, Result<Execution, Status>>;
+
+    /// Sends every stored execution matching the request's filters
+    async fn export_executions(
+        &self,
+        request: Request<ExportExecutionsRequest>,
+    ) -> Result<Response<Self::ExportExecutionsStream>, Status> {
+        let request = request.into_inner();
+        self.record("ExportExecutions", None, None)?;
+
+        let status = (!request.status.is_empty())
+            .then(|| serde_json::from_value(Value::String(request.status)))
+            .transpose()
+            .map_err(|_| Status::invalid_argument("unknown execution status"))?;
+        let filter = ExecutionFilter {
+            status,
+            pattern: (!request.pattern_name.is_empty()).then_some(request.pattern_name),
+            started_after: request.started_after.map(from_timestamp),
+            started_before: request.started_before.map(from_timestamp),
+        };
+        let matching: Vec<_> = self
+            .executions
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|execution| filter.matches(execution))
+            .map(|execution| Ok(execution_to_proto(execution)))
+            .collect();
+        Ok(Response::new(futures::stream::iter(matching).boxed()))
+    }
+}
+
+fn pattern_to_proto(pattern: &Pattern) -> patterns_proto::Pattern {
+    patterns_proto::Pattern {
+        name: pattern.name.clone(),
+        description: pattern.description.clone(),
+        requirements: Some(patterns_proto::pattern::Requirements {
+            capabilities: pattern.required_capabilities.clone(),
+            min_agents: pattern.config.min_agents.unwrap_or(0) as i32,
+            max_agents: pattern.config.max_agents.unwrap_or(0) as i32,
+            min_confidence: pattern.config.confidence_threshold.unwrap_or(0.0),
+        }),
+        metadata: pattern
+            .input_schema
+            .clone()
+            .map(|schema| json_to_struct(serde_json::json!({ "input_schema": schema }))),
+        ..Default::default()
+    }
+}
+
+fn execute_response(execution: &PatternExecution) -> ExecutePatternResponse {
+    let status = match execution.status {
+        ExecutionStatus::Completed => ProtoPatternStatus::Success,
+        ExecutionStatus::Failed => ProtoPatternStatus::Failure,
+        ExecutionStatus::Cancelled => ProtoPatternStatus::Cancelled,
+        ExecutionStatus::Pending | ExecutionStatus::Running => ProtoPatternStatus::Unknown,
+    };
+
+    ExecutePatternResponse {
+        execution_id: execution.id.clone(),
+        pattern_name: execution.pattern.clone(),
+        status: status as i32,
+        result: execution.output.clone().map(json_to_struct),
+        confidence: execution.confidence.unwrap_or_default(),
+        metrics: Some(ExecutionMetrics {
+            start_time: Some(to_timestamp(execution.start_time)),
+            end_time: execution.end_time.map(to_timestamp),
+            ..Default::default()
+        }),
+        error_message: execution.error.clone().unwrap_or_default(),
+        ..Default::default()
+    }
+}
+
+/// `StreamExecutePattern` message reporting `event_type` for `execution`
+fn event_response(
+    execution: &PatternExecution,
+    event_type: &str,
+    event_data: Option<Value>,
+) -> ExecutePatternResponse {
+    ExecutePatternResponse {
+        event_type: event_type.to_string(),
+        event_data: event_data.map(json_to_struct),
+        ..execute_response(execution)
+    }
+}
+
+fn event_to_proto(event: &ExecutionEvent) -> StreamExecutionResponse {
+    StreamExecutionResponse {
+        event_type: event.event_type.clone(),
+        execution: event.execution.as_ref().map(execution_to_proto),
+        event_time: event.event_time.map(to_timestamp),
+        event_data: event.event_data.clone().map(json_to_struct),
+    }
+}
+
+fn execution_to_proto(execution: &PatternExecution) -> Execution {
+    let status = match execution.status {
+        ExecutionStatus::Pending => ProtoExecutionStatus::Pending,
+        ExecutionStatus::Running => ProtoExecutionStatus::Running,
+        ExecutionStatus::Completed => ProtoExecutionStatus::Completed,
+        ExecutionStatus::Failed => ProtoExecutionStatus::Failed,
+        ExecutionStatus::Cancelled => ProtoExecutionStatus::Cancelled,
+    };
+
+    Execution {
+        id: execution.id.clone(),
+        pattern_name: execution.pattern.clone(),
+        status: status as i32,
+        start_time: Some(to_timestamp(execution.start_time)),
+        end_time: execution.end_time.map(to_timestamp),
+        input: Some(execution.input.clone())
+            .filter(|input| !input.is_null())
+            .map(json_to_struct),
+        result: execution.output.clone().map(json_to_struct),
+        error: execution.error.clone().unwrap_or_default(),
+        confidence: execution.confidence.unwrap_or_default(),
+        metrics: (!execution.metadata.is_empty()).then(|| execution.metadata.clone().into()),
+        agents: execution
+            .agents
+            .iter()
+            .map(|agent_id| ExecutionAgent {
+                agent_id: agent_id.clone(),
+                confidence: execution
+                    .agent_contributions
+                    .iter()
+                    .find(|contribution| &contribution.agent_id == agent_id)
+                    .map(|contribution| contribution.confidence),
+            })
+            .collect(),
+    }
+}
+
+/// Status name as used by the list filter
+fn status_name(status: ExecutionStatus) -> String {
+    serde_json::to_value(status)
+        .ok()
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn from_timestamp(timestamp: prost_types::Timestamp) -> DateTime<Utc> {
+    DateTime::from_timestamp(timestamp.seconds, timestamp.nanos as u32).unwrap_or_default()
+}
+
+fn to_timestamp(time: DateTime<Utc>) -> prost_types::Timestamp {
+    prost_types::Timestamp {
+        seconds: time.timestamp(),
+        nanos: time.timestamp_subsec_nanos() as i32,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        types::{AgentStatus, ExecutionFilter, Metadata, PatternConfig},
+        Client, Error,
+    };
+    use serde_json::json;
+
+    fn pattern(name: &str) -> Pattern {
+        Pattern {
+            name: name.to_string(),
+            description: format!("{} pattern", name),
+            enabled: true,
+            required_capabilities: vec!["analysis".to_string()],
+            config: PatternConfig {
+                min_agents: Some(2),
+                ..Default::default()
+            },
+            input_schema: None,
+        }
+    }
+
+    fn completed(confidence: f64) -> PatternExecution {
+        PatternExecution {
+            id: String::new(),
+            pattern: String::new(),
+            status: ExecutionStatus::Completed,
+            input: Value::Null,
+            output: Some(json!({ "answer": 42 })),
+            agents: Vec::new(),
+            agent_contributions: Vec::new(),
+            start_time: Utc::now(),
+            end_time: Some(Utc::now()),
+            duration_ms: None,
+            confidence: Some(confidence),
+            error: None,
+            metadata: Metadata::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_executes_enqueued_results_and_records_calls() {
+        let (client, control_plane) = Client::mock();
+        control_plane.add_pattern(pattern("consensus"));
+        control_plane.enqueue_execution("consensus", completed(0.9));
+
+        let patterns = client.patterns();
+        assert_eq!(patterns.get("consensus").await.unwrap().config.min_agents, Some(2));
+
+        let execution = patterns
+            .execute("consensus", json!({ "task": "review" }), None)
+            .await
+            .unwrap();
+        assert_eq!(execution.status, ExecutionStatus::Completed);
+        assert_eq!(execution.confidence, Some(0.9));
+        assert_eq!(execution.output.unwrap()["answer"], 42.0);
+
+        let calls = control_plane.calls_to("ExecutePattern");
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].target.as_deref(), Some("consensus"));
+        assert_eq!(calls[0].input, Some(json!({ "task": "review" })));
+
+        // The execution is then visible through the execution service
+        let stored = client.executions().get(&execution.id).await.unwrap();
+        assert_eq!(stored.pattern, "consensus");
+        let listed = client
+            .executions()
+            .list_filtered(10, 0, &ExecutionFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(listed.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_mock_reports_missing_and_unqueued_executions() {
+        let (client, control_plane) = Client::mock();
+        control_plane.add_pattern(pattern("consensus"));
+
+        let unknown = client.patterns().execute("debate", json!({}), None).await;
+        assert!(matches!(unknown.unwrap_err(), Error::NotFound(_)));
+
+        let unqueued = client.patterns().execute("consensus", json!({}), None).await;
+        assert!(unqueued.is_err());
+
+        control_plane.enqueue_execute_error("consensus", Status::unavailable("overloaded"));
+        let failed = client
+            .patterns()
+            .execute("consensus", json!({}), None)
+            .await
+            .unwrap_err();
+        assert!(failed.is_retryable());
+        control_plane.assert_called("ExecutePattern", 3);
+    }
+
+    #[tokio::test]
+    async fn test_mock_tracks_agents_and_injected_failures() {
+        let (client, control_plane) = Client::mock();
+        let agents = client.agents();
+
+        let registered = agents
+            .register(Agent {
+                id: "agent-1".to_string(),
+                name: "Agent One".to_string(),
+                status: AgentStatus::Active,
+                capabilities: vec!["analysis".to_string()],
+                endpoint: "localhost:50051".to_string(),
+                last_seen: Utc::now(),
+                confidence: 0.7,
+                metadata: Metadata::new(),
+            })
+            .await
+            .unwrap();
+        agents
+            .update_status(&registered.id, AgentStatus::Draining)
+            .await
+            .unwrap();
+        assert_eq!(control_plane.agents()[0].status, AgentStatus::Draining);
+        control_plane.assert_called("Register", 2);
+
+        control_plane.fail_next("ListAgents", Status::unavailable("restarting"));
+        assert!(agents.list().await.is_err());
+        assert_eq!(agents.list().await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,52 @@
+//! Transport the client's services send their calls over
+//!
+//! With the `native` feature (the default) this is tonic's HTTP/2
+//! [`Channel`](tonic::transport::Channel). Built with only the `wasm`
+//! feature, for `wasm32-unknown-unknown`, calls go out as grpc-web requests
+//! over the browser's `fetch` through `tonic-web-wasm-client`; the control
+//! plane, or a proxy in front of it, must accept grpc-web.
+
+use futures::future::BoxFuture;
+
+#[cfg(not(any(feature = "native", feature = "wasm")))]
+compile_error!("enable the `native` feature, or `wasm` for wasm32-unknown-unknown");
+
+#[cfg(feature = "native")]
+pub(crate) type Transport = tonic::transport::Channel;
+#[cfg(feature = "native")]
+pub(crate) type TransportBody = tonic::body::BoxBody;
+#[cfg(feature = "native")]
+pub(crate) type TransportError = tonic::transport::Error;
+
+#[cfg(not(feature = "native"))]
+pub(crate) type Transport = tonic_web_wasm_client::Client;
+#[cfg(not(feature = "native"))]
+pub(crate) type TransportBody = tonic_web_wasm_client::ResponseBody;
+#[cfg(not(feature = "native"))]
+pub(crate) type TransportError = tonic_web_wasm_client::Error;
+
+/// Response to a call sent over [`Transport`]
+pub(crate) type TransportResult =
+    std::result::Result<tonic::codegen::http::Response<TransportBody>, TransportError>;
+
+/// Box a call in flight on the transport
+#[cfg(feature = "native")]
+pub(crate) fn boxed<F>(call: F) -> BoxFuture<'static, TransportResult>
+where
+    F: std::future::Future<Output = TransportResult> + Send + 'static,
+{
+    Box::pin(call)
+}
+
+/// Box a call in flight on the transport
+///
+/// `fetch` futures are not `Send`. The browser runs them on its one thread,
+/// so wrapping them lets every service keep the `Send` futures and streams
+/// it has on native targets.
+#[cfg(not(feature = "native"))]
+pub(crate) fn boxed<F>(call: F) -> BoxFuture<'static, TransportResult>
+where
+    F: std::future::Future<Output = TransportResult> + 'static,
+{
+    Box::pin(send_wrapper::SendWrapper::new(call))
+}
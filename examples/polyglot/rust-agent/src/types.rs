@@ -1,5 +1,5 @@
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
 
@@ -11,21 +11,87 @@ pub struct Agent {
     pub status: AgentStatus,
     pub capabilities: Vec<String>,
     pub endpoint: String,
+    /// Last heartbeat the registry saw, or when the agent registered if it
+    /// reports none
     pub last_seen: DateTime<Utc>,
     pub confidence: f64,
     #[serde(default)]
-    pub metadata: HashMap<String, String>,
+    pub metadata: Metadata,
 }
 
 /// Agent status
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum AgentStatus {
+    #[default]
     Active,
+    /// Finishing in-flight work before going inactive
+    Draining,
     Inactive,
     Error,
 }
 
+impl AgentStatus {
+    /// Whether an agent in this status may move to `next`
+    ///
+    /// Staying in the same status is always allowed. Draining is only
+    /// entered from `Active` and only left for `Inactive` or `Error`.
+    pub fn can_transition_to(&self, next: AgentStatus) -> bool {
+        use AgentStatus::*;
+
+        matches!(
+            (self, next),
+            (Active, Active)
+                | (Active, Draining)
+                | (Active, Inactive)
+                | (Active, Error)
+                | (Draining, Draining)
+                | (Draining, Inactive)
+                | (Draining, Error)
+                | (Inactive, Inactive)
+                | (Inactive, Active)
+                | (Error, Error)
+                | (Error, Active)
+                | (Error, Inactive)
+        )
+    }
+
+    /// Name of the status, as serialized
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AgentStatus::Active => "active",
+            AgentStatus::Draining => "draining",
+            AgentStatus::Inactive => "inactive",
+            AgentStatus::Error => "error",
+        }
+    }
+}
+
+impl std::fmt::Display for AgentStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for AgentStatus {
+    type Err = crate::error::Error;
+
+    /// Parse a status name, ignoring case
+    fn from_str(name: &str) -> crate::error::Result<Self> {
+        [
+            AgentStatus::Active,
+            AgentStatus::Draining,
+            AgentStatus::Inactive,
+            AgentStatus::Error,
+        ]
+        .into_iter()
+        .find(|status| status.as_str().eq_ignore_ascii_case(name.trim()))
+        .ok_or_else(|| {
+            crate::error::Error::InvalidArgument(format!("unknown agent status {:?}", name))
+        })
+    }
+}
+
 /// Represents a coordination pattern
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Pattern {
@@ -34,6 +100,9 @@ pub struct Pattern {
     pub enabled: bool,
     pub required_capabilities: Vec<String>,
     pub config: PatternConfig,
+    /// JSON schema the execution input must satisfy, if the pattern declares one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_schema: Option<serde_json::Value>,
 }
 
 /// Pattern-specific configuration
@@ -53,16 +122,187 @@ pub struct PatternConfig {
     pub parameters: HashMap<String, serde_json::Value>,
 }
 
+impl PatternConfig {
+    /// Check the configuration is internally consistent
+    ///
+    /// Rejects `min_agents` above `max_agents`, thresholds outside
+    /// `[0.0, 1.0]` and a zero `timeout_ms`, so misconfigurations surface
+    /// before an execution is sent. Returns `Error::InvalidArgument`
+    /// describing the first problem found.
+    pub fn validate(&self) -> crate::error::Result<()> {
+        let invalid = |message: String| Err(crate::error::Error::InvalidArgument(message));
+
+        if let (Some(min), Some(max)) = (self.min_agents, self.max_agents) {
+            if min > max {
+                return invalid(format!(
+                    "min_agents ({}) must not exceed max_agents ({})",
+                    min, max
+                ));
+            }
+        }
+        let thresholds = [
+            ("consensus_threshold", self.consensus_threshold),
+            ("confidence_threshold", self.confidence_threshold),
+        ];
+        for (name, threshold) in thresholds {
+            if let Some(value) = threshold.filter(|value| !(0.0..=1.0).contains(value)) {
+                return invalid(format!("{} must be between 0.0 and 1.0, got {}", name, value));
+            }
+        }
+        if self.timeout_ms == Some(0) {
+            return invalid("timeout_ms must be greater than 0 when set".to_string());
+        }
+        Ok(())
+    }
+
+    /// Deserialize `parameters` into a typed config such as [`ConsensusConfig`]
+    /// or [`MapReduceConfig`]. Returns `Error::InvalidArgument` if the
+    /// parameters don't have the expected shape.
+    pub fn typed<T: DeserializeOwned>(&self) -> crate::error::Result<T> {
+        let parameters = serde_json::Value::Object(
+            self.parameters
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+        );
+
+        serde_json::from_value(parameters).map_err(|error| {
+            let type_name = std::any::type_name::<T>();
+            crate::error::Error::InvalidArgument(format!(
+                "pattern parameters do not match {}: {}",
+                type_name.rsplit("::").next().unwrap_or(type_name),
+                error
+            ))
+        })
+    }
+}
+
+impl Pattern {
+    /// Check whether `agents` can run this pattern
+    ///
+    /// Only active agents count. Every required capability must be offered
+    /// by at least one of them, and at least `min_agents` (one if unset)
+    /// must offer a required capability, or any capability if the pattern
+    /// requires none. Returns the first unmet requirement, so callers can
+    /// tell users why a pattern can't run before executing it.
+    pub fn is_executable_by(&self, agents: &[Agent]) -> Result<(), UnmetRequirement> {
+        let active: Vec<&Agent> = agents
+            .iter()
+            .filter(|agent| agent.status == AgentStatus::Active)
+            .collect();
+        let offers = |agent: &Agent, capability: &str| {
+            agent.capabilities.iter().any(|offered| offered == capability)
+        };
+
+        if let Some(capability) = self
+            .required_capabilities
+            .iter()
+            .find(|capability| !active.iter().any(|agent| offers(agent, capability)))
+        {
+            return Err(UnmetRequirement::MissingCapability {
+                capability: capability.clone(),
+            });
+        }
+
+        let required = self.config.min_agents.unwrap_or(1);
+        let available = active
+            .iter()
+            .filter(|agent| {
+                self.required_capabilities.is_empty()
+                    || self
+                        .required_capabilities
+                        .iter()
+                        .any(|capability| offers(agent, capability))
+            })
+            .count();
+        if available < required as usize {
+            return Err(UnmetRequirement::TooFewAgents {
+                required,
+                available,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Why a set of agents can't run a pattern; see [`Pattern::is_executable_by`]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum UnmetRequirement {
+    /// No active agent offers a required capability
+    #[error("no active agent offers capability {capability}")]
+    MissingCapability { capability: String },
+    /// Fewer active, capable agents than the pattern's `min_agents`
+    #[error("only {available} of {required} required agents are available")]
+    TooFewAgents { required: u32, available: usize },
+}
+
+/// Parameters of the `ConsensusBuilder` pattern
+///
+/// Unknown keys are rejected rather than ignored, so a misspelled or
+/// unsupported parameter surfaces as an error from [`PatternConfig::typed`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ConsensusConfig {
+    /// Fraction of agents that must agree for consensus (0.0-1.0)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consensus_threshold: Option<f64>,
+    /// Minimum confidence for an agent's result to count
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_confidence: Option<f64>,
+}
+
+/// Name of the map-reduce pattern
+pub const MAP_REDUCE_PATTERN: &str = "UncertaintyMapReduce";
+
+/// Parameters of the map-reduce pattern
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MapReduceConfig {
+    /// Number of input items handed to each mapping agent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunk_size: Option<u32>,
+    /// Instruction given to mapping agents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub map_function: Option<String>,
+    /// Instruction used to combine mapped results
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reduce_function: Option<String>,
+    /// Minimum confidence for a mapped result to be reduced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_map_confidence: Option<f64>,
+}
+
 /// Represents the execution of a pattern
-#[derive(Debug, Clone, Serialize, Deserialize)]
+///
+/// # JSON form
+///
+/// Serializes to an object with the field names below. `status` is the
+/// lowercase status name and timestamps are RFC 3339 strings in UTC with
+/// their full sub-second precision, so every value round-trips exactly.
+///
+/// Optional fields are omitted when unset, and an explicit `null` reads back
+/// as unset too — except for `output`, where `null` is the JSON null a
+/// pattern produced (`Some(Value::Null)`) and only an absent key means no
+/// output. `metadata` and `agent_contributions` may be omitted when empty.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct PatternExecution {
     pub id: String,
     pub pattern: String,
     pub status: ExecutionStatus,
     pub input: serde_json::Value,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(
+        default,
+        deserialize_with = "present_value",
+        skip_serializing_if = "Option::is_none"
+    )]
     pub output: Option<serde_json::Value>,
+    /// Ids of the agents that took part, in the order the control plane
+    /// reported them
     pub agents: Vec<String>,
+    /// Confidences of the agents in `agents` whose confidence the control
+    /// plane reported; an agent without one has no entry
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub agent_contributions: Vec<AgentContribution>,
     pub start_time: DateTime<Utc>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub end_time: Option<DateTime<Utc>>,
@@ -73,12 +313,188 @@ pub struct PatternExecution {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
     #[serde(default)]
-    pub metadata: HashMap<String, serde_json::Value>,
+    pub metadata: Metadata,
+}
+
+impl PatternExecution {
+    /// Read the output of a `ConsensusBuilder` execution
+    ///
+    /// Fails with `Error::InvalidArgument` if the execution ran another
+    /// pattern or has no output yet, and with a serialization error if the
+    /// output isn't a [`PatternOutput`] holding a [`ConsensusResult`].
+    pub fn as_consensus(&self) -> crate::error::Result<PatternOutput<ConsensusResult>> {
+        self.output_of(crate::client::CONSENSUS_PATTERN)
+    }
+
+    /// Read the output of an `UncertaintyMapReduce` execution, failing as
+    /// [`as_consensus`](Self::as_consensus) does
+    pub fn as_map_reduce(&self) -> crate::error::Result<PatternOutput<MapReduceResult>> {
+        self.output_of(MAP_REDUCE_PATTERN)
+    }
+
+    fn output_of<T: DeserializeOwned>(
+        &self,
+        pattern: &str,
+    ) -> crate::error::Result<PatternOutput<T>> {
+        if self.pattern != pattern {
+            return Err(crate::error::Error::InvalidArgument(format!(
+                "execution {} ran pattern {}, not {}",
+                self.id, self.pattern, pattern
+            )));
+        }
+        let output = self.output.as_ref().ok_or_else(|| {
+            crate::error::Error::InvalidArgument(format!("execution {} has no output", self.id))
+        })?;
+        PatternOutput::deserialize(output).map_err(|error| {
+            crate::error::Error::from(error)
+                .with_context("execution_id", self.id.as_str())
+                .with_context("pattern", pattern)
+        })
+    }
+}
+
+/// Result the pattern engine records for a completed execution: the
+/// pattern's value with the confidence it reported
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PatternOutput<T> {
+    pub value: T,
+    /// Confidence the pattern reported for `value` (0.0-1.0)
+    pub confidence: f64,
+    /// When the pattern finished, if the engine's timestamp survived
+    /// conversion to a protobuf `Struct`
+    #[serde(default, deserialize_with = "lenient_timestamp")]
+    pub executed_at: Option<DateTime<Utc>>,
+}
+
+/// Value of the `ConsensusBuilder` pattern; see
+/// [`PatternExecution::as_consensus`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConsensusResult {
+    /// `strong_consensus`, `moderate_consensus` or `weak_consensus`
+    #[serde(rename = "type")]
+    pub consensus_type: String,
+    /// Expertise-weighted confidence of the agents (0.0-1.0)
+    pub confidence: f64,
+    /// Human-readable summary of how strongly the agents agree
+    pub message: String,
+    /// Recommendation of the most confident agent
+    pub recommendation: String,
+    pub agent_count: u32,
+    pub successful_agents: u32,
+    /// Agents that answered with confidence above 0.8
+    pub high_confidence_agents: u32,
+    /// Each agent's result as the engine reported it
+    #[serde(default)]
+    pub results: Vec<serde_json::Value>,
+}
+
+/// Value of the `UncertaintyMapReduce` pattern; see
+/// [`PatternExecution::as_map_reduce`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MapReduceResult {
+    /// The reduced result
+    pub value: MapReduceValue,
+    /// Confidence in the reduced result (0.0-1.0)
+    pub confidence: f64,
+    pub processing: MapReduceProcessing,
+    /// Advice on whether the result is good enough to use
+    pub strategy: String,
+}
+
+/// Reduced result of an `UncertaintyMapReduce` execution
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MapReduceValue {
+    /// The mapped results that were combined
+    pub combined: Vec<serde_json::Value>,
+    /// `good`, `partial` or `poor`, by how many maps cleared 0.7 confidence
+    pub map_quality: String,
+    pub processed_chunks: u32,
+}
+
+/// How the map phase of an `UncertaintyMapReduce` execution went
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MapReduceProcessing {
+    pub chunks: u32,
+    pub avg_map_confidence: f64,
+    pub successful_maps: u32,
+    pub map_quality: String,
+}
+
+/// One agent's part in a [`PatternExecution`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AgentContribution {
+    pub agent_id: String,
+    /// Confidence of the agent's own result
+    pub confidence: f64,
+}
+
+/// Read an RFC 3339 timestamp, treating anything else as absent
+fn lenient_timestamp<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<DateTime<Utc>>, D::Error> {
+    let value = serde_json::Value::deserialize(deserializer)?;
+    Ok(value
+        .as_str()
+        .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+        .map(|timestamp| timestamp.with_timezone(&Utc)))
+}
+
+/// Read a present field as `Some`, keeping an explicit JSON `null`
+fn present_value<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<serde_json::Value>, D::Error> {
+    serde_json::Value::deserialize(deserializer).map(Some)
+}
+
+/// Change to the registered agents, as streamed by
+/// [`AgentService::stream_agents`](crate::AgentService::stream_agents)
+#[derive(Debug, Clone)]
+pub enum AgentEvent {
+    Added(Agent),
+    Modified(Agent),
+    /// The agent unregistered or its lease expired; it is reported with
+    /// status `Inactive`
+    Deleted(Agent),
+}
+
+impl AgentEvent {
+    /// The agent the event is about
+    pub fn agent(&self) -> &Agent {
+        match self {
+            AgentEvent::Added(agent) | AgentEvent::Modified(agent) | AgentEvent::Deleted(agent) => {
+                agent
+            }
+        }
+    }
+
+    pub fn into_agent(self) -> Agent {
+        match self {
+            AgentEvent::Added(agent) | AgentEvent::Modified(agent) | AgentEvent::Deleted(agent) => {
+                agent
+            }
+        }
+    }
+}
+
+/// Change to the pattern catalog, as streamed by
+/// [`PatternService::subscribe_catalog`](crate::PatternService::subscribe_catalog)
+#[derive(Debug, Clone)]
+pub enum PatternCatalogEvent {
+    Added(Pattern),
+    Updated(Pattern),
+    /// The pattern with this name was removed
+    Removed(String),
 }
 
 /// Represents a streamed execution event
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExecutionEvent {
+    /// Event type as sent by the control plane; see [`ExecutionEvent::kind`]
     pub event_type: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub execution: Option<PatternExecution>,
@@ -88,14 +504,172 @@ pub struct ExecutionEvent {
     pub event_data: Option<serde_json::Value>,
 }
 
+impl ExecutionEvent {
+    /// The event type, parsed
+    pub fn kind(&self) -> ExecutionEventKind {
+        ExecutionEventKind::from(self.event_type.as_str())
+    }
+
+    /// Deserialize `event_data` into `T`, or `None` if the event carries no
+    /// data. See [`ExecutionEventKind`] for the payload each kind carries.
+    pub fn data_as<T: DeserializeOwned>(&self) -> crate::error::Result<Option<T>> {
+        match &self.event_data {
+            Some(data) => Ok(Some(T::deserialize(data)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Kind of an [`ExecutionEvent`]
+///
+/// The doc of each kind gives the `event_data` the control plane sends with
+/// it, for reading through [`ExecutionEvent::data_as`]. Keys are camelCase
+/// and other keys may be added over time. A control plane that can only
+/// poll the execution, rather than follow the pattern engine's events,
+/// sends `{}` for every kind but `Started`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ExecutionEventKind {
+    /// First event of a stream, carrying the execution as it stood.
+    /// Data: `{ "source": "snapshot" }` when replayed from stored state,
+    /// otherwise `{ "patternName": string }`.
+    Started,
+    /// Agents finished during a fan-out. Data:
+    /// `{ "total": number, "completed": number, "failed": number }`.
+    Progress,
+    /// The execution changed without finishing. Data: `{}`.
+    Updated,
+    /// Data: `{ "patternName": string, "confidence": number, "durationMs":
+    /// number, "agentCount": number, "prUrl"?: string }`, where `prUrl` is
+    /// present when the execution opened a pull request. The result is on
+    /// the event's `execution`.
+    Completed,
+    /// Data: `{ "patternName": string, "error": string }`.
+    Failed,
+    /// Data: `{}`.
+    Cancelled,
+    /// An event type this SDK does not know, kept verbatim, such as
+    /// `agent_completed` with `{ "agentId", "agentName", "confidence" }`
+    Other(String),
+}
+
+impl ExecutionEventKind {
+    /// Wire name of the kind
+    pub fn as_str(&self) -> &str {
+        match self {
+            ExecutionEventKind::Started => "started",
+            ExecutionEventKind::Progress => "progress",
+            ExecutionEventKind::Updated => "updated",
+            ExecutionEventKind::Completed => "completed",
+            ExecutionEventKind::Failed => "failed",
+            ExecutionEventKind::Cancelled => "cancelled",
+            ExecutionEventKind::Other(event_type) => event_type,
+        }
+    }
+
+    /// Whether the event reports the execution finishing
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            ExecutionEventKind::Completed | ExecutionEventKind::Failed | ExecutionEventKind::Cancelled
+        )
+    }
+
+    /// Kind of the event reporting an execution that reached `status`
+    pub fn for_status(status: ExecutionStatus) -> Self {
+        match status {
+            ExecutionStatus::Completed => ExecutionEventKind::Completed,
+            ExecutionStatus::Failed => ExecutionEventKind::Failed,
+            ExecutionStatus::Cancelled => ExecutionEventKind::Cancelled,
+            ExecutionStatus::Pending | ExecutionStatus::Running => ExecutionEventKind::Updated,
+        }
+    }
+}
+
+impl From<&str> for ExecutionEventKind {
+    fn from(event_type: &str) -> Self {
+        match event_type {
+            "started" => ExecutionEventKind::Started,
+            "progress" => ExecutionEventKind::Progress,
+            "updated" => ExecutionEventKind::Updated,
+            "completed" => ExecutionEventKind::Completed,
+            "failed" => ExecutionEventKind::Failed,
+            "cancelled" => ExecutionEventKind::Cancelled,
+            other => ExecutionEventKind::Other(other.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for ExecutionEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// Execution status
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
 #[serde(rename_all = "lowercase")]
 pub enum ExecutionStatus {
+    #[default]
     Pending,
     Running,
     Completed,
     Failed,
+    Cancelled,
+}
+
+/// Criteria for listing executions
+///
+/// Every criterion is sent to the control plane, which applies them before
+/// paginating. Time bounds are inclusive.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ExecutionFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<ExecutionStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_after: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub started_before: Option<DateTime<Utc>>,
+}
+
+impl ExecutionFilter {
+    /// Check that the filter describes a non-empty time range
+    pub fn validate(&self) -> crate::error::Result<()> {
+        if let (Some(after), Some(before)) = (self.started_after, self.started_before) {
+            if after > before {
+                return Err(crate::error::Error::InvalidArgument(format!(
+                    "started_after ({}) must not be later than started_before ({})",
+                    after, before
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether an execution satisfies every criterion in the filter
+    pub fn matches(&self, execution: &PatternExecution) -> bool {
+        self.status.is_none_or(|status| execution.status == status)
+            && self
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| &execution.pattern == pattern)
+            && self
+                .started_after
+                .is_none_or(|after| execution.start_time >= after)
+            && self
+                .started_before
+                .is_none_or(|before| execution.start_time <= before)
+    }
+}
+
+/// A page of results plus the cursor for fetching the next one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Opaque cursor for the following page; `None` on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
 /// Options for pattern execution
@@ -110,9 +684,126 @@ pub struct ExecuteOptions {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub agent_selector: Option<AgentSelector>,
     #[serde(default)]
-    pub metadata: HashMap<String, serde_json::Value>,
+    pub metadata: Metadata,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub trace_id: Option<String>,
+    /// Check the input against the pattern's schema before executing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub validate: Option<bool>,
+    /// Reject completed executions whose confidence is below this (0.0-1.0)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_confidence: Option<f64>,
+    /// Key the control plane uses to recognise a repeated request, sent as
+    /// the `idempotency-key` gRPC metadata entry. A control plane that has
+    /// already started an execution for the key returns that execution
+    /// instead of running the pattern again, so resend with the same key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<String>,
+}
+
+impl ExecuteOptions {
+    /// Lowest priority accepted by [`ExecuteOptionsBuilder::build`]
+    pub const MIN_PRIORITY: i32 = 0;
+    /// Highest priority accepted by [`ExecuteOptionsBuilder::build`]
+    pub const MAX_PRIORITY: i32 = 100;
+
+    /// Start building options fluently
+    pub fn builder() -> ExecuteOptionsBuilder {
+        ExecuteOptionsBuilder::default()
+    }
+
+    /// Check `min_confidence`, if set, is within 0.0-1.0
+    pub(crate) fn validate_min_confidence(&self) -> crate::error::Result<()> {
+        match self.min_confidence {
+            Some(min) if !(0.0..=1.0).contains(&min) => Err(crate::error::Error::InvalidArgument(
+                format!("min_confidence {} is outside 0..=1", min),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Fluent builder for [`ExecuteOptions`]
+#[derive(Debug, Clone, Default)]
+pub struct ExecuteOptionsBuilder {
+    options: ExecuteOptions,
+}
+
+impl ExecuteOptionsBuilder {
+    /// Overall execution timeout
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.options.timeout_ms = Some(timeout.as_millis().min(u64::MAX as u128) as u64);
+        self
+    }
+
+    /// Scheduling priority, between `MIN_PRIORITY` and `MAX_PRIORITY`
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.options.priority = Some(priority);
+        self
+    }
+
+    /// Return immediately instead of waiting for the execution to finish
+    pub fn async_execution(mut self, async_execution: bool) -> Self {
+        self.options.async_execution = Some(async_execution);
+        self
+    }
+
+    /// Criteria for choosing the participating agents
+    pub fn agent_selector(mut self, selector: AgentSelector) -> Self {
+        self.options.agent_selector = Some(selector);
+        self
+    }
+
+    /// Trace id to correlate the execution with the caller's spans
+    pub fn trace_id(mut self, trace_id: impl Into<String>) -> Self {
+        self.options.trace_id = Some(trace_id.into());
+        self
+    }
+
+    /// Validate the input against the pattern's schema before executing
+    pub fn validate(mut self, validate: bool) -> Self {
+        self.options.validate = Some(validate);
+        self
+    }
+
+    /// Reject completed executions with a confidence below `min_confidence`
+    pub fn min_confidence(mut self, min_confidence: f64) -> Self {
+        self.options.min_confidence = Some(min_confidence);
+        self
+    }
+
+    /// Key that makes resending the same execute safe
+    pub fn idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.options.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// Add a metadata entry, replacing any previous value for `key`
+    pub fn metadata(mut self, key: impl Into<String>, value: impl Into<serde_json::Value>) -> Self {
+        self.options.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Validate and return the options
+    pub fn build(self) -> crate::error::Result<ExecuteOptions> {
+        if let Some(priority) = self.options.priority {
+            if !(ExecuteOptions::MIN_PRIORITY..=ExecuteOptions::MAX_PRIORITY).contains(&priority) {
+                return Err(crate::error::Error::InvalidArgument(format!(
+                    "priority {} is outside {}..={}",
+                    priority,
+                    ExecuteOptions::MIN_PRIORITY,
+                    ExecuteOptions::MAX_PRIORITY
+                )));
+            }
+        }
+        if self.options.timeout_ms == Some(0) {
+            return Err(crate::error::Error::InvalidArgument(
+                "timeout must be at least one millisecond".to_string(),
+            ));
+        }
+        self.options.validate_min_confidence()?;
+        Ok(self.options)
+    }
 }
 
 /// Agent selection criteria
@@ -131,6 +822,10 @@ pub struct AgentSelector {
 }
 
 /// Agent selection strategy
+///
+/// Sent to the control plane as a hint in the execute request's
+/// `agent_selector` context entry. The control plane does not act on it
+/// yet: agents are still chosen by the pattern's own rules.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum SelectionStrategy {
@@ -138,35 +833,136 @@ pub enum SelectionStrategy {
     RoundRobin,
     BestFit,
     All,
+    /// Ask for agents picked with probability proportional to their reported
+    /// confidence. Not implemented by the control plane, which currently
+    /// ignores it like the other strategies.
+    WeightedByConfidence,
 }
 
-impl Default for AgentStatus {
-    fn default() -> Self {
-        AgentStatus::Active
+impl ExecutionStatus {
+    /// Whether the execution has finished and will not change further
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            ExecutionStatus::Completed | ExecutionStatus::Failed | ExecutionStatus::Cancelled
+        )
+    }
+
+    /// Name of the status, as serialized
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExecutionStatus::Pending => "pending",
+            ExecutionStatus::Running => "running",
+            ExecutionStatus::Completed => "completed",
+            ExecutionStatus::Failed => "failed",
+            ExecutionStatus::Cancelled => "cancelled",
+        }
     }
 }
 
-impl Default for ExecutionStatus {
-    fn default() -> Self {
-        ExecutionStatus::Pending
+impl std::fmt::Display for ExecutionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
     }
 }
 
+impl std::str::FromStr for ExecutionStatus {
+    type Err = crate::error::Error;
+
+    /// Parse a status name, ignoring case
+    fn from_str(name: &str) -> crate::error::Result<Self> {
+        [
+            ExecutionStatus::Pending,
+            ExecutionStatus::Running,
+            ExecutionStatus::Completed,
+            ExecutionStatus::Failed,
+            ExecutionStatus::Cancelled,
+        ]
+        .into_iter()
+        .find(|status| status.as_str().eq_ignore_ascii_case(name.trim()))
+        .ok_or_else(|| {
+            crate::error::Error::InvalidArgument(format!("unknown execution status {:?}", name))
+        })
+    }
+}
+
+/// Trim and dedupe capabilities, keeping the first occurrence of each
+///
+/// Blank entries are dropped. With `lowercase`, capabilities differing only
+/// in case are merged too.
+pub fn normalize_capabilities<I, S>(capabilities: I, lowercase: bool) -> Vec<String>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut normalized: Vec<String> = Vec::new();
+    for capability in capabilities {
+        let capability = capability.as_ref().trim();
+        let capability = if lowercase {
+            capability.to_lowercase()
+        } else {
+            capability.to_string()
+        };
+        if !capability.is_empty() && !normalized.contains(&capability) {
+            normalized.push(capability);
+        }
+    }
+    normalized
+}
+
+/// [`normalize_capabilities`], failing with `Error::InvalidArgument` on a
+/// blank entry instead of dropping it
+pub(crate) fn checked_capabilities<I, S>(
+    capabilities: I,
+    lowercase: bool,
+) -> crate::error::Result<Vec<String>>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let capabilities: Vec<S> = capabilities.into_iter().collect();
+    if let Some(position) = capabilities
+        .iter()
+        .position(|capability| capability.as_ref().trim().is_empty())
+    {
+        return Err(crate::error::Error::InvalidArgument(format!(
+            "capability {} is empty",
+            position
+        )));
+    }
+    Ok(normalize_capabilities(capabilities, lowercase))
+}
+
 impl Agent {
     /// Create a new agent
+    ///
+    /// Capabilities are trimmed and deduplicated, and blank ones dropped;
+    /// use [`with_capabilities`](Self::with_capabilities) to reject blanks
+    /// instead.
     pub fn new(name: impl Into<String>, capabilities: Vec<String>) -> Self {
         Self {
             id: Uuid::new_v4().to_string(),
             name: name.into(),
             status: AgentStatus::Active,
-            capabilities,
+            capabilities: normalize_capabilities(capabilities, false),
             endpoint: String::new(),
             last_seen: Utc::now(),
             confidence: 0.8,
-            metadata: HashMap::new(),
+            metadata: Metadata::default(),
         }
     }
     
+    /// Replace the capabilities, trimmed and deduplicated. Returns
+    /// `Error::InvalidArgument` if any is blank.
+    pub fn with_capabilities<I, S>(mut self, capabilities: I) -> crate::error::Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.capabilities = checked_capabilities(capabilities, false)?;
+        Ok(self)
+    }
+
     /// Set the agent endpoint
     pub fn with_endpoint(mut self, endpoint: impl Into<String>) -> Self {
         self.endpoint = endpoint.into();
@@ -174,8 +970,732 @@ impl Agent {
     }
     
     /// Add metadata
-    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
-        self.metadata.insert(key.into(), value.into());
+    pub fn with_metadata(
+        mut self,
+        key: impl Into<String>,
+        value: impl Into<serde_json::Value>,
+    ) -> Self {
+        self.metadata.insert(key, value);
         self
     }
 }
+
+/// Metadata attached to agents and executions
+///
+/// Values keep their JSON types, so a numeric metric stays a number and can
+/// be read back with [`get_f64`](Self::get_f64). Derefs to the underlying
+/// map and serializes as a plain JSON object.
+///
+/// Execution metadata travels as a protobuf `Struct` (see the `From`
+/// impls), which preserves numbers, booleans and nesting. Agent metadata
+/// travels as registry labels, which are strings only: non-string values are
+/// sent JSON-encoded and labels always read back as strings, so the getters
+/// also accept a string holding a number or boolean.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Metadata(HashMap<String, serde_json::Value>);
+
+impl Metadata {
+    /// Create empty metadata
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set `key` to `value`, returning the previous value
+    pub fn insert(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<serde_json::Value>,
+    ) -> Option<serde_json::Value> {
+        self.0.insert(key.into(), value.into())
+    }
+
+    /// Value of `key` as a number, also parsing numeric strings
+    pub fn get_f64(&self, key: &str) -> Option<f64> {
+        match self.0.get(key)? {
+            serde_json::Value::Number(number) => number.as_f64(),
+            serde_json::Value::String(text) => text.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Value of `key` if it is a string
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.0.get(key)?.as_str()
+    }
+
+    /// Value of `key` as a boolean, also accepting `"true"` and `"false"`
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.0.get(key)? {
+            serde_json::Value::Bool(flag) => Some(*flag),
+            serde_json::Value::String(text) => text.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// Read registry labels; every value comes back as a string
+    pub fn from_labels(labels: HashMap<String, String>) -> Self {
+        labels
+            .into_iter()
+            .map(|(key, value)| (key, serde_json::Value::String(value)))
+            .collect()
+    }
+
+    /// Encode as registry labels: strings as-is, anything else as JSON
+    pub fn to_labels(&self) -> HashMap<String, String> {
+        self.0
+            .iter()
+            .map(|(key, value)| {
+                let label = match value {
+                    serde_json::Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                };
+                (key.clone(), label)
+            })
+            .collect()
+    }
+
+    /// The underlying map
+    pub fn into_inner(self) -> HashMap<String, serde_json::Value> {
+        self.0
+    }
+}
+
+impl std::ops::Deref for Metadata {
+    type Target = HashMap<String, serde_json::Value>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::ops::DerefMut for Metadata {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl From<HashMap<String, serde_json::Value>> for Metadata {
+    fn from(map: HashMap<String, serde_json::Value>) -> Self {
+        Self(map)
+    }
+}
+
+impl FromIterator<(String, serde_json::Value)> for Metadata {
+    fn from_iter<I: IntoIterator<Item = (String, serde_json::Value)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl IntoIterator for Metadata {
+    type Item = (String, serde_json::Value);
+    type IntoIter = std::collections::hash_map::IntoIter<String, serde_json::Value>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+impl From<prost_types::Struct> for Metadata {
+    fn from(value: prost_types::Struct) -> Self {
+        value
+            .fields
+            .into_iter()
+            .map(|(key, value)| (key, crate::conversions::prost_value_to_json(value)))
+            .collect()
+    }
+}
+
+impl From<Metadata> for prost_types::Struct {
+    fn from(metadata: Metadata) -> Self {
+        prost_types::Struct {
+            fields: metadata
+                .into_iter()
+                .map(|(key, value)| (key, crate::conversions::json_to_prost_value(value)))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finished(pattern: &str, output: serde_json::Value) -> PatternExecution {
+        PatternExecution {
+            id: "exec-1".to_string(),
+            pattern: pattern.to_string(),
+            status: ExecutionStatus::Completed,
+            input: serde_json::json!({}),
+            output: Some(output),
+            agents: vec![],
+            agent_contributions: vec![],
+            start_time: Utc::now(),
+            end_time: None,
+            duration_ms: None,
+            confidence: None,
+            error: None,
+            metadata: Metadata::default(),
+        }
+    }
+
+    #[test]
+    fn test_consensus_output_reads_as_typed_result() {
+        // As recorded by the pattern engine for the ConsensusBuilder module
+        let execution = finished(
+            crate::client::CONSENSUS_PATTERN,
+            serde_json::json!({
+                "value": {
+                    "type": "strong_consensus",
+                    "confidence": 0.85,
+                    "message": "Strong agreement among experts",
+                    "recommendation": "approve",
+                    "agentCount": 3,
+                    "successfulAgents": 3,
+                    "highConfidenceAgents": 3,
+                    "results": [{"agentId": "a", "confidence": 0.9}],
+                },
+                "confidence": 0.85,
+                "executedAt": "2024-05-01T12:00:00.000Z",
+            }),
+        );
+
+        let consensus = execution.as_consensus().unwrap();
+        assert_eq!(consensus.confidence, 0.85);
+        assert_eq!(
+            consensus.executed_at,
+            Some("2024-05-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap())
+        );
+        assert_eq!(consensus.value.consensus_type, "strong_consensus");
+        assert_eq!(consensus.value.recommendation, "approve");
+        assert_eq!(consensus.value.agent_count, 3);
+        assert_eq!(consensus.value.results.len(), 1);
+        assert!(matches!(
+            execution.as_map_reduce(),
+            Err(crate::error::Error::InvalidArgument(_))
+        ));
+
+        let mismatched = finished(
+            crate::client::CONSENSUS_PATTERN,
+            serde_json::json!({"result": "positive", "confidence": 0.85}),
+        );
+        let error = mismatched.as_consensus().unwrap_err();
+        assert!(matches!(error.root_cause(), crate::error::Error::Serialization(_)));
+        assert!(error.context().contains(&("execution_id".to_string(), "exec-1".to_string())));
+    }
+
+    #[test]
+    fn test_map_reduce_output_reads_as_typed_result() {
+        // A Date in the engine's result may not survive as a string
+        let reduced = finished(
+            MAP_REDUCE_PATTERN,
+            serde_json::json!({
+                "value": {
+                    "value": {"combined": [1, 2], "mapQuality": "good", "processedChunks": 2},
+                    "confidence": 0.83,
+                    "processing": {
+                        "chunks": 2,
+                        "avgMapConfidence": 0.8,
+                        "successfulMaps": 2,
+                        "mapQuality": "good",
+                    },
+                    "strategy": "Processing successful",
+                },
+                "confidence": 0.83,
+                "executedAt": {},
+            }),
+        );
+
+        let output = reduced.as_map_reduce().unwrap();
+        assert_eq!(output.executed_at, None);
+        assert_eq!(output.value.value.combined, vec![serde_json::json!(1), serde_json::json!(2)]);
+        assert_eq!(output.value.processing.successful_maps, 2);
+        assert_eq!(output.value.strategy, "Processing successful");
+    }
+
+    #[test]
+    fn test_statuses_round_trip_through_display_and_from_str() {
+        for status in [
+            AgentStatus::Active,
+            AgentStatus::Draining,
+            AgentStatus::Inactive,
+            AgentStatus::Error,
+        ] {
+            let name = status.to_string();
+            assert_eq!(serde_json::to_value(status).unwrap(), name.as_str());
+            assert_eq!(name.parse::<AgentStatus>().unwrap(), status);
+            assert_eq!(name.to_uppercase().parse::<AgentStatus>().unwrap(), status);
+        }
+        for status in [
+            ExecutionStatus::Pending,
+            ExecutionStatus::Running,
+            ExecutionStatus::Completed,
+            ExecutionStatus::Failed,
+            ExecutionStatus::Cancelled,
+        ] {
+            let name = status.to_string();
+            assert_eq!(serde_json::to_value(status).unwrap(), name.as_str());
+            assert_eq!(name.parse::<ExecutionStatus>().unwrap(), status);
+            assert_eq!(name.to_uppercase().parse::<ExecutionStatus>().unwrap(), status);
+        }
+
+        for garbage in ["", "done", "complete d", "0"] {
+            assert!(matches!(
+                garbage.parse::<AgentStatus>(),
+                Err(crate::error::Error::InvalidArgument(_))
+            ));
+            assert!(matches!(
+                garbage.parse::<ExecutionStatus>(),
+                Err(crate::error::Error::InvalidArgument(_))
+            ));
+        }
+    }
+
+    fn config_with(parameters: serde_json::Value) -> PatternConfig {
+        PatternConfig {
+            parameters: serde_json::from_value(parameters).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_validate_accepts_consistent_config() {
+        let config = PatternConfig {
+            min_agents: Some(3),
+            max_agents: Some(3),
+            consensus_threshold: Some(1.0),
+            confidence_threshold: Some(0.0),
+            timeout_ms: Some(1),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert!(PatternConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_rejects_inconsistent_config() {
+        let cases = [
+            (
+                PatternConfig {
+                    min_agents: Some(5),
+                    max_agents: Some(2),
+                    ..Default::default()
+                },
+                "min_agents (5) must not exceed max_agents (2)",
+            ),
+            (
+                PatternConfig {
+                    consensus_threshold: Some(1.5),
+                    ..Default::default()
+                },
+                "consensus_threshold must be between 0.0 and 1.0, got 1.5",
+            ),
+            (
+                PatternConfig {
+                    confidence_threshold: Some(-0.1),
+                    ..Default::default()
+                },
+                "confidence_threshold must be between 0.0 and 1.0, got -0.1",
+            ),
+            (
+                PatternConfig {
+                    consensus_threshold: Some(f64::NAN),
+                    ..Default::default()
+                },
+                "consensus_threshold must be between 0.0 and 1.0, got NaN",
+            ),
+            (
+                PatternConfig {
+                    timeout_ms: Some(0),
+                    ..Default::default()
+                },
+                "timeout_ms must be greater than 0 when set",
+            ),
+        ];
+
+        for (config, expected) in cases {
+            match config.validate() {
+                Err(crate::error::Error::InvalidArgument(message)) => assert_eq!(message, expected),
+                other => panic!("expected InvalidArgument for {:?}, got {:?}", config, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_typed_parameters_deserialize() {
+        let config = config_with(serde_json::json!({
+            "consensusThreshold": 0.75,
+            "minConfidence": 0.6
+        }));
+
+        assert_eq!(
+            config.typed::<ConsensusConfig>().unwrap(),
+            ConsensusConfig {
+                consensus_threshold: Some(0.75),
+                min_confidence: Some(0.6),
+            }
+        );
+
+        let config = config_with(serde_json::json!({ "chunkSize": 3, "mapFunction": "summarize" }));
+        let map_reduce = config.typed::<MapReduceConfig>().unwrap();
+        assert_eq!(map_reduce.chunk_size, Some(3));
+        assert_eq!(map_reduce.map_function.as_deref(), Some("summarize"));
+    }
+
+    #[test]
+    fn test_typed_parameters_reject_malformed_shape() {
+        let config = config_with(serde_json::json!({ "chunkSize": "three" }));
+
+        let error = config.typed::<MapReduceConfig>().unwrap_err();
+        match error {
+            crate::error::Error::InvalidArgument(message) => {
+                assert!(message.starts_with("pattern parameters do not match MapReduceConfig"));
+            }
+            other => panic!("expected InvalidArgument, got {:?}", other),
+        }
+
+        let config = config_with(serde_json::json!({ "maxRounds": 3 }));
+        assert!(matches!(
+            config.typed::<ConsensusConfig>(),
+            Err(crate::error::Error::InvalidArgument(_))
+        ));
+    }
+
+    fn review_pattern(min_agents: Option<u32>) -> Pattern {
+        Pattern {
+            name: "ConsensusBuilder".to_string(),
+            description: String::new(),
+            enabled: true,
+            required_capabilities: vec!["review".to_string(), "security".to_string()],
+            config: PatternConfig {
+                min_agents,
+                ..Default::default()
+            },
+            input_schema: None,
+        }
+    }
+
+    fn agent_with(capabilities: &[&str]) -> Agent {
+        Agent::new("agent", capabilities.iter().map(|c| c.to_string()).collect())
+    }
+
+    #[test]
+    fn test_is_executable_by_satisfied() {
+        let agents = [
+            agent_with(&["review"]),
+            agent_with(&["security", "review"]),
+            agent_with(&["translation"]),
+        ];
+        assert_eq!(review_pattern(Some(2)).is_executable_by(&agents), Ok(()));
+        assert_eq!(review_pattern(None).is_executable_by(&agents), Ok(()));
+    }
+
+    #[test]
+    fn test_is_executable_by_reports_missing_capability() {
+        let mut security = agent_with(&["security"]);
+        security.status = AgentStatus::Inactive;
+        let agents = [agent_with(&["review"]), security];
+
+        let unmet = review_pattern(None).is_executable_by(&agents).unwrap_err();
+        assert_eq!(
+            unmet,
+            UnmetRequirement::MissingCapability {
+                capability: "security".to_string()
+            }
+        );
+        assert_eq!(unmet.to_string(), "no active agent offers capability security");
+    }
+
+    #[test]
+    fn test_is_executable_by_reports_too_few_agents() {
+        let agents = [
+            agent_with(&["review", "security"]),
+            agent_with(&["review"]),
+            agent_with(&["translation"]),
+        ];
+
+        let unmet = review_pattern(Some(3)).is_executable_by(&agents).unwrap_err();
+        assert_eq!(
+            unmet,
+            UnmetRequirement::TooFewAgents {
+                required: 3,
+                available: 2
+            }
+        );
+        assert_eq!(unmet.to_string(), "only 2 of 3 required agents are available");
+        assert!(review_pattern(Some(1)).is_executable_by(&[]).is_err());
+    }
+
+    #[test]
+    fn test_capabilities_normalize_to_deduped_set() {
+        let raw = ["a", "a", "", "A", " b "];
+        assert_eq!(normalize_capabilities(raw, false), ["a", "A", "b"]);
+        assert_eq!(normalize_capabilities(raw, true), ["a", "b"]);
+
+        let agent = Agent::new("agent", raw.iter().map(|c| c.to_string()).collect());
+        assert_eq!(agent.capabilities, ["a", "A", "b"]);
+
+        let error = agent.clone().with_capabilities(raw).unwrap_err();
+        assert!(matches!(error, crate::error::Error::InvalidArgument(_)));
+        let agent = agent
+            .with_capabilities(["review", " review"])
+            .unwrap()
+            .with_endpoint("http://agent:50051");
+        assert_eq!(agent.capabilities, ["review"]);
+    }
+
+    #[test]
+    fn test_agent_status_transition_matrix() {
+        use AgentStatus::*;
+
+        let all = [Active, Draining, Inactive, Error];
+        let allowed = [
+            (Active, Draining),
+            (Active, Inactive),
+            (Active, Error),
+            (Draining, Inactive),
+            (Draining, Error),
+            (Inactive, Active),
+            (Error, Active),
+            (Error, Inactive),
+        ];
+
+        for from in all {
+            for to in all {
+                let expected = from == to || allowed.contains(&(from, to));
+                assert_eq!(
+                    from.can_transition_to(to),
+                    expected,
+                    "{:?} -> {:?}",
+                    from,
+                    to
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_execute_options_builder_round_trip() {
+        let options = ExecuteOptions::builder()
+            .timeout(std::time::Duration::from_secs(30))
+            .priority(5)
+            .async_execution(true)
+            .agent_selector(AgentSelector {
+                min_count: Some(3),
+                ..Default::default()
+            })
+            .trace_id("trace-1")
+            .metadata("team", "search")
+            .build()
+            .unwrap();
+
+        assert_eq!(options.timeout_ms, Some(30_000));
+        assert_eq!(options.priority, Some(5));
+        assert_eq!(options.async_execution, Some(true));
+        assert_eq!(options.agent_selector.unwrap().min_count, Some(3));
+        assert_eq!(options.trace_id.as_deref(), Some("trace-1"));
+        assert_eq!(options.metadata["team"], "search");
+    }
+
+    #[test]
+    fn test_execute_options_builder_rejects_invalid_values() {
+        for priority in [-1, 101] {
+            let result = ExecuteOptions::builder().priority(priority).build();
+            assert!(matches!(result, Err(crate::error::Error::InvalidArgument(_))));
+        }
+
+        let result = ExecuteOptions::builder()
+            .timeout(std::time::Duration::from_micros(500))
+            .build();
+        assert!(matches!(result, Err(crate::error::Error::InvalidArgument(_))));
+    }
+
+    /// Every combination of status and set/unset optional fields
+    fn execution_variants() -> Vec<PatternExecution> {
+        use chrono::TimeZone;
+
+        let statuses = [
+            ExecutionStatus::Pending,
+            ExecutionStatus::Running,
+            ExecutionStatus::Completed,
+            ExecutionStatus::Failed,
+            ExecutionStatus::Cancelled,
+        ];
+        let start_time = Utc.timestamp_opt(1_700_000_000, 123_456_789).unwrap();
+        let mut variants = Vec::new();
+        for status in statuses {
+            for mask in 0u32..64 {
+                let set = |bit: u32| mask & (1 << bit) != 0;
+                variants.push(PatternExecution {
+                    id: format!("exec-{}", mask),
+                    pattern: "consensus".to_string(),
+                    status,
+                    input: serde_json::json!({ "task": "review", "weight": 0.5 }),
+                    output: set(0).then(|| serde_json::json!({ "answer": [1, 2.5, null] })),
+                    agents: vec!["agent-1".to_string()],
+                    agent_contributions: if set(5) {
+                        vec![AgentContribution {
+                            agent_id: "agent-1".to_string(),
+                            confidence: 0.7,
+                        }]
+                    } else {
+                        Vec::new()
+                    },
+                    start_time,
+                    end_time: set(1).then(|| start_time + chrono::Duration::nanoseconds(1_000_001)),
+                    duration_ms: set(2).then_some(1),
+                    confidence: set(3).then_some(0.1 + 0.2),
+                    error: set(4).then(|| "agent timed out".to_string()),
+                    metadata: if set(5) {
+                        Metadata::from(HashMap::from([(
+                            "team".to_string(),
+                            serde_json::json!("search"),
+                        )]))
+                    } else {
+                        Metadata::new()
+                    },
+                });
+            }
+        }
+        variants
+    }
+
+    #[test]
+    fn test_pattern_execution_round_trips_through_json() {
+        for execution in execution_variants() {
+            let json = serde_json::to_string(&execution).unwrap();
+            let parsed: PatternExecution = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed, execution, "{}", json);
+            // Serialization is stable across a second pass
+            assert_eq!(serde_json::to_string(&parsed).unwrap(), json);
+        }
+    }
+
+    #[test]
+    fn test_pattern_execution_json_form() {
+        let mut execution = execution_variants().remove(63);
+        let json = serde_json::to_value(&execution).unwrap();
+        assert_eq!(json["status"], "pending");
+        assert_eq!(json["start_time"], "2023-11-14T22:13:20.123456789Z");
+        assert_eq!(json["end_time"], "2023-11-14T22:13:20.124456790Z");
+
+        // Unset fields are omitted, and explicit nulls read back as unset
+        execution.end_time = None;
+        execution.error = None;
+        let json = serde_json::to_value(&execution).unwrap();
+        assert!(json.get("end_time").is_none() && json.get("error").is_none());
+        let mut with_nulls = json.clone();
+        with_nulls["end_time"] = serde_json::Value::Null;
+        with_nulls["error"] = serde_json::Value::Null;
+        assert_eq!(serde_json::from_value::<PatternExecution>(with_nulls).unwrap(), execution);
+
+        // A null output is a value of its own, distinct from no output
+        execution.output = Some(serde_json::Value::Null);
+        let json = serde_json::to_value(&execution).unwrap();
+        assert_eq!(json["output"], serde_json::Value::Null);
+        let parsed: PatternExecution = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(parsed.output, Some(serde_json::Value::Null));
+
+        let mut without_output = json;
+        without_output.as_object_mut().unwrap().remove("output");
+        let parsed: PatternExecution = serde_json::from_value(without_output).unwrap();
+        assert_eq!(parsed.output, None);
+    }
+
+    #[test]
+    fn test_execution_event_kind_parses_known_and_unknown_types() {
+        let known = [
+            ("started", ExecutionEventKind::Started),
+            ("progress", ExecutionEventKind::Progress),
+            ("updated", ExecutionEventKind::Updated),
+            ("completed", ExecutionEventKind::Completed),
+            ("failed", ExecutionEventKind::Failed),
+            ("cancelled", ExecutionEventKind::Cancelled),
+        ];
+        for (event_type, kind) in known {
+            assert_eq!(ExecutionEventKind::from(event_type), kind);
+            assert_eq!(kind.to_string(), event_type);
+        }
+
+        let event = ExecutionEvent {
+            event_type: "agent_spawned".to_string(),
+            execution: None,
+            event_time: None,
+            event_data: None,
+        };
+        assert_eq!(event.kind(), ExecutionEventKind::Other("agent_spawned".to_string()));
+        assert_eq!(event.kind().as_str(), event.event_type);
+        // Matching is exact, so near misses are not mistaken for known kinds
+        assert!(matches!(ExecutionEventKind::from("Completed"), ExecutionEventKind::Other(_)));
+    }
+
+    #[test]
+    fn test_execution_event_data_deserializes_typed_payload() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        struct Progress {
+            percent: u8,
+        }
+
+        let mut event = ExecutionEvent {
+            event_type: "progress".to_string(),
+            execution: None,
+            event_time: None,
+            event_data: Some(serde_json::json!({ "percent": 40, "stage": "review" })),
+        };
+        assert_eq!(event.kind(), ExecutionEventKind::Progress);
+        assert_eq!(event.data_as::<Progress>().unwrap(), Some(Progress { percent: 40 }));
+
+        event.event_data = Some(serde_json::json!({ "percent": "most" }));
+        assert!(matches!(
+            event.data_as::<Progress>(),
+            Err(crate::error::Error::Serialization(_))
+        ));
+
+        event.event_data = None;
+        assert_eq!(event.data_as::<Progress>().unwrap(), None);
+    }
+
+    #[test]
+    fn test_agent_status_serializes_lowercase() {
+        assert_eq!(serde_json::to_value(AgentStatus::Draining).unwrap(), "draining");
+        let parsed: AgentStatus = serde_json::from_value("draining".into()).unwrap();
+        assert_eq!(parsed, AgentStatus::Draining);
+    }
+
+    #[test]
+    fn test_metadata_getters_and_label_encoding() {
+        let metadata: Metadata = serde_json::from_value(serde_json::json!({
+            "tokens": 1234,
+            "streaming": false,
+            "region": "eu",
+            "limits": { "rps": 5 },
+        }))
+        .unwrap();
+
+        assert_eq!(metadata.get_f64("tokens"), Some(1234.0));
+        assert_eq!(metadata.get_bool("streaming"), Some(false));
+        assert_eq!(metadata.get_str("region"), Some("eu"));
+        assert_eq!(metadata.get_f64("region"), None);
+        assert_eq!(metadata.get_f64("missing"), None);
+
+        let labels = metadata.to_labels();
+        assert_eq!(labels["tokens"], "1234");
+        assert_eq!(labels["region"], "eu");
+        assert_eq!(labels["limits"], r#"{"rps":5}"#);
+
+        // Labels come back as strings, which the getters still read
+        let decoded = Metadata::from_labels(labels);
+        assert_eq!(decoded["tokens"], "1234");
+        assert_eq!(decoded.get_f64("tokens"), Some(1234.0));
+        assert_eq!(decoded.get_bool("streaming"), Some(false));
+        assert_eq!(decoded.get_str("region"), Some("eu"));
+    }
+
+    #[test]
+    fn test_selection_strategy_round_trips_snake_case() {
+        let json = serde_json::to_value(SelectionStrategy::WeightedByConfidence).unwrap();
+        assert_eq!(json, "weighted_by_confidence");
+        let parsed: SelectionStrategy = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, SelectionStrategy::WeightedByConfidence);
+    }
+}
@@ -34,7 +34,9 @@ export class ExecutionRepository extends BaseRepository {
     skip?: number;
     take?: number;
     where?: Prisma.ExecutionWhereInput;
-    orderBy?: Prisma.ExecutionOrderByWithRelationInput;
+    orderBy?:
+      | Prisma.ExecutionOrderByWithRelationInput
+      | Prisma.ExecutionOrderByWithRelationInput[];
   }): Promise<Execution[]> {
     return this.executeQuery(
       () =>
@@ -48,6 +50,13 @@ export class ExecutionRepository extends BaseRepository {
     );
   }
 
+  async count(where?: Prisma.ExecutionWhereInput): Promise<number> {
+    return this.executeQuery(
+      () => this.prisma.execution.count({ where }),
+      'ExecutionRepository.count'
+    );
+  }
+
   async findRecent(limit: number = 10): Promise<Execution[]> {
     return this.executeQuery(
       () =>
@@ -119,6 +128,20 @@ export class ExecutionRepository extends BaseRepository {
     );
   }
 
+  async getAgentEvents(executionIds: string[]): Promise<ExecutionEvent[]> {
+    return this.executeQuery(
+      () =>
+        this.prisma.executionEvent.findMany({
+          where: {
+            executionId: { in: executionIds },
+            type: { in: ['agent_completed', 'agent_failed'] },
+          },
+          orderBy: { time: 'asc' },
+        }),
+      'ExecutionRepository.getAgentEvents'
+    );
+  }
+
   async updateStatus(
     id: string,
     status: string,
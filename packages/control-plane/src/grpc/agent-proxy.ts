@@ -12,6 +12,10 @@ import type { GatewayService } from './services/gateway-service';
 
 const PROTO_DIR = path.join(__dirname, '../../../../proto');
 
+// Levels an agent may put on a streamed log line
+const AGENT_LOG_LEVELS = ['debug', 'info', 'warn', 'error'] as const;
+type AgentLogLevel = (typeof AGENT_LOG_LEVELS)[number];
+
 export interface AgentTask {
   description: string;
   data?: any;
@@ -228,11 +232,30 @@ export class AgentProxy {
 
       const stream = client.streamAnalyze(request, { deadline });
 
+      // Agents may split a large value_json across messages: every piece
+      // but the last has more_chunks set and carries nothing else
+      let pendingJson = '';
+
       stream.on('data', (response: any) => {
+        // Log lines the agent sends ahead of its result are not results:
+        // forward them to our log instead
+        if (response.log) {
+          const { level, message } = response.log;
+          const logLevel: AgentLogLevel = AGENT_LOG_LEVELS.includes(level)
+            ? level
+            : 'info';
+          this.logger[logLevel]({ agentAddress }, message);
+          return;
+        }
+        if (response.more_chunks) {
+          pendingJson += response.value_json || '';
+          return;
+        }
+        const valueJson = pendingJson + (response.value_json || '');
+        pendingJson = '';
+
         const result: AgentResult = {
-          value: response.value_json
-            ? JSON.parse(response.value_json)
-            : undefined,
+          value: valueJson ? JSON.parse(valueJson) : undefined,
           confidence: response.confidence || 0,
           reasoning: response.reasoning,
           metadata: response.metadata || {},
@@ -249,6 +272,10 @@ export class AgentProxy {
 
       stream.on('end', () => {
         this.logger.debug({ agentAddress }, 'Stream ended from agent');
+        if (pendingJson) {
+          reject(new Error('Agent stream ended in the middle of a result'));
+          return;
+        }
         resolve();
       });
     });
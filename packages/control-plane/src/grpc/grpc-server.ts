@@ -46,7 +46,8 @@ export class GrpcServer {
     this.patternService = new PatternServiceImpl(
       patternEngine,
       database,
-      logger
+      logger,
+      executionEvents
     );
     this.coordinatorService = new CoordinatorServiceImpl(
       patternEngine,
@@ -19,6 +19,24 @@ type ExecutionRecord = {
   error?: string;
   confidence?: number;
   metrics?: any;
+  agents?: Array<{ agentId: string; confidence: number }>;
+};
+
+/** Executions read per query while streaming an export */
+const EXPORT_BATCH_SIZE = 100;
+
+/** Where a page of executions ends: its last execution's start time and id */
+type ExecutionCursor = {
+  startTime: Date;
+  id: string;
+};
+
+/** Filters shared by listing, counting and exporting executions */
+type ExecutionFilters = {
+  status?: string;
+  patternName?: string;
+  startedAfter?: Date;
+  startedBefore?: Date;
 };
 
 export class ExecutionServiceImpl {
@@ -33,6 +51,7 @@ export class ExecutionServiceImpl {
     return {
       getExecution: this.getExecution.bind(this),
       listExecutions: this.listExecutions.bind(this),
+      exportExecutions: this.exportExecutions.bind(this),
       streamExecution: this.streamExecution.bind(this),
     };
   }
@@ -68,18 +87,37 @@ export class ExecutionServiceImpl {
     callback: grpc.sendUnaryData<any>
   ) {
     try {
-      const { limit, offset, status } = call.request || {};
-      const executions = await this.fetchExecutions({
-        limit,
-        offset,
-        status,
-      });
+      const { limit, offset, cursor } = call.request || {};
+      const after = cursor ? decodeExecutionCursor(cursor) : undefined;
+      if (cursor && !after) {
+        callback({
+          code: grpc.status.INVALID_ARGUMENT,
+          details: 'Invalid page cursor',
+        });
+        return;
+      }
+
+      // Fetch one extra execution to learn whether another page follows
+      const pageSize = limit > 0 ? limit : undefined;
+      const filters = this.toExecutionFilters(call.request || {});
+      const [executions, total] = await Promise.all([
+        this.fetchExecutions({
+          limit: pageSize ? pageSize + 1 : undefined,
+          offset: after ? undefined : offset,
+          ...filters,
+          after,
+        }),
+        this.countExecutions(filters),
+      ]);
+      const hasMore = pageSize !== undefined && executions.length > pageSize;
+      const page = hasMore ? executions.slice(0, pageSize) : executions;
 
       callback(null, {
-        executions: executions.map((execution) =>
-          this.toProtoExecution(execution)
-        ),
-        total: executions.length,
+        executions: page.map((execution) => this.toProtoExecution(execution)),
+        total,
+        next_cursor: hasMore
+          ? encodeExecutionCursor(page[page.length - 1])
+          : '',
       });
     } catch (error: any) {
       this.logger.error({ error }, 'Failed to list executions');
@@ -90,6 +128,58 @@ export class ExecutionServiceImpl {
     }
   }
 
+  async exportExecutions(call: grpc.ServerWritableStream<any, any>) {
+    const filters = this.toExecutionFilters(call.request || {});
+    let active = true;
+    const stop = () => {
+      active = false;
+    };
+    call.on('cancelled', stop);
+    call.on('close', stop);
+
+    // Resolves once the client drains, or is gone and never will
+    const drained = () =>
+      new Promise<void>((resolve) => {
+        const done = () => {
+          call.off('drain', done);
+          call.off('cancelled', done);
+          call.off('close', done);
+          resolve();
+        };
+        call.on('drain', done);
+        call.on('cancelled', done);
+        call.on('close', done);
+      });
+
+    try {
+      // Walk the executions in batches so only one is held at a time, and
+      // wait for the client to drain what was written before reading more
+      let after: ExecutionCursor | undefined;
+      while (active) {
+        const batch = await this.fetchExecutions({
+          limit: EXPORT_BATCH_SIZE,
+          ...filters,
+          after,
+        });
+        for (const execution of batch) {
+          if (!active) break;
+          if (!call.write(this.toProtoExecution(execution))) {
+            await drained();
+          }
+        }
+        if (batch.length < EXPORT_BATCH_SIZE) break;
+        after = batch[batch.length - 1];
+      }
+      call.end();
+    } catch (error: any) {
+      this.logger.error({ error }, 'Failed to export executions');
+      call.emit('error', {
+        code: grpc.status.INTERNAL,
+        details: error.message,
+      });
+    }
+  }
+
   async streamExecution(call: grpc.ServerWritableStream<any, any>) {
     const { execution_id } = call.request;
     if (!execution_id) {
@@ -248,6 +338,7 @@ export class ExecutionServiceImpl {
           error: execution.error,
           confidence: execution.confidence,
           metrics: execution.metrics,
+          agents: execution.agents,
         };
       }
     }
@@ -255,6 +346,7 @@ export class ExecutionServiceImpl {
     if (this.database) {
       const record = await this.database.executions.findById(executionId);
       if (!record) return null;
+      const agents = await this.fetchAgents([record.id]);
       return {
         id: record.id,
         patternName: record.pattern?.name || record.patternId || '',
@@ -268,6 +360,7 @@ export class ExecutionServiceImpl {
         error: record.error || undefined,
         confidence: record.confidence || undefined,
         metrics: record.metrics || undefined,
+        agents: agents.get(record.id),
       };
     }
 
@@ -284,22 +377,39 @@ export class ExecutionServiceImpl {
       error: execution.error,
       confidence: execution.confidence,
       metrics: execution.metrics,
+      agents: execution.agents,
     };
   }
 
-  private async fetchExecutions(options: {
-    limit?: number;
-    offset?: number;
-    status?: string;
-  }) {
+  private async fetchExecutions(
+    options: ExecutionFilters & {
+      limit?: number;
+      offset?: number;
+      after?: ExecutionCursor;
+    }
+  ): Promise<ExecutionRecord[]> {
     if (this.database) {
+      const { after } = options;
       const records = await this.database.executions.findAll({
-        where: options.status ? { status: options.status } : undefined,
-        orderBy: { time: 'desc' },
+        where: {
+          ...this.toExecutionWhere(options),
+          ...(after
+            ? {
+                OR: [
+                  { time: { lt: after.startTime } },
+                  { time: after.startTime, id: { lt: after.id } },
+                ],
+              }
+            : {}),
+        },
+        orderBy: [{ time: 'desc' }, { id: 'desc' }],
         skip: options.offset,
         take: options.limit,
       });
 
+      const agents = await this.fetchAgents(
+        records.map((record) => record.id)
+      );
       return records.map((record) => ({
         id: record.id,
         patternName: (record as any).pattern?.name || record.patternId,
@@ -313,13 +423,104 @@ export class ExecutionServiceImpl {
         error: record.error || undefined,
         confidence: record.confidence || undefined,
         metrics: record.metrics || undefined,
+        agents: agents.get(record.id),
       }));
     }
 
-    return this.patternEngine.listExecutions({
-      limit: options.limit,
-      status: options.status,
-    });
+    const executions = this.listEngineExecutions(options)
+      .sort(compareNewestFirst)
+      .filter(
+        (execution) =>
+          !options.after || compareNewestFirst(execution, options.after) > 0
+      );
+    const start = options.offset ?? 0;
+    return executions.slice(
+      start,
+      options.limit ? start + options.limit : undefined
+    );
+  }
+
+  /** Number of executions matching the filters, across all pages */
+  private async countExecutions(filters: ExecutionFilters): Promise<number> {
+    if (this.database) {
+      return this.database.executions.count(this.toExecutionWhere(filters));
+    }
+    return this.listEngineExecutions(filters).length;
+  }
+
+  private toExecutionWhere(filters: ExecutionFilters) {
+    const { startedAfter, startedBefore } = filters;
+    return {
+      ...(filters.status ? { status: filters.status } : {}),
+      ...(filters.patternName
+        ? { pattern: { name: filters.patternName } }
+        : {}),
+      ...(startedAfter || startedBefore
+        ? { time: { gte: startedAfter, lte: startedBefore } }
+        : {}),
+    };
+  }
+
+  private listEngineExecutions(filters: ExecutionFilters) {
+    return this.patternEngine
+      .listExecutions({ status: filters.status })
+      .filter(
+        (execution) =>
+          (!filters.patternName ||
+            execution.patternName === filters.patternName) &&
+          (!filters.startedAfter ||
+            execution.startTime >= filters.startedAfter) &&
+          (!filters.startedBefore ||
+            execution.startTime <= filters.startedBefore)
+      );
+  }
+
+  /**
+   * Agents of stored executions, rebuilt from their persisted agent events.
+   * A failed agent counts with zero confidence, as it does in the engine.
+   */
+  private async fetchAgents(
+    executionIds: string[]
+  ): Promise<Map<string, ExecutionRecord['agents']>> {
+    if (!this.database || executionIds.length === 0) return new Map();
+
+    const events = await this.database.executions.getAgentEvents(executionIds);
+    const byExecution = new Map<string, Map<string, number>>();
+    for (const event of events) {
+      if (!event.agentId) continue;
+      const agents = byExecution.get(event.executionId) ?? new Map();
+      const confidence =
+        event.type === 'agent_completed'
+          ? Number((event.data as any)?.confidence) || 0
+          : 0;
+      agents.set(event.agentId, confidence);
+      byExecution.set(event.executionId, agents);
+    }
+
+    return new Map(
+      Array.from(byExecution, ([executionId, agents]) => [
+        executionId,
+        Array.from(agents, ([agentId, confidence]) => ({
+          agentId,
+          confidence,
+        })),
+      ])
+    );
+  }
+
+  /** Filters shared by ListExecutions and ExportExecutions requests */
+  private toExecutionFilters(request: any): ExecutionFilters {
+    const { status, pattern_name, started_after, started_before } = request;
+    return {
+      status: status || undefined,
+      patternName: pattern_name || undefined,
+      startedAfter: started_after
+        ? this.fromTimestamp(started_after)
+        : undefined,
+      startedBefore: started_before
+        ? this.fromTimestamp(started_before)
+        : undefined,
+    };
   }
 
   private toProtoExecution(execution: ExecutionRecord): any {
@@ -336,6 +537,10 @@ export class ExecutionServiceImpl {
       error: execution.error || '',
       confidence: execution.confidence || 0,
       metrics: execution.metrics || {},
+      agents: (execution.agents || []).map((agent) => ({
+        agent_id: agent.agentId,
+        confidence: agent.confidence,
+      })),
     };
   }
 
@@ -356,6 +561,14 @@ export class ExecutionServiceImpl {
     }
   }
 
+  private fromTimestamp(timestamp: {
+    seconds: number | string;
+    nanos?: number;
+  }): Date {
+    const millis = Math.floor((timestamp.nanos || 0) / 1e6);
+    return new Date(Number(timestamp.seconds) * 1000 + millis);
+  }
+
   private toTimestamp(date: Date): { seconds: number; nanos: number } {
     return {
       seconds: Math.floor(date.getTime() / 1000),
@@ -363,3 +576,30 @@ export class ExecutionServiceImpl {
     };
   }
 }
+
+/** Orders executions by start time, then id, newest first */
+function compareNewestFirst(a: ExecutionCursor, b: ExecutionCursor): number {
+  const byTime = b.startTime.getTime() - a.startTime.getTime();
+  if (byTime !== 0) return byTime;
+  return a.id < b.id ? 1 : a.id > b.id ? -1 : 0;
+}
+
+function encodeExecutionCursor(execution: ExecutionCursor): string {
+  return Buffer.from(
+    JSON.stringify({ t: execution.startTime.getTime(), id: execution.id })
+  ).toString('base64url');
+}
+
+function decodeExecutionCursor(
+  cursor: string
+): ExecutionCursor | undefined {
+  try {
+    const { t, id } = JSON.parse(
+      Buffer.from(cursor, 'base64url').toString('utf8')
+    );
+    if (typeof t !== 'number' || typeof id !== 'string') return undefined;
+    return { startTime: new Date(t), id };
+  } catch {
+    return undefined;
+  }
+}
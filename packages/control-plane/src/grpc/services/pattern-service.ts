@@ -4,8 +4,13 @@
 
 import * as grpc from '@grpc/grpc-js';
 import type { Logger } from 'pino';
+import { v4 as uuidv4 } from 'uuid';
 import type { DatabaseService } from '../../db/database.service';
-import type { IPatternEngine } from '../../pattern-engine/interfaces';
+import type { ExecutionEventBus } from '../../execution-events';
+import type {
+  IPatternEngine,
+  PatternExecutionOptions,
+} from '../../pattern-engine/interfaces';
 import type { Pattern } from '../../pattern-engine/types';
 
 /**
@@ -38,11 +43,33 @@ function valueToJs(value: any): any {
   return null;
 }
 
+/** How often WatchPatterns re-reads the catalog to find changes */
+const PATTERN_WATCH_INTERVAL_MS = 2000;
+
+/** Engine events that end an execution; StreamExecutePattern reports these from the result instead */
+const TERMINAL_EVENTS = ['completed', 'failed', 'cancelled'];
+
+/** Metadata entry a client sets so a resent execute returns the first execution */
+const IDEMPOTENCY_KEY_HEADER = 'idempotency-key';
+
+/** How long an idempotency key is remembered after its execution starts */
+const IDEMPOTENCY_KEY_TTL_MS = 24 * 60 * 60 * 1000;
+
+/** Keys remembered at once; past this the oldest is forgotten */
+const MAX_IDEMPOTENCY_KEYS = 10000;
+
 export class PatternServiceImpl {
+  /** Execution started for each idempotency key, oldest first */
+  private executionsByKey = new Map<
+    string,
+    { execution: Promise<any>; expiresAt: number }
+  >();
+
   constructor(
     private patternEngine: IPatternEngine,
     _database: DatabaseService,
-    private logger: Logger
+    private logger: Logger,
+    private executionEvents?: ExecutionEventBus
   ) {}
 
   getImplementation() {
@@ -52,6 +79,7 @@ export class PatternServiceImpl {
       listPatterns: this.listPatterns.bind(this),
       getPattern: this.getPattern.bind(this),
       uploadPattern: this.uploadPattern.bind(this),
+      watchPatterns: this.watchPatterns.bind(this),
     };
   }
 
@@ -70,13 +98,15 @@ export class PatternServiceImpl {
         : structToObject(input);
 
       // Execute pattern
-      const result = await this.patternEngine.executePattern(
-        pattern_name,
-        {
-          ...parsedInput,
-          ...(parameters || {}),
-        },
-        { timeout: options?.timeout_ms ?? 30000 }
+      const result = await this.executeOnce(call, () =>
+        this.patternEngine.executePattern(
+          pattern_name,
+          {
+            ...parsedInput,
+            ...(parameters || {}),
+          },
+          this.toExecutionOptions(options)
+        )
       );
 
       callback(null, this.toExecuteResponse(result, pattern_name));
@@ -103,17 +133,44 @@ export class PatternServiceImpl {
         ? JSON.parse(input_json)
         : structToObject(input);
 
-      // Execute pattern (best-effort streaming until engine supports progress events)
-      const result = await this.patternEngine.executePattern(
-        pattern_name,
-        {
-          ...parsedInput,
-          ...(parameters || {}),
-        },
-        { timeout: options?.timeout_ms ?? 30000 }
+      // Pick the execution id up front so the engine's progress events can
+      // be forwarded from the first one ("started") on. Terminal events are
+      // left to the final message, which carries the result.
+      const executionId = uuidv4();
+      const unsubscribe = this.executionEvents?.onExecutionId(
+        executionId,
+        (event) => {
+          if (TERMINAL_EVENTS.includes(event.type)) return;
+          const execution = this.patternEngine.getExecution(executionId);
+          if (!execution) return;
+          call.write({
+            ...this.toExecuteResponse(execution, pattern_name),
+            event_type: event.type,
+            event_data: event.data || {},
+          });
+        }
       );
 
-      call.write(this.toExecuteResponse(result, pattern_name));
+      let result: any;
+      try {
+        result = await this.executeOnce(call, () =>
+          this.patternEngine.executePattern(
+            pattern_name,
+            {
+              ...parsedInput,
+              ...(parameters || {}),
+            },
+            { ...this.toExecutionOptions(options), executionId }
+          )
+        );
+      } finally {
+        unsubscribe?.();
+      }
+
+      call.write({
+        ...this.toExecuteResponse(result, pattern_name),
+        event_type: result.status === 'completed' ? 'completed' : 'failed',
+      });
 
       call.end();
     } catch (error) {
@@ -134,22 +191,9 @@ export class PatternServiceImpl {
       const patterns = await this.patternEngine.listPatterns();
 
       // Convert to proto format
-      const protoPatterns = patterns.map((pattern) => ({
-        name: pattern.name,
-        version: pattern.version || '1.0.0',
-        description: pattern.description || '',
-        requirements: {
-          capabilities: pattern.agents?.capabilities || [],
-          min_agents: pattern.minAgents || 0,
-          max_agents: pattern.maxAgents || 0,
-          min_confidence: pattern.agents?.minConfidence || 0,
-        },
-        definition_type: (pattern.metadata as any)?.orgChart
-          ? 'ORG_CHART_YAML'
-          : 'TYPESCRIPT_MODULE',
-        definition: includeDefinitions ? pattern.script : '',
-        metadata: pattern.metadata || {},
-      }));
+      const protoPatterns = patterns.map((pattern) =>
+        this.toProtoPattern(pattern, includeDefinitions)
+      );
 
       callback(null, { patterns: protoPatterns });
     } catch (error) {
@@ -178,24 +222,8 @@ export class PatternServiceImpl {
         return;
       }
 
-      const protoPattern = {
-        name: pattern.name,
-        version: pattern.version || '1.0.0',
-        description: pattern.description || '',
-        requirements: {
-          capabilities: pattern.agents?.capabilities || [],
-          min_agents: pattern.minAgents || 0,
-          max_agents: pattern.maxAgents || 0,
-          min_confidence: pattern.agents?.minConfidence || 0,
-        },
-        definition_type: (pattern.metadata as any)?.orgChart
-          ? 'ORG_CHART_YAML'
-          : 'TYPESCRIPT_MODULE',
-        definition: pattern.script || '',
-        metadata: pattern.metadata || {},
-      };
-
-      callback(null, { pattern: protoPattern });
+      // GetPattern replies with the Pattern message itself
+      callback(null, this.toProtoPattern(pattern, true));
     } catch (error) {
       this.logger.error({ error }, 'Failed to get pattern');
       callback({
@@ -285,6 +313,149 @@ export class PatternServiceImpl {
     }
   }
 
+  /**
+   * Stream pattern catalog changes. The engine has no change notifications,
+   * and patterns come from files, the database and uploads alike, so the
+   * catalog is re-read on an interval and diffed against the last read.
+   */
+  async watchPatterns(call: grpc.ServerWritableStream<any, any>) {
+    const includeInitial = Boolean(call.request?.include_initial);
+    // Serialized proto pattern by name, as of the last read
+    let known: Map<string, string> | null = null;
+    let interval: NodeJS.Timeout | null = null;
+    let polling = false;
+
+    const stop = () => {
+      if (interval) {
+        clearInterval(interval);
+        interval = null;
+      }
+    };
+
+    call.on('cancelled', stop);
+    call.on('close', stop);
+    call.on('error', stop);
+
+    const send = (type: string, pattern: any) => {
+      call.write({
+        type,
+        pattern,
+        timestamp: this.toTimestamp(new Date()),
+      });
+    };
+
+    const poll = async () => {
+      if (polling) return;
+      polling = true;
+      try {
+        const patterns = await this.patternEngine.listPatterns();
+        const current = new Map<string, string>();
+        for (const pattern of patterns) {
+          const protoPattern = this.toProtoPattern(pattern, true);
+          const serialized = JSON.stringify(protoPattern);
+          current.set(pattern.name, serialized);
+
+          const previous = known?.get(pattern.name);
+          if (known === null) {
+            if (includeInitial) send('ADDED', protoPattern);
+          } else if (previous === undefined) {
+            send('ADDED', protoPattern);
+          } else if (previous !== serialized) {
+            send('UPDATED', protoPattern);
+          }
+        }
+        for (const name of known?.keys() ?? []) {
+          if (!current.has(name)) send('REMOVED', { name });
+        }
+        known = current;
+      } catch (error) {
+        this.logger.error({ error }, 'Failed to watch patterns');
+        stop();
+        call.emit('error', {
+          code: grpc.status.INTERNAL,
+          details: error instanceof Error ? error.message : 'Unknown error',
+        });
+      } finally {
+        polling = false;
+      }
+    };
+
+    await poll();
+    if (known) {
+      interval = setInterval(poll, PATTERN_WATCH_INTERVAL_MS);
+    }
+  }
+
+  private toProtoPattern(pattern: Pattern, includeDefinition: boolean): any {
+    return {
+      name: pattern.name,
+      version: pattern.version || '1.0.0',
+      description: pattern.description || '',
+      requirements: {
+        capabilities: pattern.agents?.capabilities || [],
+        min_agents: pattern.minAgents || 0,
+        max_agents: pattern.maxAgents || 0,
+        min_confidence: pattern.agents?.minConfidence || 0,
+      },
+      definition_type: (pattern.metadata as any)?.orgChart
+        ? 'ORG_CHART_YAML'
+        : 'TYPESCRIPT_MODULE',
+      definition: includeDefinition ? pattern.script || '' : '',
+      metadata: pattern.metadata || {},
+    };
+  }
+
+  /**
+   * Run `execute` once per idempotency key. A call carrying a key seen
+   * before gets the first call's execution, finished or still running,
+   * instead of starting another. A key whose execution threw is forgotten
+   * so the client can retry with it.
+   */
+  private executeOnce(
+    call: { metadata?: grpc.Metadata },
+    execute: () => Promise<any>
+  ): Promise<any> {
+    const key = call.metadata?.get(IDEMPOTENCY_KEY_HEADER)[0]?.toString();
+    if (!key) return execute();
+
+    const now = Date.now();
+    for (const [seenKey, seen] of this.executionsByKey) {
+      if (
+        seen.expiresAt > now &&
+        this.executionsByKey.size < MAX_IDEMPOTENCY_KEYS
+      ) {
+        break;
+      }
+      this.executionsByKey.delete(seenKey);
+    }
+
+    const seen = this.executionsByKey.get(key);
+    if (seen) {
+      this.logger.info({ idempotencyKey: key }, 'Returning deduped execution');
+      return seen.execution;
+    }
+    const execution = execute();
+    this.executionsByKey.set(key, {
+      execution,
+      expiresAt: now + IDEMPOTENCY_KEY_TTL_MS,
+    });
+    execution.catch(() => {
+      if (this.executionsByKey.get(key)?.execution === execution) {
+        this.executionsByKey.delete(key);
+      }
+    });
+    return execution;
+  }
+
+  private toExecutionOptions(options: any): PatternExecutionOptions {
+    return {
+      timeout: options?.timeout_ms ?? 30000,
+      // 0 (the proto default) keeps the pattern's own agent counts
+      minAgents: options?.min_agents || undefined,
+      maxAgents: options?.max_agents || undefined,
+    };
+  }
+
   private toExecuteResponse(result: any, patternName: string): any {
     const status =
       result.status === 'failed'
@@ -200,12 +200,13 @@ export class RegistryServiceImpl {
   }
 
   async watch(call: grpc.ServerWritableStream<any, any>) {
-    const { include_initial, capabilities } = call.request || {};
+    const { include_initial, capabilities, agent_id } = call.request || {};
     let active = true;
-    let previous = new Map<
+    // Agents as of the last poll, or null before the first one
+    let previous: Map<
       string,
       { agent: ServiceRegistration; hash: string }
-    >();
+    > | null = null;
 
     let interval: NodeJS.Timeout | null = null;
     const stop = () => {
@@ -230,8 +231,10 @@ export class RegistryServiceImpl {
 
     const poll = async () => {
       if (!active) return;
-      const agents = (await this.listAgentsInternal()).filter((agent) =>
-        this.matchesCapabilities(agent, capabilities)
+      const agents = (await this.listAgentsInternal()).filter(
+        (agent) =>
+          (!agent_id || agent.id === agent_id) &&
+          this.matchesCapabilities(agent, capabilities)
       );
 
       const current = new Map<
@@ -248,9 +251,11 @@ export class RegistryServiceImpl {
         current.set(agent.id, { agent, hash });
       }
 
-      if (include_initial && previous.size === 0) {
-        for (const entry of current.values()) {
-          emitEvent('ADDED', entry.agent);
+      if (previous === null) {
+        if (include_initial) {
+          for (const entry of current.values()) {
+            emitEvent('ADDED', entry.agent);
+          }
         }
       } else {
         for (const [id, entry] of current.entries()) {
@@ -314,6 +319,7 @@ export class RegistryServiceImpl {
     metadata: Record<string, unknown>;
     registered_at: { seconds: number; nanos: number };
     ttl?: { seconds: number; nanos: number };
+    last_heartbeat?: { seconds: number; nanos: number };
   } {
     const metadata = agent.metadata || {};
     return {
@@ -329,13 +335,19 @@ export class RegistryServiceImpl {
       },
       registered_at: this.toTimestamp(agent.registeredAt || new Date()),
       ttl: agent.ttl ? { seconds: agent.ttl, nanos: 0 } : undefined,
+      // Refreshed on every lease renewal; stored registrations may hold
+      // it as a string
+      last_heartbeat: agent.health?.lastCheck
+        ? this.toTimestamp(new Date(agent.health.lastCheck))
+        : undefined,
     };
   }
 
   private toTimestamp(date: Date): { seconds: number; nanos: number } {
+    const millis = date.getTime();
     return {
-      seconds: Math.floor(date.getTime() / 1000),
-      nanos: 0,
+      seconds: Math.floor(millis / 1000),
+      nanos: (millis % 1000) * 1_000_000,
     };
   }
 }
@@ -48,6 +48,10 @@ export interface PatternExecutionOptions {
    * If provided, these are used instead of GitHub App credentials
    */
   credentials?: UserProvidedCredentials;
+  /** Agents to select at least, in place of the pattern's minAgents */
+  minAgents?: number;
+  /** Agents to select at most, in place of the pattern's maxAgents */
+  maxAgents?: number;
 }
 
 export interface PatternWithSource extends Pattern {
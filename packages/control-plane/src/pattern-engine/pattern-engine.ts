@@ -47,6 +47,15 @@ import { LocalAgentManager } from './local-agents';
 import { PatternLoader } from './pattern-loader';
 import type { ExecutionMetrics, Pattern, PatternExecution } from './types';
 
+/**
+ * Data an agent task carries for a pattern input: the input's `data` itself,
+ * never wrapped in a further envelope, or the whole input when it has none.
+ * Agents receive it as their request data unchanged.
+ */
+function agentTaskData(input: any): any {
+  return input?.data ?? input;
+}
+
 export class PatternEngine implements IPatternEngine {
   private agentRegistry: EtcdRegistry;
   private patternsDir: string;
@@ -593,10 +602,11 @@ export class PatternEngine implements IPatternEngine {
       throw new Error('Server is shutting down — not accepting new executions');
     }
 
-    const pattern = this.getPattern(patternName);
-    if (!pattern) {
+    const registered = this.getPattern(patternName);
+    if (!registered) {
       throw new Error(`Pattern ${patternName} not found`);
     }
+    const pattern = withAgentCounts(registered, options);
 
     // Resolve timeout: options > pattern metadata > default
     // Thread-based patterns default to no timeout (0); non-thread patterns default to 5 min
@@ -807,7 +817,7 @@ export class PatternEngine implements IPatternEngine {
                   agent.threadId,
                   {
                     description: input.task || 'analyze',
-                    data: input.data || input,
+                    data: agentTaskData(input),
                   },
                   timeoutMs
                 );
@@ -992,7 +1002,7 @@ export class PatternEngine implements IPatternEngine {
                   agent.address || agent.endpoint,
                   {
                     description: input.task || 'analyze',
-                    data: input.data || input,
+                    data: agentTaskData(input),
                   },
                   30000 // 30 second timeout
                 );
@@ -1058,6 +1068,10 @@ export class PatternEngine implements IPatternEngine {
         }
 
         preProcessedData.agentResults = agentResults;
+        execution.agents = agentResults.map((r) => ({
+          agentId: r.agentId,
+          confidence: r.confidence || 0,
+        }));
         preProcessedData.successfulResults = agentResults.filter(
           (r) => r.confidence > 0
         );
@@ -1881,7 +1895,7 @@ export class PatternEngine implements IPatternEngine {
       target: agent.id,
       payload: {
         task: input.task || 'analyze',
-        data: input.data || input,
+        data: agentTaskData(input),
         agentAddress: agent.address || agent.endpoint,
       },
       metadata: {
@@ -1967,3 +1981,24 @@ export class PatternEngine implements IPatternEngine {
     }
   }
 }
+
+/**
+ * Apply per-execution agent counts from the caller over the pattern's own.
+ * A raised minimum lifts the maximum with it, so selection isn't capped
+ * below the count asked for.
+ */
+export function withAgentCounts(
+  pattern: Pattern,
+  options?: PatternExecutionOptions
+): Pattern {
+  if (!options?.minAgents && !options?.maxAgents) {
+    return pattern;
+  }
+
+  const minAgents = options.minAgents || pattern.minAgents;
+  let maxAgents = options.maxAgents || pattern.maxAgents;
+  if (!options.maxAgents && minAgents && maxAgents && maxAgents < minAgents) {
+    maxAgents = minAgents;
+  }
+  return { ...pattern, minAgents, maxAgents };
+}
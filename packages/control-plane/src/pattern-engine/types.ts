@@ -101,6 +101,10 @@ export interface PatternExecution {
     message: string;
     upgrade_url?: string;
   }>;
+  /**
+   * Agents dispatched for the execution, with the confidence of each result
+   */
+  agents?: Array<{ agentId: string; confidence: number }>;
   /**
    * Workspace info if pattern uses git workspace
    */
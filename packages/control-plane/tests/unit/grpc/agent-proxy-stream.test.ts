@@ -0,0 +1,120 @@
+import { EventEmitter } from 'node:events';
+import pino from 'pino';
+import { beforeEach, describe, expect, it, vi } from 'vitest';
+
+// Mock proto-loader and grpc before importing AgentProxy
+vi.mock('@grpc/proto-loader', () => ({
+  loadSync: vi.fn(() => ({})),
+}));
+
+vi.mock('@grpc/grpc-js', () => ({
+  loadPackageDefinition: vi.fn(() => ({
+    parallax: {
+      confidence: {
+        ConfidenceAgent: vi.fn(() => ({
+          analyze: vi.fn(),
+          streamAnalyze: vi.fn(),
+          getCapabilities: vi.fn(),
+          healthCheck: vi.fn(),
+        })),
+      },
+    },
+  })),
+  credentials: {
+    createInsecure: vi.fn(),
+  },
+}));
+
+import { AgentProxy, type AgentResult } from '@/grpc/agent-proxy';
+
+const logger = pino({ level: 'silent' });
+const address = 'localhost:50051';
+
+describe('AgentProxy.executeTaskStream', () => {
+  let proxy: AgentProxy;
+  let stream: EventEmitter;
+
+  beforeEach(() => {
+    proxy = new AgentProxy(logger);
+    stream = new EventEmitter();
+    (proxy as any).getClient(address).streamAnalyze.mockReturnValue(stream);
+  });
+
+  it('reassembles a value split across messages', async () => {
+    const results: AgentResult[] = [];
+    const done = proxy.executeTaskStream(
+      address,
+      { description: 'Analyze this' },
+      (result) => results.push(result)
+    );
+
+    stream.emit('data', { value_json: '{"summary":', more_chunks: true });
+    stream.emit('data', { value_json: '"all clear",', more_chunks: true });
+    stream.emit('data', {
+      value_json: '"count":2}',
+      confidence: 0.9,
+      reasoning: 'checked twice',
+      more_chunks: false,
+    });
+    stream.emit('end');
+    await done;
+
+    expect(results).toHaveLength(1);
+    expect(results[0].value).toEqual({ summary: 'all clear', count: 2 });
+    expect(results[0].confidence).toBe(0.9);
+    expect(results[0].reasoning).toBe('checked twice');
+  });
+
+  it('passes unchunked results through as they arrive', async () => {
+    const results: AgentResult[] = [];
+    const done = proxy.executeTaskStream(
+      address,
+      { description: 'Analyze this' },
+      (result) => results.push(result)
+    );
+
+    stream.emit('data', { value_json: '{"step":1}', confidence: 0.5 });
+    stream.emit('data', { value_json: '{"step":2}', confidence: 0.8 });
+    stream.emit('end');
+    await done;
+
+    expect(results.map((result) => result.value)).toEqual([
+      { step: 1 },
+      { step: 2 },
+    ]);
+  });
+
+  it('forwards log lines to the logger instead of reporting them', async () => {
+    const warn = vi.spyOn(logger, 'warn');
+    const results: AgentResult[] = [];
+    const done = proxy.executeTaskStream(
+      address,
+      { description: 'Analyze this' },
+      (result) => results.push(result)
+    );
+
+    stream.emit('data', { log: { level: 'warn', message: 'cache cold' } });
+    stream.emit('data', { value_json: '{"ok":true}', confidence: 0.7 });
+    stream.emit('end');
+    await done;
+
+    expect(warn).toHaveBeenCalledWith({ agentAddress: address }, 'cache cold');
+    expect(results).toHaveLength(1);
+    expect(results[0].value).toEqual({ ok: true });
+  });
+
+  it('fails when the stream ends before the last chunk', async () => {
+    const onResult = vi.fn();
+    const done = proxy.executeTaskStream(
+      address,
+      { description: 'Analyze this' },
+      onResult
+    );
+
+    stream.emit('data', { value_json: '{"summary":', more_chunks: true });
+    stream.emit('end');
+
+    await expect(done).rejects.toThrow('middle of a result');
+    expect(onResult).not.toHaveBeenCalled();
+  });
+});
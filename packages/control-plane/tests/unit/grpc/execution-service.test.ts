@@ -0,0 +1,339 @@
+import { EventEmitter } from 'node:events';
+import * as grpc from '@grpc/grpc-js';
+import pino from 'pino';
+import { describe, expect, it, vi } from 'vitest';
+import type { DatabaseService } from '@/db/database.service';
+import { ExecutionServiceImpl } from '@/grpc/services/execution-service';
+import type { IPatternEngine } from '@/pattern-engine/interfaces';
+
+const logger = pino({ level: 'silent' });
+
+// ── Helpers ──
+
+function makeExecution(
+  id: string,
+  seconds: number,
+  status = 'completed',
+  patternName = 'consensus'
+) {
+  return {
+    id,
+    patternName,
+    status,
+    startTime: new Date(seconds * 1000),
+  };
+}
+
+function createEngine(executions: any[]): IPatternEngine {
+  return {
+    listExecutions: vi.fn(({ status }: { status?: string } = {}) =>
+      executions.filter((execution) => !status || execution.status === status)
+    ),
+  } as unknown as IPatternEngine;
+}
+
+async function list(service: ExecutionServiceImpl, request: any) {
+  const callback = vi.fn();
+  await service.listExecutions({ request } as any, callback);
+  return callback.mock.calls[0];
+}
+
+describe('ExecutionServiceImpl.listExecutions', () => {
+  it('pages through executions with server-issued cursors', async () => {
+    // Two executions share a start time, so the id breaks the tie
+    const service = new ExecutionServiceImpl(
+      createEngine([
+        makeExecution('exec-a', 10),
+        makeExecution('exec-b', 20),
+        makeExecution('exec-c', 20),
+        makeExecution('exec-d', 30),
+        makeExecution('exec-e', 40),
+      ]),
+      undefined,
+      logger
+    );
+
+    const ids: string[] = [];
+    let cursor = '';
+    for (let pages = 0; pages < 5; pages++) {
+      const [error, response] = await list(service, { limit: 2, cursor });
+      expect(error).toBeNull();
+      expect(response.total).toBe(5);
+      ids.push(...response.executions.map((execution: any) => execution.id));
+      cursor = response.next_cursor;
+      if (!cursor) break;
+    }
+
+    expect(ids).toEqual(['exec-e', 'exec-d', 'exec-c', 'exec-b', 'exec-a']);
+    expect(cursor).toBe('');
+  });
+
+  it('leaves next_cursor empty when the page is the last one', async () => {
+    const service = new ExecutionServiceImpl(
+      createEngine([makeExecution('exec-a', 10), makeExecution('exec-b', 20)]),
+      undefined,
+      logger
+    );
+
+    const [, response] = await list(service, { limit: 2, cursor: '' });
+
+    expect(response.executions).toHaveLength(2);
+    expect(response.next_cursor).toBe('');
+  });
+
+  it('rejects a cursor it did not issue', async () => {
+    const service = new ExecutionServiceImpl(createEngine([]), undefined, logger);
+
+    const [error] = await list(service, { limit: 2, cursor: 'bogus' });
+
+    expect(error.code).toBe(grpc.status.INVALID_ARGUMENT);
+  });
+
+  it('filters by pattern and start time before paginating', async () => {
+    const service = new ExecutionServiceImpl(
+      createEngine([
+        makeExecution('exec-a', 10, 'completed', 'consensus'),
+        makeExecution('exec-b', 20, 'completed', 'map-reduce'),
+        makeExecution('exec-c', 30, 'completed', 'consensus'),
+        makeExecution('exec-d', 40, 'completed', 'consensus'),
+      ]),
+      undefined,
+      logger
+    );
+    const filters = {
+      pattern_name: 'consensus',
+      started_after: { seconds: '10', nanos: 0 },
+      started_before: { seconds: '30', nanos: 0 },
+    };
+
+    const [, first] = await list(service, { limit: 1, cursor: '', ...filters });
+    expect(first.executions.map((execution: any) => execution.id)).toEqual([
+      'exec-c',
+    ]);
+    expect(first.total).toBe(2);
+
+    const [, second] = await list(service, {
+      limit: 1,
+      cursor: first.next_cursor,
+      ...filters,
+    });
+    expect(second.executions.map((execution: any) => execution.id)).toEqual([
+      'exec-a',
+    ]);
+    expect(second.next_cursor).toBe('');
+  });
+
+  it('continues after the cursor with a keyset query on the database', async () => {
+    const findAll = vi.fn().mockResolvedValue([]);
+    const count = vi.fn().mockResolvedValue(2);
+    const database = {
+      executions: {
+        findAll,
+        count,
+        getAgentEvents: vi.fn().mockResolvedValue([]),
+      },
+    } as unknown as DatabaseService;
+    const service = new ExecutionServiceImpl(createEngine([]), database, logger);
+
+    const [, first] = await list(service, { limit: 1, cursor: '' });
+    expect(first.next_cursor).toBe('');
+
+    findAll.mockResolvedValueOnce([
+      { id: 'exec-b', time: new Date(20_000), status: 'completed', input: {} },
+      { id: 'exec-a', time: new Date(10_000), status: 'completed', input: {} },
+    ]);
+    const [, second] = await list(service, { limit: 1, cursor: '' });
+    expect(second.executions.map((execution: any) => execution.id)).toEqual([
+      'exec-b',
+    ]);
+    expect(second.total).toBe(2);
+
+    await list(service, {
+      limit: 1,
+      cursor: second.next_cursor,
+      pattern_name: 'consensus',
+      started_after: { seconds: '5', nanos: 0 },
+      started_before: null,
+    });
+    const query = findAll.mock.calls[2][0];
+    expect(query.where.pattern).toEqual({ name: 'consensus' });
+    expect(query.where.time).toEqual({ gte: new Date(5_000), lte: undefined });
+    expect(query.orderBy).toEqual([{ time: 'desc' }, { id: 'desc' }]);
+    expect(query.take).toBe(2);
+    expect(query.skip).toBeUndefined();
+    expect(query.where.OR).toEqual([
+      { time: { lt: new Date(20_000) } },
+      { time: new Date(20_000), id: { lt: 'exec-b' } },
+    ]);
+    // The total counts every match, not just what follows the cursor
+    expect(count).toHaveBeenLastCalledWith({
+      pattern: { name: 'consensus' },
+      time: { gte: new Date(5_000), lte: undefined },
+    });
+  });
+});
+
+describe('ExecutionServiceImpl agents', () => {
+  it('reports the agents the engine dispatched', async () => {
+    const service = new ExecutionServiceImpl(
+      createEngine([
+        {
+          ...makeExecution('exec-a', 10),
+          agents: [
+            { agentId: 'agent-1', confidence: 0.9 },
+            { agentId: 'agent-2', confidence: 0 },
+          ],
+        },
+      ]),
+      undefined,
+      logger
+    );
+
+    const [, response] = await list(service, { limit: 10 });
+
+    expect(response.executions[0].agents).toEqual([
+      { agent_id: 'agent-1', confidence: 0.9 },
+      { agent_id: 'agent-2', confidence: 0 },
+    ]);
+  });
+
+  it('rebuilds agents of stored executions from their events', async () => {
+    const record = (id: string) => ({
+      id,
+      time: new Date(10_000),
+      status: 'completed',
+      input: {},
+    });
+    const getAgentEvents = vi.fn().mockResolvedValue([
+      {
+        executionId: 'exec-a',
+        type: 'agent_completed',
+        agentId: 'agent-1',
+        data: { confidence: 0.8 },
+      },
+      {
+        executionId: 'exec-a',
+        type: 'agent_failed',
+        agentId: 'agent-2',
+        data: { error: 'timed out' },
+      },
+      {
+        executionId: 'exec-b',
+        type: 'agent_completed',
+        agentId: 'agent-3',
+        data: { confidence: 0.7 },
+      },
+    ]);
+    const database = {
+      executions: {
+        findAll: vi.fn().mockResolvedValue([record('exec-a'), record('exec-b')]),
+        count: vi.fn().mockResolvedValue(2),
+        findById: vi.fn().mockResolvedValue(record('exec-a')),
+        getAgentEvents,
+      },
+    } as unknown as DatabaseService;
+    const service = new ExecutionServiceImpl(createEngine([]), database, logger);
+
+    const [, listed] = await list(service, { limit: 10 });
+    expect(getAgentEvents).toHaveBeenCalledWith(['exec-a', 'exec-b']);
+    expect(listed.executions[0].agents).toEqual([
+      { agent_id: 'agent-1', confidence: 0.8 },
+      { agent_id: 'agent-2', confidence: 0 },
+    ]);
+    expect(listed.executions[1].agents).toEqual([
+      { agent_id: 'agent-3', confidence: 0.7 },
+    ]);
+
+    const callback = vi.fn();
+    await service.getExecution(
+      { request: { execution_id: 'exec-a' } } as any,
+      callback
+    );
+    expect(callback.mock.calls[0][1].execution.agents).toHaveLength(2);
+  });
+});
+
+describe('ExecutionServiceImpl.exportExecutions', () => {
+  function createMockStream(request: any) {
+    const stream = new EventEmitter() as EventEmitter & {
+      request: any;
+      write: ReturnType<typeof vi.fn>;
+      end: ReturnType<typeof vi.fn>;
+    };
+    stream.request = request;
+    stream.write = vi.fn().mockReturnValue(true);
+    stream.end = vi.fn();
+    return stream;
+  }
+
+  it('streams every matching execution across batches', async () => {
+    const executions = Array.from({ length: 250 }, (_, index) =>
+      makeExecution(
+        `exec-${String(index).padStart(3, '0')}`,
+        index,
+        'completed',
+        index % 5 === 0 ? 'map-reduce' : 'consensus'
+      )
+    );
+    const engine = createEngine(executions);
+    const service = new ExecutionServiceImpl(engine, undefined, logger);
+    const stream = createMockStream({
+      status: 'completed',
+      pattern_name: 'consensus',
+      started_after: null,
+      started_before: { seconds: '199', nanos: 0 },
+    });
+
+    await service.exportExecutions(stream as any);
+
+    const ids = stream.write.mock.calls.map(([execution]) => execution.id);
+    expect(ids).toHaveLength(160);
+    expect(ids[0]).toBe('exec-199');
+    expect(ids).not.toContain('exec-195');
+    expect(new Set(ids).size).toBe(ids.length);
+    expect(stream.end).toHaveBeenCalledOnce();
+    expect(engine.listExecutions).toHaveBeenCalledTimes(2);
+  });
+
+  it('waits for the client to drain before writing more', async () => {
+    const service = new ExecutionServiceImpl(
+      createEngine([makeExecution('exec-a', 10), makeExecution('exec-b', 20)]),
+      undefined,
+      logger
+    );
+    const stream = createMockStream({});
+    stream.write.mockReturnValueOnce(false);
+
+    const exporting = service.exportExecutions(stream as any);
+    await vi.waitFor(() => expect(stream.write).toHaveBeenCalledTimes(1));
+    expect(stream.end).not.toHaveBeenCalled();
+
+    stream.emit('drain');
+    await exporting;
+
+    expect(stream.write).toHaveBeenCalledTimes(2);
+    expect(stream.end).toHaveBeenCalledOnce();
+  });
+
+  it.each(['cancelled', 'close'])(
+    'stops waiting to drain once the call is %s',
+    async (event) => {
+      const service = new ExecutionServiceImpl(
+        createEngine([makeExecution('exec-a', 10), makeExecution('exec-b', 20)]),
+        undefined,
+        logger
+      );
+      const stream = createMockStream({});
+      stream.write.mockReturnValueOnce(false);
+
+      const exporting = service.exportExecutions(stream as any);
+      await vi.waitFor(() => expect(stream.write).toHaveBeenCalledTimes(1));
+
+      stream.emit(event);
+      await exporting;
+
+      expect(stream.write).toHaveBeenCalledTimes(1);
+      expect(stream.listenerCount('drain')).toBe(0);
+    }
+  );
+});
@@ -0,0 +1,263 @@
+import { EventEmitter } from 'node:events';
+import * as grpc from '@grpc/grpc-js';
+import pino from 'pino';
+import { afterEach, beforeEach, describe, expect, it, vi } from 'vitest';
+import type { DatabaseService } from '@/db/database.service';
+import { ExecutionEventBus } from '@/execution-events';
+import { PatternServiceImpl } from '@/grpc/services/pattern-service';
+import type { IPatternEngine } from '@/pattern-engine/interfaces';
+
+const logger = pino({ level: 'silent' });
+
+// ── Helpers ──
+
+function createMockStream(request: any) {
+  const stream = new EventEmitter() as EventEmitter & {
+    request: any;
+    write: ReturnType<typeof vi.fn>;
+    end: ReturnType<typeof vi.fn>;
+  };
+  stream.request = request;
+  stream.write = vi.fn();
+  stream.end = vi.fn();
+  return stream;
+}
+
+function makePattern(name: string, description = `${name} pattern`) {
+  return {
+    name,
+    version: '1.0.0',
+    description,
+    input: { type: 'any' },
+    script: 'return input;',
+  };
+}
+
+function written(stream: ReturnType<typeof createMockStream>) {
+  return stream.write.mock.calls.map(([event]) => [
+    event.type,
+    event.pattern.name,
+  ]);
+}
+
+describe('PatternServiceImpl.watchPatterns', () => {
+  let patterns: any[];
+  let service: PatternServiceImpl;
+
+  beforeEach(() => {
+    vi.useFakeTimers();
+    patterns = [makePattern('consensus'), makePattern('map-reduce')];
+    const engine = {
+      listPatterns: vi.fn(() => patterns),
+    } as unknown as IPatternEngine;
+    service = new PatternServiceImpl(engine, {} as DatabaseService, logger);
+  });
+
+  afterEach(() => {
+    vi.useRealTimers();
+  });
+
+  it('sends the current catalog as ADDED when asked to', async () => {
+    const stream = createMockStream({ include_initial: true });
+
+    await service.watchPatterns(stream as any);
+
+    expect(written(stream)).toEqual([
+      ['ADDED', 'consensus'],
+      ['ADDED', 'map-reduce'],
+    ]);
+    stream.emit('cancelled');
+  });
+
+  it('reports additions, updates and removals as they happen', async () => {
+    const stream = createMockStream({ include_initial: false });
+
+    await service.watchPatterns(stream as any);
+    expect(stream.write).not.toHaveBeenCalled();
+
+    patterns = [
+      makePattern('consensus', 'Reach agreement'),
+      makePattern('triage'),
+    ];
+    await vi.advanceTimersByTimeAsync(2000);
+
+    expect(written(stream)).toEqual([
+      ['UPDATED', 'consensus'],
+      ['ADDED', 'triage'],
+      ['REMOVED', 'map-reduce'],
+    ]);
+
+    // Nothing changed, so nothing more is sent
+    await vi.advanceTimersByTimeAsync(2000);
+    expect(stream.write).toHaveBeenCalledTimes(3);
+    stream.emit('cancelled');
+  });
+
+  it('stops re-reading the catalog once the client goes away', async () => {
+    const stream = createMockStream({ include_initial: false });
+
+    await service.watchPatterns(stream as any);
+    stream.emit('cancelled');
+    patterns = [];
+    await vi.advanceTimersByTimeAsync(10_000);
+
+    expect(stream.write).not.toHaveBeenCalled();
+  });
+});
+
+describe('PatternServiceImpl.getPattern', () => {
+  it('replies with the bare Pattern message', async () => {
+    const engine = {
+      listPatterns: vi.fn(() => [makePattern('consensus')]),
+    } as unknown as IPatternEngine;
+    const service = new PatternServiceImpl(
+      engine,
+      {} as DatabaseService,
+      logger
+    );
+    const callback = vi.fn();
+
+    await service.getPattern(
+      { request: { name: 'consensus' } } as any,
+      callback
+    );
+
+    const [error, reply] = callback.mock.calls[0];
+    expect(error).toBeNull();
+    expect(reply.name).toBe('consensus');
+    expect(reply.definition).toBe('return input;');
+    expect(reply).not.toHaveProperty('pattern');
+  });
+
+  it('answers NOT_FOUND for an unknown pattern', async () => {
+    const engine = {
+      listPatterns: vi.fn(() => [makePattern('consensus')]),
+    } as unknown as IPatternEngine;
+    const service = new PatternServiceImpl(
+      engine,
+      {} as DatabaseService,
+      logger
+    );
+    const callback = vi.fn();
+
+    await service.getPattern({ request: { name: 'debate' } } as any, callback);
+
+    expect(callback.mock.calls[0][0].code).toBe(grpc.status.NOT_FOUND);
+  });
+});
+
+describe('PatternServiceImpl.executePattern', () => {
+  function executeCall(idempotencyKey?: string) {
+    const metadata = new grpc.Metadata();
+    if (idempotencyKey) metadata.set('idempotency-key', idempotencyKey);
+    return { request: { pattern_name: 'consensus', input: {} }, metadata };
+  }
+
+  async function execute(service: PatternServiceImpl, key?: string) {
+    const callback = vi.fn();
+    await service.executePattern(executeCall(key) as any, callback);
+    return callback.mock.calls[0];
+  }
+
+  let runs: number;
+  let service: PatternServiceImpl;
+
+  beforeEach(() => {
+    runs = 0;
+    const engine = {
+      executePattern: vi.fn(async () => {
+        runs += 1;
+        return {
+          id: `exec-${runs}`,
+          status: 'completed',
+          startTime: new Date(),
+          result: { value: 'ok' },
+        };
+      }),
+    } as unknown as IPatternEngine;
+    service = new PatternServiceImpl(engine, {} as DatabaseService, logger);
+  });
+
+  it('returns the first execution for a repeated idempotency key', async () => {
+    const [, first] = await execute(service, 'order-42');
+    const [, second] = await execute(service, 'order-42');
+
+    expect(runs).toBe(1);
+    expect(second.execution_id).toBe(first.execution_id);
+  });
+
+  it('runs again for a new key or no key', async () => {
+    await execute(service, 'order-42');
+    const [, other] = await execute(service, 'order-43');
+    const [, unkeyed] = await execute(service);
+
+    expect(runs).toBe(3);
+    expect(other.execution_id).toBe('exec-2');
+    expect(unkeyed.execution_id).toBe('exec-3');
+  });
+});
+
+describe('PatternServiceImpl.streamExecutePattern', () => {
+  it('forwards engine events before the final result', async () => {
+    const events = new ExecutionEventBus();
+    const executions = new Map<string, any>();
+    const engine = {
+      getExecution: vi.fn((id: string) => executions.get(id)),
+      executePattern: vi.fn(
+        async (patternName: string, input: any, options: any) => {
+          const execution: any = {
+            id: options.executionId,
+            patternName,
+            status: 'running',
+            startTime: new Date(),
+            input,
+          };
+          executions.set(execution.id, execution);
+          const emit = (type: string, data: any) =>
+            events.emitEvent({
+              executionId: execution.id,
+              type,
+              data,
+              timestamp: new Date(),
+            });
+          emit('started', { patternName });
+          emit('agent_completed', { agentId: 'agent-1', confidence: 0.8 });
+          execution.status = 'completed';
+          execution.result = {
+            value: 'ok',
+            confidence: 0.8,
+            executedAt: new Date(),
+          };
+          execution.confidence = 0.8;
+          emit('completed', { patternName, confidence: 0.8 });
+          return execution;
+        }
+      ),
+    } as unknown as IPatternEngine;
+    const service = new PatternServiceImpl(
+      engine,
+      {} as DatabaseService,
+      logger,
+      events
+    );
+    const stream = createMockStream({ pattern_name: 'consensus', input: {} });
+
+    await service.streamExecutePattern(stream as any);
+
+    const messages = stream.write.mock.calls.map(([message]) => message);
+    expect(
+      messages.map((message) => [message.event_type, message.status])
+    ).toEqual([
+      ['started', 'UNKNOWN'],
+      ['agent_completed', 'UNKNOWN'],
+      ['completed', 'SUCCESS'],
+    ]);
+    expect(messages[1].event_data).toEqual({
+      agentId: 'agent-1',
+      confidence: 0.8,
+    });
+    expect(new Set(messages.map((message) => message.execution_id)).size).toBe(1);
+    expect(stream.end).toHaveBeenCalledTimes(1);
+    expect(events.listenerCount('execution')).toBe(0);
+  });
+});
@@ -0,0 +1,120 @@
+import { EventEmitter } from 'node:events';
+import pino from 'pino';
+import { afterEach, beforeEach, describe, expect, it, vi } from 'vitest';
+import { RegistryServiceImpl } from '@/grpc/services/registry-service';
+import type { IAgentRegistry, ServiceRegistration } from '@/registry';
+
+const logger = pino({ level: 'silent' });
+
+// ── Helpers ──
+
+function createMockStream(request: any) {
+  const stream = new EventEmitter() as EventEmitter & {
+    request: any;
+    write: ReturnType<typeof vi.fn>;
+    end: ReturnType<typeof vi.fn>;
+  };
+  stream.request = request;
+  stream.write = vi.fn();
+  stream.end = vi.fn();
+  return stream;
+}
+
+function makeAgent(id: string, version = '1.0.0'): ServiceRegistration {
+  return {
+    id,
+    name: id,
+    type: 'agent',
+    endpoint: `${id}:50051`,
+    metadata: { capabilities: ['analysis'], version },
+    health: { status: 'healthy', lastCheck: new Date(), checkInterval: 0 },
+  } as ServiceRegistration;
+}
+
+function written(stream: ReturnType<typeof createMockStream>) {
+  return stream.write.mock.calls.map(([event]) => [event.type, event.agent.id]);
+}
+
+describe('RegistryServiceImpl.watch', () => {
+  let agents: ServiceRegistration[];
+  let service: RegistryServiceImpl;
+
+  beforeEach(() => {
+    vi.useFakeTimers();
+    agents = [makeAgent('alpha'), makeAgent('beta')];
+    const registry = {
+      listServices: vi.fn(async () => agents),
+    } as unknown as IAgentRegistry;
+    service = new RegistryServiceImpl(registry, logger);
+  });
+
+  afterEach(() => {
+    vi.useRealTimers();
+  });
+
+  it('sends the current agents as ADDED when asked to', async () => {
+    const stream = createMockStream({ include_initial: true });
+
+    await service.watch(stream as any);
+
+    expect(written(stream)).toEqual([
+      ['ADDED', 'alpha'],
+      ['ADDED', 'beta'],
+    ]);
+    stream.emit('cancelled');
+  });
+
+  it('sends only later changes without include_initial', async () => {
+    const stream = createMockStream({ include_initial: false });
+
+    await service.watch(stream as any);
+    expect(stream.write).not.toHaveBeenCalled();
+
+    agents = [makeAgent('alpha', '1.1.0'), makeAgent('gamma')];
+    await vi.advanceTimersByTimeAsync(2000);
+
+    expect(written(stream)).toEqual([
+      ['MODIFIED', 'alpha'],
+      ['ADDED', 'gamma'],
+      ['DELETED', 'beta'],
+    ]);
+    stream.emit('cancelled');
+  });
+
+  it('reports only the agent named by agent_id', async () => {
+    const stream = createMockStream({
+      include_initial: true,
+      agent_id: 'beta',
+    });
+
+    await service.watch(stream as any);
+    agents = [makeAgent('alpha', '1.1.0')];
+    await vi.advanceTimersByTimeAsync(2000);
+
+    expect(written(stream)).toEqual([
+      ['ADDED', 'beta'],
+      ['DELETED', 'beta'],
+    ]);
+    stream.emit('cancelled');
+  });
+});
+
+describe('RegistryServiceImpl.listAgents', () => {
+  it('reports when each agent last renewed its lease', async () => {
+    const renewed = makeAgent('alpha');
+    renewed.health.lastCheck = new Date('2026-01-02T03:04:05.678Z');
+    const registry = {
+      listServices: vi.fn(async () => [renewed]),
+    } as unknown as IAgentRegistry;
+    const service = new RegistryServiceImpl(registry, logger);
+    const callback = vi.fn();
+
+    await service.listAgents({ request: {} } as any, callback);
+
+    const [agent] = callback.mock.calls[0][1].agents;
+    expect(agent.last_heartbeat).toEqual({
+      seconds: Date.parse('2026-01-02T03:04:05Z') / 1000,
+      nanos: 678_000_000,
+    });
+  });
+});
@@ -2,7 +2,7 @@ import path from 'node:path';
 import pino from 'pino';
 import { beforeEach, describe, expect, it, vi } from 'vitest';
 import { DatabaseService } from '@/db/database.service';
-import { PatternEngine } from '@/pattern-engine/pattern-engine';
+import { PatternEngine, withAgentCounts } from '@/pattern-engine/pattern-engine';
 import { EtcdRegistry } from '@/registry';
 
 // Mock dependencies
@@ -272,3 +272,35 @@ describe('PatternEngine', () => {
     });
   });
 });
+
+describe('withAgentCounts', () => {
+  const pattern = {
+    name: 'consensus',
+    version: '1.0.0',
+    description: '',
+    input: { type: 'any' },
+    minAgents: 3,
+    maxAgents: 4,
+    script: '',
+  } as any;
+
+  it('keeps the pattern when no counts are requested', () => {
+    expect(withAgentCounts(pattern, { timeout: 1000 })).toBe(pattern);
+    expect(withAgentCounts(pattern, { minAgents: 0, maxAgents: 0 })).toBe(
+      pattern
+    );
+  });
+
+  it('raises the maximum along with an escalated minimum', () => {
+    const escalated = withAgentCounts(pattern, { minAgents: 5 });
+    expect(escalated.minAgents).toBe(5);
+    expect(escalated.maxAgents).toBe(5);
+    expect(pattern.minAgents).toBe(3);
+  });
+
+  it('uses an explicit maximum as given', () => {
+    const capped = withAgentCounts(pattern, { minAgents: 2, maxAgents: 6 });
+    expect(capped.minAgents).toBe(2);
+    expect(capped.maxAgents).toBe(6);
+  });
+});